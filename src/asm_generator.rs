@@ -0,0 +1,552 @@
+//! Assembly for a small stack machine. Every instruction takes its operands
+//! from a stack of integers and pushes its result back; variables and
+//! arrays are named slots. The first line, `.bits 32` or `.bits 64`, gives
+//! the width every value wraps to. `vm` runs the output.
+//!
+//! | Instruction | Effect |
+//! |---|---|
+//! | `PUSH n` | push `n` |
+//! | `POP` | drop the top value |
+//! | `DUP` | push the top value again |
+//! | `LOAD x`, `STORE x` | push `x`; pop into `x` |
+//! | `ARRAY a n` | pop a value and make `a` an array of `n` copies of it |
+//! | `LOADI a`, `STOREI a` | pop an index and push `a[index]`; pop a value, then an index, and store it there |
+//! | `ADD` `SUB` `MUL` `DIV` `MOD` `POW` | pop `b`, then `a`, and push `a op b` |
+//! | `AND` `OR` `XOR` `SHL` `SHR` | bitwise operators, also on `a` and `b` |
+//! | `EQ` `NE` `LT` `LE` `GT` `GE` | push 1 if the comparison holds, else 0 |
+//! | `MIN` `MAX` | push the smaller or larger of `a` and `b` |
+//! | `NEG`, `ABS` | replace the top value with its negation or absolute value |
+//! | `JMP label` | continue after `label:` |
+//! | `JZ label` | pop a value and jump to `label` if it's 0 |
+//! | `PRINT`, `PRINTX`, `PRINTC` | pop a value and write it in decimal, in hex, or as a byte |
+//! | `TEXT "..."` | write the text |
+//! | `DIGITS` | replace the top value with the length of its decimal form |
+//! | `READ` | push the next integer of input |
+//! | `ASSERT "..."` | pop a value; if it's 0, report the message and exit with code 1 |
+//! | `EXIT` | pop a value and exit with it as the code |
+//!
+//! Lines starting with `;` are comments. `POW` with a negative exponent
+//! pushes 1, like the C backend's `_hand_pow`.
+
+use crate::ast::*;
+use crate::const_eval::{IntWidth, eval_const};
+use crate::scope::ScopeStack;
+use std::collections::HashMap;
+
+pub fn generate_asm(ast: &AbstractSyntaxTree, int_width: IntWidth) -> String {
+    let mut generator = AsmGen {
+        int_width,
+        scopes: ScopeStack::new(),
+        names: Vec::new(),
+        arrays: HashMap::new(),
+        constants: ScopeStack::new(),
+        labels: 0,
+        loop_ends: Vec::new(),
+    };
+    let mut lines = vec![format!(".bits {}", int_width.bits())];
+    generator.generate_statement_list(&ast.statement_list, &mut lines);
+    lines.push("    PUSH 0".to_string());
+    lines.push("    EXIT".to_string());
+    lines.join("\n") + "\n"
+}
+
+struct AsmGen {
+    int_width: IntWidth,
+    /// The slot each source name refers to in the current scope.
+    scopes: ScopeStack<String>,
+    /// Every slot used. Slots are global, so a shadowing declaration gets a
+    /// fresh one.
+    names: Vec<String>,
+    /// The length of every array, by slot.
+    arrays: HashMap<String, usize>,
+    constants: ScopeStack<i64>,
+    /// Counter for unique labels and loop bookkeeping slots.
+    labels: usize,
+    /// The label after every loop being generated, innermost last, which
+    /// `break` jumps to.
+    loop_ends: Vec<String>,
+}
+
+impl AsmGen {
+    fn new_name(&mut self, name: &str) -> String {
+        let mut slot = name.to_string();
+        let mut suffix = 1;
+        while self.names.contains(&slot) {
+            slot = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        self.names.push(slot.clone());
+        slot
+    }
+
+    fn declare(&mut self, name: &str) -> String {
+        let slot = self.new_name(name);
+        self.scopes.declare(name.to_string(), slot.clone());
+        slot
+    }
+
+    fn slot(&self, identifier: &Identifier) -> String {
+        self.scopes
+            .lookup(&identifier.name)
+            .cloned()
+            .expect("undeclared variables are rejected by semantic analysis")
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn generate_statement_list(&mut self, statement_list: &StatementList, out: &mut Vec<String>) {
+        for statement in &statement_list.statements {
+            self.generate_statement(statement, out);
+        }
+    }
+
+    fn generate_block(&mut self, block: &Block, out: &mut Vec<String>) {
+        self.scopes.enter_scope();
+        self.constants.enter_scope();
+        self.generate_statement_list(&block.statements, out);
+        self.constants.exit_scope();
+        self.scopes.exit_scope();
+    }
+
+    fn generate_statement(&mut self, statement: &Statement, out: &mut Vec<String>) {
+        match statement {
+            Statement::Let(let_stmt) => {
+                // Stored even without an initializer, since a `let` in a
+                // loop body runs again on every iteration.
+                match &let_stmt.value {
+                    Some(value) => self.generate_expression(value, out),
+                    None => out.push("    PUSH 0".to_string()),
+                }
+                let slot = self.declare(&let_stmt.identifier.name);
+                out.push(format!("    STORE {}", slot));
+            }
+            Statement::Const(const_stmt) => {
+                if let Some(value) = eval_const(&const_stmt.value, self.int_width) {
+                    self.constants
+                        .declare(const_stmt.identifier.name.clone(), value);
+                }
+            }
+            Statement::ArrayLet(array_let_stmt) => {
+                self.generate_array_let_statement(array_let_stmt, out)
+            }
+            Statement::Assignment(assign_stmt) => match &assign_stmt.index {
+                Some(index) => {
+                    self.generate_expression(index, out);
+                    self.generate_expression(&assign_stmt.value, out);
+                    out.push(format!("    STOREI {}", self.slot(&assign_stmt.identifier)));
+                }
+                None => {
+                    self.generate_expression(&assign_stmt.value, out);
+                    out.push(format!("    STORE {}", self.slot(&assign_stmt.identifier)));
+                }
+            },
+            Statement::Loop(loop_stmt) => self.generate_loop_statement(loop_stmt, out),
+            Statement::Repeat(repeat_stmt) => {
+                let label = self.new_label();
+                out.push(format!("repeat_{}:", label));
+                self.loop_ends.push(format!("end_repeat_{}", label));
+                self.generate_block(&repeat_stmt.body, out);
+                self.loop_ends.pop();
+                self.generate_expression(&repeat_stmt.condition, out);
+                out.push(format!("    JZ repeat_{}", label));
+                out.push(format!("end_repeat_{}:", label));
+            }
+            Statement::If(if_stmt) => self.generate_if_statement(if_stmt, out),
+            Statement::Print(print_stmt) => {
+                let print_stmt = print_stmt.expand_arrays(|name| {
+                    let slot = self.scopes.lookup(name)?;
+                    self.arrays.get(slot).copied()
+                });
+                self.generate_print(&print_stmt.arguments, &print_stmt.format, out);
+            }
+            Statement::Read(read_stmt) => {
+                out.push("    READ".to_string());
+                out.push(format!("    STORE {}", self.slot(&read_stmt.identifier)));
+            }
+            Statement::Assert(assert_stmt) => {
+                self.generate_expression(&assert_stmt.condition, out);
+                out.push(match &assert_stmt.message {
+                    Some(message) => format!("    ASSERT {}", asm_string(message)),
+                    None => "    ASSERT".to_string(),
+                });
+            }
+            Statement::Break(_) => {
+                let end = self
+                    .loop_ends
+                    .last()
+                    .expect("analysis rejects `break` outside a loop");
+                out.push(format!("    JMP {}", end));
+            }
+            Statement::Exit(exit_stmt) => {
+                self.generate_expression(&exit_stmt.code, out);
+                out.push("    EXIT".to_string());
+            }
+            Statement::Expression(expr_stmt) => {
+                self.generate_expression(&expr_stmt.value, out);
+                out.push("    POP".to_string());
+            }
+            Statement::Comment(comment) => out.push(format!("; {}", comment.text)),
+            // Rejected by semantic analysis.
+            Statement::Include(_) => {}
+            // The module's consts follow it.
+            Statement::Use(_) => {}
+        }
+    }
+
+    /// Elements of a list are stored one by one over an array of zeros.
+    fn generate_array_let_statement(
+        &mut self,
+        array_let_stmt: &ArrayLetStatement,
+        out: &mut Vec<String>,
+    ) {
+        let elements = match &array_let_stmt.initializer {
+            ArrayInitializer::Fill(value) => {
+                self.generate_expression(value, out);
+                &[][..]
+            }
+            ArrayInitializer::List(elements) => {
+                out.push("    PUSH 0".to_string());
+                elements.as_slice()
+            }
+        };
+        let slot = self.new_name(&array_let_stmt.identifier.name);
+        out.push(format!("    ARRAY {} {}", slot, array_let_stmt.length));
+        self.arrays.insert(slot.clone(), array_let_stmt.length);
+        for (i, element) in elements.iter().enumerate() {
+            out.push(format!("    PUSH {}", i));
+            self.generate_expression(element, out);
+            out.push(format!("    STOREI {}", slot));
+        }
+        // Declared last so the elements can't refer to the array.
+        self.scopes
+            .declare(array_let_stmt.identifier.name.clone(), slot);
+    }
+
+    fn generate_loop_statement(&mut self, loop_stmt: &LoopStatement, out: &mut Vec<String>) {
+        let label = self.new_label();
+        // As in C, the bounds and step are evaluated once, before the counter
+        // is declared.
+        match &loop_stmt.start {
+            Some(start) => self.generate_expression(start, out),
+            None => out.push("    PUSH 0".to_string()),
+        }
+        let start = self.new_name(&format!("_start_{}", label));
+        out.push(format!("    STORE {}", start));
+        let count = self.new_name(&format!("_count_{}", label));
+        self.generate_expression(&loop_stmt.count, out);
+        out.push(format!("    STORE {}", count));
+        let step = match &loop_stmt.step {
+            Some(step) => {
+                let slot = self.new_name(&format!("_step_{}", label));
+                self.generate_expression(step, out);
+                out.push(format!("    STORE {}", slot));
+                format!("LOAD {}", slot)
+            }
+            None => "PUSH 1".to_string(),
+        };
+
+        self.scopes.enter_scope();
+        let counter = match &loop_stmt.counter {
+            Some(counter) => self.declare(&counter.name),
+            None => self.new_name(&format!("_counter_{}", label)),
+        };
+        out.push(format!("    LOAD {}", start));
+        out.push(format!("    STORE {}", counter));
+        let range = loop_stmt.start.is_some();
+        if range {
+            // A range loop is entered once if the start is at most the end,
+            // and left after the iteration that reaches the end, so the
+            // counter never steps past it.
+            out.push(format!("    LOAD {}", counter));
+            out.push(format!("    LOAD {}", count));
+            out.push("    LE".to_string());
+            out.push(format!("    JZ end_loop_{}", label));
+        }
+        out.push(format!("loop_{}:", label));
+        if !range {
+            out.push(format!("    LOAD {}", counter));
+            out.push(format!("    LOAD {}", count));
+            out.push("    LT".to_string());
+            out.push(format!("    JZ end_loop_{}", label));
+        }
+        self.loop_ends.push(format!("end_loop_{}", label));
+        self.generate_block(&loop_stmt.body, out);
+        self.loop_ends.pop();
+        if range && loop_stmt.step.is_none() {
+            out.push(format!("    LOAD {}", counter));
+            out.push(format!("    LOAD {}", count));
+            out.push("    NE".to_string());
+            out.push(format!("    JZ end_loop_{}", label));
+        } else if range {
+            // Stop once the distance left is below the step, both read as
+            // unsigned. Flipping the sign bit turns that into a signed
+            // compare.
+            let min = self.int_width.min();
+            out.push(format!("    LOAD {}", count));
+            out.push(format!("    LOAD {}", counter));
+            out.push("    SUB".to_string());
+            out.push(format!("    PUSH {}", min));
+            out.push("    XOR".to_string());
+            out.push(format!("    {}", step));
+            out.push(format!("    PUSH {}", min));
+            out.push("    XOR".to_string());
+            out.push("    GE".to_string());
+            out.push(format!("    JZ end_loop_{}", label));
+        }
+        out.push(format!("    LOAD {}", counter));
+        out.push(format!("    {}", step));
+        out.push("    ADD".to_string());
+        out.push(format!("    STORE {}", counter));
+        out.push(format!("    JMP loop_{}", label));
+        out.push(format!("end_loop_{}:", label));
+        self.scopes.exit_scope();
+    }
+
+    fn generate_if_statement(&mut self, if_stmt: &IfStatement, out: &mut Vec<String>) {
+        let label = self.new_label();
+        self.generate_expression(&if_stmt.condition, out);
+        out.push(format!("    JZ else_{}", label));
+        self.generate_block(&if_stmt.then_block, out);
+        let Some(else_branch) = if_stmt.else_branch.as_deref() else {
+            out.push(format!("else_{}:", label));
+            return;
+        };
+        out.push(format!("    JMP end_if_{}", label));
+        out.push(format!("else_{}:", label));
+        match else_branch {
+            ElseBranch::Block(block) => self.generate_block(block, out),
+            ElseBranch::If(next) => self.generate_if_statement(next, out),
+        }
+        out.push(format!("end_if_{}:", label));
+    }
+
+    /// Writes `arguments` like `print` does, ending the line unless they're
+    /// printed as characters.
+    fn generate_print(
+        &mut self,
+        arguments: &[PrintArgument],
+        format: &PrintFormat,
+        out: &mut Vec<String>,
+    ) {
+        let instruction = match format {
+            PrintFormat::Decimal => "PRINT",
+            PrintFormat::Hex => "PRINTX",
+            PrintFormat::Char => "PRINTC",
+        };
+        for argument in arguments {
+            match argument {
+                PrintArgument::Text(text) => out.push(format!("    TEXT {}", asm_string(text))),
+                PrintArgument::Value(value) => {
+                    self.generate_expression(value, out);
+                    out.push(format!("    {}", instruction));
+                }
+            }
+        }
+        if *format != PrintFormat::Char {
+            out.push("    TEXT \"\\n\"".to_string());
+        }
+    }
+
+    /// Prints like `print`, keeping a running total of the bytes written
+    /// under each value.
+    fn generate_write(&mut self, write: &WriteExpr, out: &mut Vec<String>) {
+        out.push("    PUSH 0".to_string());
+        for argument in &write.arguments {
+            match argument {
+                PrintArgument::Text(text) => {
+                    out.push(format!("    TEXT {}", asm_string(text)));
+                    out.push(format!("    PUSH {}", text.len()));
+                }
+                PrintArgument::Value(value) => {
+                    self.generate_expression(value, out);
+                    out.push("    DUP".to_string());
+                    out.push("    PRINT".to_string());
+                    out.push("    DIGITS".to_string());
+                }
+            }
+            out.push("    ADD".to_string());
+        }
+        out.push("    TEXT \"\\n\"".to_string());
+        out.push("    PUSH 1".to_string());
+        out.push("    ADD".to_string());
+    }
+
+    fn generate_expression(&mut self, expr: &Expr, out: &mut Vec<String>) {
+        match &expr.kind {
+            ExprKind::Term(Term::Number(n)) => out.push(format!("    PUSH {}", n)),
+            ExprKind::Term(Term::Bool(value)) => {
+                out.push(format!("    PUSH {}", i64::from(*value)))
+            }
+            ExprKind::Term(Term::Identifier(identifier)) => {
+                match self.constants.lookup(&identifier.name) {
+                    Some(value) => out.push(format!("    PUSH {}", value)),
+                    None => out.push(format!("    LOAD {}", self.slot(identifier))),
+                }
+            }
+            ExprKind::Term(Term::Index { base, index }) => {
+                self.generate_expression(index, out);
+                out.push(format!("    LOADI {}", self.slot(base)));
+            }
+            ExprKind::Call(call) => {
+                let instruction = match Intrinsic::from_name(&call.function.name) {
+                    Some(Intrinsic::Min | Intrinsic::MinOf) => "MIN",
+                    Some(Intrinsic::Max | Intrinsic::MaxOf) => "MAX",
+                    Some(Intrinsic::Abs) => "ABS",
+                    None => unreachable!("unknown functions are rejected by semantic analysis"),
+                };
+                // `min_of` and `max_of` fold their arguments in pairs.
+                for (i, argument) in call.arguments.iter().enumerate() {
+                    self.generate_expression(argument, out);
+                    if i > 0 || instruction == "ABS" {
+                        out.push(format!("    {}", instruction));
+                    }
+                }
+            }
+            ExprKind::Binary(binary) => self.generate_binary_expression(binary, out),
+            ExprKind::Negate(operand) => {
+                self.generate_expression(operand, out);
+                out.push("    NEG".to_string());
+            }
+            ExprKind::Write(write) => self.generate_write(write, out),
+            ExprKind::StringLength(text) => out.push(format!("    PUSH {}", text.len())),
+            ExprKind::Block(block) => {
+                self.scopes.enter_scope();
+                self.constants.enter_scope();
+                self.generate_statement_list(&block.statements, out);
+                self.generate_expression(&block.value, out);
+                self.constants.exit_scope();
+                self.scopes.exit_scope();
+            }
+        }
+    }
+
+    fn generate_binary_expression(&mut self, binary: &BinaryExpr, out: &mut Vec<String>) {
+        // Short-circuit like C, so `x != 0 && 10 / x > 1` can't divide by
+        // zero.
+        match binary.operator {
+            BinaryOperator::LogicalAnd => {
+                let label = self.new_label();
+                self.generate_expression(&binary.lhs, out);
+                out.push(format!("    JZ false_{}", label));
+                self.generate_expression(&binary.rhs, out);
+                out.push(format!("    JZ false_{}", label));
+                out.push("    PUSH 1".to_string());
+                out.push(format!("    JMP end_and_{}", label));
+                out.push(format!("false_{}:", label));
+                out.push("    PUSH 0".to_string());
+                out.push(format!("end_and_{}:", label));
+                return;
+            }
+            BinaryOperator::LogicalOr => {
+                let label = self.new_label();
+                self.generate_expression(&binary.lhs, out);
+                out.push(format!("    JZ rhs_{}", label));
+                out.push("    PUSH 1".to_string());
+                out.push(format!("    JMP end_or_{}", label));
+                out.push(format!("rhs_{}:", label));
+                self.generate_expression(&binary.rhs, out);
+                out.push("    PUSH 0".to_string());
+                out.push("    NE".to_string());
+                out.push(format!("end_or_{}:", label));
+                return;
+            }
+            _ => {}
+        }
+        self.generate_expression(&binary.lhs, out);
+        self.generate_expression(&binary.rhs, out);
+        let instruction = match binary.operator {
+            BinaryOperator::Add => "ADD",
+            BinaryOperator::Subtract => "SUB",
+            BinaryOperator::Multiply => "MUL",
+            BinaryOperator::Divide => "DIV",
+            BinaryOperator::Modulo => "MOD",
+            BinaryOperator::Power => "POW",
+            BinaryOperator::BitwiseAnd => "AND",
+            BinaryOperator::BitwiseOr => "OR",
+            BinaryOperator::BitwiseXor => "XOR",
+            BinaryOperator::ShiftLeft => "SHL",
+            BinaryOperator::ShiftRight => "SHR",
+            BinaryOperator::Equal => "EQ",
+            BinaryOperator::NotEqual => "NE",
+            BinaryOperator::Less => "LT",
+            BinaryOperator::LessEqual => "LE",
+            BinaryOperator::Greater => "GT",
+            BinaryOperator::GreaterEqual => "GE",
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => unreachable!("handled above"),
+        };
+        out.push(format!("    {}", instruction));
+    }
+}
+
+/// `text` as a quoted string, escaping quotes, backslashes and every byte
+/// that isn't printable ASCII.
+fn asm_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => result.push_str(&format!("\\{}", byte as char)),
+            b'\n' => result.push_str("\\n"),
+            b' '..=b'~' => result.push(byte as char),
+            _ => result.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+
+    fn compile_source_to_asm(source: &str) -> String {
+        let lexer = Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+        SemanticAnalyzer::analyze(&ast).unwrap();
+        generate_asm(&ast, IntWidth::Bits32)
+    }
+
+    #[test]
+    fn test_loop_program() {
+        let asm = compile_source_to_asm(
+            "let mut i = 0; let mut sum = 0; loop 10 { i = i + 1; sum = sum + i; print sum; };",
+        );
+
+        assert!(asm.starts_with(".bits 32\n"));
+        assert!(asm.contains("loop_0:\n"));
+        assert!(asm.contains("    JZ end_loop_0\n"));
+        assert!(asm.contains("    LOAD sum\n    LOAD i\n    ADD\n    STORE sum\n"));
+        assert!(asm.contains("    LOAD sum\n    PRINT\n    TEXT \"\\n\"\n"));
+        // The jump back to the top comes after the label it targets.
+        let back_jump = asm.find("    JMP loop_0\n").unwrap();
+        assert!(asm.find("loop_0:\n").unwrap() < back_jump);
+        assert!(back_jump < asm.find("end_loop_0:\n").unwrap());
+        assert!(asm.ends_with("    PUSH 0\n    EXIT\n"));
+    }
+
+    #[test]
+    fn test_shadowing_gets_a_fresh_slot() {
+        let asm = compile_source_to_asm("let x = 1; loop 2 { let x = 5; print x; } print x;");
+
+        assert!(asm.contains("    PUSH 5\n    STORE x_1\n    LOAD x_1\n    PRINT\n"));
+        assert!(asm.ends_with("    LOAD x\n    PRINT\n    TEXT \"\\n\"\n    PUSH 0\n    EXIT\n"));
+    }
+
+    #[test]
+    fn test_arrays_and_short_circuit() {
+        let asm = compile_source_to_asm(
+            "let a = [4, 5]; let x = 0; if x != 0 && 10 / x > 1 { print a[1]; }",
+        );
+
+        assert!(asm.contains(
+            "    PUSH 0\n    ARRAY a 2\n    PUSH 0\n    PUSH 4\n    STOREI a\n    PUSH 1\n    PUSH 5\n    STOREI a\n"
+        ));
+        assert!(asm.contains("    NE\n    JZ false_1\n    PUSH 10\n    LOAD x\n    DIV\n"));
+        assert!(asm.contains("    PUSH 1\n    LOADI a\n    PRINT\n"));
+    }
+}
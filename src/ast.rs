@@ -1,59 +1,446 @@
 #![allow(dead_code)]
 
-#[derive(Debug)]
+use std::fmt;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    /// A fixed-size array of ints with the given length.
+    Array(usize),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Array(length) => write!(f, "int[{}]", length),
+        }
+    }
+}
+
+impl Type {
+    /// The type written as `name` in an annotation such as `let x: int`.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "bool" => Some(Type::Bool),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identifier {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Term {
-    Identifier(String),
-    Number(i32),
+    Identifier(Identifier),
+    Number(i64),
+    /// `true` or `false`, which every backend represents as `1` or `0`.
+    Bool(bool),
+    /// `base[index]`, reading one element of an array.
+    Index {
+        base: Identifier,
+        index: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryExpr {
+    pub operator: BinaryOperator,
+    pub lhs: Box<Expr>,
+    pub rhs: Box<Expr>,
 }
 
-#[derive(Debug)]
+/// A built-in function that can be called in expressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intrinsic {
+    Min,
+    Max,
+    Abs,
+    /// `min_of(a, ...)`, the smallest of one or more arguments.
+    MinOf,
+    /// `max_of(a, ...)`, the largest of one or more arguments.
+    MaxOf,
+}
+
+impl Intrinsic {
+    pub fn from_name(name: &str) -> Option<Intrinsic> {
+        match name {
+            "min" => Some(Intrinsic::Min),
+            "max" => Some(Intrinsic::Max),
+            "abs" => Some(Intrinsic::Abs),
+            "min_of" => Some(Intrinsic::MinOf),
+            "max_of" => Some(Intrinsic::MaxOf),
+            _ => None,
+        }
+    }
+
+    /// The number of arguments, or the fewest a variadic one takes.
+    pub fn arity(self) -> usize {
+        match self {
+            Intrinsic::Min | Intrinsic::Max => 2,
+            Intrinsic::Abs | Intrinsic::MinOf | Intrinsic::MaxOf => 1,
+        }
+    }
+
+    pub fn is_variadic(self) -> bool {
+        matches!(self, Intrinsic::MinOf | Intrinsic::MaxOf)
+    }
+
+    /// Whether `count` arguments are right for this function.
+    pub fn accepts(self, count: usize) -> bool {
+        if self.is_variadic() {
+            count >= self.arity()
+        } else {
+            count == self.arity()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallExpr {
+    pub function: Identifier,
+    pub arguments: Vec<Expr>,
+}
+
+/// `write(...)`, which prints its arguments on one line like `print` does
+/// and evaluates to the number of bytes written, newline included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteExpr {
+    pub arguments: Vec<PrintArgument>,
+}
+
+/// `{ statements... value }`, a block that evaluates to the expression it
+/// ends with. Its declarations are only visible inside it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockExpr {
+    pub statements: Box<StatementList>,
+    pub value: Box<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Term(Term),
+    Binary(BinaryExpr),
+    Call(CallExpr),
+    /// `-operand`. A minus directly before a number literal is folded into
+    /// the literal instead, so this never holds a `Term::Number`.
+    Negate(Box<Expr>),
+    Write(WriteExpr),
+    /// `len("...")`, the length of a string literal in bytes. It's always
+    /// known at compile time, so every backend emits it as a number.
+    StringLength(String),
+    Block(BlockExpr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Expr {
-    pub lhs: Term,
-    pub rhs: Option<Box<Expr>>,
+    pub kind: ExprKind,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LetStatement {
-    pub identifier: String,
-    pub value: Expr,
+    pub identifier: Identifier,
+    /// Declared with `let mut`, so it can be assigned or read into.
+    pub mutable: bool,
+    /// The type from a `let x: <type> = ...` annotation, if any.
+    pub declared_type: Option<Type>,
+    /// `None` for `let x;`, which starts out as 0.
+    pub value: Option<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayInitializer {
+    /// `let a[3] = 0;` sets every element to the same value.
+    Fill(Expr),
+    /// `let a = [1, 2, 3];` lists every element.
+    List(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrayLetStatement {
+    pub identifier: Identifier,
+    /// Declared with `let mut`, so its elements can be assigned.
+    pub mutable: bool,
+    pub length: usize,
+    pub initializer: ArrayInitializer,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AssignmentStatement {
-    pub identifier: String,
+    pub identifier: Identifier,
+    /// Set for `a[i] = v;`, which writes one element of an array.
+    pub index: Option<Expr>,
     pub value: Expr,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Block {
     pub statements: Box<StatementList>,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoopStatement {
+    pub counter: Option<Identifier>,
+    /// The counter's first value in `loop i from a to b`; `count` is then
+    /// its last value, inclusive, instead of the number of iterations.
+    pub start: Option<Expr>,
     pub count: Expr,
+    /// How much the counter grows per iteration; 1 when absent.
+    pub step: Option<Expr>,
     pub body: Box<Block>,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+/// `repeat { ... } until condition;`, which tests `condition` after each
+/// run of the body, so the body always runs at least once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatStatement {
+    pub body: Box<Block>,
+    pub condition: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfStatement {
+    pub condition: Expr,
+    pub then_block: Block,
+    pub else_branch: Option<Box<ElseBranch>>,
+    pub span: Span,
+}
+
+/// What follows `else`: a plain block, or the next `if` of an
+/// `else if` chain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElseBranch {
+    Block(Block),
+    If(IfStatement),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintFormat {
+    Decimal,
+    Hex,
+    Char,
+}
+
+/// One comma-separated part of a `print`, such as the label in
+/// `print "x = ", x;`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintArgument {
+    Text(String),
+    Value(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PrintStatement {
+    /// Printed one after another on a single line. Empty for a bare
+    /// `print;`, which prints just the newline.
+    pub arguments: Vec<PrintArgument>,
+    pub format: PrintFormat,
+    pub span: Span,
+}
+
+impl PrintStatement {
+    /// This statement with every whole array among its arguments written out
+    /// element by element, so for a two-element `a`, `print a;` becomes
+    /// `print a[0], " ", a[1];`. Characters get no space between them.
+    /// `array_length` gives the length of the array a name refers to, or
+    /// `None` if it isn't one.
+    pub fn expand_arrays(&self, array_length: impl Fn(&str) -> Option<usize>) -> PrintStatement {
+        let mut arguments = Vec::new();
+        for argument in &self.arguments {
+            let array = match argument {
+                PrintArgument::Value(Expr {
+                    kind: ExprKind::Term(Term::Identifier(identifier)),
+                    ..
+                }) => array_length(&identifier.name).map(|length| (identifier, length)),
+                _ => None,
+            };
+            let Some((identifier, length)) = array else {
+                arguments.push(argument.clone());
+                continue;
+            };
+            for i in 0..length {
+                if i > 0 && self.format != PrintFormat::Char {
+                    arguments.push(PrintArgument::Text(" ".to_string()));
+                }
+                let index = Expr {
+                    kind: ExprKind::Term(Term::Number(i as i64)),
+                    span: identifier.span,
+                };
+                arguments.push(PrintArgument::Value(Expr {
+                    kind: ExprKind::Term(Term::Index {
+                        base: identifier.clone(),
+                        index: Box::new(index),
+                    }),
+                    span: identifier.span,
+                }));
+            }
+        }
+        PrintStatement {
+            arguments,
+            format: self.format.clone(),
+            span: self.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertStatement {
+    pub condition: Expr,
+    /// The text of `assert cond, "message";`, reported along with the
+    /// failure.
+    pub message: Option<String>,
+    pub span: Span,
+}
+
+/// `read x;`, which stores the next integer from the input in `x`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadStatement {
+    pub identifier: Identifier,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncludeStatement {
+    pub path: String,
+    pub span: Span,
+}
+
+/// `use math;`, which imports the consts of the module `math.hand` as
+/// `math.NAME`. Resolving it, as `include` does, puts the module's consts,
+/// renamed, right after it and sets `resolved`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UseStatement {
+    pub module: Identifier,
+    pub resolved: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExitStatement {
+    pub code: Expr,
+    pub span: Span,
+}
+
+/// `break;`, which leaves the innermost loop. `break if c;` is parsed as
+/// `if c { break; }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakStatement {
+    pub span: Span,
+}
+
+/// `const NAME = value;`, folded into every use of `NAME` in its block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstStatement {
+    pub identifier: Identifier,
+    pub value: Expr,
+    pub span: Span,
+}
+
+/// An expression on its own, such as `x + 3;` or `write("hi");`, evaluated
+/// for its effects. The interpreter also keeps its value as the result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionStatement {
     pub value: Expr,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+/// A `//` comment kept by the lexer, re-emitted above the statement after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommentStatement {
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
     Let(LetStatement),
+    Const(ConstStatement),
+    ArrayLet(ArrayLetStatement),
     Assignment(AssignmentStatement),
     Loop(LoopStatement),
+    Repeat(RepeatStatement),
+    If(IfStatement),
     Print(PrintStatement),
+    Assert(AssertStatement),
+    Read(ReadStatement),
+    Include(IncludeStatement),
+    Use(UseStatement),
+    Exit(ExitStatement),
+    Break(BreakStatement),
+    Expression(ExpressionStatement),
+    Comment(CommentStatement),
+}
+
+impl Statement {
+    /// The source range of the whole statement, from its first token to its
+    /// terminating `;` (or the end of the line for a comment).
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let(let_stmt) => let_stmt.span,
+            Statement::Const(const_stmt) => const_stmt.span,
+            Statement::ArrayLet(array_let_stmt) => array_let_stmt.span,
+            Statement::Assignment(assign_stmt) => assign_stmt.span,
+            Statement::Loop(loop_stmt) => loop_stmt.span,
+            Statement::Repeat(repeat_stmt) => repeat_stmt.span,
+            Statement::If(if_stmt) => if_stmt.span,
+            Statement::Print(print_stmt) => print_stmt.span,
+            Statement::Assert(assert_stmt) => assert_stmt.span,
+            Statement::Read(read_stmt) => read_stmt.span,
+            Statement::Include(include_stmt) => include_stmt.span,
+            Statement::Use(use_stmt) => use_stmt.span,
+            Statement::Exit(exit_stmt) => exit_stmt.span,
+            Statement::Break(break_stmt) => break_stmt.span,
+            Statement::Expression(expr_stmt) => expr_stmt.span,
+            Statement::Comment(comment) => comment.span,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StatementList {
     pub statements: Vec<Statement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AbstractSyntaxTree {
     pub statement_list: StatementList,
 }
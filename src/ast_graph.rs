@@ -0,0 +1,258 @@
+//! Renders an AST as a Graphviz DOT `digraph` for `--emit graph`: one node
+//! per AST node, labeled with its kind, and an edge to each child. Edges are
+//! labeled with the child's role where a node has several kinds of children,
+//! such as a loop's `count` and `body`.
+
+use std::fmt::Write;
+
+use crate::ast::*;
+
+pub fn ast_to_dot(ast: &AbstractSyntaxTree) -> String {
+    let mut graph = GraphBuilder {
+        out: String::from("digraph ast {\n    node [shape=box];\n"),
+        nodes: 0,
+    };
+    let program = graph.node("Program");
+    graph.statement_list(program, &ast.statement_list);
+    graph.out.push_str("}\n");
+    graph.out
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+struct GraphBuilder {
+    out: String,
+    nodes: usize,
+}
+
+impl GraphBuilder {
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.nodes;
+        self.nodes += 1;
+        writeln!(self.out, "    n{} [label=\"{}\"];", id, dot_escape(label)).unwrap();
+        id
+    }
+
+    /// A new node labeled `label`, with an edge from `parent` labeled `role`.
+    fn child(&mut self, parent: usize, role: Option<&str>, label: &str) -> usize {
+        let id = self.node(label);
+        match role {
+            Some(role) => writeln!(self.out, "    n{} -> n{} [label=\"{}\"];", parent, id, role),
+            None => writeln!(self.out, "    n{} -> n{};", parent, id),
+        }
+        .unwrap();
+        id
+    }
+
+    fn identifier(&mut self, parent: usize, identifier: &Identifier) {
+        self.child(parent, None, &format!("Identifier {}", identifier.name));
+    }
+
+    fn statement_list(&mut self, parent: usize, statement_list: &StatementList) {
+        for statement in &statement_list.statements {
+            self.statement(parent, statement);
+        }
+    }
+
+    /// A block is a node of its own, so an empty block still shows up.
+    fn block(&mut self, parent: usize, role: &str, block: &Block) {
+        let id = self.child(parent, Some(role), "Block");
+        self.statement_list(id, &block.statements);
+    }
+
+    fn statement(&mut self, parent: usize, statement: &Statement) {
+        match statement {
+            Statement::Let(let_stmt) => {
+                let id = self.child(parent, None, "Let");
+                self.identifier(id, &let_stmt.identifier);
+                if let Some(ty) = let_stmt.declared_type {
+                    self.child(id, None, &format!("Type {}", ty));
+                }
+                if let Some(value) = &let_stmt.value {
+                    self.expression(id, None, value);
+                }
+            }
+            Statement::Const(const_stmt) => {
+                let id = self.child(parent, None, "Const");
+                self.identifier(id, &const_stmt.identifier);
+                self.expression(id, None, &const_stmt.value);
+            }
+            Statement::ArrayLet(array_let_stmt) => {
+                let id = self.child(parent, None, "ArrayLet");
+                self.identifier(id, &array_let_stmt.identifier);
+                self.child(
+                    id,
+                    Some("length"),
+                    &format!("Number {}", array_let_stmt.length),
+                );
+                match &array_let_stmt.initializer {
+                    ArrayInitializer::Fill(value) => self.expression(id, Some("fill"), value),
+                    ArrayInitializer::List(elements) => {
+                        for element in elements {
+                            self.expression(id, None, element);
+                        }
+                    }
+                }
+            }
+            Statement::Assignment(assign_stmt) => {
+                let id = self.child(parent, None, "Assign");
+                self.identifier(id, &assign_stmt.identifier);
+                if let Some(index) = &assign_stmt.index {
+                    self.expression(id, Some("index"), index);
+                }
+                self.expression(id, Some("value"), &assign_stmt.value);
+            }
+            Statement::Loop(loop_stmt) => {
+                let id = self.child(parent, None, "Loop");
+                if let Some(counter) = &loop_stmt.counter {
+                    self.identifier(id, counter);
+                }
+                match &loop_stmt.start {
+                    Some(start) => {
+                        self.expression(id, Some("from"), start);
+                        self.expression(id, Some("to"), &loop_stmt.count);
+                    }
+                    None => self.expression(id, Some("count"), &loop_stmt.count),
+                }
+                if let Some(step) = &loop_stmt.step {
+                    self.expression(id, Some("step"), step);
+                }
+                self.block(id, "body", &loop_stmt.body);
+            }
+            Statement::Repeat(repeat_stmt) => {
+                let id = self.child(parent, None, "Repeat");
+                self.block(id, "body", &repeat_stmt.body);
+                self.expression(id, Some("until"), &repeat_stmt.condition);
+            }
+            Statement::If(if_stmt) => self.if_statement(parent, None, if_stmt),
+            Statement::Print(print_stmt) => {
+                let label = match print_stmt.format {
+                    PrintFormat::Decimal => "Print",
+                    PrintFormat::Hex => "Print hex",
+                    PrintFormat::Char => "Print char",
+                };
+                let id = self.child(parent, None, label);
+                self.print_arguments(id, &print_stmt.arguments);
+            }
+            Statement::Assert(assert_stmt) => {
+                let id = self.child(parent, None, "Assert");
+                self.expression(id, None, &assert_stmt.condition);
+                if let Some(message) = &assert_stmt.message {
+                    self.child(id, Some("message"), &format!("Text {:?}", message));
+                }
+            }
+            Statement::Read(read_stmt) => {
+                let id = self.child(parent, None, "Read");
+                self.identifier(id, &read_stmt.identifier);
+            }
+            Statement::Include(include_stmt) => {
+                self.child(parent, None, &format!("Include {:?}", include_stmt.path));
+            }
+            Statement::Use(use_stmt) => {
+                self.child(parent, None, &format!("Use {}", use_stmt.module.name));
+            }
+            Statement::Exit(exit_stmt) => {
+                let id = self.child(parent, None, "Exit");
+                self.expression(id, None, &exit_stmt.code);
+            }
+            Statement::Break(_) => {
+                self.child(parent, None, "Break");
+            }
+            Statement::Expression(expr_stmt) => {
+                let id = self.child(parent, None, "Expression");
+                self.expression(id, None, &expr_stmt.value);
+            }
+            Statement::Comment(comment) => {
+                self.child(parent, None, &format!("Comment {:?}", comment.text));
+            }
+        }
+    }
+
+    /// An `else if` is an `If` node hanging off the `else` edge.
+    fn if_statement(&mut self, parent: usize, role: Option<&str>, if_stmt: &IfStatement) {
+        let id = self.child(parent, role, "If");
+        self.expression(id, Some("condition"), &if_stmt.condition);
+        self.block(id, "then", &if_stmt.then_block);
+        match if_stmt.else_branch.as_deref() {
+            None => {}
+            Some(ElseBranch::Block(block)) => self.block(id, "else", block),
+            Some(ElseBranch::If(next)) => self.if_statement(id, Some("else"), next),
+        }
+    }
+
+    fn expression(&mut self, parent: usize, role: Option<&str>, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Term(Term::Identifier(identifier)) => {
+                self.child(parent, role, &format!("Identifier {}", identifier.name));
+            }
+            ExprKind::Term(Term::Number(value)) => {
+                self.child(parent, role, &format!("Number {}", value));
+            }
+            ExprKind::Term(Term::Bool(value)) => {
+                self.child(parent, role, &format!("Bool {}", value));
+            }
+            ExprKind::Term(Term::Index { base, index }) => {
+                let id = self.child(parent, role, "Index");
+                self.identifier(id, base);
+                self.expression(id, Some("index"), index);
+            }
+            ExprKind::Binary(binary) => {
+                let id = self.child(parent, role, &format!("Binary {:?}", binary.operator));
+                self.expression(id, None, &binary.lhs);
+                self.expression(id, None, &binary.rhs);
+            }
+            ExprKind::Call(call) => {
+                let id = self.child(parent, role, &format!("Call {}", call.function.name));
+                for argument in &call.arguments {
+                    self.expression(id, None, argument);
+                }
+            }
+            ExprKind::Negate(operand) => {
+                let id = self.child(parent, role, "Negate");
+                self.expression(id, None, operand);
+            }
+            ExprKind::Write(write) => {
+                let id = self.child(parent, role, "Write");
+                self.print_arguments(id, &write.arguments);
+            }
+            ExprKind::StringLength(text) => {
+                self.child(parent, role, &format!("Len {:?}", text));
+            }
+            ExprKind::Block(block) => {
+                let id = self.child(parent, role, "Block");
+                self.statement_list(id, &block.statements);
+                self.expression(id, Some("value"), &block.value);
+            }
+        }
+    }
+
+    fn print_arguments(&mut self, parent: usize, arguments: &[PrintArgument]) {
+        for argument in arguments {
+            match argument {
+                PrintArgument::Text(text) => {
+                    self.child(parent, None, &format!("Text {:?}", text));
+                }
+                PrintArgument::Value(value) => self.expression(parent, None, value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_let_graph() {
+        let ast = Parser::new(Lexer::new("let x = 1;".to_string()).collect()).parse();
+
+        assert_eq!(
+            ast_to_dot(&ast),
+            "digraph ast {\n    node [shape=box];\n    n0 [label=\"Program\"];\n    n1 [label=\"Let\"];\n    n0 -> n1;\n    n2 [label=\"Identifier x\"];\n    n1 -> n2;\n    n3 [label=\"Number 1\"];\n    n1 -> n3;\n}\n"
+        );
+    }
+}
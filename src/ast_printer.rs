@@ -0,0 +1,272 @@
+//! Renders an AST as an indented tree for `--ast`, one node per line with
+//! its children indented below it. Boxes, blocks and spans are left out.
+
+use std::fmt;
+
+use crate::ast::*;
+
+impl fmt::Display for AbstractSyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut printer = TreePrinter { out: String::new() };
+        printer.line(0, "Program");
+        printer.statement_list(1, &self.statement_list);
+        f.write_str(&printer.out)
+    }
+}
+
+struct TreePrinter {
+    out: String,
+}
+
+impl TreePrinter {
+    fn line(&mut self, depth: usize, text: &str) {
+        self.out.push_str(&"  ".repeat(depth));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn statement_list(&mut self, depth: usize, statement_list: &StatementList) {
+        for statement in &statement_list.statements {
+            self.statement(depth, statement);
+        }
+    }
+
+    fn statement(&mut self, depth: usize, statement: &Statement) {
+        match statement {
+            Statement::Let(let_stmt) => {
+                let keyword = if let_stmt.mutable { "Let mut" } else { "Let" };
+                match let_stmt.declared_type {
+                    Some(ty) => self.line(
+                        depth,
+                        &format!("{} {}: {}", keyword, let_stmt.identifier.name, ty),
+                    ),
+                    None => self.line(depth, &format!("{} {}", keyword, let_stmt.identifier.name)),
+                }
+                if let Some(value) = &let_stmt.value {
+                    self.expression(depth + 1, "", value);
+                }
+            }
+            Statement::Const(const_stmt) => {
+                self.line(depth, &format!("Const {}", const_stmt.identifier.name));
+                self.expression(depth + 1, "", &const_stmt.value);
+            }
+            Statement::ArrayLet(array_let_stmt) => {
+                self.line(
+                    depth,
+                    &format!(
+                        "{} {}[{}]",
+                        if array_let_stmt.mutable {
+                            "ArrayLet mut"
+                        } else {
+                            "ArrayLet"
+                        },
+                        array_let_stmt.identifier.name,
+                        array_let_stmt.length
+                    ),
+                );
+                match &array_let_stmt.initializer {
+                    ArrayInitializer::Fill(value) => self.expression(depth + 1, "fill: ", value),
+                    ArrayInitializer::List(elements) => {
+                        for element in elements {
+                            self.expression(depth + 1, "", element);
+                        }
+                    }
+                }
+            }
+            Statement::Assignment(assign_stmt) => {
+                self.line(depth, &format!("Assign {}", assign_stmt.identifier.name));
+                match &assign_stmt.index {
+                    Some(index) => {
+                        self.expression(depth + 1, "index: ", index);
+                        self.expression(depth + 1, "value: ", &assign_stmt.value);
+                    }
+                    None => self.expression(depth + 1, "", &assign_stmt.value),
+                }
+            }
+            Statement::Loop(loop_stmt) => {
+                match &loop_stmt.counter {
+                    Some(counter) => self.line(depth, &format!("Loop {}", counter.name)),
+                    None => self.line(depth, "Loop"),
+                }
+                match &loop_stmt.start {
+                    Some(start) => {
+                        self.expression(depth + 1, "from: ", start);
+                        self.expression(depth + 1, "to: ", &loop_stmt.count);
+                    }
+                    None => self.expression(depth + 1, "count: ", &loop_stmt.count),
+                }
+                if let Some(step) = &loop_stmt.step {
+                    self.expression(depth + 1, "step: ", step);
+                }
+                self.line(depth + 1, "body");
+                self.statement_list(depth + 2, &loop_stmt.body.statements);
+            }
+            Statement::Repeat(repeat_stmt) => {
+                self.line(depth, "Repeat");
+                self.line(depth + 1, "body");
+                self.statement_list(depth + 2, &repeat_stmt.body.statements);
+                self.expression(depth + 1, "until: ", &repeat_stmt.condition);
+            }
+            Statement::If(if_stmt) => {
+                self.line(depth, "If");
+                self.if_branches(depth + 1, if_stmt);
+            }
+            Statement::Print(print_stmt) => {
+                let name = match print_stmt.format {
+                    PrintFormat::Decimal => "Print",
+                    PrintFormat::Hex => "Print hex",
+                    PrintFormat::Char => "Print char",
+                };
+                self.line(depth, name);
+                self.print_arguments(depth + 1, &print_stmt.arguments);
+            }
+            Statement::Assert(assert_stmt) => {
+                self.line(depth, "Assert");
+                self.expression(depth + 1, "", &assert_stmt.condition);
+                if let Some(message) = &assert_stmt.message {
+                    self.line(depth + 1, &format!("message: {:?}", message));
+                }
+            }
+            Statement::Read(read_stmt) => {
+                self.line(depth, &format!("Read {}", read_stmt.identifier.name))
+            }
+            Statement::Include(include_stmt) => {
+                self.line(depth, &format!("Include {:?}", include_stmt.path))
+            }
+            Statement::Use(use_stmt) => self.line(depth, &format!("Use {}", use_stmt.module.name)),
+            Statement::Exit(exit_stmt) => {
+                self.line(depth, "Exit");
+                self.expression(depth + 1, "", &exit_stmt.code);
+            }
+            Statement::Break(_) => self.line(depth, "Break"),
+            Statement::Expression(expr_stmt) => {
+                self.line(depth, "Expression");
+                self.expression(depth + 1, "", &expr_stmt.value);
+            }
+            Statement::Comment(comment) => self.line(depth, &format!("Comment {:?}", comment.text)),
+        }
+    }
+
+    /// The condition and branches of `if_stmt`, with an `else if` chain
+    /// listed flat rather than nested.
+    fn if_branches(&mut self, depth: usize, if_stmt: &IfStatement) {
+        self.expression(depth, "condition: ", &if_stmt.condition);
+        self.line(depth, "then");
+        self.statement_list(depth + 1, &if_stmt.then_block.statements);
+        match if_stmt.else_branch.as_deref() {
+            None => {}
+            Some(ElseBranch::Block(block)) => {
+                self.line(depth, "else");
+                self.statement_list(depth + 1, &block.statements);
+            }
+            Some(ElseBranch::If(next)) => {
+                self.line(depth, "else if");
+                self.if_branches(depth + 1, next);
+            }
+        }
+    }
+
+    /// `expr` on a line starting with `label`, then its operands below it.
+    fn expression(&mut self, depth: usize, label: &str, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Term(Term::Identifier(identifier)) => {
+                self.line(depth, &format!("{}Identifier {}", label, identifier.name))
+            }
+            ExprKind::Term(Term::Number(value)) => {
+                self.line(depth, &format!("{}Number {}", label, value))
+            }
+            ExprKind::Term(Term::Bool(value)) => {
+                self.line(depth, &format!("{}Bool {}", label, value))
+            }
+            ExprKind::Term(Term::Index { base, index }) => {
+                self.line(depth, &format!("{}Index {}", label, base.name));
+                self.expression(depth + 1, "", index);
+            }
+            ExprKind::Binary(binary) => {
+                self.line(depth, &format!("{}Binary {:?}", label, binary.operator));
+                self.expression(depth + 1, "", &binary.lhs);
+                self.expression(depth + 1, "", &binary.rhs);
+            }
+            ExprKind::Call(call) => {
+                self.line(depth, &format!("{}Call {}", label, call.function.name));
+                for argument in &call.arguments {
+                    self.expression(depth + 1, "", argument);
+                }
+            }
+            ExprKind::Negate(operand) => {
+                self.line(depth, &format!("{}Negate", label));
+                self.expression(depth + 1, "", operand);
+            }
+            ExprKind::Write(write) => {
+                self.line(depth, &format!("{}Write", label));
+                self.print_arguments(depth + 1, &write.arguments);
+            }
+            ExprKind::StringLength(text) => {
+                self.line(depth, &format!("{}Len {:?}", label, text));
+            }
+            ExprKind::Block(block) => {
+                self.line(depth, &format!("{}Block", label));
+                self.statement_list(depth + 1, &block.statements);
+                self.expression(depth + 1, "value: ", &block.value);
+            }
+        }
+    }
+
+    fn print_arguments(&mut self, depth: usize, arguments: &[PrintArgument]) {
+        for argument in arguments {
+            match argument {
+                PrintArgument::Text(text) => self.line(depth, &format!("Text {:?}", text)),
+                PrintArgument::Value(value) => self.expression(depth, "", value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_tree_for_small_program() {
+        let source = "let x: int = 1;\nloop i in 3 { print \"i = \", x + i; }\nif x < 2 { exit 1; } else if x == 2 { x = min(x, 0); } else { let a = [1, 2]; }";
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+
+        assert_eq!(
+            ast.to_string(),
+            "\
+Program
+  Let x: int
+    Number 1
+  Loop i
+    count: Number 3
+    body
+      Print
+        Text \"i = \"
+        Binary Add
+          Identifier x
+          Identifier i
+  If
+    condition: Binary Less
+      Identifier x
+      Number 2
+    then
+      Exit
+        Number 1
+    else if
+      condition: Binary Equal
+        Identifier x
+        Number 2
+      then
+        Assign x
+          Call min
+            Identifier x
+            Number 0
+      else
+        ArrayLet a[2]
+          Number 1
+          Number 2
+"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt;
+
+use crate::asm_generator::generate_asm;
+use crate::ast::AbstractSyntaxTree;
+use crate::ast_graph::ast_to_dot;
+use crate::code_generator::{CodegenOptions, generate_c_code_with_options};
+use crate::const_eval::IntWidth;
+use crate::llvm_generator::generate_llvm;
+use crate::wat_generator::generate_wat;
+
+/// Something that turns a checked program into output text.
+pub trait Backend {
+    fn emit(&self, ast: &AbstractSyntaxTree) -> String;
+
+    /// Extension of the file the output is written to, without the dot.
+    fn file_extension(&self) -> &'static str;
+}
+
+/// Emits a C program, see `code_generator`.
+pub struct CBackend {
+    options: CodegenOptions,
+}
+
+impl CBackend {
+    pub fn new(options: CodegenOptions) -> Self {
+        CBackend { options }
+    }
+}
+
+impl Backend for CBackend {
+    fn emit(&self, ast: &AbstractSyntaxTree) -> String {
+        generate_c_code_with_options(ast, &self.options)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "c"
+    }
+}
+
+/// Emits a WebAssembly text module, see `wat_generator`.
+pub struct WatBackend {
+    int_width: IntWidth,
+}
+
+impl WatBackend {
+    pub fn new(int_width: IntWidth) -> Self {
+        WatBackend { int_width }
+    }
+}
+
+impl Backend for WatBackend {
+    fn emit(&self, ast: &AbstractSyntaxTree) -> String {
+        generate_wat(ast, self.int_width)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "wat"
+    }
+}
+
+/// Emits LLVM IR text, see `llvm_generator`.
+pub struct LlvmBackend {
+    int_width: IntWidth,
+}
+
+impl LlvmBackend {
+    pub fn new(int_width: IntWidth) -> Self {
+        LlvmBackend { int_width }
+    }
+}
+
+impl Backend for LlvmBackend {
+    fn emit(&self, ast: &AbstractSyntaxTree) -> String {
+        generate_llvm(ast, self.int_width)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "ll"
+    }
+}
+
+/// Emits assembly for a stack machine, see `asm_generator`.
+pub struct AsmBackend {
+    int_width: IntWidth,
+}
+
+impl AsmBackend {
+    pub fn new(int_width: IntWidth) -> Self {
+        AsmBackend { int_width }
+    }
+}
+
+impl Backend for AsmBackend {
+    fn emit(&self, ast: &AbstractSyntaxTree) -> String {
+        generate_asm(ast, self.int_width)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "asm"
+    }
+}
+
+/// Draws the AST as a Graphviz graph, see `ast_graph`.
+pub struct GraphBackend;
+
+impl Backend for GraphBackend {
+    fn emit(&self, ast: &AbstractSyntaxTree) -> String {
+        ast_to_dot(ast)
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "dot"
+    }
+}
+
+type BackendConstructor = fn(&CodegenOptions) -> Box<dyn Backend>;
+
+/// Every backend selectable with `--emit <name>`.
+const BACKENDS: &[(&str, BackendConstructor)] = &[
+    ("c", |options| Box::new(CBackend::new(options.clone()))),
+    ("wat", |options| {
+        Box::new(WatBackend::new(options.int_width))
+    }),
+    ("llvm", |options| {
+        Box::new(LlvmBackend::new(options.int_width))
+    }),
+    ("asm", |options| {
+        Box::new(AsmBackend::new(options.int_width))
+    }),
+    ("graph", |_| Box::new(GraphBackend)),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownBackend {
+    pub name: String,
+}
+
+impl fmt::Display for UnknownBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = BACKENDS.iter().map(|(name, _)| *name).collect();
+        write!(
+            f,
+            "unknown backend '{}' (available: {})",
+            self.name,
+            names.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownBackend {}
+
+/// Looks up the backend registered as `name`.
+pub fn backend_for(
+    name: &str,
+    options: &CodegenOptions,
+) -> Result<Box<dyn Backend>, UnknownBackend> {
+    BACKENDS
+        .iter()
+        .find(|(backend_name, _)| *backend_name == name)
+        .map(|(_, constructor)| constructor(options))
+        .ok_or_else(|| UnknownBackend {
+            name: name.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_registry_returns_c_backend() {
+        let tokens = Lexer::new("let x = 1; print x;".to_string()).collect();
+        let ast = Parser::new(tokens).parse();
+        let backend = backend_for("c", &CodegenOptions::default()).unwrap();
+
+        assert_eq!(backend.file_extension(), "c");
+        assert_eq!(
+            backend.emit(&ast),
+            generate_c_code_with_options(&ast, &CodegenOptions::default())
+        );
+    }
+
+    #[test]
+    fn test_registry_rejects_unknown_backend() {
+        let error = backend_for("cobol", &CodegenOptions::default())
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            UnknownBackend {
+                name: "cobol".to_string()
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "unknown backend 'cobol' (available: c, wat, llvm, asm, graph)"
+        );
+    }
+}
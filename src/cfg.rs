@@ -0,0 +1,316 @@
+use std::fmt::Write;
+
+use crate::ast::{AbstractSyntaxTree, ElseBranch, IfStatement, Statement, StatementList};
+
+/// A straight-line run of statements, each described by its line and kind,
+/// such as `3: let x`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BasicBlock {
+    pub statements: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    Fallthrough,
+    /// Taken when an `if` condition holds or a loop has iterations left.
+    True,
+    /// Taken when an `if` condition fails or a loop is done.
+    False,
+    /// From the end of a loop body back to its header.
+    Back,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// The control-flow graph of a program. Block `ENTRY` is where the program
+/// starts and block `EXIT` is where it ends, including through `exit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+impl ControlFlowGraph {
+    pub const ENTRY: usize = 0;
+    pub const EXIT: usize = 1;
+
+    pub fn build(ast: &AbstractSyntaxTree) -> Self {
+        let mut builder = CfgBuilder {
+            cfg: ControlFlowGraph {
+                blocks: vec![BasicBlock::default(), BasicBlock::default()],
+                edges: Vec::new(),
+            },
+            current: Self::ENTRY,
+            breaks: Vec::new(),
+        };
+        builder.build_statement_list(&ast.statement_list);
+        builder.add_edge(builder.current, Self::EXIT, EdgeKind::Fallthrough);
+        builder.cfg
+    }
+
+    /// The graph in Graphviz DOT format. Back edges are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box];\n");
+        for (id, block) in self.blocks.iter().enumerate() {
+            let name = match id {
+                Self::ENTRY => "entry".to_string(),
+                Self::EXIT => "exit".to_string(),
+                _ => format!("bb{}", id),
+            };
+            let mut label = name;
+            label.push_str("\\n");
+            for statement in &block.statements {
+                label.push_str(&dot_escape(statement));
+                label.push_str("\\l");
+            }
+            writeln!(dot, "    bb{} [label=\"{}\"];", id, label).unwrap();
+        }
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::True => " [label=\"true\"]",
+                EdgeKind::False => " [label=\"false\"]",
+                EdgeKind::Back => " [label=\"back\", style=dashed]",
+            };
+            writeln!(dot, "    bb{} -> bb{}{};", edge.from, edge.to, attributes).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+struct CfgBuilder {
+    cfg: ControlFlowGraph,
+    /// The block statements are currently appended to.
+    current: usize,
+    /// For every loop being built, innermost last, the blocks that end in a
+    /// `break` out of it.
+    breaks: Vec<Vec<usize>>,
+}
+
+impl CfgBuilder {
+    fn new_block(&mut self) -> usize {
+        self.cfg.blocks.push(BasicBlock::default());
+        self.cfg.blocks.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, kind: EdgeKind) {
+        self.cfg.edges.push(Edge { from, to, kind });
+    }
+
+    fn push(&mut self, line: usize, description: String) {
+        self.cfg.blocks[self.current]
+            .statements
+            .push(format!("{}: {}", line, description));
+    }
+
+    fn build_statement_list(&mut self, statement_list: &StatementList) {
+        for statement in &statement_list.statements {
+            self.build_statement(statement);
+        }
+    }
+
+    fn build_statement(&mut self, statement: &Statement) {
+        let line = statement.span().line;
+        match statement {
+            Statement::Let(let_stmt) => {
+                self.push(line, format!("let {}", let_stmt.identifier.name))
+            }
+            Statement::Const(const_stmt) => {
+                self.push(line, format!("const {}", const_stmt.identifier.name))
+            }
+            Statement::ArrayLet(array_let_stmt) => self.push(
+                line,
+                format!(
+                    "let {}[{}]",
+                    array_let_stmt.identifier.name, array_let_stmt.length
+                ),
+            ),
+            Statement::Assignment(assign_stmt) => {
+                let target = match assign_stmt.index {
+                    Some(_) => format!("{}[...]", assign_stmt.identifier.name),
+                    None => assign_stmt.identifier.name.clone(),
+                };
+                self.push(line, format!("{} = ...", target))
+            }
+            Statement::Print(_) => self.push(line, "print".to_string()),
+            Statement::Assert(_) => self.push(line, "assert".to_string()),
+            Statement::Expression(_) => self.push(line, "expression".to_string()),
+            Statement::Read(read_stmt) => {
+                self.push(line, format!("read {}", read_stmt.identifier.name))
+            }
+            Statement::Include(include_stmt) => {
+                self.push(line, format!("include {}", include_stmt.path))
+            }
+            Statement::Use(use_stmt) => self.push(line, format!("use {}", use_stmt.module.name)),
+            Statement::Comment(_) => {}
+            Statement::Exit(_) => {
+                self.push(line, "exit".to_string());
+                self.add_edge(self.current, ControlFlowGraph::EXIT, EdgeKind::Fallthrough);
+                // Whatever follows is unreachable, so its block has no edge in
+                self.current = self.new_block();
+            }
+            Statement::Break(_) => {
+                self.push(line, "break".to_string());
+                // Joined to the block after the loop once that exists
+                let current = self.current;
+                if let Some(breaks) = self.breaks.last_mut() {
+                    breaks.push(current);
+                }
+                self.current = self.new_block();
+            }
+            Statement::Loop(loop_stmt) => {
+                let header = self.new_block();
+                self.add_edge(self.current, header, EdgeKind::Fallthrough);
+                self.current = header;
+                match &loop_stmt.counter {
+                    Some(counter) => self.push(line, format!("loop {}", counter.name)),
+                    None => self.push(line, "loop".to_string()),
+                }
+
+                let body = self.new_block();
+                self.add_edge(header, body, EdgeKind::True);
+                self.current = body;
+                self.breaks.push(Vec::new());
+                self.build_statement_list(&loop_stmt.body.statements);
+                self.add_edge(self.current, header, EdgeKind::Back);
+
+                let after = self.new_block();
+                self.add_edge(header, after, EdgeKind::False);
+                self.join_breaks(after);
+                self.current = after;
+            }
+            Statement::Repeat(repeat_stmt) => {
+                let body = self.new_block();
+                self.add_edge(self.current, body, EdgeKind::Fallthrough);
+                self.current = body;
+                self.push(line, "repeat".to_string());
+                self.breaks.push(Vec::new());
+                self.build_statement_list(&repeat_stmt.body.statements);
+                // The condition is tested at the end of the body.
+                self.push(repeat_stmt.condition.span.line, "until".to_string());
+                self.add_edge(self.current, body, EdgeKind::Back);
+
+                let after = self.new_block();
+                self.add_edge(self.current, after, EdgeKind::False);
+                self.join_breaks(after);
+                self.current = after;
+            }
+            Statement::If(if_stmt) => {
+                let join = self.new_block();
+                self.build_if_statement(if_stmt, join);
+                self.current = join;
+            }
+        }
+    }
+
+    /// Adds an edge to `after` from every block that breaks out of the
+    /// innermost loop.
+    fn join_breaks(&mut self, after: usize) {
+        for block in self.breaks.pop().unwrap_or_default() {
+            self.add_edge(block, after, EdgeKind::Fallthrough);
+        }
+    }
+
+    /// Builds `if_stmt` from the current block, with every branch ending up
+    /// in `join`.
+    fn build_if_statement(&mut self, if_stmt: &IfStatement, join: usize) {
+        self.push(if_stmt.span.line, "if".to_string());
+        let condition = self.current;
+
+        let then_block = self.new_block();
+        self.add_edge(condition, then_block, EdgeKind::True);
+        self.current = then_block;
+        self.build_statement_list(&if_stmt.then_block.statements);
+        self.add_edge(self.current, join, EdgeKind::Fallthrough);
+
+        match if_stmt.else_branch.as_deref() {
+            None => self.add_edge(condition, join, EdgeKind::False),
+            Some(else_branch) => {
+                let else_block = self.new_block();
+                self.add_edge(condition, else_block, EdgeKind::False);
+                self.current = else_block;
+                match else_branch {
+                    ElseBranch::Block(block) => {
+                        self.build_statement_list(&block.statements);
+                        self.add_edge(self.current, join, EdgeKind::Fallthrough);
+                    }
+                    ElseBranch::If(next) => self.build_if_statement(next, join),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn build(source: &str) -> ControlFlowGraph {
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        ControlFlowGraph::build(&ast)
+    }
+
+    #[test]
+    fn test_loop_has_back_edge() {
+        let cfg = build("let x = 0;\nloop 3 {\n    x = x + 1;\n}\nprint x;");
+
+        assert_eq!(
+            cfg.to_dot(),
+            "digraph cfg {\n    node [shape=box];\n    bb0 [label=\"entry\\n1: let x\\l\"];\n    bb1 [label=\"exit\\n\"];\n    bb2 [label=\"bb2\\n2: loop\\l\"];\n    bb3 [label=\"bb3\\n3: x = ...\\l\"];\n    bb4 [label=\"bb4\\n5: print\\l\"];\n    bb0 -> bb2;\n    bb2 -> bb3 [label=\"true\"];\n    bb3 -> bb2 [label=\"back\", style=dashed];\n    bb2 -> bb4 [label=\"false\"];\n    bb4 -> bb1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_if_else_branches_join() {
+        let cfg = build("let x = 1;\nif x > 0 { print x; } else { exit 1; }\nprint 0;");
+
+        assert_eq!(
+            cfg.edges,
+            vec![
+                Edge {
+                    from: 0,
+                    to: 3,
+                    kind: EdgeKind::True
+                },
+                Edge {
+                    from: 3,
+                    to: 2,
+                    kind: EdgeKind::Fallthrough
+                },
+                Edge {
+                    from: 0,
+                    to: 4,
+                    kind: EdgeKind::False
+                },
+                Edge {
+                    from: 4,
+                    to: ControlFlowGraph::EXIT,
+                    kind: EdgeKind::Fallthrough
+                },
+                Edge {
+                    from: 5,
+                    to: 2,
+                    kind: EdgeKind::Fallthrough
+                },
+                Edge {
+                    from: 2,
+                    to: ControlFlowGraph::EXIT,
+                    kind: EdgeKind::Fallthrough
+                },
+            ]
+        );
+        assert_eq!(cfg.blocks[2].statements, vec!["3: print"]);
+    }
+}
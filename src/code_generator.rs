@@ -1,89 +1,1265 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashSet};
+
 use crate::ast::*;
+use crate::const_eval::{IntWidth, eval_const, eval_const_with};
+use crate::diagnostic::json_string;
+use crate::peephole::simplify;
+use crate::scope::ScopeStack;
+use crate::semantic_analyzer::SemanticAnalyzer;
+use crate::span::Span;
+
+#[derive(Debug, Default, Clone)]
+pub struct CodegenOptions {
+    /// Hoist every declaration to the top of its block so the output is
+    /// valid C89, which forbids mixing declarations and statements.
+    pub c89: bool,
+    /// Width of the C integer type used for every variable and literal.
+    pub int_width: IntWidth,
+    /// What `+`, `-`, `*` and `**` do when the result doesn't fit.
+    pub overflow: Overflow,
+    /// Leave out code that provably never runs, such as a `loop 0`.
+    pub optimize: bool,
+    /// `print` booleans as `true` or `false` instead of `1` or `0`.
+    pub bool_words: bool,
+    /// Follow the declaration of every variable that's never read with
+    /// `(void)x;`, so `-Wunused-variable` stays quiet.
+    pub suppress_unused: bool,
+    /// Put the program in `int <name>(void)`, returning its exit code, and
+    /// have `main` just call it, so other C code can link against it.
+    pub as_function: Option<String>,
+    /// Follow every `let` and assignment with a `printf` of the new value,
+    /// such as `x = 3`, so the program's execution can be watched.
+    pub trace: bool,
+    /// Spaces to indent each level of braces by. With 0, every line is
+    /// flush left.
+    pub indent: usize,
+    /// Where the `{` opening a block goes.
+    pub brace_style: BraceStyle,
+}
+
+/// How arithmetic that overflows behaves in the generated C.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Plain C arithmetic, where signed overflow is undefined behavior.
+    #[default]
+    Unchecked,
+    /// Wraps around modulo 2^bits, computed on unsigned integers.
+    Wrap,
+    /// Prints `integer overflow` to stderr and aborts.
+    Checked,
+}
+
+/// Where the generated C puts the `{` after an `if`, a loop or a function.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum BraceStyle {
+    /// At the end of the line, as in `if (x) {`.
+    #[default]
+    Attach,
+    /// On a line of its own, lined up with the `if`.
+    Allman,
+}
 
 pub fn generate_c_code(ast: &AbstractSyntaxTree) -> String {
+    generate_c_code_with_options(ast, &CodegenOptions::default())
+}
+
+pub fn generate_c_code_with_options(ast: &AbstractSyntaxTree, options: &CodegenOptions) -> String {
+    generate(ast, options, None)
+}
+
+/// Like `generate_c_code_with_options`, also returning which statement each
+/// line of the C comes from, for debuggers that can't use `#line`.
+pub fn generate_c_code_with_sourcemap(
+    ast: &AbstractSyntaxTree,
+    options: &CodegenOptions,
+) -> (String, SourceMap) {
+    let spans = RefCell::new(Vec::new());
+    let marked = generate(ast, options, Some(&spans));
+    SourceMap::extract(&marked, &spans.into_inner())
+}
+
+/// Which statement of the source each line of generated C comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMap {
+    /// The 1-based number of a line of C and the span of the innermost
+    /// statement it was generated for, in order. Lines that belong to no
+    /// statement, such as the `#include`s, are left out.
+    pub lines: Vec<(usize, Span)>,
+}
+
+/// Around the code of every statement when recording a source map: the
+/// start marker, the statement's index into the recorded spans, the start
+/// marker again, the code and the end marker. The generated C escapes every
+/// control character in strings and blanks them in comments, so these can't
+/// occur in it otherwise.
+const STATEMENT_START: char = '\u{1}';
+const STATEMENT_END: char = '\u{2}';
+
+impl SourceMap {
+    /// Strips the statement markers out of `marked` and maps each line to
+    /// the statement that was innermost where the line starts.
+    fn extract(marked: &str, spans: &[Span]) -> (String, SourceMap) {
+        let mut code = String::new();
+        let mut lines = Vec::new();
+        let mut open: Vec<Span> = Vec::new();
+        let mut line = 1;
+        let mut at_line_start = true;
+        let mut chars = marked.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                STATEMENT_START => {
+                    let index: String = chars
+                        .by_ref()
+                        .take_while(|&c| c != STATEMENT_START)
+                        .collect();
+                    // A stray marker only costs the mapping, never the code.
+                    if let Some(span) = index.parse::<usize>().ok().and_then(|i| spans.get(i)) {
+                        open.push(*span);
+                    }
+                }
+                STATEMENT_END => {
+                    open.pop();
+                }
+                _ => {
+                    if at_line_start && let Some(span) = open.last() {
+                        lines.push((line, *span));
+                    }
+                    at_line_start = c == '\n';
+                    if at_line_start {
+                        line += 1;
+                    }
+                    code.push(c);
+                }
+            }
+        }
+        (code, SourceMap { lines })
+    }
+
+    /// The map as JSON, one object per line of C naming the line and column
+    /// the statement starts at in `file` along with its byte range.
+    pub fn to_json(&self, file: &str) -> String {
+        let mappings: Vec<String> = self
+            .lines
+            .iter()
+            .map(|(line, span)| {
+                format!(
+                    "{{\"c_line\":{},\"line\":{},\"column\":{},\"start\":{},\"end\":{}}}",
+                    line, span.line, span.column, span.start, span.end
+                )
+            })
+            .collect();
+        format!(
+            "{{\"file\":{},\"mappings\":[{}]}}",
+            json_string(file),
+            mappings.join(",")
+        )
+    }
+}
+
+/// The C for `ast`, with every statement's code wrapped in markers if its
+/// span is to be recorded in `spans`.
+fn generate(
+    ast: &AbstractSyntaxTree,
+    options: &CodegenOptions,
+    spans: Option<&RefCell<Vec<Span>>>,
+) -> String {
+    let simplified;
+    let ast = if options.optimize {
+        simplified = simplify(ast);
+        &simplified
+    } else {
+        ast
+    };
+    let codegen = CodeGen {
+        options,
+        uses_pow: Cell::new(false),
+        arithmetic_helpers: RefCell::new(BTreeSet::new()),
+        reduction_helpers: RefCell::new(BTreeSet::new()),
+        constants: RefCell::new(ScopeStack::new()),
+        variable_types: RefCell::new(ScopeStack::new()),
+        unused: if options.suppress_unused {
+            unread_variables(ast, options.int_width)
+        } else {
+            HashSet::new()
+        },
+        spans,
+    };
+    let mut body = String::new();
+    body.push_str(&codegen.generate_hoisted_declarations(&ast.statement_list));
+    body.push_str(&codegen.generate_statement_list(&ast.statement_list));
+
+    // The pow helper multiplies through `_hand_mul` unless overflow is
+    // unchecked, so generate it first to know which helpers it needs.
+    let pow_helper = codegen
+        .uses_pow
+        .get()
+        .then(|| codegen.generate_pow_helper());
+
     let mut result = String::new();
     result.push_str("#include <stdio.h>\n");
-    result.push_str("int main() {\n");
-    result.push_str(&generate_statement_list(&ast.statement_list));
+    let arithmetic_helpers = codegen.arithmetic_helpers.borrow();
+    if options.overflow == Overflow::Checked && !arithmetic_helpers.is_empty() {
+        result.push_str("#include <limits.h>\n");
+        result.push_str("#include <stdlib.h>\n");
+    }
+    for operator in arithmetic_helpers.iter() {
+        result.push_str(&codegen.generate_arithmetic_helper(operator));
+    }
+    for name in codegen.reduction_helpers.borrow().iter() {
+        result.push_str(&codegen.generate_reduction_helper(name));
+    }
+    if let Some(pow_helper) = pow_helper {
+        result.push_str(&pow_helper);
+    }
+    let function = options.as_function.as_deref().unwrap_or("main");
+    match function {
+        "main" => result.push_str("int main() {\n"),
+        _ => result.push_str(&format!("int {}(void) {{\n", function)),
+    }
+    result.push_str(&body);
     result.push_str("return 0;\n");
     result.push_str("}\n");
-    result
+    if function != "main" {
+        result.push_str(&format!("int main() {{\nreturn {}();\n}}\n", function));
+    }
+    layout(&result, options)
 }
 
-fn generate_statement_list(statement_list: &StatementList) -> String {
+/// Indents every line of `code` by its depth of braces and, for
+/// `BraceStyle::Allman`, moves each opening brace onto a line of its own.
+/// The statement markers at the start of a line stay in front of the
+/// indentation, so the source map still starts the line with them.
+fn layout(code: &str, options: &CodegenOptions) -> String {
+    if options.indent == 0 && options.brace_style == BraceStyle::Attach {
+        return code.to_string();
+    }
     let mut result = String::new();
-    for statement in &statement_list.statements {
-        result.push_str(&generate_statement(statement));
+    let mut depth: usize = 0;
+    for line in code.split_inclusive('\n') {
+        let (markers, text) = split_markers(line);
+        let (text, newline) = match text.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (text, ""),
+        };
+        if text.starts_with('}') {
+            depth = depth.saturating_sub(1);
+        }
+        let indent = " ".repeat(depth * options.indent);
+        result.push_str(markers);
+        // A GNU statement expression must keep its `({` together
+        match text.strip_suffix(" {") {
+            Some(head) if options.brace_style == BraceStyle::Allman && !head.ends_with('(') => {
+                if let Some(rest) = head.strip_prefix("} ") {
+                    result.push_str(&format!("{}}}\n{}{}\n", indent, indent, rest));
+                } else {
+                    result.push_str(&format!("{}{}\n", indent, head));
+                }
+                result.push_str(&format!("{}{{{}", indent, newline));
+            }
+            _ if text.is_empty() => result.push_str(newline),
+            _ => result.push_str(&format!("{}{}{}", indent, text, newline)),
+        }
+        if text.ends_with('{') {
+            depth += 1;
+        }
     }
     result
 }
 
-fn generate_statement(statement: &Statement) -> String {
-    match statement {
-        Statement::Let(let_stmt) => generate_let_statement(let_stmt),
-        Statement::Assignment(assign_stmt) => generate_assignment_statement(assign_stmt),
-        Statement::Loop(loop_stmt) => generate_loop_statement(loop_stmt),
-        Statement::Print(print_stmt) => generate_print_statement(print_stmt),
+/// Splits the statement markers off the start of `line`.
+fn split_markers(line: &str) -> (&str, &str) {
+    let mut rest = line;
+    loop {
+        if let Some(after) = rest.strip_prefix(STATEMENT_END) {
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix(STATEMENT_START)
+            && let Some(index_end) = after.find(STATEMENT_START)
+        {
+            rest = &after[index_end + STATEMENT_START.len_utf8()..];
+        } else {
+            break;
+        }
     }
+    line.split_at(line.len() - rest.len())
 }
 
-fn generate_let_statement(let_stmt: &LetStatement) -> String {
-    let mut result = String::new();
-    result.push_str(&format!("int {} = ", let_stmt.identifier));
-    result.push_str(&generate_expression(&let_stmt.value));
-    result.push_str(";\n");
-    result
+struct CodeGen<'a> {
+    options: &'a CodegenOptions,
+    /// Set once an expression lowers `**` to a call to `_hand_pow`.
+    uses_pow: Cell<bool>,
+    /// Names of the `_hand_add`, `_hand_sub` and `_hand_mul` helpers called
+    /// so far, which implement the overflow mode.
+    arithmetic_helpers: RefCell<BTreeSet<&'static str>>,
+    /// Names of the `_hand_min` and `_hand_max` helpers called so far, which
+    /// `min_of` and `max_of` nest.
+    reduction_helpers: RefCell<BTreeSet<&'static str>>,
+    /// Values of the `const`s in scope, substituted for every reference.
+    /// Analysis guarantees a constant's name is never reused while it's in
+    /// scope.
+    constants: RefCell<ScopeStack<i64>>,
+    /// The type of every variable in scope, so `print` can tell booleans
+    /// apart for `bool_words` and print whole arrays.
+    variable_types: RefCell<ScopeStack<Type>>,
+    /// With `suppress_unused`, the start offsets of the names of variables
+    /// analysis found are never read.
+    unused: HashSet<usize>,
+    /// The span of every statement generated so far, when recording a
+    /// source map.
+    spans: Option<&'a RefCell<Vec<Span>>>,
 }
 
-fn generate_assignment_statement(assign_stmt: &AssignmentStatement) -> String {
-    let mut result = String::new();
-    result.push_str(&format!("{} = ", assign_stmt.identifier));
-    result.push_str(&generate_expression(&assign_stmt.value));
-    result.push_str(";\n");
-    result
+impl CodeGen<'_> {
+    fn int_type(&self) -> &'static str {
+        match self.options.int_width {
+            IntWidth::Bits32 => "int",
+            IntWidth::Bits64 => "long long",
+        }
+    }
+
+    /// C type for a variable declared with the annotation `declared`. Bools
+    /// are plain `int`s, which is what C comparisons evaluate to.
+    fn c_type(&self, declared: Option<Type>) -> &'static str {
+        match declared {
+            Some(Type::Bool) => "int",
+            _ => self.int_type(),
+        }
+    }
+
+    fn generate_hoisted_declarations(&self, statement_list: &StatementList) -> String {
+        if !self.options.c89 {
+            return String::new();
+        }
+        let mut result = String::new();
+        for (declared_type, declarator) in hoisted_declarators(statement_list) {
+            result.push_str(&format!("{} {};\n", self.c_type(declared_type), declarator));
+        }
+        result
+    }
+
+    /// C has no exponent operator, so `a ** b` becomes `_hand_pow(a, b)`.
+    fn generate_pow_helper(&self) -> String {
+        let ty = self.int_type();
+        let mut result = String::new();
+        result.push_str(&format!(
+            "static {} _hand_pow({} base, {} exp) {{\n",
+            ty, ty, ty
+        ));
+        result.push_str(&format!("{} result = 1;\n", ty));
+        result.push_str("while (exp > 0) {\n");
+        match self.arithmetic_helper(&BinaryOperator::Multiply) {
+            Some(helper) => result.push_str(&format!("result = {}(result, base);\n", helper)),
+            None => result.push_str("result *= base;\n"),
+        }
+        result.push_str("exp--;\n");
+        result.push_str("}\n");
+        result.push_str("return result;\n");
+        result.push_str("}\n");
+        result
+    }
+
+    /// Name of the helper that performs `operator` under the overflow mode,
+    /// recording that it is used, or `None` for plain C arithmetic.
+    fn arithmetic_helper(&self, operator: &BinaryOperator) -> Option<String> {
+        if self.options.overflow == Overflow::Unchecked {
+            return None;
+        }
+        let name = match operator {
+            BinaryOperator::Add => "add",
+            BinaryOperator::Subtract => "sub",
+            BinaryOperator::Multiply => "mul",
+            _ => return None,
+        };
+        self.arithmetic_helpers.borrow_mut().insert(name);
+        Some(format!("_hand_{}", name))
+    }
+
+    /// `_hand_min(a, b)` or `_hand_max(a, b)`. Unlike the inline conditional
+    /// for `min`, a call evaluates each argument once.
+    fn generate_reduction_helper(&self, name: &str) -> String {
+        let comparison = if name == "min" { "<" } else { ">" };
+        format!(
+            "static {0} _hand_{1}({0} a, {0} b) {{\nreturn a {2} b ? a : b;\n}}\n",
+            self.int_type(),
+            name,
+            comparison
+        )
+    }
+
+    /// `_hand_<name>(a, b)`, which either wraps by computing on unsigned
+    /// integers or aborts when the result would be out of range.
+    fn generate_arithmetic_helper(&self, name: &str) -> String {
+        let ty = self.int_type();
+        let (max, min) = match self.options.int_width {
+            IntWidth::Bits32 => ("INT_MAX", "INT_MIN"),
+            IntWidth::Bits64 => ("LLONG_MAX", "LLONG_MIN"),
+        };
+        let symbol = match name {
+            "add" => "+",
+            "sub" => "-",
+            _ => "*",
+        };
+        let mut result = String::new();
+        result.push_str(&format!(
+            "static {} _hand_{}({} a, {} b) {{\n",
+            ty, name, ty, ty
+        ));
+        if self.options.overflow == Overflow::Wrap {
+            result.push_str(&format!(
+                "return ({})((unsigned {})a {} (unsigned {})b);\n",
+                ty, ty, symbol, ty
+            ));
+            result.push_str("}\n");
+            return result;
+        }
+        let overflows = match name {
+            "add" => format!("(b > 0 && a > {max} - b) || (b < 0 && a < {min} - b)"),
+            "sub" => format!("(b < 0 && a > {max} + b) || (b > 0 && a < {min} + b)"),
+            _ => format!(
+                "a > 0 ? (b > 0 ? a > {max} / b : b < {min} / a) : (b > 0 ? a < {min} / b : a != 0 && b < {max} / a)"
+            ),
+        };
+        result.push_str(&format!("if ({}) {{\n", overflows));
+        result.push_str("fprintf(stderr, \"integer overflow\\n\");\n");
+        // abort() doesn't flush, so keep what was printed before the overflow.
+        result.push_str("fflush(stdout);\n");
+        result.push_str("abort();\n");
+        result.push_str("}\n");
+        result.push_str(&format!("return a {} b;\n", symbol));
+        result.push_str("}\n");
+        result
+    }
+
+    fn generate_statement_list(&self, statement_list: &StatementList) -> String {
+        let mut result = String::new();
+        for statement in &statement_list.statements {
+            result.push_str(&self.generate_statement(statement));
+        }
+        result
+    }
+
+    fn generate_statement(&self, statement: &Statement) -> String {
+        let code = self.generate_statement_code(statement);
+        let Some(spans) = self.spans else {
+            return code;
+        };
+        let mut spans = spans.borrow_mut();
+        spans.push(statement.span());
+        format!(
+            "{STATEMENT_START}{}{STATEMENT_START}{}{STATEMENT_END}",
+            spans.len() - 1,
+            code
+        )
+    }
+
+    fn generate_statement_code(&self, statement: &Statement) -> String {
+        match statement {
+            Statement::Let(let_stmt) => self.generate_let_statement(let_stmt),
+            Statement::ArrayLet(array_let_stmt) => {
+                self.generate_array_let_statement(array_let_stmt)
+            }
+            Statement::Assignment(assign_stmt) => self.generate_assignment_statement(assign_stmt),
+            Statement::Loop(loop_stmt) => self.generate_loop_statement(loop_stmt),
+            Statement::Repeat(repeat_stmt) => self.generate_repeat_statement(repeat_stmt),
+            Statement::If(if_stmt) => self.generate_if_statement(if_stmt),
+            Statement::Print(print_stmt) => self.generate_print_statement(print_stmt),
+            Statement::Assert(assert_stmt) => self.generate_assert_statement(assert_stmt),
+            Statement::Read(read_stmt) => self.generate_read_statement(read_stmt),
+            Statement::Include(_) => unreachable!("includes are resolved before code generation"),
+            // The module's consts follow it.
+            Statement::Use(_) => String::new(),
+            Statement::Exit(exit_stmt) => self.generate_exit_statement(exit_stmt),
+            Statement::Break(_) => "break;\n".to_string(),
+            Statement::Expression(expr_stmt) => {
+                // The cast keeps C from warning that the value is unused.
+                format!("(void)({});\n", self.generate_expression(&expr_stmt.value))
+            }
+            Statement::Const(const_stmt) => self.generate_const_statement(const_stmt),
+            Statement::Comment(comment) => self.generate_comment(comment),
+        }
+    }
+
+    fn generate_let_statement(&self, let_stmt: &LetStatement) -> String {
+        let is_bool = match let_stmt.declared_type {
+            Some(declared) => declared == Type::Bool,
+            None => let_stmt
+                .value
+                .as_ref()
+                .is_some_and(|value| self.is_boolean(value)),
+        };
+        let ty = if is_bool { Type::Bool } else { Type::Int };
+        self.declare(&let_stmt.identifier.name, ty);
+        let mut result = String::new();
+        if self.options.c89 {
+            result.push_str(&format!("{} = ", let_stmt.identifier.name));
+        } else {
+            result.push_str(&format!(
+                "{} {} = ",
+                self.c_type(let_stmt.declared_type),
+                let_stmt.identifier.name
+            ));
+        }
+        match &let_stmt.value {
+            Some(value) => result.push_str(&self.generate_expression(value)),
+            None => result.push('0'),
+        }
+        result.push_str(";\n");
+        result.push_str(&self.generate_trace(&let_stmt.identifier.name));
+        result.push_str(&self.generate_unused_cast(&let_stmt.identifier));
+        result
+    }
+
+    /// With `trace`, a `printf` of `name = <value>` for the variable just
+    /// set.
+    fn generate_trace(&self, name: &str) -> String {
+        if !self.options.trace {
+            return String::new();
+        }
+        match self.options.int_width {
+            IntWidth::Bits32 => format!("printf(\"{0} = %d\\n\", {0});\n", name),
+            // Bools are plain `int`s, so widen them to match the format.
+            IntWidth::Bits64 => format!("printf(\"{0} = %lld\\n\", (long long){0});\n", name),
+        }
+    }
+
+    /// `(void)x;` if `identifier` declares a variable that's never read.
+    fn generate_unused_cast(&self, identifier: &Identifier) -> String {
+        if self.unused.contains(&identifier.span.start) {
+            format!("(void){};\n", identifier.name)
+        } else {
+            String::new()
+        }
+    }
+
+    fn generate_array_let_statement(&self, array_let_stmt: &ArrayLetStatement) -> String {
+        let name = &array_let_stmt.identifier.name;
+        self.declare(name, Type::Array(array_let_stmt.length));
+        let elements: Vec<String> = match &array_let_stmt.initializer {
+            ArrayInitializer::Fill(value) => {
+                vec![self.generate_expression(value); array_let_stmt.length]
+            }
+            ArrayInitializer::List(elements) => elements
+                .iter()
+                .map(|element| self.generate_expression(element))
+                .collect(),
+        };
+        let mut result = String::new();
+        if self.options.c89 {
+            // C89 only allows constant initializers, so assign each element.
+            for (i, element) in elements.iter().enumerate() {
+                result.push_str(&format!("{}[{}] = {};\n", name, i, element));
+            }
+        } else {
+            result.push_str(&format!(
+                "{} {}[{}] = {{{}}};\n",
+                self.int_type(),
+                name,
+                array_let_stmt.length,
+                elements.join(", ")
+            ));
+        }
+        result.push_str(&self.generate_unused_cast(&array_let_stmt.identifier));
+        result
+    }
+
+    fn generate_assignment_statement(&self, assign_stmt: &AssignmentStatement) -> String {
+        let name = &assign_stmt.identifier.name;
+        if let (true, Some(index)) = (self.options.trace, &assign_stmt.index) {
+            // The index is evaluated once, and the trace shows its value.
+            let (ty, conversion) = match self.options.int_width {
+                IntWidth::Bits32 => ("int", "%d"),
+                IntWidth::Bits64 => ("long long", "%lld"),
+            };
+            return format!(
+                "{{\n{0} _index = {1};\n{2}[_index] = {3};\nprintf(\"{2}[{4}] = {4}\\n\", _index, {2}[_index]);\n}}\n",
+                ty,
+                self.generate_expression(index),
+                name,
+                self.generate_expression(&assign_stmt.value),
+                conversion
+            );
+        }
+        let mut result = String::new();
+        result.push_str(&assign_stmt.identifier.name);
+        if let Some(index) = &assign_stmt.index {
+            result.push_str(&format!("[{}]", self.generate_expression(index)));
+        }
+        result.push_str(" = ");
+        result.push_str(&self.generate_expression(&assign_stmt.value));
+        result.push_str(";\n");
+        result.push_str(&self.generate_trace(name));
+        result
+    }
+
+    fn generate_print_statement(&self, print_stmt: &PrintStatement) -> String {
+        let print_stmt = print_stmt.expand_arrays(|name| self.array_length(name));
+        format!(
+            "{};\n",
+            self.generate_printf(&print_stmt.arguments, &print_stmt.format)
+        )
+    }
+
+    /// A single `printf` call whose format string has one conversion per
+    /// argument, `%s` for text, so `print "x = ", x;` becomes
+    /// `printf("%s%d\n", "x = ", x)`. `write` shares it with `print`.
+    fn generate_printf(
+        &self,
+        print_arguments: &[PrintArgument],
+        print_format: &PrintFormat,
+    ) -> String {
+        let wide = self.options.int_width == IntWidth::Bits64;
+        let conversion = match (print_format, wide) {
+            (PrintFormat::Decimal, false) => "%d",
+            (PrintFormat::Decimal, true) => "%lld",
+            (PrintFormat::Hex, false) => "%x",
+            (PrintFormat::Hex, true) => "%llx",
+            (PrintFormat::Char, _) => "%c",
+        };
+        let mut format = String::new();
+        let mut arguments = Vec::new();
+        for argument in print_arguments {
+            match argument {
+                PrintArgument::Text(text) => {
+                    format.push_str("%s");
+                    arguments.push(c_string_literal(text));
+                }
+                PrintArgument::Value(value)
+                    if self.options.bool_words
+                        && *print_format == PrintFormat::Decimal
+                        && self.is_boolean(value) =>
+                {
+                    format.push_str("%s");
+                    arguments.push(format!(
+                        "{} ? \"true\" : \"false\"",
+                        self.generate_expression(value)
+                    ));
+                }
+                PrintArgument::Value(value) => {
+                    format.push_str(conversion);
+                    arguments.push(self.generate_print_value(value, print_format));
+                }
+            }
+        }
+        if *print_format != PrintFormat::Char {
+            format.push_str("\\n");
+        }
+        if arguments.is_empty() {
+            return format!("printf(\"{}\")", format);
+        }
+        format!("printf(\"{}\", {})", format, arguments.join(", "))
+    }
+
+    fn generate_print_value(&self, value: &Expr, format: &PrintFormat) -> String {
+        let code = self.generate_expression(value);
+        if self.options.int_width == IntWidth::Bits32 {
+            return code;
+        }
+        // Comparisons are plain `int` in C, so cast to match the format.
+        let cast = match format {
+            PrintFormat::Char => "int",
+            _ => "long long",
+        };
+        match value.kind {
+            ExprKind::Term(_)
+            | ExprKind::Call(_)
+            | ExprKind::Write(_)
+            | ExprKind::StringLength(_)
+            | ExprKind::Block(_) => {
+                format!("({}){}", cast, code)
+            }
+            ExprKind::Binary(_) | ExprKind::Negate(_) => format!("({})({})", cast, code),
+        }
+    }
+
+    fn generate_assert_statement(&self, assert_stmt: &AssertStatement) -> String {
+        let mut result = String::new();
+        result.push_str("if (!(");
+        result.push_str(&self.generate_expression(&assert_stmt.condition));
+        result.push_str(")) {\n");
+        match &assert_stmt.message {
+            Some(message) => result.push_str(&format!(
+                "fprintf(stderr, \"assertion failed: %s\\n\", {});\n",
+                c_string_literal(message)
+            )),
+            None => result.push_str("fprintf(stderr, \"assertion failed\\n\");\n"),
+        }
+        result.push_str("return 1;\n");
+        result.push_str("}\n");
+        result
+    }
+
+    /// Reading anything but an integer ends the program like a failed
+    /// assertion does.
+    fn generate_read_statement(&self, read_stmt: &ReadStatement) -> String {
+        let conversion = match self.options.int_width {
+            IntWidth::Bits32 => "%d",
+            IntWidth::Bits64 => "%lld",
+        };
+        let mut result = String::new();
+        result.push_str(&format!(
+            "if (scanf(\"{}\", &{}) != 1) {{\n",
+            conversion, read_stmt.identifier.name
+        ));
+        result.push_str("fprintf(stderr, \"invalid input\\n\");\n");
+        result.push_str("return 1;\n");
+        result.push_str("}\n");
+        result
+    }
+
+    fn generate_loop_statement(&self, loop_stmt: &LoopStatement) -> String {
+        // A loop that runs zero times has no effect, and whatever its body
+        // declares isn't visible after it. A count loop runs as often as a
+        // range from 1 to its count.
+        let first = match &loop_stmt.start {
+            Some(start) => self.eval_const(start),
+            None => Some(1),
+        };
+        if self.options.optimize
+            && let (Some(first), Some(last)) = (first, self.eval_const(&loop_stmt.count))
+            && first > last
+        {
+            return String::new();
+        }
+        if let Some(start) = &loop_stmt.start {
+            return self.generate_range_loop(loop_stmt, start);
+        }
+        let counter = loop_stmt
+            .counter
+            .as_ref()
+            .map_or("_", |counter| counter.name.as_str());
+        let ty = self.int_type();
+        let mut result = String::new();
+        // The count and step are evaluated once, before the counter exists,
+        // so the body can't change the trip count and `loop i in i` reads the
+        // outer `i`. The counter never passes `_count`, so `++` can't
+        // overflow, and a negative count runs zero times.
+        result.push_str("{\n");
+        result.push_str(&format!(
+            "{} _count = {};\n",
+            ty,
+            self.generate_expression(&loop_stmt.count)
+        ));
+        if let Some(step) = &loop_stmt.step {
+            result.push_str(&format!(
+                "{} _step = {};\n",
+                ty,
+                self.generate_expression(step)
+            ));
+        }
+        if self.options.c89 {
+            result.push_str(&format!("{} {};\n", ty, counter));
+            result.push_str(&format!("for ({} = 0; ", counter));
+        } else {
+            result.push_str(&format!("for ({} {} = 0; ", ty, counter));
+        }
+        result.push_str(&format!("{} < _count; ", counter));
+        match loop_stmt.step {
+            Some(_) => result.push_str(&format!("{} += _step) ", counter)),
+            None => result.push_str(&format!("{}++) ", counter)),
+        }
+        // The counter is an int even if it shadows a bool.
+        self.variable_types.borrow_mut().enter_scope();
+        self.declare(counter, Type::Int);
+        result.push_str(&self.generate_block(&loop_stmt.body));
+        self.variable_types.borrow_mut().exit_scope();
+        result.push_str("}\n");
+        result
+    }
+
+    /// `loop i from a to b`, counting up to and including `b`. Like the
+    /// count, both bounds are evaluated once before the counter exists. A `b`
+    /// at the largest int overflows the counter, as it would in C; analysis
+    /// rejects it if it's constant.
+    fn generate_range_loop(&self, loop_stmt: &LoopStatement, start: &Expr) -> String {
+        let counter = loop_stmt
+            .counter
+            .as_ref()
+            .map_or("_", |counter| counter.name.as_str());
+        let ty = self.int_type();
+        let mut result = String::from("{\n");
+        result.push_str(&format!(
+            "{} _start = {};\n",
+            ty,
+            self.generate_expression(start)
+        ));
+        result.push_str(&format!(
+            "{} _end = {};\n",
+            ty,
+            self.generate_expression(&loop_stmt.count)
+        ));
+        if let Some(step) = &loop_stmt.step {
+            result.push_str(&format!(
+                "{} _step = {};\n",
+                ty,
+                self.generate_expression(step)
+            ));
+        }
+        // Stepping past `_end` could overflow, so the loop ends on the last
+        // value in range instead; the difference is taken unsigned, where it
+        // can't overflow, since the counter never passes `_end`.
+        result.push_str("if (_start <= _end) {\n");
+        if self.options.c89 {
+            result.push_str(&format!("{} {};\n", ty, counter));
+            result.push_str(&format!("for ({} = _start; ; ", counter));
+        } else {
+            result.push_str(&format!("for ({} {} = _start; ; ", ty, counter));
+        }
+        let last = match loop_stmt.step {
+            Some(_) => {
+                result.push_str(&format!("{} += _step) ", counter));
+                format!(
+                    "(unsigned {ty})_end - (unsigned {ty}){counter} < (unsigned {ty})_step",
+                    ty = ty,
+                    counter = counter
+                )
+            }
+            None => {
+                result.push_str(&format!("{}++) ", counter));
+                format!("{} == _end", counter)
+            }
+        };
+        self.variable_types.borrow_mut().enter_scope();
+        self.declare(counter, Type::Int);
+        let body = self.generate_block(&loop_stmt.body);
+        self.variable_types.borrow_mut().exit_scope();
+        result.push_str(body.strip_suffix("}\n").unwrap());
+        result.push_str(&format!("if ({}) break;\n}}\n}}\n}}\n", last));
+        result
+    }
+
+    fn generate_repeat_statement(&self, repeat_stmt: &RepeatStatement) -> String {
+        let body = self.generate_block(&repeat_stmt.body);
+        format!(
+            "do {} while (!({}));\n",
+            body.trim_end(),
+            self.generate_expression(&repeat_stmt.condition)
+        )
+    }
+
+    fn generate_exit_statement(&self, exit_stmt: &ExitStatement) -> String {
+        // Everything is generated inside `main`, so returning ends the program.
+        format!("return {};\n", self.generate_expression(&exit_stmt.code))
+    }
+
+    fn generate_comment(&self, comment: &CommentStatement) -> String {
+        // Block comments keep the output valid C89; break up any `*/` so the
+        // comment can't end early. Control characters become spaces, as
+        // they would be mistaken for source map markers.
+        let text: String = comment
+            .text
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        format!("/* {} */\n", text.replace("*/", "* /"))
+    }
+
+    /// Emits an `else if` chain flat, as C programmers write it, instead of
+    /// nesting each `if` inside the previous `else` block.
+    fn generate_if_statement(&self, if_stmt: &IfStatement) -> String {
+        let mut result = String::new();
+        let mut current = if_stmt;
+        loop {
+            result.push_str(&format!(
+                "if ({}) ",
+                self.generate_expression(&current.condition)
+            ));
+            result.push_str(&self.generate_block(&current.then_block));
+            let Some(else_branch) = current.else_branch.as_deref() else {
+                break;
+            };
+            // Continue on the line of the closing `}`.
+            result.pop();
+            result.push_str(" else ");
+            match else_branch {
+                ElseBranch::If(next) => current = next,
+                ElseBranch::Block(block) => {
+                    result.push_str(&self.generate_block(block));
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    fn generate_block(&self, block: &Block) -> String {
+        self.variable_types.borrow_mut().enter_scope();
+        self.constants.borrow_mut().enter_scope();
+        let mut result = String::new();
+        result.push_str("{\n");
+        result.push_str(&self.generate_hoisted_declarations(&block.statements));
+        result.push_str(&self.generate_statement_list(&block.statements));
+        result.push_str("}\n");
+        self.constants.borrow_mut().exit_scope();
+        self.variable_types.borrow_mut().exit_scope();
+        result
+    }
+
+    /// A GCC statement expression, `({ ... value; })`, which evaluates to its
+    /// last statement. Standard C has no equivalent.
+    fn generate_block_expression(&self, block: &BlockExpr) -> String {
+        self.variable_types.borrow_mut().enter_scope();
+        self.constants.borrow_mut().enter_scope();
+        let mut result = String::new();
+        result.push_str("({\n");
+        result.push_str(&self.generate_hoisted_declarations(&block.statements));
+        result.push_str(&self.generate_statement_list(&block.statements));
+        result.push_str(&format!("{};\n}})", self.generate_expression(&block.value)));
+        self.constants.borrow_mut().exit_scope();
+        self.variable_types.borrow_mut().exit_scope();
+        result
+    }
+
+    fn declare(&self, name: &str, ty: Type) {
+        self.variable_types
+            .borrow_mut()
+            .declare(name.to_string(), ty);
+    }
+
+    fn array_length(&self, name: &str) -> Option<usize> {
+        match self.variable_types.borrow().lookup(name) {
+            Some(Type::Array(length)) => Some(*length),
+            _ => None,
+        }
+    }
+
+    /// Whether `expr` is a bool: `true` or `false`, a comparison, a logical
+    /// operator, or a variable holding one.
+    fn is_boolean(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Binary(binary) => matches!(
+                binary.operator,
+                BinaryOperator::Equal
+                    | BinaryOperator::NotEqual
+                    | BinaryOperator::Less
+                    | BinaryOperator::LessEqual
+                    | BinaryOperator::Greater
+                    | BinaryOperator::GreaterEqual
+                    | BinaryOperator::LogicalAnd
+                    | BinaryOperator::LogicalOr
+            ),
+            ExprKind::Term(Term::Bool(_)) => true,
+            ExprKind::Term(Term::Identifier(identifier)) => self
+                .variable_types
+                .borrow()
+                .lookup(&identifier.name)
+                .is_some_and(|ty| *ty == Type::Bool),
+            _ => false,
+        }
+    }
+
+    fn generate_expression(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Term(term) => self.generate_term(term),
+            ExprKind::Binary(binary) => self.generate_binary_expression(binary),
+            ExprKind::Call(call) => self.generate_call(call),
+            ExprKind::Write(write) => self.generate_printf(&write.arguments, &PrintFormat::Decimal),
+            ExprKind::StringLength(text) => self.generate_literal(text.len() as i64),
+            ExprKind::Block(block) => self.generate_block_expression(block),
+            ExprKind::Negate(operand) => {
+                if let Some(helper) = self.arithmetic_helper(&BinaryOperator::Subtract) {
+                    let zero = self.generate_literal(0);
+                    return format!(
+                        "{}({}, {})",
+                        helper,
+                        zero,
+                        self.generate_expression(operand)
+                    );
+                }
+                negated(&self.generate_operand(operand, u8::MAX))
+            }
+        }
+    }
+
+    /// Intrinsics become inline conditionals. Arguments are evaluated more
+    /// than once, which is fine since expressions have no side effects.
+    fn generate_call(&self, call: &CallExpr) -> String {
+        if let Some(intrinsic @ (Intrinsic::MinOf | Intrinsic::MaxOf)) =
+            Intrinsic::from_name(&call.function.name)
+        {
+            return self.generate_reduction(intrinsic, call);
+        }
+        // Parenthesize any binary argument so it groups correctly inside `?:`.
+        let arguments: Vec<String> = call
+            .arguments
+            .iter()
+            .map(|argument| self.generate_operand(argument, u8::MAX))
+            .collect();
+        match Intrinsic::from_name(&call.function.name) {
+            Some(Intrinsic::Min) => format!(
+                "({} < {} ? {} : {})",
+                arguments[0], arguments[1], arguments[0], arguments[1]
+            ),
+            Some(Intrinsic::Max) => format!(
+                "({} > {} ? {} : {})",
+                arguments[0], arguments[1], arguments[0], arguments[1]
+            ),
+            Some(Intrinsic::Abs) => format!(
+                "({} < 0 ? {} : {})",
+                arguments[0],
+                negated(&arguments[0]),
+                arguments[0]
+            ),
+            Some(Intrinsic::MinOf | Intrinsic::MaxOf) => unreachable!("handled above"),
+            None => unreachable!("unknown functions are rejected by semantic analysis"),
+        }
+    }
+
+    /// `min_of` or `max_of`: a literal if every argument is constant, and
+    /// otherwise nested calls to `_hand_min` or `_hand_max`, such as
+    /// `_hand_max(_hand_max(a, b), c)`.
+    fn generate_reduction(&self, intrinsic: Intrinsic, call: &CallExpr) -> String {
+        let values: Option<Vec<i64>> = call
+            .arguments
+            .iter()
+            .map(|argument| self.eval_const(argument))
+            .collect();
+        if let Some(values) = values {
+            let value = match intrinsic {
+                Intrinsic::MinOf => values.iter().min(),
+                _ => values.iter().max(),
+            };
+            return self.generate_literal(*value.unwrap());
+        }
+        let name = match intrinsic {
+            Intrinsic::MinOf => "min",
+            _ => "max",
+        };
+        if call.arguments.len() > 1 {
+            self.reduction_helpers.borrow_mut().insert(name);
+        }
+        call.arguments
+            .iter()
+            .map(|argument| self.generate_expression(argument))
+            .reduce(|result, argument| format!("_hand_{}({}, {})", name, result, argument))
+            .unwrap()
+    }
+
+    fn generate_binary_expression(&self, binary: &BinaryExpr) -> String {
+        if binary.operator == BinaryOperator::Power {
+            self.uses_pow.set(true);
+            return format!(
+                "_hand_pow({}, {})",
+                self.generate_expression(&binary.lhs),
+                self.generate_expression(&binary.rhs)
+            );
+        }
+        if let Some(helper) = self.arithmetic_helper(&binary.operator) {
+            return format!(
+                "{}({}, {})",
+                helper,
+                self.generate_expression(&binary.lhs),
+                self.generate_expression(&binary.rhs)
+            );
+        }
+        let precedence = operator_precedence(&binary.operator);
+        let mut result = self.generate_operand(&binary.lhs, precedence);
+        result.push_str(&format!(" {} ", operator_symbol(&binary.operator)));
+        let bits = i64::from(self.options.int_width.bits());
+        let is_shift = matches!(
+            binary.operator,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight
+        );
+        if is_shift
+            && !self
+                .eval_const(&binary.rhs)
+                .is_some_and(|n| (0..bits).contains(&n))
+        {
+            // Shifting by the width or more is undefined in C, so the amount
+            // is masked as every other backend does.
+            result.push_str(&format!(
+                "({} & {})",
+                self.generate_operand(&binary.rhs, u8::MAX),
+                bits - 1
+            ));
+            return result;
+        }
+        // Operators are left-associative, so an equal-precedence right operand
+        // needs parentheses to keep its grouping.
+        result.push_str(&self.generate_operand(&binary.rhs, precedence + 1));
+        result
+    }
+
+    fn generate_operand(&self, expr: &Expr, min_precedence: u8) -> String {
+        match &expr.kind {
+            ExprKind::Binary(binary) if operator_precedence(&binary.operator) < min_precedence => {
+                format!("({})", self.generate_expression(expr))
+            }
+            _ => self.generate_expression(expr),
+        }
+    }
+
+    /// Records the constant's value for later references; it has no C
+    /// declaration of its own.
+    fn generate_const_statement(&self, const_stmt: &ConstStatement) -> String {
+        if let Some(value) = eval_const(&const_stmt.value, self.options.int_width) {
+            self.constants
+                .borrow_mut()
+                .declare(const_stmt.identifier.name.clone(), value);
+        }
+        String::new()
+    }
+
+    /// `expr` evaluated at compile time, with every `const` generated so far
+    /// folded in.
+    fn eval_const(&self, expr: &Expr) -> Option<i64> {
+        let constants = self.constants.borrow();
+        eval_const_with(expr, self.options.int_width, &|name| {
+            constants.lookup(name).copied()
+        })
+    }
+
+    fn generate_literal(&self, n: i64) -> String {
+        // The minimum can't be written directly: C reads `-2147483648` as
+        // the negation of a literal too large for `int`.
+        if n == self.options.int_width.min() {
+            return match self.options.int_width {
+                IntWidth::Bits32 => format!("({} - 1)", n + 1),
+                IntWidth::Bits64 => format!("({}LL - 1)", n + 1),
+            };
+        }
+        match self.options.int_width {
+            IntWidth::Bits32 => n.to_string(),
+            // Suffix literals so arithmetic on them happens in 64 bits too.
+            IntWidth::Bits64 => format!("{}LL", n),
+        }
+    }
+
+    fn generate_term(&self, term: &Term) -> String {
+        match term {
+            Term::Number(n) => self.generate_literal(*n),
+            Term::Bool(value) => self.generate_literal((*value).into()),
+            Term::Identifier(identifier) => {
+                match self.constants.borrow().lookup(&identifier.name) {
+                    Some(&value) => self.generate_literal(value),
+                    None => c_name(&identifier.name),
+                }
+            }
+            Term::Index { base, index } => {
+                format!("{}[{}]", base.name, self.generate_expression(index))
+            }
+        }
+    }
 }
 
-fn generate_print_statement(print_stmt: &PrintStatement) -> String {
-    let mut result = String::new();
-    result.push_str("printf(\"%d\\n\", ");
-    result.push_str(&generate_expression(&print_stmt.value));
-    result.push_str(");\n");
-    result
+/// Start offsets of the names in every declaration of a variable that
+/// analysis found is never read.
+fn unread_variables(ast: &AbstractSyntaxTree, int_width: IntWidth) -> HashSet<usize> {
+    SemanticAnalyzer::check(ast, int_width)
+        .symbols
+        .iter()
+        .filter(|symbol| !symbol.read)
+        .map(|symbol| symbol.span.start)
+        .collect()
 }
 
-fn generate_loop_statement(loop_stmt: &LoopStatement) -> String {
-    let mut result = String::new();
-    result.push_str("for (int _ = 0; _ < ");
-    result.push_str(&generate_expression(&loop_stmt.count));
-    result.push_str("; _++) ");
-    result.push_str(&generate_block(&loop_stmt.body));
-    result
+/// Declarators (`x` or `a[3]`) for the variables declared directly in
+/// `statement_list`, in declaration order.
+fn hoisted_declarators(statement_list: &StatementList) -> Vec<(Option<Type>, String)> {
+    let mut declarators: Vec<(Option<Type>, String)> = Vec::new();
+    for statement in &statement_list.statements {
+        let (declared_type, declarator) = match statement {
+            Statement::Let(let_stmt) => (let_stmt.declared_type, let_stmt.identifier.name.clone()),
+            Statement::ArrayLet(array_let_stmt) => (
+                None,
+                format!(
+                    "{}[{}]",
+                    array_let_stmt.identifier.name, array_let_stmt.length
+                ),
+            ),
+            _ => continue,
+        };
+        if !declarators
+            .iter()
+            .any(|(_, existing)| *existing == declarator)
+        {
+            declarators.push((declared_type, declarator));
+        }
+    }
+    declarators
 }
 
-fn generate_block(block: &Block) -> String {
-    let mut result = String::new();
-    result.push_str("{\n");
-    result.push_str(&generate_statement_list(&block.statements));
-    result.push_str("}\n");
-    result
+fn operator_precedence(operator: &BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::LogicalOr => 1,
+        BinaryOperator::LogicalAnd => 2,
+        BinaryOperator::BitwiseOr => 3,
+        BinaryOperator::BitwiseXor => 4,
+        BinaryOperator::BitwiseAnd => 5,
+        BinaryOperator::Equal | BinaryOperator::NotEqual => 6,
+        BinaryOperator::Less
+        | BinaryOperator::LessEqual
+        | BinaryOperator::Greater
+        | BinaryOperator::GreaterEqual => 7,
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => 8,
+        BinaryOperator::Add | BinaryOperator::Subtract => 9,
+        BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Modulo => 10,
+        // Lowered to a function call, which never needs parentheses.
+        BinaryOperator::Power => 11,
+    }
 }
 
-fn generate_expression(expr: &Expr) -> String {
-    let mut result = generate_term(&expr.lhs);
-    if let Some(rhs) = &expr.rhs {
-        result.push_str(" + ");
-        result.push_str(&generate_expression(rhs));
+/// The C name for `name`. A qualified name such as `math.PI` isn't a C
+/// identifier, so it becomes `_math__PI`, which no source name can clash
+/// with since those never start with `_`. Module consts are folded like any
+/// other, so this is only a fallback.
+fn c_name(name: &str) -> String {
+    match name.split_once('.') {
+        Some((module, member)) => format!("_{}__{}", module, member),
+        None => name.to_string(),
+    }
+}
+
+/// `-code`, parenthesized when `code` already starts with a minus so the
+/// two don't read as C's `--`.
+fn negated(code: &str) -> String {
+    if code.starts_with('-') {
+        format!("-({})", code)
+    } else {
+        format!("-{}", code)
     }
-    result
 }
 
-fn generate_term(term: &Term) -> String {
-    match term {
-        Term::Number(n) => n.to_string(),
-        Term::Identifier(id) => id.clone(),
+fn operator_symbol(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Modulo => "%",
+        BinaryOperator::Power => unreachable!("`**` is lowered to a call to _hand_pow"),
+        BinaryOperator::BitwiseAnd => "&",
+        BinaryOperator::BitwiseOr => "|",
+        BinaryOperator::BitwiseXor => "^",
+        BinaryOperator::ShiftLeft => "<<",
+        BinaryOperator::ShiftRight => ">>",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::LogicalAnd => "&&",
+        BinaryOperator::LogicalOr => "||",
     }
 }
 
+/// `text` as a quoted C string literal.
+fn c_string_literal(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_ascii_control() => result.push_str(&format!("\\{:03o}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,6 +1276,13 @@ mod tests {
         generate_c_code(&ast)
     }
 
+    #[test]
+    fn test_qualified_name_is_a_c_identifier() {
+        // Unanalyzed, so `math.PI` isn't folded.
+        let ast = Parser::new(Lexer::new("print math.PI;".to_string()).collect()).parse();
+        assert!(generate_c_code(&ast).contains("printf(\"%d\\n\", _math__PI);"));
+    }
+
     #[test]
     fn test_basic_variables_and_print() {
         assert_eq!(
@@ -115,4 +1298,648 @@ mod tests {
             "#include <stdio.h>\nint main() {\nint x = 1;\nint y = 2;\nprintf(\"%d\\n\", x);\nreturn 0;\n}\n"
         );
     }
+
+    #[test]
+    fn test_arithmetic_precedence() {
+        assert_eq!(
+            compile_source_to_c("let x = (1 + 2) * 3 - (4 - 5) % 6;"),
+            "#include <stdio.h>\nint main() {\nint x = (1 + 2) * 3 - (4 - 5) % 6;\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_c89_hoists_declarations() {
+        let source = "loop 2 { print 1; let x = 2; print x; };";
+        let lexer = Lexer::new(source.to_string());
+        let mut parser = Parser::new(lexer.collect());
+        let ast = parser.parse();
+
+        assert_eq!(
+            generate_c_code(&ast),
+            "#include <stdio.h>\nint main() {\n{\nint _count = 2;\nfor (int _ = 0; _ < _count; _++) {\nprintf(\"%d\\n\", 1);\nint x = 2;\nprintf(\"%d\\n\", x);\n}\n}\nreturn 0;\n}\n"
+        );
+        assert_eq!(
+            generate_c_code_with_options(
+                &ast,
+                &CodegenOptions {
+                    c89: true,
+                    ..Default::default()
+                }
+            ),
+            "#include <stdio.h>\nint main() {\n{\nint _count = 2;\nint _;\nfor (_ = 0; _ < _count; _++) {\nint x;\nprintf(\"%d\\n\", 1);\nx = 2;\nprintf(\"%d\\n\", x);\n}\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_named_loop_counter() {
+        assert_eq!(
+            compile_source_to_c("loop i in 3 { print i; };"),
+            "#include <stdio.h>\nint main() {\n{\nint _count = 3;\nfor (int i = 0; i < _count; i++) {\nprintf(\"%d\\n\", i);\n}\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        assert_eq!(
+            compile_source_to_c("let x = (1 | 2) & 3 ^ 1 << 2 + 3 >> 1;"),
+            "#include <stdio.h>\nint main() {\nint x = (1 | 2) & 3 ^ 1 << 2 + 3 >> 1;\nreturn 0;\n}\n"
+        );
+        // Shift amounts that aren't constant are masked to the width.
+        assert_eq!(
+            compile_source_to_c("let n = 3; print 1 << n, 8 >> n + 1;"),
+            "#include <stdio.h>\nint main() {\nint n = 3;\nprintf(\"%d%d\\n\", 1 << (n & 31), 8 >> ((n + 1) & 31));\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_assert_guard() {
+        assert_eq!(
+            compile_source_to_c("let x = 5; assert x == 2 + 3;"),
+            "#include <stdio.h>\nint main() {\nint x = 5;\nif (!(x == 2 + 3)) {\nfprintf(stderr, \"assertion failed\\n\");\nreturn 1;\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_as_function() {
+        let ast = Parser::new(Lexer::new("let x = 5; exit x;".to_string()).collect()).parse();
+        let options = CodegenOptions {
+            as_function: Some("run".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_c_code_with_options(&ast, &options),
+            "#include <stdio.h>\nint run(void) {\nint x = 5;\nreturn x;\nreturn 0;\n}\nint main() {\nreturn run();\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let ast = Parser::new(
+            Lexer::new("let x = 5; x = x + 1; let a[2] = 0; a[x - 5] = 3;".to_string()).collect(),
+        )
+        .parse();
+        let options = CodegenOptions {
+            trace: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_c_code_with_options(&ast, &options),
+            "#include <stdio.h>\nint main() {\nint x = 5;\nprintf(\"x = %d\\n\", x);\nx = x + 1;\nprintf(\"x = %d\\n\", x);\nint a[2] = {0, 0};\n{\nint _index = x - 5;\na[_index] = 3;\nprintf(\"a[%d] = %d\\n\", _index, a[_index]);\n}\nreturn 0;\n}\n"
+        );
+        assert!(!generate_c_code(&ast).contains("x = %d"));
+    }
+
+    #[test]
+    fn test_assert_message() {
+        assert_eq!(
+            compile_source_to_c("let x = 5; assert x == 4, \"x should be four\";"),
+            "#include <stdio.h>\nint main() {\nint x = 5;\nif (!(x == 4)) {\nfprintf(stderr, \"assertion failed: %s\\n\", \"x should be four\");\nreturn 1;\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_print_hex() {
+        assert_eq!(
+            compile_source_to_c("let x = 255; printx x;"),
+            "#include <stdio.h>\nint main() {\nint x = 255;\nprintf(\"%x\\n\", x);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_print_char() {
+        assert_eq!(
+            compile_source_to_c("let x = 65; printc x;"),
+            "#include <stdio.h>\nint main() {\nint x = 65;\nprintf(\"%c\", x);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_int_width_64() {
+        let source = "let x = 5000000000; print x * 2; printx x; printc 65;";
+        let lexer = Lexer::new(source.to_string());
+        let mut parser = Parser::new(lexer.collect());
+        let ast = parser.parse();
+        let options = CodegenOptions {
+            int_width: IntWidth::Bits64,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            generate_c_code_with_options(&ast, &options),
+            "#include <stdio.h>\nint main() {\nlong long x = 5000000000LL;\nprintf(\"%lld\\n\", (long long)(x * 2LL));\nprintf(\"%llx\\n\", (long long)x);\nprintf(\"%c\", (int)65LL);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_keep_comments() {
+        let source = "// the answer */\nlet x = 42;";
+        let lexer = Lexer::new(source.to_string()).keep_comments();
+        let mut parser = Parser::new(lexer.collect());
+        let ast = parser.parse();
+        SemanticAnalyzer::analyze(&ast).unwrap();
+
+        assert_eq!(
+            generate_c_code(&ast),
+            "#include <stdio.h>\nint main() {\n/* the answer * / */\nint x = 42;\nreturn 0;\n}\n"
+        );
+        assert_eq!(
+            compile_source_to_c(source),
+            "#include <stdio.h>\nint main() {\nint x = 42;\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_exit_returns_from_main() {
+        assert_eq!(
+            compile_source_to_c("let x = 1; exit x + 1;"),
+            "#include <stdio.h>\nint main() {\nint x = 1;\nreturn x + 1;\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_power_helper_emitted_only_when_used() {
+        assert!(!compile_source_to_c("let x = 2 * 3;").contains("_hand_pow"));
+        assert_eq!(
+            compile_source_to_c("let x = 2 * 3 ** 2; print (x + 1) ** 2;"),
+            "#include <stdio.h>\nstatic int _hand_pow(int base, int exp) {\nint result = 1;\nwhile (exp > 0) {\nresult *= base;\nexp--;\n}\nreturn result;\n}\nint main() {\nint x = 2 * _hand_pow(3, 2);\nprintf(\"%d\\n\", _hand_pow(x + 1, 2));\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_loop_count_evaluated_once() {
+        // The count reads the outer `i` and is fixed before the body runs.
+        // Under 64-bit ints the counter is as wide as the count.
+        let source = "let i = 0 - 2; loop i in i + 5 { i = i + 10; };";
+        let lexer = Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+        let options = CodegenOptions {
+            int_width: IntWidth::Bits64,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            generate_c_code_with_options(&ast, &options),
+            "#include <stdio.h>\nint main() {\nlong long i = 0LL - 2LL;\n{\nlong long _count = i + 5LL;\nfor (long long i = 0; i < _count; i++) {\ni = i + 10LL;\n}\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_arrays() {
+        let source = "let mut a[2] = 7; let b = [1, 2, 3]; a[1] = b[a[0] - 6]; print a[1];";
+        assert_eq!(
+            compile_source_to_c(source),
+            "#include <stdio.h>\nint main() {\nint a[2] = {7, 7};\nint b[3] = {1, 2, 3};\na[1] = b[a[0] - 6];\nprintf(\"%d\\n\", a[1]);\nreturn 0;\n}\n"
+        );
+
+        let lexer = Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+        let options = CodegenOptions {
+            c89: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_c_code_with_options(&ast, &options),
+            "#include <stdio.h>\nint main() {\nint a[2];\nint b[3];\na[0] = 7;\na[1] = 7;\nb[0] = 1;\nb[1] = 2;\nb[2] = 3;\na[1] = b[a[0] - 6];\nprintf(\"%d\\n\", a[1]);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_sourcemap() {
+        let source = "let mut x = 0;\nloop 2 {\n    x = x + 1;\n    print x;\n};\n";
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        let options = CodegenOptions::default();
+        let (code, map) = generate_c_code_with_sourcemap(&ast, &options);
+        assert_eq!(code, generate_c_code_with_options(&ast, &options));
+
+        let printf_line = code
+            .lines()
+            .position(|line| line.starts_with("printf"))
+            .unwrap()
+            + 1;
+        let (_, span) = map
+            .lines
+            .iter()
+            .find(|(line, _)| *line == printf_line)
+            .unwrap();
+        assert_eq!(&source[span.start..span.end], "print x;");
+        assert_eq!((span.line, span.column), (4, 5));
+        // The loop's closing brace belongs to the loop, the `#include` to
+        // no statement.
+        let closing_line = code.lines().position(|line| line == "}").unwrap() + 1;
+        let (_, span) = map
+            .lines
+            .iter()
+            .find(|(line, _)| *line == closing_line)
+            .unwrap();
+        assert_eq!(span.line, 2);
+        assert_ne!(map.lines[0].0, 1);
+        assert!(map.to_json("loop.hand").starts_with(
+            "{\"file\":\"loop.hand\",\"mappings\":[{\"c_line\":3,\"line\":1,\"column\":1,\"start\":0,\"end\":14}"
+        ));
+    }
+
+    #[test]
+    fn test_sourcemap_with_control_characters_in_comments() {
+        let source = "// a \u{1}7\u{1} b \u{2}\nprint 1;\n";
+        let lexer = Lexer::new(source.to_string()).keep_comments();
+        let ast = Parser::new(lexer.collect()).parse();
+        let (code, map) = generate_c_code_with_sourcemap(&ast, &CodegenOptions::default());
+        assert!(code.contains("/* a  7  b   */\n"));
+        assert_eq!(map.lines.len(), 2);
+
+        let (code, _) = SourceMap::extract("\u{1}7\u{1}x\n\u{2}", &[]);
+        assert_eq!(code, "x\n");
+    }
+
+    #[test]
+    fn test_indent() {
+        let source = "let mut x = 0;\nloop 2 {\n    x = x + 1;\n};\n";
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        let with_indent = |indent| {
+            generate_c_code_with_options(
+                &ast,
+                &CodegenOptions {
+                    indent,
+                    ..Default::default()
+                },
+            )
+        };
+        assert_eq!(
+            with_indent(4),
+            "#include <stdio.h>\nint main() {\n    int x = 0;\n    {\n        int _count = 2;\n        for (int _ = 0; _ < _count; _++) {\n            x = x + 1;\n        }\n    }\n    return 0;\n}\n"
+        );
+        assert_eq!(
+            with_indent(2),
+            "#include <stdio.h>\nint main() {\n  int x = 0;\n  {\n    int _count = 2;\n    for (int _ = 0; _ < _count; _++) {\n      x = x + 1;\n    }\n  }\n  return 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_brace_style() {
+        let source = "let mut x = 0;\nrepeat {\n    if x > 1 { print x; } else { x = x + 1; };\n} until x > 2;\n";
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        let with_style = |brace_style| CodegenOptions {
+            indent: 4,
+            brace_style,
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_c_code_with_options(&ast, &with_style(BraceStyle::Attach)),
+            "#include <stdio.h>\nint main() {\n    int x = 0;\n    do {\n        if (x > 1) {\n            printf(\"%d\\n\", x);\n        } else {\n            x = x + 1;\n        }\n    } while (!(x > 2));\n    return 0;\n}\n"
+        );
+        let options = with_style(BraceStyle::Allman);
+        let (code, map) = generate_c_code_with_sourcemap(&ast, &options);
+        assert_eq!(
+            code,
+            "#include <stdio.h>\nint main()\n{\n    int x = 0;\n    do\n    {\n        if (x > 1)\n        {\n            printf(\"%d\\n\", x);\n        }\n        else\n        {\n            x = x + 1;\n        }\n    } while (!(x > 2));\n    return 0;\n}\n"
+        );
+        // Both halves of a split line belong to the statement.
+        let printf_line = code
+            .lines()
+            .position(|line| line.contains("printf"))
+            .unwrap()
+            + 1;
+        let (_, span) = map
+            .lines
+            .iter()
+            .find(|(line, _)| *line == printf_line)
+            .unwrap();
+        assert_eq!(&source[span.start..span.end], "print x;");
+        let lines: Vec<usize> = map.lines.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, (4..=15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_print_array() {
+        assert_eq!(
+            compile_source_to_c("let a = [1, 2, 3]; print a; print \"a[1] = \", a[1];"),
+            "#include <stdio.h>\nint main() {\nint a[3] = {1, 2, 3};\nprintf(\"%d%s%d%s%d\\n\", a[0], \" \", a[1], \" \", a[2]);\nprintf(\"%s%d\\n\", \"a[1] = \", a[1]);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_loop_step() {
+        assert_eq!(
+            compile_source_to_c("loop i in 10 step 1 + 1 { print i; };"),
+            "#include <stdio.h>\nint main() {\n{\nint _count = 10;\nint _step = 1 + 1;\nfor (int i = 0; i < _count; i += _step) {\nprintf(\"%d\\n\", i);\n}\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_range_loop() {
+        assert_eq!(
+            compile_source_to_c("loop i from 1 to 5 { print i; };"),
+            "#include <stdio.h>\nint main() {\n{\nint _start = 1;\nint _end = 5;\nif (_start <= _end) {\nfor (int i = _start; ; i++) {\nprintf(\"%d\\n\", i);\nif (i == _end) break;\n}\n}\n}\nreturn 0;\n}\n"
+        );
+        // The counter never steps past the end, which could overflow.
+        assert_eq!(
+            compile_source_to_c("let n = 1; loop i from n to 2147483647 step 2 { print i; }"),
+            "#include <stdio.h>\nint main() {\nint n = 1;\n{\nint _start = n;\nint _end = 2147483647;\nint _step = 2;\nif (_start <= _end) {\nfor (int i = _start; ; i += _step) {\nprintf(\"%d\\n\", i);\nif ((unsigned int)_end - (unsigned int)i < (unsigned int)_step) break;\n}\n}\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_repeat_until() {
+        assert_eq!(
+            compile_source_to_c("let x = 10; repeat { print x; } until x > 3;"),
+            "#include <stdio.h>\nint main() {\nint x = 10;\ndo {\nprintf(\"%d\\n\", x);\n} while (!(x > 3));\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_string_length() {
+        assert_eq!(
+            compile_source_to_c("loop len(\"hi\") { print len(\"a b\"); }"),
+            "#include <stdio.h>\nint main() {\n{\nint _count = 2;\nfor (int _ = 0; _ < _count; _++) {\nprintf(\"%d\\n\", 3);\n}\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_block_expression() {
+        assert_eq!(
+            compile_source_to_c("let x = { let t = 1; t + 2 };"),
+            "#include <stdio.h>\nint main() {\nint x = ({\nint t = 1;\nt + 2;\n});\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_intrinsics() {
+        assert_eq!(
+            compile_source_to_c("let x = 3; print min(x, 2) + max(x + 1, 4) * abs(x - 5);"),
+            "#include <stdio.h>\nint main() {\nint x = 3;\nprintf(\"%d\\n\", (x < 2 ? x : 2) + ((x + 1) > 4 ? (x + 1) : 4) * ((x - 5) < 0 ? -(x - 5) : (x - 5)));\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_min_of_and_max_of() {
+        // Constant arguments fold to the result.
+        assert_eq!(
+            compile_source_to_c("const N = 4; print max_of(1, 5, 3) + min_of(N, 2 * 3);"),
+            "#include <stdio.h>\nint main() {\nprintf(\"%d\\n\", 5 + 4);\nreturn 0;\n}\n"
+        );
+        assert_eq!(
+            compile_source_to_c("let x = 3; print max_of(x, 5, x + 1);"),
+            "#include <stdio.h>\nstatic int _hand_max(int a, int b) {\nreturn a > b ? a : b;\n}\nint main() {\nint x = 3;\nprintf(\"%d\\n\", _hand_max(_hand_max(x, 5), x + 1));\nreturn 0;\n}\n"
+        );
+        // One argument needs no helper.
+        assert!(!compile_source_to_c("let x = 3; print min_of(x);").contains("_hand_min"));
+    }
+
+    #[test]
+    fn test_let_type_annotation() {
+        let source = "let x: int = 5; let b: bool = x < 3;";
+        let lexer = Lexer::new(source.to_string());
+        let mut parser = Parser::new(lexer.collect());
+        let ast = parser.parse();
+        let options = CodegenOptions {
+            int_width: IntWidth::Bits64,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            generate_c_code_with_options(&ast, &options),
+            "#include <stdio.h>\nint main() {\nlong long x = 5LL;\nint b = x < 3LL;\nreturn 0;\n}\n"
+        );
+        assert_eq!(
+            generate_c_code_with_options(
+                &ast,
+                &CodegenOptions {
+                    c89: true,
+                    ..options
+                }
+            ),
+            "#include <stdio.h>\nint main() {\nlong long x;\nint b;\nx = 5LL;\nb = x < 3LL;\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        assert_eq!(
+            compile_source_to_c("print 0xFF + 1; print 0b1010 | 0x10;"),
+            "#include <stdio.h>\nint main() {\nprintf(\"%d\\n\", 255 + 1);\nprintf(\"%d\\n\", 10 | 16);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_else_if_chain_is_flat() {
+        assert_eq!(
+            compile_source_to_c(
+                "let x = 5; if x < 0 { print 0; } else if x < 10 { print 1; } else { print 2; }"
+            ),
+            "#include <stdio.h>\nint main() {\nint x = 5;\nif (x < 0) {\nprintf(\"%d\\n\", 0);\n} else if (x < 10) {\nprintf(\"%d\\n\", 1);\n} else {\nprintf(\"%d\\n\", 2);\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_const_is_folded_into_uses() {
+        assert_eq!(
+            compile_source_to_c("const N = 2 + 1; let x = N * 2; loop N { print x; }"),
+            "#include <stdio.h>\nint main() {\nint x = 3 * 2;\n{\nint _count = 3;\nfor (int _ = 0; _ < _count; _++) {\nprintf(\"%d\\n\", x);\n}\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_block_const_is_folded_only_in_its_block() {
+        assert_eq!(
+            compile_source_to_c("loop 2 { const K = 2; print K; } let K = 5; print K;"),
+            "#include <stdio.h>\nint main() {\n{\nint _count = 2;\nfor (int _ = 0; _ < _count; _++) {\nprintf(\"%d\\n\", 2);\n}\n}\nint K = 5;\nprintf(\"%d\\n\", K);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        assert_eq!(
+            compile_source_to_c("let x = 5; assert x > 0 && x < 10 || x == 20;"),
+            compile_source_to_c("let x = 5; assert ((x > 0) && (x < 10)) || (x == 20);")
+        );
+        assert!(
+            compile_source_to_c("let x = 5; assert x > 0 && x < 10 || x == 20;")
+                .contains("x > 0 && x < 10 || x == 20")
+        );
+        assert!(
+            compile_source_to_c("let x = 5; assert x > 0 && (x < 10 || x == 20);")
+                .contains("x > 0 && (x < 10 || x == 20)")
+        );
+    }
+
+    #[test]
+    fn test_print_label_and_value() {
+        assert_eq!(
+            compile_source_to_c("let x = 5; print \"x =\", x;"),
+            "#include <stdio.h>\nint main() {\nint x = 5;\nprintf(\"%s%d\\n\", \"x =\", x);\nreturn 0;\n}\n"
+        );
+        assert!(
+            compile_source_to_c("let x = 5; printx x, \" is \\ hex\";")
+                .contains("printf(\"%x%s\\n\", x, \" is \\\\ hex\");")
+        );
+    }
+
+    #[test]
+    fn test_overflow_wrap() {
+        let ast = Parser::new(Lexer::new("let x = 2147483647; print x + 1;".to_string()).collect())
+            .parse();
+        let options = CodegenOptions {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            generate_c_code_with_options(&ast, &options),
+            "#include <stdio.h>\nstatic int _hand_add(int a, int b) {\nreturn (int)((unsigned int)a + (unsigned int)b);\n}\nint main() {\nint x = 2147483647;\nprintf(\"%d\\n\", _hand_add(x, 1));\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_overflow_checked() {
+        let ast = Parser::new(Lexer::new("let x = 2147483647; print x + 1;".to_string()).collect())
+            .parse();
+        let options = CodegenOptions {
+            overflow: Overflow::Checked,
+            ..Default::default()
+        };
+        let code = generate_c_code_with_options(&ast, &options);
+
+        assert!(code.starts_with("#include <stdio.h>\n#include <limits.h>\n#include <stdlib.h>\nstatic int _hand_add(int a, int b) {\nif ((b > 0 && a > INT_MAX - b) || (b < 0 && a < INT_MIN - b)) {\nfprintf(stderr, \"integer overflow\\n\");\nfflush(stdout);\nabort();\n}\nreturn a + b;\n}\n"));
+        assert!(code.contains("printf(\"%d\\n\", _hand_add(x, 1));"));
+        // Only the helpers the program uses are emitted.
+        assert!(!code.contains("_hand_sub"));
+        assert_eq!(
+            generate_c_code_with_options(&ast, &CodegenOptions::default()),
+            "#include <stdio.h>\nint main() {\nint x = 2147483647;\nprintf(\"%d\\n\", x + 1);\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_optimize_drops_zero_count_loops() {
+        let compile = |source: &str, optimize: bool| {
+            let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+            SemanticAnalyzer::analyze(&ast).unwrap();
+            let options = CodegenOptions {
+                optimize,
+                ..Default::default()
+            };
+            generate_c_code_with_options(&ast, &options)
+        };
+
+        assert_eq!(
+            compile("let x = 1; loop 0 { print x; }", true),
+            "#include <stdio.h>\nint main() {\nint x = 1;\nreturn 0;\n}\n"
+        );
+        assert!(compile("let x = 1; loop 0 { print x; }", false).contains("for ("));
+        assert!(
+            !compile("const N = 2; loop N - 2 { let y = 1; } loop 3 - 5 {}", true)
+                .contains("for (")
+        );
+        assert!(compile("let x = 0; loop x { print x; }", true).contains("for ("));
+        assert!(compile("loop 1 { print 1; }", true).contains("for ("));
+    }
+
+    #[test]
+    fn test_suppress_unused() {
+        let compile = |source: &str, suppress_unused: bool, c89: bool| {
+            let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+            SemanticAnalyzer::analyze(&ast).unwrap();
+            let options = CodegenOptions {
+                suppress_unused,
+                c89,
+                ..Default::default()
+            };
+            generate_c_code_with_options(&ast, &options)
+        };
+        let source = "let mut x = 1; let y = 2; x = 3; let mut a = [1, 2]; a[0] = y;";
+
+        assert_eq!(
+            compile(source, true, false),
+            "#include <stdio.h>\nint main() {\nint x = 1;\n(void)x;\nint y = 2;\nx = 3;\nint a[2] = {1, 2};\n(void)a;\na[0] = y;\nreturn 0;\n}\n"
+        );
+        assert!(compile(source, true, true).contains("x = 1;\n(void)x;\n"));
+        assert!(!compile(source, false, false).contains("(void)"));
+    }
+
+    #[test]
+    fn test_sibling_blocks_declare_independently() {
+        let compile = |c89: bool| {
+            let source =
+                "let c = 1; if c > 0 { let x = c < 2; print x; } else { let x = 5; print x; }";
+            let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+            SemanticAnalyzer::analyze(&ast).unwrap();
+            let options = CodegenOptions {
+                c89,
+                bool_words: true,
+                ..Default::default()
+            };
+            generate_c_code_with_options(&ast, &options)
+        };
+
+        // The `x` in the else branch is an int even though the one before it
+        // was a bool.
+        assert_eq!(
+            compile(false),
+            "#include <stdio.h>\nint main() {\nint c = 1;\nif (c > 0) {\nint x = c < 2;\nprintf(\"%s\\n\", x ? \"true\" : \"false\");\n} else {\nint x = 5;\nprintf(\"%d\\n\", x);\n}\nreturn 0;\n}\n"
+        );
+        assert_eq!(
+            compile(true),
+            "#include <stdio.h>\nint main() {\nint c;\nc = 1;\nif (c > 0) {\nint x;\nx = c < 2;\nprintf(\"%s\\n\", x ? \"true\" : \"false\");\n} else {\nint x;\nx = 5;\nprintf(\"%d\\n\", x);\n}\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let code = compile_source_to_c(
+            "let a = 1; let b = 2; let n = write(\"sum=\", a + b); print write(a < b, n);",
+        );
+        assert!(code.contains("int n = printf(\"%s%d\\n\", \"sum=\", a + b);\n"));
+        assert!(code.contains("printf(\"%d\\n\", printf(\"%d%d\\n\", a < b, n));\n"));
+    }
+
+    #[test]
+    fn test_bare_print() {
+        assert_eq!(
+            compile_source_to_c("print; print \"a\";"),
+            "#include <stdio.h>\nint main() {\nprintf(\"\\n\");\nprintf(\"%s\\n\", \"a\");\nreturn 0;\n}\n"
+        );
+        let tokens = Lexer::new("printx;".to_string()).collect();
+        assert!(Parser::new(tokens).try_parse().is_err());
+    }
+
+    #[test]
+    fn test_negation() {
+        assert_eq!(
+            compile_source_to_c("let x = -2147483648; print -x - -(x + 1); print abs(-x);"),
+            "#include <stdio.h>\nint main() {\nint x = (-2147483647 - 1);\nprintf(\"%d\\n\", -x - -(x + 1));\nprintf(\"%d\\n\", (-x < 0 ? -(-x) : -x));\nreturn 0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_bool_words() {
+        let compile = |source: &str| {
+            let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+            SemanticAnalyzer::analyze(&ast).unwrap();
+            let options = CodegenOptions {
+                bool_words: true,
+                ..Default::default()
+            };
+            generate_c_code_with_options(&ast, &options)
+        };
+
+        let code =
+            compile("let x = 1; let y = 2; let b = x == y; print (x == y); print b; print x;");
+        assert!(code.contains("printf(\"%s\\n\", x == y ? \"true\" : \"false\");"));
+        assert!(code.contains("printf(\"%s\\n\", b ? \"true\" : \"false\");"));
+        assert!(code.contains("printf(\"%d\\n\", x);"));
+
+        // A loop counter shadowing a bool is an int.
+        let code = compile("let b = 1 < 2; loop b in 2 { print b; }");
+        assert!(code.contains("printf(\"%d\\n\", b);"));
+        assert!(!compile("let x = 1 < 2; print x;").contains("%d"));
+        assert!(compile("let x = 1 < 2; printx x;").contains("printf(\"%x\\n\", x);"));
+
+        let code = compile("let b: bool = true; print b, false;");
+        assert!(code.contains("int b = 1;"));
+        assert!(
+            code.contains(
+                "printf(\"%s%s\\n\", b ? \"true\" : \"false\", 0 ? \"true\" : \"false\");"
+            )
+        );
+    }
 }
@@ -0,0 +1,238 @@
+use crate::ast::{BinaryOperator, Expr, ExprKind, Intrinsic, Term};
+
+/// The size of the integer type used for every value in a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntWidth {
+    #[default]
+    Bits32,
+    Bits64,
+}
+
+impl IntWidth {
+    pub fn bits(self) -> u32 {
+        match self {
+            IntWidth::Bits32 => 32,
+            IntWidth::Bits64 => 64,
+        }
+    }
+
+    pub fn min(self) -> i64 {
+        match self {
+            IntWidth::Bits32 => i32::MIN.into(),
+            IntWidth::Bits64 => i64::MIN,
+        }
+    }
+
+    pub fn max(self) -> i64 {
+        match self {
+            IntWidth::Bits32 => i32::MAX.into(),
+            IntWidth::Bits64 => i64::MAX,
+        }
+    }
+
+    pub fn fits(self, value: i64) -> bool {
+        (self.min()..=self.max()).contains(&value)
+    }
+}
+
+/// Evaluates `expr` at compile time using integers of the given width.
+///
+/// Comparisons evaluate to `1` or `0`, as they do in C. Returns `None` if the
+/// expression depends on a variable, or if evaluating it would overflow or
+/// divide by zero.
+pub fn eval_const(expr: &Expr, width: IntWidth) -> Option<i64> {
+    eval_const_with(expr, width, &|_| None)
+}
+
+/// Like `eval_const`, also folding every identifier `constant` knows the
+/// value of, such as a top-level `const`.
+pub fn eval_const_with(
+    expr: &Expr,
+    width: IntWidth,
+    constant: &dyn Fn(&str) -> Option<i64>,
+) -> Option<i64> {
+    let value = match &expr.kind {
+        ExprKind::Term(Term::Number(n)) => *n,
+        ExprKind::Term(Term::Bool(value)) => i64::from(*value),
+        ExprKind::Term(Term::Identifier(identifier)) => constant(&identifier.name)?,
+        ExprKind::Term(Term::Index { .. }) | ExprKind::Write(_) => return None,
+        ExprKind::Negate(operand) => eval_const_with(operand, width, constant)?.checked_neg()?,
+        ExprKind::StringLength(text) => i64::try_from(text.len()).ok()?,
+        // Statements could declare names the value depends on.
+        ExprKind::Block(block) if block.statements.statements.is_empty() => {
+            eval_const_with(&block.value, width, constant)?
+        }
+        ExprKind::Block(_) => return None,
+        ExprKind::Call(call) => {
+            let arguments = call
+                .arguments
+                .iter()
+                .map(|argument| eval_const_with(argument, width, constant))
+                .collect::<Option<Vec<_>>>()?;
+            match (
+                Intrinsic::from_name(&call.function.name)?,
+                arguments.as_slice(),
+            ) {
+                (Intrinsic::Min, &[a, b]) => a.min(b),
+                (Intrinsic::Max, &[a, b]) => a.max(b),
+                (Intrinsic::Abs, &[a]) => a.checked_abs()?,
+                (Intrinsic::MinOf, arguments) => *arguments.iter().min()?,
+                (Intrinsic::MaxOf, arguments) => *arguments.iter().max()?,
+                _ => return None,
+            }
+        }
+        ExprKind::Binary(binary) => {
+            let lhs = eval_const_with(&binary.lhs, width, constant)?;
+            let rhs = eval_const_with(&binary.rhs, width, constant)?;
+            match binary.operator {
+                BinaryOperator::Add => lhs.checked_add(rhs)?,
+                BinaryOperator::Subtract => lhs.checked_sub(rhs)?,
+                BinaryOperator::Multiply => lhs.checked_mul(rhs)?,
+                BinaryOperator::Divide => lhs.checked_div(rhs)?,
+                BinaryOperator::Modulo => lhs.checked_rem(rhs)?,
+                BinaryOperator::Power => power(lhs, rhs)?,
+                BinaryOperator::BitwiseAnd => lhs & rhs,
+                BinaryOperator::BitwiseOr => lhs | rhs,
+                BinaryOperator::LogicalAnd => (lhs != 0 && rhs != 0) as i64,
+                BinaryOperator::LogicalOr => (lhs != 0 || rhs != 0) as i64,
+                BinaryOperator::BitwiseXor => lhs ^ rhs,
+                BinaryOperator::ShiftLeft => shift_left(lhs, rhs, width)?,
+                BinaryOperator::ShiftRight => shift_right(lhs, rhs, width)?,
+                BinaryOperator::Equal => (lhs == rhs) as i64,
+                BinaryOperator::NotEqual => (lhs != rhs) as i64,
+                BinaryOperator::Less => (lhs < rhs) as i64,
+                BinaryOperator::LessEqual => (lhs <= rhs) as i64,
+                BinaryOperator::Greater => (lhs > rhs) as i64,
+                BinaryOperator::GreaterEqual => (lhs >= rhs) as i64,
+            }
+        }
+    };
+    width.fits(value).then_some(value)
+}
+
+/// `lhs ** rhs`, matching the generated `_hand_pow`: a negative exponent
+/// multiplies zero times and gives 1.
+fn power(lhs: i64, rhs: i64) -> Option<i64> {
+    if rhs < 0 {
+        return Some(1);
+    }
+    lhs.checked_pow(u32::try_from(rhs).ok()?)
+}
+
+/// Left shift with C's rules: a negative operand, an out-of-range shift
+/// amount, or a result that doesn't fit are all undefined.
+fn shift_left(lhs: i64, rhs: i64, width: IntWidth) -> Option<i64> {
+    if lhs < 0 || !(0..i64::from(width.bits())).contains(&rhs) {
+        return None;
+    }
+    i64::try_from(i128::from(lhs) << rhs).ok()
+}
+
+fn shift_right(lhs: i64, rhs: i64, width: IntWidth) -> Option<i64> {
+    if !(0..i64::from(width.bits())).contains(&rhs) {
+        return None;
+    }
+    Some(lhs >> rhs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn eval_source(source: &str) -> Option<i64> {
+        eval_source_with_width(source, IntWidth::Bits32)
+    }
+
+    fn eval_source_with_width(source: &str, width: IntWidth) -> Option<i64> {
+        let lexer = Lexer::new(source.to_string());
+        let mut parser = Parser::new(lexer.collect());
+        eval_const(&parser.parse_expression().unwrap(), width)
+    }
+
+    #[test]
+    fn test_nested_constant_expression() {
+        assert_eq!(eval_source("(1 + 2) * (10 - 4) / 3 % 5"), Some(1));
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert_eq!(eval_source("1 + 1 == 2"), Some(1));
+        assert_eq!(eval_source("3 < 2"), Some(0));
+    }
+
+    #[test]
+    fn test_bitwise() {
+        assert_eq!(eval_source("1 | 2 & 3"), Some(3));
+        assert_eq!(eval_source("1 << 2 + 3"), Some(32));
+        assert_eq!(eval_source("6 ^ 3 >> 1"), Some(7));
+        assert_eq!(eval_source("1 << 31"), None);
+        assert_eq!(eval_source("1 >> 32"), None);
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(eval_source("2 * 3 ** 2"), Some(18));
+        assert_eq!(eval_source("2 ** 3 ** 2"), Some(512));
+        assert_eq!(eval_source("5 ** (0 - 1)"), Some(1));
+        assert_eq!(eval_source("2 ** 31"), None);
+    }
+
+    #[test]
+    fn test_intrinsics() {
+        assert_eq!(eval_source("min(3, 1 + 1) + max(3, 4)"), Some(6));
+        assert_eq!(eval_source("abs(2 - 7)"), Some(5));
+        assert_eq!(eval_source("abs(0 - 2147483647 - 1)"), None);
+        assert_eq!(eval_source("len(\"hello\") * 2 + len(\"\")"), Some(10));
+        assert_eq!(eval_source("max_of(1, 5, 3) - min_of(4, 2 * 3)"), Some(1));
+        assert_eq!(eval_source("max_of(1, x)"), None);
+    }
+
+    #[test]
+    fn test_variable_is_not_constant() {
+        assert_eq!(eval_source("1 + x"), None);
+    }
+
+    #[test]
+    fn test_overflow_returns_none() {
+        assert_eq!(eval_source("2147483647 + 1"), None);
+        assert_eq!(eval_source("65536 * 65536"), None);
+    }
+
+    #[test]
+    fn test_width_64() {
+        assert_eq!(
+            eval_source_with_width("2147483647 + 1", IntWidth::Bits64),
+            Some(2147483648)
+        );
+        assert_eq!(
+            eval_source_with_width("1 << 40", IntWidth::Bits64),
+            Some(1 << 40)
+        );
+        assert_eq!(
+            eval_source_with_width("9223372036854775807 + 1", IntWidth::Bits64),
+            None
+        );
+        assert_eq!(eval_source("3000000000"), None);
+    }
+
+    #[test]
+    fn test_division_by_zero_returns_none() {
+        assert_eq!(eval_source("1 / (2 - 2)"), None);
+    }
+
+    #[test]
+    fn test_radix_literals_fold_to_their_value() {
+        assert_eq!(eval_source("0xFF + 1"), Some(256));
+        assert_eq!(eval_source("0b1010 * 0x10 - 10"), Some(150));
+        assert_eq!(eval_source("0xFF == 255"), Some(1));
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        assert_eq!(eval_source("1 < 2 && 2 < 3"), Some(1));
+        assert_eq!(eval_source("1 < 2 && 3 < 2"), Some(0));
+        assert_eq!(eval_source("3 < 2 || 1 == 1"), Some(1));
+    }
+}
@@ -0,0 +1,569 @@
+use std::fmt;
+
+use crate::ast::Intrinsic;
+use crate::const_eval::IntWidth;
+use crate::parser::ParseError;
+use crate::semantic_analyzer::{SemanticError, SemanticWarning};
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    /// `Error` or `Warning`, as it starts a human-readable message. With
+    /// `color` it's bold red or yellow.
+    pub fn label(self, color: bool) -> String {
+        let (text, code) = match self {
+            Severity::Error => ("Error", "1;31"),
+            Severity::Warning => ("Warning", "1;33"),
+        };
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// When to color human-readable diagnostics, from `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorChoice {
+    /// Color only when writing to a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Whether to color output going to a stream that is, or isn't, a
+    /// terminal.
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A semantic error or warning rendered to its user-facing message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The error code, such as `E0001`; warnings have none.
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// `int_width` is the width the program was checked with, which the
+    /// out-of-range message mentions.
+    pub fn from_error(error: &SemanticError, int_width: IntWidth) -> Self {
+        let message = match error {
+            SemanticError::UndeclaredVariable { name, .. } => {
+                format!("Use of undeclared variable '{}'", name)
+            }
+            SemanticError::DivisionByZero { .. } => "Division by zero".to_string(),
+            SemanticError::TypeMismatch {
+                expected, found, ..
+            } => format!("Expected {}, found {}", expected, found),
+            SemanticError::UnresolvedInclude { path, .. } => {
+                format!("Cannot resolve include '{}'", path)
+            }
+            SemanticError::IntegerOutOfRange { value, .. } => format!(
+                "Integer literal {} does not fit in a {}-bit integer",
+                value,
+                int_width.bits()
+            ),
+            SemanticError::NotAnArray { name, .. } => format!("'{}' is not an array", name),
+            SemanticError::UnknownFunction { name, .. } => {
+                format!("Unknown function '{}'", name)
+            }
+            SemanticError::WrongArgumentCount {
+                name,
+                expected,
+                found,
+                ..
+            } => {
+                let at_least = match Intrinsic::from_name(name) {
+                    Some(intrinsic) if intrinsic.is_variadic() => "at least ",
+                    _ => "",
+                };
+                format!(
+                    "'{}' takes {}{} argument(s), found {}",
+                    name, at_least, expected, found
+                )
+            }
+            SemanticError::NonPositiveStep { value, .. } => {
+                format!("Loop step must be positive, found {}", value)
+            }
+            SemanticError::ChainedComparison { .. } => {
+                "Comparisons can't be chained; write `a < b && b < c` instead".to_string()
+            }
+            SemanticError::AssignToConst { name, .. } => {
+                format!(
+                    "'{}' is a constant and can't be assigned or redeclared",
+                    name
+                )
+            }
+            SemanticError::NotConstant { name, .. } => {
+                format!("Value of constant '{}' must be known at compile time", name)
+            }
+            SemanticError::WriteInArgument { function, .. } => {
+                format!("'write' can't be used in the arguments of '{}'", function)
+            }
+            SemanticError::LoopBoundOverflow { .. } => {
+                format!("Loop counter overflows a {}-bit integer", int_width.bits())
+            }
+            SemanticError::AssignToImmutable { name, .. } => {
+                format!("'{}' isn't declared `mut`, so it can't be assigned", name)
+            }
+            SemanticError::BreakOutsideLoop { .. } => "`break` outside of a loop".to_string(),
+            SemanticError::ShiftOutOfRange { amount, .. } => format!(
+                "Shift amount {} is out of range for a {}-bit integer",
+                amount,
+                int_width.bits()
+            ),
+            SemanticError::StatementInArgument { function, .. } => format!(
+                "A block in the arguments of '{}' can only declare variables",
+                function
+            ),
+            SemanticError::UnknownModule { name, .. } => format!("Unknown module '{}'", name),
+            SemanticError::UnknownMember { module, member, .. } => {
+                format!("Module '{}' has no const '{}'", module, member)
+            }
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            code: Some(error.code()),
+            message,
+            span: error.span(),
+        }
+    }
+
+    pub fn from_warning(warning: &SemanticWarning) -> Self {
+        let message = match warning {
+            SemanticWarning::UnreachableCode { after, .. } => {
+                format!("Unreachable code after '{}'", after)
+            }
+            SemanticWarning::IndexOutOfBounds { index, length, .. } => format!(
+                "Index {} is out of bounds for an array of length {}",
+                index, length
+            ),
+            SemanticWarning::SelfAssignment { name, .. } => {
+                format!("Assigning '{}' to itself has no effect", name)
+            }
+            SemanticWarning::UseOfUninitialized { name, .. } => {
+                format!("'{}' is read before anything is assigned to it", name)
+            }
+            SemanticWarning::CharOutOfRange { value, .. } => format!(
+                "Character code {} is outside 0 to 127, so what it prints depends on the platform",
+                value
+            ),
+            SemanticWarning::PossibleInfiniteLoop { .. } => {
+                "The condition is never true and nothing breaks out, so the loop never ends"
+                    .to_string()
+            }
+            SemanticWarning::EmptyRange { from, to, .. } => format!(
+                "The range {} to {} is empty, so the loop never runs",
+                from, to
+            ),
+        };
+        Diagnostic {
+            severity: Severity::Warning,
+            code: None,
+            message,
+            span: warning.span(),
+        }
+    }
+
+    /// A syntax error or invalid token, which the parser reports.
+    pub fn from_parse_error(error: &ParseError) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: Some(error.code()),
+            message: error.to_string(),
+            span: error.span,
+        }
+    }
+
+    /// The diagnostic as one line for people to read, such as
+    /// `Error at main.hand:2:7: Use of undeclared variable 'y' [E0001]`.
+    pub fn to_human(&self, file_path: &str, color: bool) -> String {
+        let code = match self.code {
+            Some(code) => format!(" [{}]", code),
+            None => String::new(),
+        };
+        format!(
+            "{} at {}:{}:{}: {}{}",
+            self.severity.label(color),
+            file_path,
+            self.span.line,
+            self.span.column,
+            self.message,
+            code
+        )
+    }
+
+    /// The diagnostic as a single-line JSON object, for `--error-format json`.
+    pub fn to_json(&self) -> String {
+        let code = match self.code {
+            Some(code) => json_string(code),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"line\":{},\"column\":{},\"span\":{{\"start\":{},\"end\":{}}}}}",
+            self.severity,
+            code,
+            json_string(&self.message),
+            self.span.line,
+            self.span.column,
+            self.span.start,
+            self.span.end
+        )
+    }
+}
+
+/// The longer description `--explain` prints for an error code, starting
+/// with a one-line header such as `E0001: use of an undeclared variable`.
+pub fn explain(code: &str) -> Option<&'static str> {
+    let text = match code {
+        "E0001" => {
+            "E0001: use of an undeclared variable
+
+A variable was used before any `let` declared it, or outside the block that
+declares it. Every block is its own scope.
+
+    loop 3 {
+        let i = 1;
+    }
+    print i;  // error: `i` only exists inside the loop
+"
+        }
+        "E0002" => {
+            "E0002: division by zero
+
+The right-hand side of `/` or `%` is known at compile time to be zero.
+
+    let x = 10 / (2 - 2);  // error
+"
+        }
+        "E0003" => {
+            "E0003: type mismatch
+
+A value has a type its context doesn't accept, such as a bool where an int
+is needed, or an initializer that doesn't match the `let`'s annotation.
+
+    let x: bool = 5;  // error: expected bool, found int
+"
+        }
+        "E0004" => {
+            "E0004: unresolved include
+
+An `include` reached semantic analysis without being replaced by the file's
+statements. The compiler loads includes before analysis, so this only
+happens when a parsed program is analyzed directly through the library.
+
+    include \"lib.hand\";  // error unless loaded first
+"
+        }
+        "E0005" => {
+            "E0005: integer literal out of range
+
+A literal doesn't fit the integer width, 32 bits unless `--int-width 64` is
+given.
+
+    let x = 2147483648;  // error with 32-bit ints
+"
+        }
+        "E0006" => {
+            "E0006: not an array
+
+A variable was indexed, or an array used whole, the wrong way round: only
+arrays can be indexed, and an array can only be used one element at a time.
+
+    let x = 1;
+    print x[0];  // error: `x` isn't an array
+"
+        }
+        "E0007" => {
+            "E0007: unknown function
+
+Only the built-in functions `min`, `max`, `abs`, `min_of`, `max_of`, `len`
+and `write` can be called.
+
+    print square(3);  // error
+"
+        }
+        "E0008" => {
+            "E0008: wrong number of arguments
+
+A built-in function was called with too many or too few arguments.
+
+    print min(1);  // error: `min` takes 2 arguments
+"
+        }
+        "E0009" => {
+            "E0009: step isn't positive
+
+A loop's `step` is known at compile time to be zero or negative, so the loop
+would never finish.
+
+    loop i in 10 step 0 { print i; }  // error
+"
+        }
+        "E0010" => {
+            "E0010: chained comparison
+
+Comparisons can't be chained, since C would read `a < b < c` as
+`(a < b) < c`. Combine them with `&&` instead.
+
+    assert a < b < c;        // error
+    assert a < b && b < c;   // ok
+"
+        }
+        "E0011" => {
+            "E0011: assignment to a constant
+
+A constant can't be assigned to, or shadowed by a variable of the same name.
+
+    const N = 3;
+    N = 4;  // error
+"
+        }
+        "E0012" => {
+            "E0012: value isn't constant
+
+A `const` must be computable from literals at compile time.
+
+    let x = 1;
+    const N = x + 1;  // error
+"
+        }
+        "E0013" => {
+            "E0013: write in a function argument
+
+`min`, `max` and `abs` may evaluate their arguments more than once, so a
+`write` inside them could print twice.
+
+    print abs(write(1));  // error
+"
+        }
+        "E0014" => {
+            "E0014: statement in a function argument
+
+`min`, `max` and `abs` may evaluate their arguments more than once, so a
+block expression inside them can only declare variables and constants. A
+statement such as `print` or an assignment could run twice.
+
+    print min({ let t = 2; t }, 3);   // ok
+    print min({ print 1; 2 }, 3);     // error
+"
+        }
+        "E0015" => {
+            "E0015: loop counter overflow
+
+A loop's constant bounds don't fit the integer width, or counting up to
+them would take the counter past the largest int before the loop ends. In
+C the counter would overflow, which is undefined behavior.
+
+    loop 2147483647 { }                  // ok: the counter stops at the count
+    loop i from 0 to 2147483647 { }      // ok: `i` stops on the end
+    loop 2147483647 + 1 { }              // error with 32-bit ints
+    loop i from 0 to 2147483647 + 1 { }  // error with 32-bit ints
+"
+        }
+        "E0016" => {
+            "E0016: assignment to an immutable variable
+
+Variables are immutable unless declared with `let mut`. Assigning to one,
+to an element of an immutable array, or reading input into one is an
+error. A loop counter is never mutable; the loop advances it.
+
+    let x = 1;
+    x = 2;      // error
+    let mut y = 1;
+    y = 2;      // ok
+"
+        }
+        "E0017" => {
+            "E0017: `break` outside of a loop
+
+`break` leaves the innermost loop, so it can only appear in the body of a
+`loop` or `repeat`. A block used as a value, such as `{ break; 1 }`, can't
+contain one even inside a loop.
+
+    break;              // error
+    loop 3 {
+        break if 1 < 2; // ok
+    }
+"
+        }
+        "E0018" => {
+            "E0018: shift amount out of range
+
+A constant shift amount must be at least 0 and less than the integer
+width. C leaves other amounts undefined. An amount that isn't constant is
+taken modulo the width instead, on every backend.
+
+    print 1 << 31;  // ok
+    print 1 << 32;  // error with 32-bit ints
+"
+        }
+        "E0019" => {
+            "E0019: unknown module
+
+`use math;` imports the consts of `math.hand`, found next to the file that
+uses it. It's an error if there is no such file, and using a qualified name
+such as `math.PI` without a `use math;` before it is an error too.
+
+    use math;      // error if there's no math.hand
+    print util.N;  // error without `use util;`
+"
+        }
+        "E0020" => {
+            "E0020: unknown module member
+
+A qualified name such as `math.PI` must name a top-level `const` of the
+module. Nothing else a module declares is imported.
+
+    use math;         // math.hand declares `const PI = 3;`
+    print math.PI;    // ok
+    print math.TAU;   // error
+"
+        }
+        "E0100" => {
+            "E0100: syntax error
+
+The parser found a token where the grammar doesn't allow one, such as a
+missing `;` or an unclosed `{`.
+
+    let x = 1
+    print x;  // error: expected `;`
+"
+        }
+        "E0101" => {
+            "E0101: invalid token
+
+The source contains text that isn't part of any token, such as a stray
+character or a block comment that's never closed.
+
+    let x = 1 $ 2;  // error
+"
+        }
+        "E0102" => {
+            "E0102: expression nested too deeply
+
+An expression nests more than 256 levels deep, counting parentheses, blocks
+used as values and each operator in a chain such as `1 + 1 + ...`. The
+compiler walks expressions recursively, so it stops here rather than run out
+of stack. Split the expression up with intermediate variables.
+
+    let sum = 1 + 1 + 1 /* ...300 terms... */;  // error
+    let half = 1 + 1 /* ...150 terms... */;
+    let sum = half + 1 + 1 /* ...150 terms... */;  // ok
+"
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// `text` as a quoted JSON string literal.
+pub(crate) fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+
+    #[test]
+    fn test_undeclared_variable_to_json() {
+        let lexer = Lexer::new("let x = 1;\nprint y;".to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+        let analysis = SemanticAnalyzer::check(&ast, IntWidth::Bits32);
+        let diagnostic = Diagnostic::from_error(&analysis.errors[0], IntWidth::Bits32);
+
+        assert_eq!(
+            diagnostic.to_json(),
+            "{\"severity\":\"error\",\"code\":\"E0001\",\"message\":\"Use of undeclared variable 'y'\",\"line\":2,\"column\":7,\"span\":{\"start\":17,\"end\":18}}"
+        );
+    }
+
+    #[test]
+    fn test_human_format_color() {
+        let lexer = Lexer::new("let x = 1;\nprint y;".to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+        let analysis = SemanticAnalyzer::check(&ast, IntWidth::Bits32);
+        let diagnostic = Diagnostic::from_error(&analysis.errors[0], IntWidth::Bits32);
+
+        assert_eq!(
+            diagnostic.to_human("main.hand", false),
+            "Error at main.hand:2:7: Use of undeclared variable 'y' [E0001]"
+        );
+        assert_eq!(
+            diagnostic.to_human("main.hand", true),
+            "\x1b[1;31mError\x1b[0m at main.hand:2:7: Use of undeclared variable 'y' [E0001]"
+        );
+        assert_eq!(Severity::Warning.label(true), "\x1b[1;33mWarning\x1b[0m");
+        assert!(!ColorChoice::Never.enabled(true));
+        assert!(ColorChoice::Auto.enabled(true) && !ColorChoice::Auto.enabled(false));
+    }
+
+    #[test]
+    fn test_explain() {
+        assert!(
+            explain("E0001")
+                .unwrap()
+                .starts_with("E0001: use of an undeclared variable\n")
+        );
+        assert_eq!(explain("E9999"), None);
+    }
+
+    #[test]
+    fn test_json_string_escapes() {
+        assert_eq!(
+            json_string("a \"b\" \\ c\n\u{1}"),
+            "\"a \\\"b\\\" \\\\ c\\n\\u0001\""
+        );
+    }
+}
@@ -0,0 +1,344 @@
+use crate::ast::{
+    AbstractSyntaxTree, Block, ConstStatement, ElseBranch, Identifier, IfStatement, LoopStatement,
+    RepeatStatement, Statement, StatementList, UseStatement,
+};
+use crate::lexer::Lexer;
+use crate::parser::ParseError;
+use crate::timings::{Timings, lex_and_parse_timed};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum IncludeError {
+    Io { path: PathBuf, error: io::Error },
+    Cycle { path: PathBuf },
+    Parse { path: PathBuf, error: ParseError },
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IncludeError::Io { path, error } => {
+                write!(f, "Could not read '{}': {}", path.display(), error)
+            }
+            IncludeError::Cycle { path } => {
+                write!(f, "Include cycle: '{}' includes itself", path.display())
+            }
+            IncludeError::Parse { path, error } => write!(
+                f,
+                "{}:{}:{}: {} [{}]",
+                path.display(),
+                error.span.line,
+                error.span.column,
+                error,
+                error.code()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+/// Parses the program at `path`, replacing every `include "file";` with the
+/// statements of that file, and following every `use name;` with the consts
+/// of `name.hand`, renamed `name.CONST`. Both paths are relative to the
+/// including file. A module that isn't found is left for the analyzer to
+/// report.
+pub fn load_program(path: &Path) -> Result<AbstractSyntaxTree, IncludeError> {
+    load_program_with_comments(path, false)
+}
+
+/// Like `load_program`, but keeps `//` comments as `Statement::Comment`s when
+/// `keep_comments` is set.
+pub fn load_program_with_comments(
+    path: &Path,
+    keep_comments: bool,
+) -> Result<AbstractSyntaxTree, IncludeError> {
+    load_program_timed(path, keep_comments, &mut Timings::default())
+}
+
+/// Like `load_program_with_comments`, adding the lexing and parsing time and
+/// the token count of every loaded file to `timings`.
+pub fn load_program_timed(
+    path: &Path,
+    keep_comments: bool,
+    timings: &mut Timings,
+) -> Result<AbstractSyntaxTree, IncludeError> {
+    let mut resolver = IncludeResolver {
+        stack: Vec::new(),
+        keep_comments,
+        timings,
+    };
+    let statement_list = resolver.load_file(path)?;
+    Ok(AbstractSyntaxTree { statement_list })
+}
+
+/// Like `load_program_timed`, for a program that isn't read from a file, such
+/// as one piped in on stdin. `name` is only used in error messages; includes
+/// are resolved relative to its directory.
+pub fn load_source_timed(
+    name: &Path,
+    source: String,
+    keep_comments: bool,
+    timings: &mut Timings,
+) -> Result<AbstractSyntaxTree, IncludeError> {
+    let mut resolver = IncludeResolver {
+        stack: Vec::new(),
+        keep_comments,
+        timings,
+    };
+    let statement_list = resolver.load_source(name, source)?;
+    Ok(AbstractSyntaxTree { statement_list })
+}
+
+struct IncludeResolver<'a> {
+    /// Canonical paths of the files currently being loaded, outermost first.
+    stack: Vec<PathBuf>,
+    keep_comments: bool,
+    timings: &'a mut Timings,
+}
+
+impl IncludeResolver<'_> {
+    fn load_file(&mut self, path: &Path) -> Result<StatementList, IncludeError> {
+        let io_error = |error| IncludeError::Io {
+            path: path.to_path_buf(),
+            error,
+        };
+        let canonical = path.canonicalize().map_err(io_error)?;
+        if self.stack.contains(&canonical) {
+            return Err(IncludeError::Cycle {
+                path: path.to_path_buf(),
+            });
+        }
+        let content = fs::read_to_string(path).map_err(io_error)?;
+
+        self.stack.push(canonical);
+        let statement_list = self.load_source(path, content);
+        self.stack.pop();
+        statement_list
+    }
+
+    fn load_source(&mut self, path: &Path, content: String) -> Result<StatementList, IncludeError> {
+        let mut lexer = Lexer::new(content);
+        if self.keep_comments {
+            lexer = lexer.keep_comments();
+        }
+        let ast =
+            lex_and_parse_timed(lexer, self.timings).map_err(|error| IncludeError::Parse {
+                path: path.to_path_buf(),
+                error,
+            })?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        self.resolve_statement_list(ast.statement_list, base)
+    }
+
+    fn resolve_statement_list(
+        &mut self,
+        statement_list: StatementList,
+        base: &Path,
+    ) -> Result<StatementList, IncludeError> {
+        let mut statements = Vec::new();
+        for statement in statement_list.statements {
+            match statement {
+                Statement::Include(include_stmt) => {
+                    let included = self.load_file(&base.join(&include_stmt.path))?;
+                    statements.extend(included.statements);
+                }
+                Statement::Use(use_stmt) => {
+                    let path = base.join(format!("{}.hand", use_stmt.module.name));
+                    if !path.is_file() {
+                        statements.push(Statement::Use(use_stmt));
+                        continue;
+                    }
+                    let module = self.load_file(&path)?;
+                    let consts = module_consts(&use_stmt.module, module);
+                    statements.push(Statement::Use(UseStatement {
+                        resolved: true,
+                        ..use_stmt
+                    }));
+                    statements.extend(consts);
+                }
+                Statement::Loop(loop_stmt) => {
+                    let body = self.resolve_block(*loop_stmt.body, base)?;
+                    statements.push(Statement::Loop(LoopStatement {
+                        body: Box::new(body),
+                        ..loop_stmt
+                    }));
+                }
+                Statement::Repeat(repeat_stmt) => {
+                    let body = self.resolve_block(*repeat_stmt.body, base)?;
+                    statements.push(Statement::Repeat(RepeatStatement {
+                        body: Box::new(body),
+                        ..repeat_stmt
+                    }));
+                }
+                Statement::If(if_stmt) => {
+                    statements.push(Statement::If(self.resolve_if_statement(if_stmt, base)?));
+                }
+                statement => statements.push(statement),
+            }
+        }
+        Ok(StatementList { statements })
+    }
+
+    fn resolve_block(&mut self, block: Block, base: &Path) -> Result<Block, IncludeError> {
+        let statements = self.resolve_statement_list(*block.statements, base)?;
+        Ok(Block {
+            statements: Box::new(statements),
+            span: block.span,
+        })
+    }
+
+    fn resolve_if_statement(
+        &mut self,
+        if_stmt: IfStatement,
+        base: &Path,
+    ) -> Result<IfStatement, IncludeError> {
+        let then_block = self.resolve_block(if_stmt.then_block, base)?;
+        let else_branch = match if_stmt.else_branch.map(|branch| *branch) {
+            None => None,
+            Some(ElseBranch::Block(block)) => Some(Box::new(ElseBranch::Block(
+                self.resolve_block(block, base)?,
+            ))),
+            Some(ElseBranch::If(next)) => Some(Box::new(ElseBranch::If(
+                self.resolve_if_statement(next, base)?,
+            ))),
+        };
+        Ok(IfStatement {
+            then_block,
+            else_branch,
+            ..if_stmt
+        })
+    }
+}
+
+/// The top-level consts of `module`, each renamed `name.CONST` after the
+/// `use` that imports them. Everything else in the module stays private.
+fn module_consts(name: &Identifier, module: StatementList) -> Vec<Statement> {
+    module
+        .statements
+        .into_iter()
+        .filter_map(|statement| match statement {
+            // Consts a module imports itself aren't passed on.
+            Statement::Const(const_stmt) if !const_stmt.identifier.name.contains('.') => {
+                Some(Statement::Const(ConstStatement {
+                    identifier: Identifier {
+                        name: format!("{}.{}", name.name, const_stmt.identifier.name),
+                        span: name.span,
+                    },
+                    value: const_stmt.value,
+                    span: const_stmt.span,
+                }))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_generator::generate_c_code;
+    use crate::semantic_analyzer::{SemanticAnalyzer, SemanticError};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hand-include-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_include_merges_statements() {
+        let dir = temp_dir("merge");
+        fs::write(dir.join("other.hand"), "let y = 2;").unwrap();
+        fs::write(
+            dir.join("main.hand"),
+            "let x = 1; include \"other.hand\"; print x + y;",
+        )
+        .unwrap();
+
+        let ast = load_program(&dir.join("main.hand")).unwrap();
+
+        assert_eq!(
+            generate_c_code(&ast),
+            "#include <stdio.h>\nint main() {\nint x = 1;\nint y = 2;\nprintf(\"%d\\n\", x + y);\nreturn 0;\n}\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_use_imports_module_consts() {
+        let dir = temp_dir("use");
+        fs::write(
+            dir.join("math.hand"),
+            "const PI = 3; const TWO = 2; let x = 1; print x;",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("main.hand"),
+            "use math; const PI = 1; print math.PI * math.TWO + PI;",
+        )
+        .unwrap();
+
+        let ast = load_program(&dir.join("main.hand")).unwrap();
+
+        // Only the module's consts come along, and they don't clash with
+        // the program's own.
+        assert_eq!(
+            generate_c_code(&ast),
+            "#include <stdio.h>\nint main() {\nprintf(\"%d\\n\", 3 * 2 + 1);\nreturn 0;\n}\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_use_unknown_module_or_member() {
+        let dir = temp_dir("use-unknown");
+        fs::write(dir.join("math.hand"), "const PI = 3;").unwrap();
+        fs::write(
+            dir.join("main.hand"),
+            "use math; use stats; print math.TAU + util.N;",
+        )
+        .unwrap();
+
+        let ast = load_program(&dir.join("main.hand")).unwrap();
+        let errors = SemanticAnalyzer::analyze(&ast).unwrap_err();
+
+        assert!(matches!(
+            &errors[..],
+            [
+                SemanticError::UnknownModule { name: stats, .. },
+                SemanticError::UnknownMember { module, member, .. },
+                SemanticError::UnknownModule { name: util, .. },
+            ] if stats == "stats" && module == "math" && member == "TAU" && util == "util"
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = temp_dir("cycle");
+        fs::write(dir.join("a.hand"), "include \"b.hand\";").unwrap();
+        fs::write(dir.join("b.hand"), "include \"a.hand\";").unwrap();
+
+        let result = load_program(&dir.join("a.hand"));
+
+        assert!(matches!(result, Err(IncludeError::Cycle { .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_include() {
+        let dir = temp_dir("missing");
+        fs::write(dir.join("main.hand"), "include \"nowhere.hand\";").unwrap();
+
+        let result = load_program(&dir.join("main.hand"));
+
+        assert!(matches!(result, Err(IncludeError::Io { .. })));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,863 @@
+//! Runs a checked program directly instead of compiling it. Arithmetic wraps
+//! at the integer width, as with `--overflow wrap`, unless `--overflow
+//! checked` asks for an `Overflow` error instead. What C leaves undefined,
+//! like dividing by zero or indexing out of bounds, stops the program with a
+//! `RuntimeError`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::ast::*;
+use crate::code_generator::Overflow;
+use crate::const_eval::IntWidth;
+use crate::scope::ScopeStack;
+use crate::span::Span;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    AssertionFailed {
+        message: Option<String>,
+        span: Span,
+    },
+    DivisionByZero {
+        span: Span,
+    },
+    IndexOutOfBounds {
+        index: i64,
+        length: usize,
+        span: Span,
+    },
+    /// `read` found no integer that fits, whether the input ran out or held
+    /// something else.
+    InvalidInput {
+        span: Span,
+    },
+    /// `+`, `-`, `*` or `**` at `span` overflowed under `--overflow checked`.
+    Overflow {
+        span: Span,
+    },
+    /// The step limit ran out entering the block at `span`, see
+    /// `Interpreter::set_step_limit`.
+    TooManySteps {
+        span: Span,
+    },
+}
+
+impl RuntimeError {
+    pub fn span(&self) -> Span {
+        match self {
+            RuntimeError::AssertionFailed { span, .. }
+            | RuntimeError::DivisionByZero { span }
+            | RuntimeError::IndexOutOfBounds { span, .. }
+            | RuntimeError::InvalidInput { span }
+            | RuntimeError::Overflow { span }
+            | RuntimeError::TooManySteps { span } => *span,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::AssertionFailed {
+                message: Some(message),
+                ..
+            } => write!(f, "assertion failed: {}", message),
+            RuntimeError::AssertionFailed { message: None, .. } => write!(f, "assertion failed"),
+            RuntimeError::DivisionByZero { .. } => write!(f, "division by zero"),
+            RuntimeError::IndexOutOfBounds { index, length, .. } => write!(
+                f,
+                "index {} is out of bounds for an array of length {}",
+                index, length
+            ),
+            RuntimeError::InvalidInput { .. } => write!(f, "invalid input"),
+            RuntimeError::Overflow { .. } => write!(f, "integer overflow"),
+            RuntimeError::TooManySteps { .. } => write!(f, "the program ran for too long"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// Where `read` takes integers from.
+pub(crate) enum Input {
+    /// Whitespace-separated integers on stdin; `pending` holds the words of
+    /// the current line not read yet.
+    Stdin {
+        pending: VecDeque<String>,
+    },
+    Queued(VecDeque<i64>),
+}
+
+impl Input {
+    pub(crate) fn stdin() -> Self {
+        Input::Stdin {
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The next integer, or `None` if the input ran out or the next word
+    /// isn't a number.
+    pub(crate) fn next_value(&mut self) -> Option<i64> {
+        match self {
+            Input::Queued(values) => values.pop_front(),
+            Input::Stdin { pending } => {
+                // A prompt printed without a newline should show before
+                // the program waits for input.
+                _ = io::stdout().flush();
+                while pending.is_empty() {
+                    let mut line = String::new();
+                    match io::stdin().lock().read_line(&mut line) {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => pending.extend(line.split_whitespace().map(str::to_string)),
+                    }
+                }
+                pending.pop_front().and_then(|word| word.parse().ok())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i64),
+    Array(Vec<i64>),
+}
+
+/// Why execution stopped before the end of the program.
+enum Stop {
+    Exit(i64),
+    /// A `break`, caught by the innermost loop.
+    Break,
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Stop {
+    fn from(error: RuntimeError) -> Self {
+        Stop::Error(error)
+    }
+}
+
+pub struct Interpreter {
+    int_width: IntWidth,
+    input: Input,
+    /// Printed output waiting to be returned by `run_capturing`, or `None`
+    /// to write it to stdout.
+    captured: Option<Vec<u8>>,
+    scopes: ScopeStack<Value>,
+    constants: ScopeStack<i64>,
+    /// Value of the most recent expression statement.
+    last_value: Option<i64>,
+    /// Print every value a `let` or assignment stores, as `--trace` does.
+    trace: bool,
+    /// How many more blocks may be entered before giving up, if limited.
+    steps_left: Option<u64>,
+    /// Whether arithmetic that overflows wraps or stops the program.
+    overflow: Overflow,
+}
+
+/// Blocks `predict_output` enters before giving up on a program, which
+/// keeps a loop that never ends from hanging the compiler.
+const PREDICT_STEP_LIMIT: u64 = 1_000_000;
+
+/// What `ast`, which must have passed semantic analysis, prints when run,
+/// found by interpreting it with no input. Fails with `InvalidInput` if it
+/// reads, or with `TooManySteps` if it doesn't finish soon enough.
+pub fn predict_output(
+    ast: &AbstractSyntaxTree,
+    int_width: IntWidth,
+    overflow: Overflow,
+) -> Result<String, RuntimeError> {
+    let mut interpreter = Interpreter::with_input(int_width, Vec::new());
+    interpreter.set_step_limit(Some(PREDICT_STEP_LIMIT));
+    interpreter.set_overflow(overflow);
+    interpreter.run_capturing(ast)
+}
+
+impl Interpreter {
+    /// An interpreter that reads from stdin and prints to stdout.
+    pub fn new(int_width: IntWidth) -> Self {
+        Interpreter {
+            int_width,
+            input: Input::stdin(),
+            captured: None,
+            scopes: ScopeStack::new(),
+            constants: ScopeStack::new(),
+            last_value: None,
+            trace: false,
+            steps_left: None,
+            overflow: Overflow::default(),
+        }
+    }
+
+    /// An interpreter whose `read`s take the values in `input` in order,
+    /// for deterministic runs in tests.
+    pub fn with_input(int_width: IntWidth, input: Vec<i64>) -> Self {
+        Interpreter {
+            input: Input::Queued(input.into()),
+            ..Self::new(int_width)
+        }
+    }
+
+    /// Runs `ast`, which must have passed semantic analysis, and returns its
+    /// exit code.
+    pub fn run(&mut self, ast: &AbstractSyntaxTree) -> Result<i32, RuntimeError> {
+        self.scopes = ScopeStack::new();
+        self.constants = ScopeStack::new();
+        self.last_value = None;
+        let steps_left = self.steps_left;
+        let result = match self.execute_statement_list(&ast.statement_list) {
+            Ok(()) => Ok(0),
+            // Like `return` from C's `main`, only the low bits reach the OS.
+            Err(Stop::Exit(code)) => Ok(code as i32),
+            Err(Stop::Error(error)) => Err(error),
+            Err(Stop::Break) => unreachable!("`break` outside a loop is rejected by analysis"),
+        };
+        self.steps_left = steps_left;
+        result
+    }
+
+    /// Prints `x = 3` after every `let` or assignment, like the C output
+    /// with `--trace`.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Stops every later `run` with `TooManySteps` once it has entered
+    /// `limit` blocks, counting a loop's body once per iteration.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.steps_left = limit;
+    }
+
+    /// Makes `+`, `-` (binary or unary), `*` and `**` fail with `Overflow` when the result
+    /// doesn't fit, like the C output with `--overflow checked`. Other
+    /// modes wrap.
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+    }
+
+    /// The value of the last expression statement the most recent `run`
+    /// evaluated, like a calculator's result. Booleans are 0 or 1.
+    pub fn last_value(&self) -> Option<i64> {
+        self.last_value
+    }
+
+    /// Like `run`, but returns everything the program printed instead of
+    /// writing it to stdout. The exit code is dropped.
+    pub fn run_capturing(&mut self, ast: &AbstractSyntaxTree) -> Result<String, RuntimeError> {
+        self.captured = Some(Vec::new());
+        let result = self.run(ast);
+        let output = self.captured.take().unwrap();
+        result.map(|_| String::from_utf8_lossy(&output).into_owned())
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match &mut self.captured {
+            Some(output) => output.extend_from_slice(bytes),
+            // Like `printf`, a failed write to stdout is ignored.
+            None => _ = io::stdout().write_all(bytes),
+        }
+    }
+
+    fn read(&mut self, span: Span) -> Result<i64, RuntimeError> {
+        self.input
+            .next_value()
+            .filter(|&value| self.int_width.fits(value))
+            .ok_or(RuntimeError::InvalidInput { span })
+    }
+
+    /// `value` truncated to the integer width, wrapping around like two's
+    /// complement arithmetic.
+    fn wrap(&self, value: i128) -> i64 {
+        match self.int_width {
+            IntWidth::Bits32 => value as i32 as i64,
+            IntWidth::Bits64 => value as i64,
+        }
+    }
+
+    /// `value` wrapped to the integer width, or an `Overflow` error at
+    /// `span` if it doesn't fit and overflow is checked.
+    fn arithmetic(&self, value: i128, span: Span) -> Result<i64, RuntimeError> {
+        let wrapped = self.wrap(value);
+        if self.overflow == Overflow::Checked && i128::from(wrapped) != value {
+            return Err(RuntimeError::Overflow { span });
+        }
+        Ok(wrapped)
+    }
+
+    fn trace_value(&mut self, target: &str, value: i64) {
+        if self.trace {
+            self.write(format!("{} = {}\n", target, value).as_bytes());
+        }
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        self.scopes.declare(name.to_string(), value);
+    }
+
+    fn variable(&mut self, name: &str) -> &mut Value {
+        self.scopes
+            .lookup_mut(name)
+            .expect("undeclared variables are rejected by semantic analysis")
+    }
+
+    fn execute_statement_list(&mut self, statement_list: &StatementList) -> Result<(), Stop> {
+        for statement in &statement_list.statements {
+            self.execute_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn execute_block(&mut self, block: &Block) -> Result<(), Stop> {
+        if let Some(steps_left) = &mut self.steps_left {
+            if *steps_left == 0 {
+                return Err(RuntimeError::TooManySteps { span: block.span }.into());
+            }
+            *steps_left -= 1;
+        }
+        self.scopes.enter_scope();
+        self.constants.enter_scope();
+        let result = self.execute_statement_list(&block.statements);
+        self.constants.exit_scope();
+        self.scopes.exit_scope();
+        result
+    }
+
+    fn execute_statement(&mut self, statement: &Statement) -> Result<(), Stop> {
+        match statement {
+            Statement::Let(let_stmt) => {
+                let value = match &let_stmt.value {
+                    Some(value) => self.evaluate(value)?,
+                    None => 0,
+                };
+                self.declare(&let_stmt.identifier.name, Value::Int(value));
+                self.trace_value(&let_stmt.identifier.name, value);
+            }
+            Statement::Const(const_stmt) => {
+                let value = self.evaluate(&const_stmt.value)?;
+                self.constants
+                    .declare(const_stmt.identifier.name.clone(), value);
+            }
+            Statement::ArrayLet(array_let_stmt) => {
+                let elements = match &array_let_stmt.initializer {
+                    ArrayInitializer::Fill(value) => {
+                        vec![self.evaluate(value)?; array_let_stmt.length]
+                    }
+                    ArrayInitializer::List(elements) => elements
+                        .iter()
+                        .map(|element| self.evaluate(element))
+                        .collect::<Result<_, _>>()?,
+                };
+                self.declare(&array_let_stmt.identifier.name, Value::Array(elements));
+            }
+            Statement::Assignment(assign_stmt) => {
+                let index = match &assign_stmt.index {
+                    Some(index) => Some((self.evaluate(index)?, index.span)),
+                    None => None,
+                };
+                let value = self.evaluate(&assign_stmt.value)?;
+                let name = &assign_stmt.identifier.name;
+                match (self.variable(name), index) {
+                    (Value::Array(elements), Some((index, span))) => {
+                        *element(elements, index, span)? = value;
+                        self.trace_value(&format!("{}[{}]", name, index), value);
+                    }
+                    (variable, _) => {
+                        *variable = Value::Int(value);
+                        self.trace_value(name, value);
+                    }
+                }
+            }
+            Statement::Loop(loop_stmt) => self.execute_loop_statement(loop_stmt)?,
+            Statement::Repeat(repeat_stmt) => loop {
+                match self.execute_block(&repeat_stmt.body) {
+                    Err(Stop::Break) => break,
+                    result => result?,
+                }
+                if self.evaluate(&repeat_stmt.condition)? != 0 {
+                    break;
+                }
+            },
+            Statement::If(if_stmt) => self.execute_if_statement(if_stmt)?,
+            Statement::Print(print_stmt) => self.execute_print_statement(print_stmt)?,
+            Statement::Assert(assert_stmt) => {
+                if self.evaluate(&assert_stmt.condition)? == 0 {
+                    return Err(RuntimeError::AssertionFailed {
+                        message: assert_stmt.message.clone(),
+                        span: assert_stmt.span,
+                    }
+                    .into());
+                }
+            }
+            Statement::Read(read_stmt) => {
+                let value = self.read(read_stmt.span)?;
+                *self.variable(&read_stmt.identifier.name) = Value::Int(value);
+            }
+            Statement::Exit(exit_stmt) => {
+                return Err(Stop::Exit(self.evaluate(&exit_stmt.code)?));
+            }
+            Statement::Break(_) => return Err(Stop::Break),
+            Statement::Expression(expr_stmt) => {
+                self.last_value = Some(self.evaluate(&expr_stmt.value)?);
+            }
+            Statement::Comment(_) => {}
+            Statement::Include(_) => unreachable!("includes are resolved before running"),
+            // The module's consts follow it.
+            Statement::Use(_) => {}
+        }
+        Ok(())
+    }
+
+    fn execute_loop_statement(&mut self, loop_stmt: &LoopStatement) -> Result<(), Stop> {
+        // As in the C output, the bounds and step are evaluated once, before
+        // the counter exists. A count loop stops before its count, a range
+        // loop at its end.
+        let start = match &loop_stmt.start {
+            Some(start) => self.evaluate(start)?,
+            None => 0,
+        };
+        let count = self.evaluate(&loop_stmt.count)?;
+        let inclusive = loop_stmt.start.is_some();
+        let step = match &loop_stmt.step {
+            Some(step) => self.evaluate(step)?,
+            None => 1,
+        };
+        let counter = loop_stmt
+            .counter
+            .as_ref()
+            .map_or("_", |counter| counter.name.as_str());
+        self.scopes.enter_scope();
+        self.declare(counter, Value::Int(start));
+        let result = loop {
+            let Value::Int(current) = *self.variable(counter) else {
+                unreachable!();
+            };
+            if current > count || (current == count && !inclusive) {
+                break Ok(());
+            }
+            match self.execute_block(&loop_stmt.body) {
+                Ok(()) => {}
+                Err(Stop::Break) => break Ok(()),
+                Err(stop) => break Err(stop),
+            }
+            let Value::Int(current) = *self.variable(counter) else {
+                unreachable!();
+            };
+            // Like the C output, a range loop ends on the last value in range
+            // rather than stepping past its end, comparing with the step as
+            // an unsigned int.
+            let unsigned_step = i128::from(step).rem_euclid(1 << self.int_width.bits());
+            if inclusive && i128::from(count) - i128::from(current) < unsigned_step {
+                break Ok(());
+            }
+            let next = self.wrap(i128::from(current) + i128::from(step));
+            *self.variable(counter) = Value::Int(next);
+        };
+        self.scopes.exit_scope();
+        result
+    }
+
+    fn execute_if_statement(&mut self, if_stmt: &IfStatement) -> Result<(), Stop> {
+        if self.evaluate(&if_stmt.condition)? != 0 {
+            return self.execute_block(&if_stmt.then_block);
+        }
+        match if_stmt.else_branch.as_deref() {
+            None => Ok(()),
+            Some(ElseBranch::Block(block)) => self.execute_block(block),
+            Some(ElseBranch::If(next)) => self.execute_if_statement(next),
+        }
+    }
+
+    fn execute_print_statement(&mut self, print_stmt: &PrintStatement) -> Result<(), Stop> {
+        let print_stmt = &print_stmt.expand_arrays(|name| match self.scopes.lookup(name) {
+            Some(Value::Array(elements)) => Some(elements.len()),
+            _ => None,
+        });
+        let output = self.format_print(&print_stmt.arguments, &print_stmt.format)?;
+        self.write(&output);
+        Ok(())
+    }
+
+    /// The bytes `print` or `write` outputs for `arguments`.
+    fn format_print(
+        &mut self,
+        arguments: &[PrintArgument],
+        format: &PrintFormat,
+    ) -> Result<Vec<u8>, Stop> {
+        let mut output = Vec::new();
+        for argument in arguments {
+            match argument {
+                PrintArgument::Text(text) => output.extend_from_slice(text.as_bytes()),
+                PrintArgument::Value(value) => {
+                    let value = self.evaluate(value)?;
+                    match format {
+                        PrintFormat::Decimal => output.extend(value.to_string().bytes()),
+                        // `%x` prints the two's complement bits.
+                        PrintFormat::Hex => {
+                            let text = match self.int_width {
+                                IntWidth::Bits32 => format!("{:x}", value as u32),
+                                IntWidth::Bits64 => format!("{:x}", value as u64),
+                            };
+                            output.extend(text.bytes());
+                        }
+                        // `%c` prints the low byte.
+                        PrintFormat::Char => output.push(value as u8),
+                    }
+                }
+            }
+        }
+        if *format != PrintFormat::Char {
+            output.push(b'\n');
+        }
+        Ok(output)
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<i64, Stop> {
+        match &expr.kind {
+            ExprKind::Term(Term::Number(n)) => Ok(*n),
+            ExprKind::Term(Term::Bool(value)) => Ok((*value).into()),
+            ExprKind::Term(Term::Identifier(identifier)) => {
+                if let Some(&value) = self.constants.lookup(&identifier.name) {
+                    return Ok(value);
+                }
+                match self.variable(&identifier.name) {
+                    Value::Int(value) => Ok(*value),
+                    Value::Array(_) => unreachable!("arrays are only used through an index"),
+                }
+            }
+            ExprKind::Term(Term::Index { base, index }) => {
+                let index_value = self.evaluate(index)?;
+                match self.variable(&base.name) {
+                    Value::Array(elements) => Ok(*element(elements, index_value, index.span)?),
+                    Value::Int(_) => unreachable!("indexing a non-array is rejected by analysis"),
+                }
+            }
+            ExprKind::Call(call) => {
+                let arguments = call
+                    .arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(match Intrinsic::from_name(&call.function.name) {
+                    Some(Intrinsic::Min) => arguments[0].min(arguments[1]),
+                    Some(Intrinsic::Max) => arguments[0].max(arguments[1]),
+                    Some(Intrinsic::Abs) => self.wrap(i128::from(arguments[0]).abs()),
+                    Some(Intrinsic::MinOf) => *arguments.iter().min().unwrap(),
+                    Some(Intrinsic::MaxOf) => *arguments.iter().max().unwrap(),
+                    None => unreachable!("unknown functions are rejected by analysis"),
+                })
+            }
+            ExprKind::Binary(binary) => self.evaluate_binary(binary),
+            ExprKind::Negate(operand) => {
+                let value = self.evaluate(operand)?;
+                Ok(self.arithmetic(-i128::from(value), expr.span)?)
+            }
+            ExprKind::Write(write) => {
+                let output = self.format_print(&write.arguments, &PrintFormat::Decimal)?;
+                self.write(&output);
+                Ok(output.len() as i64)
+            }
+            ExprKind::StringLength(text) => Ok(text.len() as i64),
+            ExprKind::Block(block) => {
+                self.scopes.enter_scope();
+                self.constants.enter_scope();
+                let result = self
+                    .execute_statement_list(&block.statements)
+                    .and_then(|()| self.evaluate(&block.value));
+                self.constants.exit_scope();
+                self.scopes.exit_scope();
+                result
+            }
+        }
+    }
+
+    fn evaluate_binary(&mut self, binary: &BinaryExpr) -> Result<i64, Stop> {
+        // `&&` and `||` short-circuit, so `x != 0 && 10 / x > 1` is safe.
+        match binary.operator {
+            BinaryOperator::LogicalAnd => {
+                let result = self.evaluate(&binary.lhs)? != 0 && self.evaluate(&binary.rhs)? != 0;
+                return Ok(result.into());
+            }
+            BinaryOperator::LogicalOr => {
+                let result = self.evaluate(&binary.lhs)? != 0 || self.evaluate(&binary.rhs)? != 0;
+                return Ok(result.into());
+            }
+            _ => {}
+        }
+        let lhs = self.evaluate(&binary.lhs)?;
+        let rhs = self.evaluate(&binary.rhs)?;
+        let (wide_lhs, wide_rhs) = (i128::from(lhs), i128::from(rhs));
+        // Shift amounts wrap around the width, as they do in hardware.
+        let shift = (rhs as u32) % self.int_width.bits();
+        let span = binary.lhs.span.to(binary.rhs.span);
+        Ok(match binary.operator {
+            BinaryOperator::Add => self.arithmetic(wide_lhs + wide_rhs, span)?,
+            BinaryOperator::Subtract => self.arithmetic(wide_lhs - wide_rhs, span)?,
+            BinaryOperator::Multiply => self.arithmetic(wide_lhs * wide_rhs, span)?,
+            BinaryOperator::Divide | BinaryOperator::Modulo if rhs == 0 => {
+                return Err(RuntimeError::DivisionByZero {
+                    span: binary.rhs.span,
+                }
+                .into());
+            }
+            BinaryOperator::Divide => self.wrap(wide_lhs / wide_rhs),
+            BinaryOperator::Modulo => self.wrap(wide_lhs % wide_rhs),
+            BinaryOperator::Power => {
+                // Like the C helper, a negative exponent gives 1.
+                let mut result = 1;
+                for _ in 0..rhs.max(0) {
+                    result = self.arithmetic(i128::from(result) * wide_lhs, span)?;
+                }
+                result
+            }
+            BinaryOperator::BitwiseAnd => lhs & rhs,
+            BinaryOperator::BitwiseOr => lhs | rhs,
+            BinaryOperator::BitwiseXor => lhs ^ rhs,
+            BinaryOperator::ShiftLeft => self.wrap(wide_lhs << shift),
+            BinaryOperator::ShiftRight => lhs >> shift,
+            BinaryOperator::Equal => (lhs == rhs).into(),
+            BinaryOperator::NotEqual => (lhs != rhs).into(),
+            BinaryOperator::Less => (lhs < rhs).into(),
+            BinaryOperator::LessEqual => (lhs <= rhs).into(),
+            BinaryOperator::Greater => (lhs > rhs).into(),
+            BinaryOperator::GreaterEqual => (lhs >= rhs).into(),
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => unreachable!(),
+        })
+    }
+}
+
+/// The element of `elements` at `index`, or an error pointing at `span` if
+/// there is none.
+fn element(elements: &mut [i64], index: i64, span: Span) -> Result<&mut i64, RuntimeError> {
+    let length = elements.len();
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| elements.get_mut(i))
+        .ok_or(RuntimeError::IndexOutOfBounds {
+            index,
+            length,
+            span,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+
+    fn parse(source: &str) -> AbstractSyntaxTree {
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        SemanticAnalyzer::analyze(&ast).unwrap();
+        ast
+    }
+
+    #[test]
+    fn test_read_from_queued_input() {
+        let ast = parse(
+            "let mut n; read n; let mut sum = 0; loop i in n { let mut x; read x; sum = sum + x; } print \"sum = \", sum;",
+        );
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, vec![3, 10, 20, 12]);
+
+        assert_eq!(interpreter.run_capturing(&ast).unwrap(), "sum = 42\n");
+    }
+
+    #[test]
+    fn test_running_out_of_input() {
+        let ast = parse("let mut x; read x; read x;");
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, vec![1]);
+
+        assert!(matches!(
+            interpreter.run_capturing(&ast),
+            Err(RuntimeError::InvalidInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_matches_c_semantics() {
+        let ast = parse(
+            "let mut a = [5, 6, 7]; a[1] = 2 ** 3 + min(a[0], 1); printx 0 - 1; printc 65; print a[1];
+             let x = 0; if x != 0 && 10 / x > 1 { print 1; } else if x == 0 { print 2147483647 + 1; }
+             loop i in 10 step 4 { print i; } exit 3; print 9;",
+        );
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+
+        assert_eq!(
+            interpreter.run_capturing(&ast).unwrap(),
+            "ffffffff\nA9\n-2147483648\n0\n4\n8\n"
+        );
+        assert_eq!(interpreter.run(&parse("exit 3; print 9;")), Ok(3));
+        assert!(matches!(
+            interpreter.run(&parse("let mut a = [1]; let i = 1; a[i] = 2;")),
+            Err(RuntimeError::IndexOutOfBounds {
+                index: 1,
+                length: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_overflow_mode() {
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        interpreter.set_overflow(Overflow::Wrap);
+        let ast = parse("let mut x = 2147483647; print x + 1, \" \", 0 - x - 2, \" \", x * 2;");
+        assert_eq!(
+            interpreter.run_capturing(&ast).unwrap(),
+            "-2147483648 2147483647 -2\n"
+        );
+
+        interpreter.set_overflow(Overflow::Checked);
+        assert_eq!(
+            interpreter.run_capturing(&parse("print 2 ** 30;")).unwrap(),
+            "1073741824\n"
+        );
+        for source in [
+            "let mut x = 2147483647; print x + 1;",
+            "let mut x = 2147483647; print 0 - x - 2;",
+            "let mut x = 2147483647; print x * 2;",
+            "let mut x = 2; print x ** 31;",
+            "let x = -2147483647 - 1; print -x;",
+        ] {
+            assert!(
+                matches!(
+                    interpreter.run_capturing(&parse(source)),
+                    Err(RuntimeError::Overflow { .. })
+                ),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_range_loop_ends_at_largest_int() {
+        let ast = parse(
+            "let n = 2147483645; loop i from n to 2147483647 { print i; } loop i from 0 - n to n step n { print i; }",
+        );
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        assert_eq!(
+            interpreter.run_capturing(&ast).unwrap(),
+            "2147483645\n2147483646\n2147483647\n-2147483645\n0\n2147483645\n"
+        );
+    }
+
+    #[test]
+    fn test_loop_local_let_is_fresh_each_iteration() {
+        // Each iteration declares a new `y`, and the outer `y` is visible
+        // again once the loop ends, as in the C output.
+        let ast =
+            parse("let y = 100; loop i in 3 { let mut y = 0; y = y + i + 1; print y; } print y;");
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        assert_eq!(interpreter.run_capturing(&ast).unwrap(), "1\n2\n3\n100\n");
+    }
+
+    #[test]
+    fn test_repeat_runs_body_once() {
+        // The condition already holds, but it's only tested after the body.
+        let ast = parse("let mut x = 10; repeat { print x; x = x + 1; } until x > 3; print x;");
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        assert_eq!(interpreter.run_capturing(&ast).unwrap(), "10\n11\n");
+
+        let ast = parse("let mut x = 0; repeat { x = x + 1; } until x == 3; print x;");
+        assert_eq!(interpreter.run_capturing(&ast).unwrap(), "3\n");
+    }
+
+    #[test]
+    fn test_break() {
+        let ast = parse(
+            "loop i in 5 { loop j in 5 { break if j > i; print j; } break if i == 1; }
+             let mut x = 0; repeat { x = x + 1; break if x == 2; } until x > 5; print x;",
+        );
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        assert_eq!(interpreter.run_capturing(&ast).unwrap(), "0\n0\n1\n2\n");
+    }
+
+    #[test]
+    fn test_print_array() {
+        let ast =
+            parse("let mut a[3] = 1; a[2] = 5; print a; print a[2]; let s = [104, 105]; printc s;");
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        assert_eq!(interpreter.run_capturing(&ast).unwrap(), "1 1 5\n5\nhi");
+    }
+
+    #[test]
+    fn test_assert_message() {
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        let error = interpreter
+            .run(&parse("let x = 5; assert x == 4, \"x should be four\";"))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "assertion failed: x should be four");
+
+        let error = interpreter.run(&parse("assert 1 > 2;")).unwrap_err();
+        assert_eq!(error.to_string(), "assertion failed");
+    }
+
+    #[test]
+    fn test_last_value() {
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        interpreter.run(&parse("let x = 2; x + 3;")).unwrap();
+        assert_eq!(interpreter.last_value(), Some(5));
+
+        interpreter.run(&parse("let x = 2; print x;")).unwrap();
+        assert_eq!(interpreter.last_value(), None);
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        interpreter.set_trace(true);
+        let output = interpreter
+            .run_capturing(&parse(
+                "let mut x = 1; let mut a[2] = 0; loop i in 2 { x = x * 3; a[i] = x; } print a[1];",
+            ))
+            .unwrap();
+        assert_eq!(output, "x = 1\nx = 3\na[0] = 3\nx = 9\na[1] = 9\n9\n");
+    }
+
+    #[test]
+    fn test_predict_output() {
+        let predicted = predict_output(
+            &parse("let mut x = 2; loop 2 { x = x + 1; print x; };"),
+            IntWidth::Bits32,
+            Overflow::Unchecked,
+        );
+        assert_eq!(predicted.unwrap(), "3\n4\n");
+
+        assert!(matches!(
+            predict_output(
+                &parse("let mut x; read x; print x;"),
+                IntWidth::Bits32,
+                Overflow::Unchecked
+            ),
+            Err(RuntimeError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            predict_output(
+                &parse("repeat { } until 1 > 2;"),
+                IntWidth::Bits32,
+                Overflow::Unchecked
+            ),
+            Err(RuntimeError::TooManySteps { .. })
+        ));
+    }
+
+    #[test]
+    fn test_block_expression() {
+        let mut interpreter = Interpreter::with_input(IntWidth::Bits32, Vec::new());
+        let output = interpreter
+            .run_capturing(&parse(
+                "let t = 10; let x = { let t = 1; t + 2 }; print x, t;\nprint { let mut n = 0; loop i in 4 { n = n + i; } n } * 2;",
+            ))
+            .unwrap();
+        assert_eq!(output, "310\n12\n");
+
+        // An `exit` inside the block ends the program.
+        let code = interpreter
+            .run(&parse("let x = { exit 3; 1 }; print x;"))
+            .unwrap();
+        assert_eq!(code, 3);
+    }
+}
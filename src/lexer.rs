@@ -1,31 +1,273 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::diagnostic::json_string;
+use crate::span::Span;
+
+/// Source text that doesn't form a valid token.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LexError {
+    UnexpectedChar(char),
+    UnterminatedString,
+    /// A `/*` with no `*/` after it.
+    UnterminatedComment,
+    /// A number literal that doesn't fit in 64 bits.
+    NumberTooLarge(String),
+    /// A `0x` or `0b` prefix, as written, with no digits after it.
+    MissingDigits(String),
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::UnexpectedChar(c) => write!(f, "unexpected character `{}`", c),
+            LexError::UnterminatedString => write!(f, "unterminated string literal"),
+            LexError::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexError::NumberTooLarge(digits) => {
+                write!(f, "number literal `{}` is too large", digits)
+            }
+            LexError::MissingDigits(prefix) => {
+                let kind = if prefix.ends_with(['x', 'X']) {
+                    "hex"
+                } else {
+                    "binary"
+                };
+                write!(f, "expected {} digits after `{}`", kind, prefix)
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Identifier(String),
-    Number(i32),
+    Number(i64),
+    StringLiteral(String),
+    Comment(String),
+    /// Emitted instead of panicking on invalid input, so the parser can
+    /// report it like any other unexpected token.
+    Error(LexError),
     Let,
+    Mut,
     Loop,
+    In,
     Plus,
+    Minus,
+    Star,
+    StarStar,
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    AmpersandAmpersand,
+    PipePipe,
+    Caret,
+    ShiftLeft,
+    ShiftRight,
     Equals,
+    EqualsEquals,
+    NotEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
     Semicolon,
     OpenBracket,
     CloseBracket,
+    OpenParen,
+    CloseParen,
+    OpenSquareBracket,
+    CloseSquareBracket,
+    Comma,
+    Colon,
+    Dot,
     Print,
+    PrintHex,
+    PrintChar,
+    Assert,
+    Read,
+    Include,
+    Use,
+    Exit,
+    Break,
+    Const,
+    Step,
+    From,
+    To,
+    Repeat,
+    Until,
+    If,
+    Else,
+    True,
+    False,
+}
+
+impl fmt::Display for Token {
+    /// Writes the token as it appears in source code.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Identifier(name) => return write!(f, "{}", name),
+            Token::Number(n) => return write!(f, "{}", n),
+            Token::StringLiteral(value) => return write!(f, "\"{}\"", value),
+            Token::Comment(text) => return write!(f, "// {}", text),
+            Token::Error(error) => return write!(f, "{}", error),
+            Token::Let => "let",
+            Token::Mut => "mut",
+            Token::Loop => "loop",
+            Token::In => "in",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::StarStar => "**",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Ampersand => "&",
+            Token::Pipe => "|",
+            Token::AmpersandAmpersand => "&&",
+            Token::PipePipe => "||",
+            Token::Caret => "^",
+            Token::ShiftLeft => "<<",
+            Token::ShiftRight => ">>",
+            Token::Equals => "=",
+            Token::EqualsEquals => "==",
+            Token::NotEquals => "!=",
+            Token::Less => "<",
+            Token::LessEquals => "<=",
+            Token::Greater => ">",
+            Token::GreaterEquals => ">=",
+            Token::Semicolon => ";",
+            Token::OpenBracket => "{",
+            Token::CloseBracket => "}",
+            Token::OpenParen => "(",
+            Token::CloseParen => ")",
+            Token::OpenSquareBracket => "[",
+            Token::CloseSquareBracket => "]",
+            Token::Comma => ",",
+            Token::Colon => ":",
+            Token::Dot => ".",
+            Token::Print => "print",
+            Token::PrintHex => "printx",
+            Token::PrintChar => "printc",
+            Token::Assert => "assert",
+            Token::Read => "read",
+            Token::Include => "include",
+            Token::Use => "use",
+            Token::Exit => "exit",
+            Token::Break => "break",
+            Token::Const => "const",
+            Token::Step => "step",
+            Token::From => "from",
+            Token::To => "to",
+            Token::Repeat => "repeat",
+            Token::Until => "until",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::True => "true",
+            Token::False => "false",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl Token {
+    /// The token's category for semantic highlighting.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Token::Identifier(_) => "identifier",
+            Token::Number(_) => "number",
+            Token::StringLiteral(_) => "string",
+            Token::Comment(_) => "comment",
+            Token::Error(_) => "error",
+            Token::Let
+            | Token::Mut
+            | Token::Loop
+            | Token::In
+            | Token::Print
+            | Token::PrintHex
+            | Token::PrintChar
+            | Token::Assert
+            | Token::Read
+            | Token::Include
+            | Token::Use
+            | Token::Exit
+            | Token::Break
+            | Token::Const
+            | Token::Step
+            | Token::From
+            | Token::To
+            | Token::Repeat
+            | Token::Until
+            | Token::If
+            | Token::Else
+            | Token::True
+            | Token::False => "keyword",
+            _ => "operator",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl From<Token> for SpannedToken {
+    fn from(token: Token) -> Self {
+        SpannedToken {
+            token,
+            span: Span::default(),
+        }
+    }
 }
 
 pub struct Lexer {
     src: String,
     pos: usize,
+    line: usize,
+    column: usize,
+    keep_comments: bool,
+    /// A token scanned by `peek` but not yet consumed. `Some(None)` means
+    /// the lookahead hit the end of input.
+    peeked: Option<Option<SpannedToken>>,
 }
 
 impl Lexer {
     pub fn new(src: String) -> Self {
-        Lexer { src, pos: 0 }
+        Lexer {
+            src,
+            pos: 0,
+            line: 1,
+            column: 1,
+            keep_comments: false,
+            peeked: None,
+        }
+    }
+
+    /// The next token, without consuming it.
+    pub fn peek(&mut self) -> Option<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.scan());
+        }
+        self.peeked.as_ref()?.as_ref().map(|t| &t.token)
+    }
+
+    /// Consumes and returns the next token.
+    pub fn bump(&mut self) -> Option<Token> {
+        self.next().map(|t| t.token)
+    }
+
+    /// Emit `//` and `/* */` comments as `Token::Comment` instead of
+    /// skipping them.
+    pub fn keep_comments(mut self) -> Self {
+        self.keep_comments = true;
+        self
     }
 
     fn skip_whitespace(&mut self) {
         while let Some(c) = self.current_char() {
             if c.is_whitespace() {
-                self.pos += 1;
+                self.advance();
             } else {
                 break;
             }
@@ -33,60 +275,203 @@ impl Lexer {
     }
 
     fn current_char(&self) -> Option<char> {
-        self.src.chars().nth(self.pos)
+        self.src[self.pos..].chars().next()
+    }
+
+    fn next_char(&self) -> Option<char> {
+        self.src[self.pos..].chars().nth(1)
+    }
+
+    fn advance(&mut self) {
+        if let Some(c) = self.current_char() {
+            self.pos += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn try_parse_two_char_operator(&mut self) -> Option<Token> {
+        let token = match (self.current_char()?, self.next_char()?) {
+            ('=', '=') => Token::EqualsEquals,
+            ('!', '=') => Token::NotEquals,
+            ('<', '=') => Token::LessEquals,
+            ('>', '=') => Token::GreaterEquals,
+            ('<', '<') => Token::ShiftLeft,
+            ('>', '>') => Token::ShiftRight,
+            ('*', '*') => Token::StarStar,
+            ('&', '&') => Token::AmpersandAmpersand,
+            ('|', '|') => Token::PipePipe,
+            _ => return None,
+        };
+        self.advance();
+        self.advance();
+        Some(token)
     }
 
     fn try_parse_identifier(&mut self) -> Option<Token> {
         let start = self.pos;
-        let Some(c) = self.current_char() else {
-            return None;
-        };
+        let c = self.current_char()?;
         if !c.is_alphabetic() {
             return None;
         }
-        self.pos += 1;
+        self.advance();
+        // A `_` can't start a name, so names never clash with the `_count`
+        // and `_hand_` names in the generated C.
         while let Some(c) = self.current_char() {
-            if !c.is_alphanumeric() {
+            if !(c.is_alphanumeric() || c == '_') {
                 break;
             } else {
-                self.pos += 1;
+                self.advance();
             }
         }
         let identifier = &self.src[start..self.pos];
         match identifier {
             "let" => Some(Token::Let),
+            "mut" => Some(Token::Mut),
             "loop" => Some(Token::Loop),
+            "in" => Some(Token::In),
             "print" => Some(Token::Print),
+            "printx" => Some(Token::PrintHex),
+            "printc" => Some(Token::PrintChar),
+            "assert" => Some(Token::Assert),
+            "read" => Some(Token::Read),
+            "include" => Some(Token::Include),
+            "use" => Some(Token::Use),
+            "exit" => Some(Token::Exit),
+            "break" => Some(Token::Break),
+            "const" => Some(Token::Const),
+            "step" => Some(Token::Step),
+            "from" => Some(Token::From),
+            "to" => Some(Token::To),
+            "repeat" => Some(Token::Repeat),
+            "until" => Some(Token::Until),
+            "if" => Some(Token::If),
+            "else" => Some(Token::Else),
+            "true" => Some(Token::True),
+            "false" => Some(Token::False),
             _ => Some(Token::Identifier(identifier.to_string())),
         }
     }
 
     fn try_parse_number(&mut self) -> Option<Token> {
         let start = self.pos;
-        let Some(c) = self.current_char() else {
-            return None;
-        };
-        if !c.is_digit(10) {
+        let c = self.current_char()?;
+        if !c.is_ascii_digit() {
             return None;
         }
-        self.pos += 1;
+        if let Some(token) = self.try_parse_prefixed_number() {
+            return Some(token);
+        }
+        self.advance();
         while let Some(c) = self.current_char() {
-            if !c.is_digit(10) {
+            if !c.is_ascii_digit() {
                 break;
             } else {
-                self.pos += 1;
+                self.advance();
             }
         }
         let number_str = &self.src[start..self.pos];
-        Some(Token::Number(number_str.parse::<i32>().unwrap()))
+        Some(number_token(number_str, number_str, 10))
+    }
+
+    /// Lexes a `0x` hex or `0b` binary literal. The value is resolved here
+    /// so later stages only ever see the number, never its spelling.
+    fn try_parse_prefixed_number(&mut self) -> Option<Token> {
+        let radix = match (self.current_char()?, self.next_char()?) {
+            ('0', 'x' | 'X') => 16,
+            ('0', 'b' | 'B') => 2,
+            _ => return None,
+        };
+        let prefix_start = self.pos;
+        self.advance();
+        self.advance();
+        let start = self.pos;
+        if !self.current_char().is_some_and(|c| c.is_digit(radix)) {
+            return Some(Token::Error(LexError::MissingDigits(
+                self.src[prefix_start..start].to_string(),
+            )));
+        }
+        while let Some(c) = self.current_char() {
+            if !c.is_digit(radix) {
+                break;
+            }
+            self.advance();
+        }
+        Some(number_token(
+            &self.src[prefix_start..self.pos],
+            &self.src[start..self.pos],
+            radix,
+        ))
+    }
+
+    fn try_parse_comment(&mut self) -> Option<Token> {
+        match (self.current_char()?, self.next_char()?) {
+            ('/', '/') => {}
+            ('/', '*') => return Some(self.parse_block_comment()),
+            _ => return None,
+        }
+        self.advance();
+        self.advance();
+        let start = self.pos;
+        while let Some(c) = self.current_char() {
+            if c == '\n' {
+                break;
+            }
+            self.advance();
+        }
+        Some(Token::Comment(self.src[start..self.pos].trim().to_string()))
+    }
+
+    /// Lexes a `/* ... */` comment, which may span lines but doesn't nest.
+    fn parse_block_comment(&mut self) -> Token {
+        self.advance();
+        self.advance();
+        let start = self.pos;
+        let Some(length) = self.src[start..].find("*/") else {
+            // Consume the rest so lexing ends after the error.
+            while self.current_char().is_some() {
+                self.advance();
+            }
+            return Token::Error(LexError::UnterminatedComment);
+        };
+        while self.pos < start + length + 2 {
+            self.advance();
+        }
+        Token::Comment(self.src[start..start + length].trim().to_string())
+    }
+
+    fn try_parse_string(&mut self) -> Option<Token> {
+        if self.current_char()? != '"' {
+            return None;
+        }
+        self.advance();
+        let start = self.pos;
+        while let Some(c) = self.current_char() {
+            if c == '"' {
+                let value = self.src[start..self.pos].to_string();
+                self.advance();
+                return Some(Token::StringLiteral(value));
+            }
+            self.advance();
+        }
+        Some(Token::Error(LexError::UnterminatedString))
     }
 }
 
-impl Iterator for Lexer {
-    type Item = Token;
+/// The token for the literal `text`, whose digits are `digits`.
+fn number_token(text: &str, digits: &str, radix: u32) -> Token {
+    match i64::from_str_radix(digits, radix) {
+        Ok(value) => Token::Number(value),
+        Err(_) => Token::Error(LexError::NumberTooLarge(text.to_string())),
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.skip_whitespace();
+impl Lexer {
+    fn next_token(&mut self) -> Option<Token> {
         let current_char = self.current_char()?;
         if let Some(token) = self.try_parse_identifier() {
             return Some(token);
@@ -94,19 +479,175 @@ impl Iterator for Lexer {
         if let Some(token) = self.try_parse_number() {
             return Some(token);
         }
+        if let Some(token) = self.try_parse_string() {
+            return Some(token);
+        }
+        if let Some(token) = self.try_parse_comment() {
+            return Some(token);
+        }
+        if let Some(token) = self.try_parse_two_char_operator() {
+            return Some(token);
+        }
         if let Some(token) = match current_char {
             '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            '*' => Some(Token::Star),
+            '/' => Some(Token::Slash),
+            '%' => Some(Token::Percent),
+            '&' => Some(Token::Ampersand),
+            '|' => Some(Token::Pipe),
+            '^' => Some(Token::Caret),
             '=' => Some(Token::Equals),
+            '<' => Some(Token::Less),
+            '>' => Some(Token::Greater),
             ';' => Some(Token::Semicolon),
             '{' => Some(Token::OpenBracket),
             '}' => Some(Token::CloseBracket),
+            '(' => Some(Token::OpenParen),
+            ')' => Some(Token::CloseParen),
+            '[' => Some(Token::OpenSquareBracket),
+            ']' => Some(Token::CloseSquareBracket),
+            ',' => Some(Token::Comma),
+            ':' => Some(Token::Colon),
+            '.' => Some(Token::Dot),
             _ => None,
         } {
-            self.pos += 1;
+            self.advance();
             return Some(token);
         }
-        panic!()
+        self.advance();
+        Some(Token::Error(LexError::UnexpectedChar(current_char)))
+    }
+
+    /// Scans the token at the current position.
+    fn scan(&mut self) -> Option<SpannedToken> {
+        self.skip_whitespace();
+        let (mut start, mut line, mut column) = (self.pos, self.line, self.column);
+        let token = loop {
+            if self.keep_comments {
+                break self.next_token()?;
+            }
+            match self.try_parse_comment() {
+                Some(Token::Comment(_)) => {
+                    self.skip_whitespace();
+                    (start, line, column) = (self.pos, self.line, self.column);
+                }
+                // An unterminated comment is an error even when skipping.
+                Some(error) => break error,
+                None => break self.next_token()?,
+            }
+        };
+        Some(SpannedToken {
+            token,
+            span: Span {
+                start,
+                end: self.pos,
+                line,
+                column,
+            },
+        })
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.scan(),
+        }
+    }
+}
+
+/// A change to source text: the bytes in `range` of the old text were
+/// replaced by `text`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl Lexer {
+    /// Re-lexes the source after `edit`, given the `tokens` of the source
+    /// before it, and returns the same tokens a full lex of the new source
+    /// would. Only the tokens around the edit are scanned again; the ones
+    /// before it are kept and the ones after it are shifted into place.
+    ///
+    /// The lexer must hold the new source and not have been advanced yet.
+    pub fn relex(mut self, tokens: &[SpannedToken], edit: &TextEdit) -> Vec<SpannedToken> {
+        // A token's end can depend on a character after it, as in `0x1`, so
+        // the last token ending before the edit is re-lexed too.
+        let first_changed = tokens
+            .iter()
+            .take_while(|t| t.span.end < edit.range.start)
+            .count()
+            .saturating_sub(1);
+        let mut result = tokens[..first_changed].to_vec();
+        if let Some(last) = result.last() {
+            self.seek(last.span);
+        }
+
+        let new_end = edit.range.start + edit.text.len();
+        let shift = |position: usize| position + new_end - edit.range.end;
+        let mut old = tokens[first_changed..]
+            .iter()
+            .position(|t| t.span.start >= edit.range.end)
+            .map_or(tokens.len(), |i| first_changed + i);
+        for token in self {
+            while old < tokens.len() && shift(tokens[old].span.start) < token.span.start {
+                old += 1;
+            }
+            // Scanning from the same place in unchanged text gives the same
+            // tokens, so the rest of the old tokens can be reused.
+            if old < tokens.len() && shift(tokens[old].span.start) == token.span.start {
+                let anchor = tokens[old].span;
+                for old_token in &tokens[old..] {
+                    let mut span = old_token.span;
+                    if span.line == anchor.line {
+                        span.column = span.column + token.span.column - anchor.column;
+                    }
+                    span.line = span.line + token.span.line - anchor.line;
+                    span.start = shift(span.start);
+                    span.end = shift(span.end);
+                    result.push(SpannedToken {
+                        token: old_token.token.clone(),
+                        span,
+                    });
+                }
+                return result;
+            }
+            result.push(token);
+        }
+        result
     }
+
+    /// Moves to the end of the token at `span`, tracking lines and columns.
+    fn seek(&mut self, span: Span) {
+        (self.pos, self.line, self.column) = (span.start, span.line, span.column);
+        while self.pos < span.end {
+            self.advance();
+        }
+    }
+}
+
+/// Every token of `source`, comments included, as a JSON array of
+/// `{"kind", "line", "column", "length"}` objects for an editor's semantic
+/// highlighting. Like `column`, `length` counts characters.
+pub fn tokens_to_json(source: &str) -> String {
+    let tokens: Vec<String> = Lexer::new(source.to_string())
+        .keep_comments()
+        .map(|t| {
+            format!(
+                "{{\"kind\":{},\"line\":{},\"column\":{},\"length\":{}}}",
+                json_string(t.token.kind()),
+                t.span.line,
+                t.span.column,
+                source[t.span.start..t.span.end].chars().count()
+            )
+        })
+        .collect();
+    format!("[{}]", tokens.join(","))
 }
 
 #[cfg(test)]
@@ -142,16 +683,393 @@ mod tests {
         ];
 
         let lexer = Lexer::new(input.to_string());
-        let actual_tokens: Vec<Token> = lexer.collect();
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(actual_tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_arithmetic_operators() {
+        let input = "(a - 1) * 2 / 3 % 4";
+
+        let expected_tokens = vec![
+            Token::OpenParen,
+            Token::Identifier("a".to_string()),
+            Token::Minus,
+            Token::Number(1),
+            Token::CloseParen,
+            Token::Star,
+            Token::Number(2),
+            Token::Slash,
+            Token::Number(3),
+            Token::Percent,
+            Token::Number(4),
+        ];
+
+        let lexer = Lexer::new(input.to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(actual_tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_comparison_operators() {
+        let input = "assert a == b != c < d <= e > f >= g = h;";
+
+        let expected_tokens = vec![
+            Token::Assert,
+            Token::Identifier("a".to_string()),
+            Token::EqualsEquals,
+            Token::Identifier("b".to_string()),
+            Token::NotEquals,
+            Token::Identifier("c".to_string()),
+            Token::Less,
+            Token::Identifier("d".to_string()),
+            Token::LessEquals,
+            Token::Identifier("e".to_string()),
+            Token::Greater,
+            Token::Identifier("f".to_string()),
+            Token::GreaterEquals,
+            Token::Identifier("g".to_string()),
+            Token::Equals,
+            Token::Identifier("h".to_string()),
+            Token::Semicolon,
+        ];
+
+        let lexer = Lexer::new(input.to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
 
         assert_eq!(actual_tokens, expected_tokens);
     }
 
     #[test]
-    #[should_panic]
-    fn test_stops_at_invalid_char() {
+    fn test_bitwise_operators() {
+        let input = "a & b | c ^ d << 1 >> 2 < e";
+
+        let expected_tokens = vec![
+            Token::Identifier("a".to_string()),
+            Token::Ampersand,
+            Token::Identifier("b".to_string()),
+            Token::Pipe,
+            Token::Identifier("c".to_string()),
+            Token::Caret,
+            Token::Identifier("d".to_string()),
+            Token::ShiftLeft,
+            Token::Number(1),
+            Token::ShiftRight,
+            Token::Number(2),
+            Token::Less,
+            Token::Identifier("e".to_string()),
+        ];
+
+        let lexer = Lexer::new(input.to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(actual_tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_string_literal() {
+        let input = "include \"lib/other.hand\";";
+
+        let expected_tokens = vec![
+            Token::Include,
+            Token::StringLiteral("lib/other.hand".to_string()),
+            Token::Semicolon,
+        ];
+
+        let lexer = Lexer::new(input.to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(actual_tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let lexer = Lexer::new("include \"other.hand;".to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(
+            actual_tokens,
+            vec![Token::Include, Token::Error(LexError::UnterminatedString)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_char_is_an_error_token() {
         let input = "let x = @123";
         let lexer = Lexer::new(input.to_string());
-        let _: Vec<Token> = lexer.collect();
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(
+            actual_tokens,
+            vec![
+                Token::Let,
+                Token::Identifier("x".to_string()),
+                Token::Equals,
+                Token::Error(LexError::UnexpectedChar('@')),
+                Token::Number(123),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_underscore_in_identifier() {
+        let lexer = Lexer::new("max_of _x".to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(
+            actual_tokens,
+            vec![
+                Token::Identifier("max_of".to_string()),
+                Token::Error(LexError::UnexpectedChar('_')),
+                Token::Identifier("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_number_too_large() {
+        let lexer = Lexer::new("99999999999999999999 0xFFFFFFFFFFFFFFFFF".to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(
+            actual_tokens,
+            vec![
+                Token::Error(LexError::NumberTooLarge("99999999999999999999".to_string())),
+                Token::Error(LexError::NumberTooLarge("0xFFFFFFFFFFFFFFFFF".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 5;\n  print x;";
+        let lexer = Lexer::new(input.to_string());
+        let spans: Vec<Span> = lexer.map(|t| t.span).collect();
+
+        assert_eq!(
+            spans[1],
+            Span {
+                start: 4,
+                end: 5,
+                line: 1,
+                column: 5
+            }
+        );
+        assert_eq!(
+            spans[5],
+            Span {
+                start: 13,
+                end: 18,
+                line: 2,
+                column: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_tokens_to_json() {
+        assert_eq!(
+            tokens_to_json("let x = 10;\n  print \"é\";"),
+            concat!(
+                "[{\"kind\":\"keyword\",\"line\":1,\"column\":1,\"length\":3},",
+                "{\"kind\":\"identifier\",\"line\":1,\"column\":5,\"length\":1},",
+                "{\"kind\":\"operator\",\"line\":1,\"column\":7,\"length\":1},",
+                "{\"kind\":\"number\",\"line\":1,\"column\":9,\"length\":2},",
+                "{\"kind\":\"operator\",\"line\":1,\"column\":11,\"length\":1},",
+                "{\"kind\":\"keyword\",\"line\":2,\"column\":3,\"length\":5},",
+                "{\"kind\":\"string\",\"line\":2,\"column\":9,\"length\":3},",
+                "{\"kind\":\"operator\",\"line\":2,\"column\":12,\"length\":1}]"
+            )
+        );
+        assert_eq!(tokens_to_json(""), "[]");
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// leading\nlet x = 1; // trailing\n";
+
+        let skipped: Vec<Token> = Lexer::new(input.to_string()).map(|t| t.token).collect();
+        assert_eq!(
+            skipped,
+            vec![
+                Token::Let,
+                Token::Identifier("x".to_string()),
+                Token::Equals,
+                Token::Number(1),
+                Token::Semicolon,
+            ]
+        );
+
+        let kept: Vec<Token> = Lexer::new(input.to_string())
+            .keep_comments()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(kept[0], Token::Comment("leading".to_string()));
+        assert_eq!(kept[6], Token::Comment("trailing".to_string()));
+    }
+
+    #[test]
+    fn test_peek_does_not_advance() {
+        let mut lexer = Lexer::new("let x;".to_string());
+
+        assert_eq!(lexer.peek(), Some(&Token::Let));
+        assert_eq!(lexer.peek(), Some(&Token::Let));
+        assert_eq!(lexer.bump(), Some(Token::Let));
+        assert_eq!(lexer.peek(), Some(&Token::Identifier("x".to_string())));
+        let spanned = lexer.next().unwrap();
+        assert_eq!((spanned.span.start, spanned.span.end), (4, 5));
+        assert_eq!(lexer.bump(), Some(Token::Semicolon));
+        assert_eq!(lexer.peek(), None);
+        assert_eq!(lexer.bump(), None);
+    }
+
+    #[test]
+    fn test_hex_and_binary_literals() {
+        let input = "0xFF 0x1f 0b1010 0B1 10 0xg 0b2";
+
+        let expected_tokens = vec![
+            Token::Number(255),
+            Token::Number(31),
+            Token::Number(10),
+            Token::Number(1),
+            Token::Number(10),
+            Token::Error(LexError::MissingDigits("0x".to_string())),
+            Token::Identifier("g".to_string()),
+            Token::Error(LexError::MissingDigits("0b".to_string())),
+            Token::Number(2),
+        ];
+
+        let lexer = Lexer::new(input.to_string());
+        let actual_tokens: Vec<Token> = lexer.map(|t| t.token).collect();
+
+        assert_eq!(actual_tokens, expected_tokens);
+    }
+
+    fn last_token(input: &str) -> SpannedToken {
+        Lexer::new(input.to_string()).last().unwrap()
+    }
+
+    #[test]
+    fn test_tokens_running_to_eof() {
+        let number = last_token("let x = 42");
+        assert_eq!(number.token, Token::Number(42));
+        assert_eq!((number.span.start, number.span.end), (8, 10));
+
+        let identifier = last_token("print xyz");
+        assert_eq!(identifier.token, Token::Identifier("xyz".to_string()));
+        assert_eq!((identifier.span.start, identifier.span.end), (6, 9));
+
+        assert_eq!(
+            last_token("0b").token,
+            Token::Error(LexError::MissingDigits("0b".to_string()))
+        );
+        assert_eq!(last_token("0x1F").token, Token::Number(31));
+        assert_eq!(
+            last_token("let é").token,
+            Token::Identifier("é".to_string())
+        );
+        assert_eq!(last_token("loop 1 { }").token, Token::CloseBracket);
+        assert_eq!(last_token("4 /").token, Token::Slash);
+        assert_eq!(
+            last_token("1 !").token,
+            Token::Error(LexError::UnexpectedChar('!'))
+        );
+    }
+
+    #[test]
+    fn test_comments_at_eof() {
+        let tokens: Vec<Token> = Lexer::new("let x = 1; // done".to_string())
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens.last(), Some(&Token::Semicolon));
+        let kept = Lexer::new("x; //".to_string())
+            .keep_comments()
+            .last()
+            .unwrap();
+        assert_eq!(kept.token, Token::Comment(String::new()));
+        assert_eq!(kept.span.end, 5);
+
+        let tokens: Vec<Token> = Lexer::new("x /* a\n b */ y /**/".to_string())
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Identifier("x".to_string()),
+                Token::Identifier("y".to_string()),
+            ]
+        );
+        let kept: Vec<Token> = Lexer::new("/* a\n b */".to_string())
+            .keep_comments()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(kept, vec![Token::Comment("a\n b".to_string())]);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        for input in ["let x = 1; /* oops", "x /*/", "/*"] {
+            for keep_comments in [false, true] {
+                let mut lexer = Lexer::new(input.to_string());
+                if keep_comments {
+                    lexer = lexer.keep_comments();
+                }
+                let error = lexer.last().unwrap();
+                assert_eq!(error.token, Token::Error(LexError::UnterminatedComment));
+                assert_eq!(error.span.end, input.len(), "{}", input);
+            }
+        }
+        let error = Lexer::new("x\n  /* a\n".to_string()).last().unwrap();
+        assert_eq!((error.span.line, error.span.column), (2, 3));
+    }
+
+    #[test]
+    fn test_relex_matches_full_lex() {
+        let source = "let x = 5;\nloop 3 {\n    x = x + 10; // add\n    print x;\n}\nprint 0xg;";
+        let at = |needle: &str| source.find(needle).unwrap();
+        let edits = [
+            // Replace the `10` in the middle.
+            (at("10")..at("10") + 2, "200"),
+            // Rename a variable so it merges with the token after it.
+            (at("x = x")..at("x = x") + 5, "xx+"),
+            // Split a line, shifting every later line.
+            (at("\nloop")..at("\nloop"), "\n\n"),
+            // Join two lines.
+            (at("\nloop")..at("loop"), ""),
+            // Make `0xg` a hex literal, which changes the token before.
+            (at("g;")..at("g;") + 1, "1"),
+            // Start a comment that swallows the rest of the line.
+            (at("+ 10")..at("+ 10"), "//"),
+            // Open a string that never closes.
+            (0..0, "\""),
+        ];
+
+        for (range, text) in edits {
+            let edit = TextEdit {
+                range: range.clone(),
+                text: text.to_string(),
+            };
+            let mut edited = source.to_string();
+            edited.replace_range(range, text);
+            for keep_comments in [false, true] {
+                let lex = |src: &str| {
+                    let lexer = Lexer::new(src.to_string());
+                    if keep_comments {
+                        lexer.keep_comments()
+                    } else {
+                        lexer
+                    }
+                };
+                let old: Vec<SpannedToken> = lex(source).collect();
+                assert_eq!(
+                    lex(&edited).relex(&old, &edit),
+                    lex(&edited).collect::<Vec<_>>(),
+                    "{:?}",
+                    edited
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,645 @@
+pub mod asm_generator;
+pub mod ast;
+pub mod ast_graph;
+pub mod ast_printer;
+pub mod backend;
+pub mod cfg;
+pub mod code_generator;
+pub mod const_eval;
+pub mod diagnostic;
+pub mod include;
+pub mod interpreter;
+pub mod lexer;
+pub mod llvm_generator;
+pub mod parser;
+pub mod peephole;
+pub mod scope;
+pub mod semantic_analyzer;
+pub mod span;
+pub mod timings;
+pub mod vm;
+pub mod wat_generator;
+
+use std::fmt;
+use std::io::{self, Read};
+
+use ast::AbstractSyntaxTree;
+use backend::{Backend, UnknownBackend, backend_for};
+use code_generator::{BraceStyle, CodegenOptions, Overflow, generate_c_code_with_options};
+use const_eval::IntWidth;
+use diagnostic::Diagnostic;
+use lexer::Lexer;
+use parser::ParseError;
+use semantic_analyzer::{Analysis, SemanticAnalyzer, SemanticError, SemanticWarning};
+use timings::{Timings, count_statements, lex_and_parse_timed, time};
+
+/// Everything that changes what the compiler accepts or produces, parsed
+/// once from the command line and handed to each stage.
+#[derive(Debug, Clone)]
+pub struct CompilerOptions {
+    pub codegen: CodegenOptions,
+    /// Reject the implicit int/bool coercions C allows, see
+    /// `SemanticAnalyzer::check_with`.
+    pub strict: bool,
+    /// Fail on any warning as if it were an error.
+    pub warnings_as_errors: bool,
+    /// Keep comments in the AST so they can be copied to the output.
+    pub keep_comments: bool,
+    /// Name of the backend to generate code with, such as `c` or `wat`.
+    pub emit: String,
+    /// Run the program instead of writing the generated code.
+    pub run: Option<Run>,
+}
+
+/// What runs the program for `--run` and `--run-vm`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Run {
+    /// Walks the AST, see `interpreter`.
+    Interpreter,
+    /// Runs the output of the `asm` backend, see `vm`.
+    Vm,
+}
+
+impl Default for CompilerOptions {
+    fn default() -> Self {
+        CompilerOptions {
+            codegen: CodegenOptions::default(),
+            strict: false,
+            warnings_as_errors: false,
+            keep_comments: false,
+            emit: "c".to_string(),
+            run: None,
+        }
+    }
+}
+
+impl CompilerOptions {
+    pub fn int_width(&self) -> IntWidth {
+        self.codegen.int_width
+    }
+
+    /// Rejects combinations the chosen backend can't honor. The WebAssembly,
+    /// LLVM and stack machine backends always wrap on overflow and print
+    /// bools as numbers, and so does the interpreter. `--run-vm` runs the
+    /// stack machine backend's output, so it's held to the same limits.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = &self.codegen.as_function {
+            let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_identifier || name == "main" || name.starts_with("_hand") {
+                return Err(format!("--as-function can't be named '{}'", name));
+            }
+            if self.emit != "c" {
+                return Err(format!(
+                    "--as-function is not supported with --emit {}",
+                    self.emit
+                ));
+            }
+        }
+        if self.run == Some(Run::Vm) {
+            if self.codegen.trace {
+                return Err("--trace is not supported with --run-vm".to_string());
+            }
+            if self.codegen.overflow == Overflow::Checked {
+                return Err("--overflow checked is not supported with --run-vm".to_string());
+            }
+            if self.codegen.bool_words {
+                return Err("--bool-words is not supported with --run-vm".to_string());
+            }
+        }
+        if self.codegen.trace && self.emit != "c" {
+            return Err(format!(
+                "--trace is not supported with --emit {}",
+                self.emit
+            ));
+        }
+        if (self.codegen.indent != 0 || self.codegen.brace_style != BraceStyle::Attach)
+            && self.emit != "c"
+        {
+            return Err(format!(
+                "--indent and --brace-style are not supported with --emit {}",
+                self.emit
+            ));
+        }
+        if self.run == Some(Run::Interpreter) && self.codegen.bool_words {
+            return Err("--bool-words is not supported with --run".to_string());
+        }
+        if !matches!(self.emit.as_str(), "wat" | "llvm" | "asm") {
+            return Ok(());
+        }
+        if self.codegen.overflow == Overflow::Checked {
+            return Err(format!(
+                "--overflow checked is not supported with --emit {}",
+                self.emit
+            ));
+        }
+        if self.codegen.bool_words {
+            return Err(format!(
+                "--bool-words is not supported with --emit {}",
+                self.emit
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Why `compile` produced no code.
+#[derive(Debug, Clone)]
+pub enum CompileError {
+    /// The options contradict each other, see `CompilerOptions::validate`.
+    Options(String),
+    UnknownBackend(UnknownBackend),
+    /// The source isn't a well-formed program; this includes invalid tokens.
+    Parse(ParseError),
+    /// Analysis found errors. The width is the one the program was analyzed
+    /// with, which some messages name; the warnings found alongside the
+    /// errors are kept for callers that report them too.
+    Semantic {
+        analysis: Analysis,
+        int_width: IntWidth,
+    },
+    /// The program has warnings and `warnings_as_errors` is set.
+    Warnings(Vec<SemanticWarning>),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Options(message) => write!(f, "{}", message),
+            CompileError::UnknownBackend(error) => write!(f, "{}", error),
+            CompileError::Parse(error) => {
+                write!(
+                    f,
+                    "{}:{}: {} [{}]",
+                    error.span.line,
+                    error.span.column,
+                    error,
+                    error.code()
+                )
+            }
+            CompileError::Semantic {
+                analysis,
+                int_width,
+            } => {
+                for (i, error) in analysis.errors.iter().enumerate() {
+                    let diagnostic = Diagnostic::from_error(error, *int_width);
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(
+                        f,
+                        "{}:{}: {} [{}]",
+                        diagnostic.span.line,
+                        diagnostic.span.column,
+                        diagnostic.message,
+                        error.code()
+                    )?;
+                }
+                Ok(())
+            }
+            CompileError::Warnings(warnings) => {
+                for (i, warning) in warnings.iter().enumerate() {
+                    let diagnostic = Diagnostic::from_warning(warning);
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(
+                        f,
+                        "{}:{}: {}",
+                        diagnostic.span.line, diagnostic.span.column, diagnostic.message
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Reads a whole program from `reader`, such as stdin.
+pub fn read_source(mut reader: impl Read) -> io::Result<String> {
+    let mut source = String::new();
+    reader.read_to_string(&mut source)?;
+    Ok(source)
+}
+
+/// Analyzes `ast` and generates C from it, for tools that build or transform
+/// an AST themselves instead of parsing source.
+pub fn analyze_and_generate(
+    ast: &AbstractSyntaxTree,
+    options: &CodegenOptions,
+) -> Result<String, Vec<SemanticError>> {
+    SemanticAnalyzer::analyze_with_width(ast, options.int_width)?;
+    Ok(generate_c_code_with_options(ast, options))
+}
+
+/// Runs the whole pipeline on `source` and returns the code generated by
+/// the backend `options` ask for. Never panics, whatever the input, so it
+/// can be used as a fuzz target.
+pub fn compile(source: &str, options: &CompilerOptions) -> Result<String, CompileError> {
+    compile_timed(source, options).0
+}
+
+/// Like `compile`, also reporting how long each stage took. Stages after a
+/// failed parse or analysis aren't run and report zero.
+pub fn compile_timed(
+    source: &str,
+    options: &CompilerOptions,
+) -> (Result<String, CompileError>, Timings) {
+    let mut timings = Timings::default();
+    let result = checked_backend(options).and_then(|backend| {
+        let ast = parse_timed(source, options, &mut timings)?;
+        emit_timed(&ast, options, backend.as_ref(), &mut timings)
+    });
+    (result, timings)
+}
+
+/// The backend `options` ask for, once they're known to make sense.
+pub fn checked_backend(options: &CompilerOptions) -> Result<Box<dyn Backend>, CompileError> {
+    options.validate().map_err(CompileError::Options)?;
+    backend_for(&options.emit, &options.codegen).map_err(CompileError::UnknownBackend)
+}
+
+fn parse_timed(
+    source: &str,
+    options: &CompilerOptions,
+    timings: &mut Timings,
+) -> Result<AbstractSyntaxTree, CompileError> {
+    let mut lexer = Lexer::new(source.to_string());
+    if options.keep_comments {
+        lexer = lexer.keep_comments();
+    }
+    let ast = lex_and_parse_timed(lexer, timings).map_err(CompileError::Parse)?;
+    timings.statements = count_statements(&ast.statement_list);
+    Ok(ast)
+}
+
+/// Analyzes `ast` as `options` ask. Errors fail it, and so do warnings with
+/// `warnings_as_errors`; otherwise the analysis is returned, warnings and
+/// all, for the caller to report.
+pub fn check_timed(
+    ast: &AbstractSyntaxTree,
+    options: &CompilerOptions,
+    timings: &mut Timings,
+) -> Result<Analysis, CompileError> {
+    let analysis = time(&mut timings.analysis, || {
+        SemanticAnalyzer::check_with(ast, options)
+    });
+    if !analysis.errors.is_empty() {
+        return Err(CompileError::Semantic {
+            analysis,
+            int_width: options.int_width(),
+        });
+    }
+    if options.warnings_as_errors && !analysis.warnings.is_empty() {
+        return Err(CompileError::Warnings(analysis.warnings));
+    }
+    Ok(analysis)
+}
+
+/// Analyzes `ast` and, if it's valid, generates code from it with `backend`.
+fn emit_timed(
+    ast: &AbstractSyntaxTree,
+    options: &CompilerOptions,
+    backend: &dyn Backend,
+    timings: &mut Timings,
+) -> Result<String, CompileError> {
+    check_timed(ast, options, timings)?;
+    Ok(time(&mut timings.codegen, || backend.emit(ast)))
+}
+
+/// Compiles one program again and again, as a watch mode does, keeping the
+/// last AST so that unchanged source isn't lexed and parsed again.
+pub struct Compiler {
+    options: CompilerOptions,
+    /// The source the AST was parsed from, and the AST.
+    cached: Option<(String, AbstractSyntaxTree)>,
+}
+
+impl Compiler {
+    pub fn new(options: CompilerOptions) -> Self {
+        Compiler {
+            options,
+            cached: None,
+        }
+    }
+
+    /// Like `compile`, reusing the cached AST if `source` is the same as
+    /// the last time it parsed. The flag says whether it was reused.
+    pub fn compile_if_changed(&mut self, source: &str) -> (Result<String, CompileError>, bool) {
+        let backend = match checked_backend(&self.options) {
+            Ok(backend) => backend,
+            Err(error) => return (Err(error), false),
+        };
+        let mut timings = Timings::default();
+        let reused = matches!(&self.cached, Some((cached, _)) if cached == source);
+        if !reused {
+            // Source that doesn't parse isn't cached, so fixing it parses
+            // again.
+            self.cached = None;
+            match parse_timed(source, &self.options, &mut timings) {
+                Ok(ast) => self.cached = Some((source.to_string(), ast)),
+                Err(error) => return (Err(error), false),
+            }
+        }
+        let Some((_, ast)) = &self.cached else {
+            unreachable!();
+        };
+        let result = emit_timed(ast, &self.options, backend.as_ref(), &mut timings);
+        (result, reused)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::*;
+    use crate::span::Span;
+    use std::io::Cursor;
+
+    fn identifier(name: &str) -> Identifier {
+        Identifier {
+            name: name.to_string(),
+            span: Span::default(),
+        }
+    }
+
+    fn expr(kind: ExprKind) -> Expr {
+        Expr {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn test_analyze_and_generate_hand_built_ast() {
+        // let x = 2; print x * 3;
+        let ast = AbstractSyntaxTree {
+            statement_list: StatementList {
+                statements: vec![
+                    Statement::Let(LetStatement {
+                        identifier: identifier("x"),
+                        mutable: false,
+                        declared_type: None,
+                        value: Some(expr(ExprKind::Term(Term::Number(2)))),
+                        span: Span::default(),
+                    }),
+                    Statement::Print(PrintStatement {
+                        arguments: vec![PrintArgument::Value(expr(ExprKind::Binary(BinaryExpr {
+                            operator: BinaryOperator::Multiply,
+                            lhs: Box::new(expr(ExprKind::Term(Term::Identifier(identifier("x"))))),
+                            rhs: Box::new(expr(ExprKind::Term(Term::Number(3)))),
+                        })))],
+                        format: PrintFormat::Decimal,
+                        span: Span::default(),
+                    }),
+                ],
+            },
+        };
+
+        assert_eq!(
+            analyze_and_generate(&ast, &CodegenOptions::default()).unwrap(),
+            "#include <stdio.h>\nint main() {\nint x = 2;\nprintf(\"%d\\n\", x * 3);\nreturn 0;\n}\n"
+        );
+
+        let mut ast = ast;
+        ast.statement_list.statements.remove(0);
+        let errors = analyze_and_generate(&ast, &CodegenOptions::default()).unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::UndeclaredVariable { name, .. }] if name == "x"
+        ));
+    }
+
+    const EMPTY_MAIN: &str = "#include <stdio.h>\nint main() {\nreturn 0;\n}\n";
+
+    #[test]
+    fn test_compile_empty_source() {
+        assert_eq!(
+            compile("", &CompilerOptions::default()).unwrap(),
+            EMPTY_MAIN
+        );
+    }
+
+    #[test]
+    fn test_compile_whitespace_only_source() {
+        assert_eq!(
+            compile(" \n\t\r\n  ", &CompilerOptions::default()).unwrap(),
+            EMPTY_MAIN
+        );
+    }
+
+    #[test]
+    fn test_compile_comment_only_source() {
+        let source = "// nothing here\n/* or\n   here */\n";
+        assert_eq!(
+            compile(source, &CompilerOptions::default()).unwrap(),
+            EMPTY_MAIN
+        );
+
+        // Kept comments still leave `main` with nothing else to do.
+        let options = CompilerOptions {
+            keep_comments: true,
+            ..CompilerOptions::default()
+        };
+        let c = compile(source, &options).unwrap();
+        assert!(c.contains("/* nothing here */") && c.ends_with("return 0;\n}\n"));
+    }
+
+    #[test]
+    fn test_compile_multiline_source_from_reader() {
+        let input = Cursor::new("let mut x = 2;\nloop 3 {\n    x = x * 2;\n}\nprint x;\n");
+        let source = read_source(input).unwrap();
+
+        assert_eq!(source.lines().count(), 5);
+        let options = CompilerOptions::default();
+        assert_eq!(
+            compile(&source, &options).unwrap(),
+            compile(&source.replace('\n', " "), &options).unwrap()
+        );
+        assert!(
+            compile(&source, &options)
+                .unwrap()
+                .contains("printf(\"%d\\n\", x);")
+        );
+    }
+
+    #[test]
+    fn test_options_change_output() {
+        let source = "let x; print x * 2;";
+        let options = CompilerOptions {
+            codegen: CodegenOptions {
+                int_width: IntWidth::Bits64,
+                overflow: Overflow::Wrap,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let code = compile(source, &options).unwrap();
+        assert!(code.contains("long long x = 0;"));
+        assert!(code.contains("_hand_mul(x, 2LL)"));
+        assert!(
+            !compile(source, &CompilerOptions::default())
+                .unwrap()
+                .contains("long long")
+        );
+
+        let options = CompilerOptions {
+            emit: "llvm".to_string(),
+            ..Default::default()
+        };
+        assert!(
+            compile(source, &options)
+                .unwrap()
+                .contains("define i32 @main()")
+        );
+
+        // `let x;` read before it's assigned is only a warning.
+        let options = CompilerOptions {
+            warnings_as_errors: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile(source, &options),
+            Err(CompileError::Warnings(warnings)) if warnings.len() == 1
+        ));
+
+        let options = CompilerOptions {
+            emit: "wat".to_string(),
+            codegen: CodegenOptions {
+                overflow: Overflow::Checked,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile(source, &options),
+            Err(CompileError::Options(_))
+        ));
+
+        let options = CompilerOptions {
+            emit: "asm".to_string(),
+            codegen: CodegenOptions {
+                bool_words: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile(source, &options),
+            Err(CompileError::Options(_))
+        ));
+
+        let options = CompilerOptions {
+            run: Some(Run::Interpreter),
+            codegen: CodegenOptions {
+                bool_words: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile(source, &options),
+            Err(CompileError::Options(_))
+        ));
+
+        for codegen in [
+            CodegenOptions {
+                overflow: Overflow::Checked,
+                ..Default::default()
+            },
+            CodegenOptions {
+                trace: true,
+                ..Default::default()
+            },
+        ] {
+            let options = CompilerOptions {
+                run: Some(Run::Vm),
+                codegen,
+                ..Default::default()
+            };
+            assert!(matches!(
+                compile(source, &options),
+                Err(CompileError::Options(_))
+            ));
+        }
+
+        // The function has to be a C name other than `main`.
+        for name in ["main", "2run", "run-it"] {
+            let options = CompilerOptions {
+                codegen: CodegenOptions {
+                    as_function: Some(name.to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert!(matches!(
+                compile(source, &options),
+                Err(CompileError::Options(_))
+            ));
+        }
+
+        let options = CompilerOptions {
+            emit: "llvm".to_string(),
+            codegen: CodegenOptions {
+                trace: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile(source, &options),
+            Err(CompileError::Options(_))
+        ));
+
+        let options = CompilerOptions {
+            emit: "wat".to_string(),
+            codegen: CodegenOptions {
+                brace_style: BraceStyle::Allman,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile(source, &options),
+            Err(CompileError::Options(_))
+        ));
+    }
+
+    #[test]
+    fn test_semantic_error_names_configured_width() {
+        let options = CompilerOptions {
+            codegen: CodegenOptions {
+                int_width: IntWidth::Bits64,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let error = compile("print 1 << 64;", &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "1:12: Shift amount 64 is out of range for a 64-bit integer [E0018]"
+        );
+    }
+
+    #[test]
+    fn test_compiler_reuses_unchanged_ast() {
+        let mut compiler = Compiler::new(CompilerOptions::default());
+        let (first, reused) = compiler.compile_if_changed("let x = 1; print x;");
+        assert!(!reused);
+        let (second, reused) = compiler.compile_if_changed("let x = 1; print x;");
+        assert!(reused);
+        assert_eq!(first.unwrap(), second.unwrap());
+
+        let (result, reused) = compiler.compile_if_changed("let x = 2; print x;");
+        assert!(!reused);
+        assert!(result.unwrap().contains("int x = 2;"));
+
+        // Source that failed to parse isn't cached, so it's parsed again.
+        let (result, reused) = compiler.compile_if_changed("let x = ;");
+        assert!(matches!(result, Err(CompileError::Parse(_))) && !reused);
+        let (_, reused) = compiler.compile_if_changed("let x = ;");
+        assert!(!reused);
+    }
+}
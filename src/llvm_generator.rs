@@ -0,0 +1,800 @@
+//! LLVM IR text backend. The program becomes `main`; every variable lives in
+//! an `alloca` slot, expressions compute into numbered temporaries, and
+//! `print` calls the C library's `printf`. The IR uses typed pointers, as
+//! LLVM 14 and earlier expect.
+
+use crate::ast::*;
+use crate::const_eval::{IntWidth, eval_const};
+use crate::scope::ScopeStack;
+
+pub fn generate_llvm(ast: &AbstractSyntaxTree, int_width: IntWidth) -> String {
+    let mut generator = LlvmGen {
+        ty: match int_width {
+            IntWidth::Bits32 => "i32",
+            IntWidth::Bits64 => "i64",
+        },
+        int_width,
+        scopes: ScopeStack::new(),
+        slots: Vec::new(),
+        allocas: Vec::new(),
+        body: Vec::new(),
+        block: "entry".to_string(),
+        temporaries: 0,
+        labels: 0,
+        loop_ends: Vec::new(),
+        constants: ScopeStack::new(),
+        strings: Vec::new(),
+        uses_pow: false,
+        uses_dprintf: false,
+        uses_scanf: false,
+    };
+    generator.generate_statement_list(&ast.statement_list);
+
+    let ty = generator.ty;
+    let mut lines = Vec::new();
+    for (i, text) in generator.strings.iter().enumerate() {
+        lines.push(format!(
+            "@.str.{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+            i,
+            text.len() + 1,
+            llvm_string(text)
+        ));
+    }
+    if !generator.strings.is_empty() {
+        lines.push(String::new());
+    }
+    lines.push("declare i32 @printf(i8*, ...)".to_string());
+    if generator.uses_scanf {
+        lines.push("declare i32 @scanf(i8*, ...)".to_string());
+    }
+    if generator.uses_dprintf {
+        lines.push("declare i32 @dprintf(i32, i8*, ...)".to_string());
+    }
+    lines.push(String::new());
+    if generator.uses_pow {
+        lines.push(pow_function(ty));
+        lines.push(String::new());
+    }
+    lines.push("define i32 @main() {".to_string());
+    lines.push("entry:".to_string());
+    lines.extend(generator.allocas.iter().map(|line| format!("  {}", line)));
+    lines.extend(generator.body);
+    lines.push("  ret i32 0".to_string());
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+/// What a source name refers to in the current scope.
+#[derive(Clone)]
+enum Binding {
+    /// An `alloca` holding one value.
+    Scalar(String),
+    /// An `alloca` holding an array of the given length.
+    Array(String, usize),
+}
+
+struct LlvmGen {
+    /// `i32` or `i64`, the type of every value.
+    ty: &'static str,
+    int_width: IntWidth,
+    scopes: ScopeStack<Binding>,
+    /// Every `alloca` name used so far; a shadowing declaration gets a
+    /// fresh one.
+    slots: Vec<String>,
+    /// The `alloca`s, all placed at the start of the entry block.
+    allocas: Vec<String>,
+    /// Instructions and labels of `main` after the `alloca`s.
+    body: Vec<String>,
+    /// Label of the basic block instructions are currently added to, for
+    /// `phi` nodes.
+    block: String,
+    temporaries: usize,
+    /// Counter for unique block labels.
+    labels: usize,
+    /// The block after every loop being generated, innermost last, which
+    /// `break` jumps to.
+    loop_ends: Vec<String>,
+    constants: ScopeStack<i64>,
+    /// Contents of every string global, `@.str.<index>`.
+    strings: Vec<String>,
+    uses_pow: bool,
+    uses_dprintf: bool,
+    uses_scanf: bool,
+}
+
+impl LlvmGen {
+    fn emit(&mut self, instruction: String) {
+        self.body.push(format!("  {}", instruction));
+    }
+
+    /// Emits `instruction`, which produces a value, into a new temporary.
+    fn assign(&mut self, instruction: String) -> String {
+        let temporary = format!("%t.{}", self.temporaries);
+        self.temporaries += 1;
+        self.emit(format!("{} = {}", temporary, instruction));
+        temporary
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    /// Starts the basic block `label`. The previous block must already end in
+    /// a terminator.
+    fn start_block(&mut self, label: String) {
+        self.body.push(format!("{}:", label));
+        self.block = label;
+    }
+
+    fn new_slot(&mut self, name: &str, ty: String) -> String {
+        let mut slot = name.to_string();
+        let mut suffix = 1;
+        while self.slots.contains(&slot) {
+            slot = format!("{}.{}", name, suffix);
+            suffix += 1;
+        }
+        self.slots.push(slot.clone());
+        self.allocas.push(format!("%{} = alloca {}", slot, ty));
+        format!("%{}", slot)
+    }
+
+    fn declare(&mut self, name: &str, binding: Binding) {
+        self.scopes.declare(name.to_string(), binding);
+    }
+
+    fn lookup(&self, name: &str) -> Binding {
+        self.scopes
+            .lookup(name)
+            .cloned()
+            .expect("undeclared variables are rejected by semantic analysis")
+    }
+
+    fn scalar(&self, identifier: &Identifier) -> String {
+        match self.lookup(&identifier.name) {
+            Binding::Scalar(slot) => slot,
+            Binding::Array(..) => unreachable!("arrays are only used through an index"),
+        }
+    }
+
+    /// A pointer to the start of the global holding `text` and a NUL.
+    fn string_pointer(&mut self, text: String) -> String {
+        let index = match self.strings.iter().position(|stored| *stored == text) {
+            Some(index) => index,
+            None => {
+                self.strings.push(text);
+                self.strings.len() - 1
+            }
+        };
+        let array = format!("[{} x i8]", self.strings[index].len() + 1);
+        format!(
+            "i8* getelementptr inbounds ({0}, {0}* @.str.{1}, i64 0, i64 0)",
+            array, index
+        )
+    }
+
+    fn generate_statement_list(&mut self, statement_list: &StatementList) {
+        for statement in &statement_list.statements {
+            self.generate_statement(statement);
+        }
+    }
+
+    fn generate_block(&mut self, block: &Block) {
+        self.scopes.enter_scope();
+        self.constants.enter_scope();
+        self.generate_statement_list(&block.statements);
+        self.constants.exit_scope();
+        self.scopes.exit_scope();
+    }
+
+    fn generate_statement(&mut self, statement: &Statement) {
+        let ty = self.ty;
+        match statement {
+            Statement::Let(let_stmt) => {
+                let value = match &let_stmt.value {
+                    Some(value) => self.generate_expression(value),
+                    None => "0".to_string(),
+                };
+                let slot = self.new_slot(&let_stmt.identifier.name, ty.to_string());
+                self.emit(format!("store {0} {1}, {0}* {2}", ty, value, slot));
+                self.declare(&let_stmt.identifier.name, Binding::Scalar(slot));
+            }
+            Statement::Const(const_stmt) => {
+                if let Some(value) = eval_const(&const_stmt.value, self.int_width) {
+                    self.constants
+                        .declare(const_stmt.identifier.name.clone(), value);
+                }
+            }
+            Statement::ArrayLet(array_let_stmt) => {
+                self.generate_array_let_statement(array_let_stmt)
+            }
+            Statement::Assignment(assign_stmt) => {
+                let value = self.generate_expression(&assign_stmt.value);
+                let pointer = match &assign_stmt.index {
+                    Some(index) => self.generate_element_pointer(&assign_stmt.identifier, index),
+                    None => self.scalar(&assign_stmt.identifier),
+                };
+                self.emit(format!("store {0} {1}, {0}* {2}", ty, value, pointer));
+            }
+            Statement::Loop(loop_stmt) => self.generate_loop_statement(loop_stmt),
+            Statement::Repeat(repeat_stmt) => self.generate_repeat_statement(repeat_stmt),
+            Statement::If(if_stmt) => self.generate_if_statement(if_stmt),
+            Statement::Print(print_stmt) => self.generate_print_statement(print_stmt),
+            Statement::Read(read_stmt) => {
+                // Like the C backend, input that isn't an integer exits with
+                // code 1.
+                let label = self.new_label();
+                let slot = self.scalar(&read_stmt.identifier);
+                self.uses_scanf = true;
+                let format = self.string_pointer(
+                    match self.int_width {
+                        IntWidth::Bits32 => "%d",
+                        IntWidth::Bits64 => "%lld",
+                    }
+                    .to_string(),
+                );
+                let count = self.assign(format!(
+                    "call i32 (i8*, ...) @scanf({}, {}* {})",
+                    format, ty, slot
+                ));
+                let read = self.assign(format!("icmp eq i32 {}, 1", count));
+                self.emit(format!(
+                    "br i1 {}, label %read.ok.{1}, label %read.fail.{1}",
+                    read, label
+                ));
+                self.start_block(format!("read.fail.{}", label));
+                self.fail("invalid input\n");
+                self.start_block(format!("read.ok.{}", label));
+            }
+            Statement::Assert(assert_stmt) => {
+                // Like the C backend, a failed assertion exits with code 1.
+                let label = self.new_label();
+                let condition = self.generate_condition(&assert_stmt.condition);
+                self.emit(format!(
+                    "br i1 {}, label %assert.ok.{1}, label %assert.fail.{1}",
+                    condition, label
+                ));
+                self.start_block(format!("assert.fail.{}", label));
+                let message = match &assert_stmt.message {
+                    Some(message) => {
+                        format!("assertion failed: {}\n", message.replace('%', "%%"))
+                    }
+                    None => "assertion failed\n".to_string(),
+                };
+                self.fail(&message);
+                self.start_block(format!("assert.ok.{}", label));
+            }
+            Statement::Exit(exit_stmt) => {
+                let code = self.generate_expression(&exit_stmt.code);
+                let code = match self.int_width {
+                    IntWidth::Bits32 => code,
+                    IntWidth::Bits64 => self.assign(format!("trunc i64 {} to i32", code)),
+                };
+                self.emit(format!("ret i32 {}", code));
+                // Whatever follows is unreachable, but still needs a block.
+                let label = self.new_label();
+                self.start_block(format!("after.exit.{}", label));
+            }
+            Statement::Break(_) => {
+                let end = self
+                    .loop_ends
+                    .last()
+                    .expect("analysis rejects `break` outside a loop");
+                self.emit(format!("br label %{}", end));
+                let label = self.new_label();
+                self.start_block(format!("after.break.{}", label));
+            }
+            Statement::Expression(expr_stmt) => {
+                self.generate_expression(&expr_stmt.value);
+            }
+            Statement::Comment(comment) => {
+                for line in comment.text.lines() {
+                    self.body.push(format!("  ; {}", line));
+                }
+            }
+            // Resolved before code generation.
+            Statement::Include(_) => {}
+            // The module's consts follow it.
+            Statement::Use(_) => {}
+        }
+    }
+
+    /// Ends the current block by printing `message` to stderr and returning
+    /// 1 from `main`.
+    fn fail(&mut self, message: &str) {
+        self.uses_dprintf = true;
+        let message = self.string_pointer(message.to_string());
+        self.emit(format!(
+            "call i32 (i32, i8*, ...) @dprintf(i32 2, {})",
+            message
+        ));
+        self.emit("ret i32 1".to_string());
+    }
+
+    fn generate_array_let_statement(&mut self, array_let_stmt: &ArrayLetStatement) {
+        let ty = self.ty;
+        let length = array_let_stmt.length;
+        let elements: Vec<String> = match &array_let_stmt.initializer {
+            ArrayInitializer::Fill(value) => vec![self.generate_expression(value); length],
+            ArrayInitializer::List(elements) => elements
+                .iter()
+                .map(|element| self.generate_expression(element))
+                .collect(),
+        };
+        let array = format!("[{} x {}]", length, ty);
+        let slot = self.new_slot(&array_let_stmt.identifier.name, array.clone());
+        for (i, element) in elements.iter().enumerate() {
+            let pointer = self.assign(format!(
+                "getelementptr inbounds {0}, {0}* {1}, i64 0, i64 {2}",
+                array, slot, i
+            ));
+            self.emit(format!("store {0} {1}, {0}* {2}", ty, element, pointer));
+        }
+        self.declare(
+            &array_let_stmt.identifier.name,
+            Binding::Array(slot, length),
+        );
+    }
+
+    fn generate_loop_statement(&mut self, loop_stmt: &LoopStatement) {
+        let ty = self.ty;
+        let label = self.new_label();
+        // As in C, the bounds and step are evaluated once, before the counter
+        // is declared.
+        let start = match &loop_stmt.start {
+            Some(start) => self.generate_expression(start),
+            None => "0".to_string(),
+        };
+        let count = self.generate_expression(&loop_stmt.count);
+        let step = match &loop_stmt.step {
+            Some(step) => self.generate_expression(step),
+            None => "1".to_string(),
+        };
+
+        self.scopes.enter_scope();
+        let counter = match &loop_stmt.counter {
+            Some(counter) => {
+                let slot = self.new_slot(&counter.name, ty.to_string());
+                self.declare(&counter.name, Binding::Scalar(slot.clone()));
+                slot
+            }
+            None => self.new_slot("counter", ty.to_string()),
+        };
+        self.emit(format!("store {0} {1}, {0}* {2}", ty, start, counter));
+        self.emit(format!("br label %loop.cond.{}", label));
+
+        self.start_block(format!("loop.cond.{}", label));
+        let current = self.assign(format!("load {0}, {0}* {1}", ty, counter));
+        let compare = if loop_stmt.start.is_some() {
+            "sle"
+        } else {
+            "slt"
+        };
+        let more = self.assign(format!("icmp {} {} {}, {}", compare, ty, current, count));
+        self.emit(format!(
+            "br i1 {}, label %loop.body.{1}, label %loop.end.{1}",
+            more, label
+        ));
+
+        self.start_block(format!("loop.body.{}", label));
+        self.loop_ends.push(format!("loop.end.{}", label));
+        self.generate_block(&loop_stmt.body);
+        self.loop_ends.pop();
+        let current = self.assign(format!("load {0}, {0}* {1}", ty, counter));
+        // A range loop is left after the iteration that reaches its end, so
+        // the counter never steps past it; the condition is only checked on
+        // the way in.
+        let again = if loop_stmt.start.is_none() {
+            format!("loop.cond.{}", label)
+        } else {
+            let last = match loop_stmt.step {
+                None => self.assign(format!("icmp eq {} {}, {}", ty, current, count)),
+                Some(_) => {
+                    let left = self.assign(format!("sub {} {}, {}", ty, count, current));
+                    self.assign(format!("icmp ult {} {}, {}", ty, left, step))
+                }
+            };
+            self.emit(format!(
+                "br i1 {}, label %loop.end.{1}, label %loop.next.{1}",
+                last, label
+            ));
+            self.start_block(format!("loop.next.{}", label));
+            format!("loop.body.{}", label)
+        };
+        let next = self.assign(format!("add {} {}, {}", ty, current, step));
+        self.emit(format!("store {0} {1}, {0}* {2}", ty, next, counter));
+        self.emit(format!("br label %{}", again));
+        self.scopes.exit_scope();
+
+        self.start_block(format!("loop.end.{}", label));
+    }
+
+    fn generate_repeat_statement(&mut self, repeat_stmt: &RepeatStatement) {
+        let label = self.new_label();
+        self.emit(format!("br label %repeat.body.{}", label));
+
+        self.start_block(format!("repeat.body.{}", label));
+        self.loop_ends.push(format!("repeat.end.{}", label));
+        self.generate_block(&repeat_stmt.body);
+        self.loop_ends.pop();
+        let done = self.generate_condition(&repeat_stmt.condition);
+        self.emit(format!(
+            "br i1 {}, label %repeat.end.{1}, label %repeat.body.{1}",
+            done, label
+        ));
+
+        self.start_block(format!("repeat.end.{}", label));
+    }
+
+    fn generate_if_statement(&mut self, if_stmt: &IfStatement) {
+        let label = self.new_label();
+        let end = format!("if.end.{}", label);
+        self.generate_if_branches(if_stmt, label, &end);
+        self.start_block(end);
+    }
+
+    /// Emits `if_stmt` and, for an `else if` chain, every `if` after it, all
+    /// joining at the block `end`.
+    fn generate_if_branches(&mut self, if_stmt: &IfStatement, label: usize, end: &str) {
+        let condition = self.generate_condition(&if_stmt.condition);
+        let else_label = match if_stmt.else_branch {
+            Some(_) => format!("if.else.{}", label),
+            None => end.to_string(),
+        };
+        self.emit(format!(
+            "br i1 {}, label %if.then.{}, label %{}",
+            condition, label, else_label
+        ));
+        self.start_block(format!("if.then.{}", label));
+        self.generate_block(&if_stmt.then_block);
+        self.emit(format!("br label %{}", end));
+
+        let Some(else_branch) = if_stmt.else_branch.as_deref() else {
+            return;
+        };
+        self.start_block(else_label);
+        match else_branch {
+            ElseBranch::Block(block) => {
+                self.generate_block(block);
+                self.emit(format!("br label %{}", end));
+            }
+            ElseBranch::If(next) => {
+                let next_label = self.new_label();
+                self.generate_if_branches(next, next_label, end);
+            }
+        }
+    }
+
+    /// One `printf` whose format has a conversion per value and the text of
+    /// the other arguments written in, like the C backend's.
+    fn generate_print_statement(&mut self, print_stmt: &PrintStatement) {
+        let print_stmt = &print_stmt.expand_arrays(|name| match self.scopes.lookup(name) {
+            Some(Binding::Array(_, length)) => Some(*length),
+            _ => None,
+        });
+        let arguments = self.printf_arguments(&print_stmt.arguments, &print_stmt.format);
+        self.emit(format!("call i32 (i8*, ...) @printf({})", arguments));
+    }
+
+    /// The typed arguments of the `printf` call for a `print` or `write`,
+    /// starting with the format string.
+    fn printf_arguments(
+        &mut self,
+        print_arguments: &[PrintArgument],
+        print_format: &PrintFormat,
+    ) -> String {
+        let wide = self.int_width == IntWidth::Bits64;
+        let conversion = match (print_format, wide) {
+            (PrintFormat::Decimal, false) => "%d",
+            (PrintFormat::Decimal, true) => "%lld",
+            (PrintFormat::Hex, false) => "%x",
+            (PrintFormat::Hex, true) => "%llx",
+            (PrintFormat::Char, _) => "%c",
+        };
+        let mut format = String::new();
+        let mut arguments = Vec::new();
+        for argument in print_arguments {
+            match argument {
+                PrintArgument::Text(text) => format.push_str(&text.replace('%', "%%")),
+                PrintArgument::Value(value) => {
+                    format.push_str(conversion);
+                    let value = self.generate_expression(value);
+                    // `%c` takes an `int`.
+                    if wide && *print_format == PrintFormat::Char {
+                        let value = self.assign(format!("trunc i64 {} to i32", value));
+                        arguments.push(format!("i32 {}", value));
+                    } else {
+                        arguments.push(format!("{} {}", self.ty, value));
+                    }
+                }
+            }
+        }
+        if *print_format != PrintFormat::Char {
+            format.push('\n');
+        }
+        let format = self.string_pointer(format);
+        arguments.insert(0, format);
+        arguments.join(", ")
+    }
+
+    /// `expr` as an `i1`, true when it's nonzero.
+    fn generate_condition(&mut self, expr: &Expr) -> String {
+        let value = self.generate_expression(expr);
+        self.assign(format!("icmp ne {} {}, 0", self.ty, value))
+    }
+
+    fn generate_element_pointer(&mut self, base: &Identifier, index: &Expr) -> String {
+        let Binding::Array(slot, length) = self.lookup(&base.name) else {
+            unreachable!("indexing a non-array is rejected by semantic analysis");
+        };
+        let index = self.generate_expression(index);
+        let array = format!("[{} x {}]", length, self.ty);
+        self.assign(format!(
+            "getelementptr inbounds {0}, {0}* {1}, i64 0, {2} {3}",
+            array, slot, self.ty, index
+        ))
+    }
+
+    /// Emits the instructions computing `expr` and returns the operand
+    /// holding its value: a temporary or a constant.
+    fn generate_expression(&mut self, expr: &Expr) -> String {
+        let ty = self.ty;
+        match &expr.kind {
+            ExprKind::Term(Term::Number(n)) => n.to_string(),
+            ExprKind::Term(Term::Bool(value)) => i64::from(*value).to_string(),
+            ExprKind::Term(Term::Identifier(identifier)) => {
+                match self.constants.lookup(&identifier.name) {
+                    Some(value) => value.to_string(),
+                    None => {
+                        let slot = self.scalar(identifier);
+                        self.assign(format!("load {0}, {0}* {1}", ty, slot))
+                    }
+                }
+            }
+            ExprKind::Term(Term::Index { base, index }) => {
+                let pointer = self.generate_element_pointer(base, index);
+                self.assign(format!("load {0}, {0}* {1}", ty, pointer))
+            }
+            ExprKind::Call(call) => {
+                let arguments: Vec<String> = call
+                    .arguments
+                    .iter()
+                    .map(|argument| self.generate_expression(argument))
+                    .collect();
+                match Intrinsic::from_name(&call.function.name) {
+                    Some(Intrinsic::Min) => self.select("slt", &arguments[0], &arguments[1]),
+                    Some(Intrinsic::Max) => self.select("sgt", &arguments[0], &arguments[1]),
+                    Some(Intrinsic::Abs) => {
+                        let negated = self.assign(format!("sub {} 0, {}", ty, arguments[0]));
+                        let negative = self.assign(format!("icmp slt {} {}, 0", ty, arguments[0]));
+                        self.assign(format!(
+                            "select i1 {1}, {0} {2}, {0} {3}",
+                            ty, negative, negated, arguments[0]
+                        ))
+                    }
+                    Some(intrinsic @ (Intrinsic::MinOf | Intrinsic::MaxOf)) => {
+                        let predicate = match intrinsic {
+                            Intrinsic::MinOf => "slt",
+                            _ => "sgt",
+                        };
+                        let mut result = arguments[0].clone();
+                        for argument in &arguments[1..] {
+                            result = self.select(predicate, &result, argument);
+                        }
+                        result
+                    }
+                    None => unreachable!("unknown functions are rejected by semantic analysis"),
+                }
+            }
+            ExprKind::Binary(binary) => self.generate_binary_expression(binary),
+            ExprKind::Negate(operand) => {
+                let value = self.generate_expression(operand);
+                self.assign(format!("sub {} 0, {}", ty, value))
+            }
+            ExprKind::Write(write) => {
+                let arguments = self.printf_arguments(&write.arguments, &PrintFormat::Decimal);
+                let written = self.assign(format!("call i32 (i8*, ...) @printf({})", arguments));
+                match self.int_width {
+                    IntWidth::Bits32 => written,
+                    IntWidth::Bits64 => self.assign(format!("sext i32 {} to i64", written)),
+                }
+            }
+            ExprKind::StringLength(text) => text.len().to_string(),
+            ExprKind::Block(block) => {
+                self.scopes.enter_scope();
+                self.constants.enter_scope();
+                self.generate_statement_list(&block.statements);
+                let value = self.generate_expression(&block.value);
+                self.constants.exit_scope();
+                self.scopes.exit_scope();
+                value
+            }
+        }
+    }
+
+    /// `a` if `a <predicate> b` holds, otherwise `b`.
+    fn select(&mut self, predicate: &str, a: &str, b: &str) -> String {
+        let ty = self.ty;
+        let holds = self.assign(format!("icmp {} {} {}, {}", predicate, ty, a, b));
+        self.assign(format!("select i1 {1}, {0} {2}, {0} {3}", ty, holds, a, b))
+    }
+
+    fn generate_binary_expression(&mut self, binary: &BinaryExpr) -> String {
+        let ty = self.ty;
+        match binary.operator {
+            BinaryOperator::LogicalAnd => return self.generate_logical(binary, true),
+            BinaryOperator::LogicalOr => return self.generate_logical(binary, false),
+            _ => {}
+        }
+        let lhs = self.generate_expression(&binary.lhs);
+        let mut rhs = self.generate_expression(&binary.rhs);
+        if matches!(
+            binary.operator,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight
+        ) {
+            // Shifting by the width or more gives poison, so the amount is
+            // masked like WebAssembly does.
+            let mask = self.int_width.bits() - 1;
+            rhs = self.assign(format!("and {} {}, {}", ty, rhs, mask));
+        }
+        let instruction = match binary.operator {
+            BinaryOperator::Add => "add",
+            BinaryOperator::Subtract => "sub",
+            BinaryOperator::Multiply => "mul",
+            BinaryOperator::Divide => "sdiv",
+            BinaryOperator::Modulo => "srem",
+            BinaryOperator::BitwiseAnd => "and",
+            BinaryOperator::BitwiseOr => "or",
+            BinaryOperator::BitwiseXor => "xor",
+            BinaryOperator::ShiftLeft => "shl",
+            BinaryOperator::ShiftRight => "ashr",
+            BinaryOperator::Power => {
+                self.uses_pow = true;
+                return self.assign(format!(
+                    "call {0} @hand_pow({0} {1}, {0} {2})",
+                    ty, lhs, rhs
+                ));
+            }
+            BinaryOperator::Equal => return self.comparison("eq", &lhs, &rhs),
+            BinaryOperator::NotEqual => return self.comparison("ne", &lhs, &rhs),
+            BinaryOperator::Less => return self.comparison("slt", &lhs, &rhs),
+            BinaryOperator::LessEqual => return self.comparison("sle", &lhs, &rhs),
+            BinaryOperator::Greater => return self.comparison("sgt", &lhs, &rhs),
+            BinaryOperator::GreaterEqual => return self.comparison("sge", &lhs, &rhs),
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => unreachable!(),
+        };
+        self.assign(format!("{} {} {}, {}", instruction, ty, lhs, rhs))
+    }
+
+    /// Comparisons give an `i1`, widened to `0` or `1` like in C.
+    fn comparison(&mut self, predicate: &str, lhs: &str, rhs: &str) -> String {
+        let ty = self.ty;
+        let result = self.assign(format!("icmp {} {} {}, {}", predicate, ty, lhs, rhs));
+        self.assign(format!("zext i1 {} to {}", result, ty))
+    }
+
+    /// `&&` or `||`, short-circuiting like C so `x != 0 && 10 / x > 1` can't
+    /// divide by zero.
+    fn generate_logical(&mut self, binary: &BinaryExpr, is_and: bool) -> String {
+        let ty = self.ty;
+        let label = self.new_label();
+        let name = if is_and { "and" } else { "or" };
+        let lhs = self.generate_condition(&binary.lhs);
+        let short_circuit_block = self.block.clone();
+        let (when_true, when_false) = if is_and {
+            (
+                format!("{}.rhs.{}", name, label),
+                format!("{}.end.{}", name, label),
+            )
+        } else {
+            (
+                format!("{}.end.{}", name, label),
+                format!("{}.rhs.{}", name, label),
+            )
+        };
+        self.emit(format!(
+            "br i1 {}, label %{}, label %{}",
+            lhs, when_true, when_false
+        ));
+
+        self.start_block(format!("{}.rhs.{}", name, label));
+        let rhs = self.generate_condition(&binary.rhs);
+        let rhs = self.assign(format!("zext i1 {} to {}", rhs, ty));
+        let rhs_block = self.block.clone();
+        self.emit(format!("br label %{}.end.{}", name, label));
+
+        self.start_block(format!("{}.end.{}", name, label));
+        let short_circuit_value = if is_and { 0 } else { 1 };
+        self.assign(format!(
+            "phi {} [ {}, %{} ], [ {}, %{} ]",
+            ty, short_circuit_value, short_circuit_block, rhs, rhs_block
+        ))
+    }
+}
+
+/// Matches the C backend's `_hand_pow`: a negative exponent gives 1.
+fn pow_function(ty: &str) -> String {
+    format!(
+        "define internal {0} @hand_pow({0} %base, {0} %exp) {{
+entry:
+  br label %cond
+cond:
+  %result = phi {0} [ 1, %entry ], [ %next_result, %body ]
+  %remaining = phi {0} [ %exp, %entry ], [ %next_remaining, %body ]
+  %more = icmp sgt {0} %remaining, 0
+  br i1 %more, label %body, label %done
+body:
+  %next_result = mul {0} %result, %base
+  %next_remaining = sub {0} %remaining, 1
+  br label %cond
+done:
+  ret {0} %result
+}}",
+        ty
+    )
+}
+
+/// `text` for a `c"..."` constant, escaping every byte that isn't printable
+/// ASCII.
+fn llvm_string(text: &str) -> String {
+    let mut result = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => result.push_str(&format!("\\{:02X}", byte)),
+            b' '..=b'~' => result.push(byte as char),
+            _ => result.push_str(&format!("\\{:02X}", byte)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+
+    fn compile_source_to_llvm(source: &str) -> String {
+        let lexer = Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+        SemanticAnalyzer::analyze(&ast).unwrap();
+        generate_llvm(&ast, IntWidth::Bits32)
+    }
+
+    #[test]
+    fn test_loop_program() {
+        let ir = compile_source_to_llvm(
+            "let mut i = 0; let mut sum = 0; loop 10 { i = i + 1; sum = sum + i; print sum; };",
+        );
+
+        assert!(ir.contains("declare i32 @printf(i8*, ...)"));
+        assert!(ir.contains("  %i = alloca i32\n  %sum = alloca i32\n"));
+        assert!(ir.contains("loop.body.0:\n"));
+        assert!(ir.contains("br label %loop.cond.0"));
+        assert!(ir.contains(
+            "call i32 (i8*, ...) @printf(i8* getelementptr inbounds ([4 x i8], [4 x i8]* @.str.0, i64 0, i64 0), i32 %t."
+        ));
+        assert!(ir.contains("@.str.0 = private unnamed_addr constant [4 x i8] c\"%d\\0A\\00\""));
+        assert!(ir.ends_with("  ret i32 0\n}\n"));
+    }
+
+    #[test]
+    fn test_shadowing_gets_a_fresh_slot() {
+        let ir = compile_source_to_llvm("let x = 1; loop 2 { let x = 5; print x; } print x;");
+
+        assert!(ir.contains("%x = alloca i32"));
+        assert!(ir.contains("%x.1 = alloca i32"));
+        assert!(ir.contains("store i32 5, i32* %x.1"));
+    }
+
+    #[test]
+    fn test_logical_and_short_circuits() {
+        let ir = compile_source_to_llvm("let x = 0; assert x == 0 || 10 / x > 1;");
+
+        assert!(ir.contains("or.rhs.1:"));
+        assert!(ir.contains("phi i32 [ 1, %entry ], [ %t."));
+        assert!(ir.contains("assert.fail.0:"));
+        assert!(ir.contains("declare i32 @dprintf(i32, i8*, ...)"));
+    }
+}
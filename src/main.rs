@@ -1,68 +1,398 @@
 use std::env;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 
-mod ast;
-mod code_generator;
-mod lexer;
-mod parser;
-mod semantic_analyzer;
-
-use code_generator::generate_c_code;
-use lexer::Lexer;
-use parser::Parser;
-use semantic_analyzer::{SemanticAnalyzer, SemanticError};
+use compiler::asm_generator::generate_asm;
+use compiler::cfg::ControlFlowGraph;
+use compiler::code_generator::{
+    BraceStyle, CodegenOptions, Overflow, generate_c_code_with_sourcemap,
+};
+use compiler::const_eval::IntWidth;
+use compiler::diagnostic::{ColorChoice, Diagnostic, Severity, explain};
+use compiler::include::{IncludeError, load_program_timed, load_source_timed};
+use compiler::interpreter::{Interpreter, RuntimeError, predict_output};
+use compiler::lexer::tokens_to_json;
+use compiler::semantic_analyzer::SemanticWarning;
+use compiler::timings::{Timings, count_statements, time};
+use compiler::vm::{Program, Vm};
+use compiler::{CompileError, CompilerOptions, Run, check_timed, checked_backend, read_source};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    // With no arguments at all, a program piped in is read as if `-` was given
+    if args.len() < 2 && !io::stdin().is_terminal() {
+        args.push("-".to_string());
+    }
     if args.len() < 2 {
-        eprintln!("Usage: {} [--ast] [--stdout] <file>", args[0]);
+        eprintln!(
+            "Usage: {} [--ast] [--dump-tokens-with-spans] [--dump-cfg] [--run] [--run-vm] [--stdout] [--sourcemap] [--predict-output] [--c89] [--strict] [-Werror] [--optimize] [--bool-words] [--suppress-unused] [--as-function NAME] [--trace] [--keep-comments] [--int-width 32|64] [--overflow wrap|checked] [--indent N] [--brace-style attach|allman] [--emit c|wat|llvm|asm|graph|symbols|metrics] [--verbose] [--error-format human|json] [--color auto|always|never] [--max-errors N] <file|->\n       {} --explain CODE",
+            args[0], args[0]
+        );
+        return Ok(());
+    }
+    if let Some(code) = flag_value(&args, "--explain") {
+        match explain(code) {
+            Some(text) => print!("{}", text),
+            None => {
+                eprintln!("Error: unknown error code '{}'", code);
+                std::process::exit(1);
+            }
+        }
         return Ok(());
     }
     let print_ast_flag = args.contains(&"--ast".to_string());
+    let dump_cfg_flag = args.contains(&"--dump-cfg".to_string());
     let stdout_flag = args.contains(&"--stdout".to_string());
-    let file_path = args.last().unwrap();
-    let content = fs::read_to_string(file_path)?;
+    let verbose_flag = args.contains(&"--verbose".to_string());
+    let sourcemap_flag = args.contains(&"--sourcemap".to_string());
+    let predict_output_flag = args.contains(&"--predict-output".to_string());
+    let options = parse_options(&args);
+    let int_width = options.int_width();
+    let json_errors_flag = match flag_value(&args, "--error-format") {
+        None | Some("human") => false,
+        Some("json") => true,
+        Some(other) => {
+            eprintln!("Error: unknown error format '{}'", other);
+            std::process::exit(1);
+        }
+    };
+    let color_choice = if args.contains(&"--no-color".to_string()) {
+        ColorChoice::Never
+    } else {
+        match flag_value(&args, "--color").map(ColorChoice::from_name) {
+            None => ColorChoice::Auto,
+            Some(Some(choice)) => choice,
+            Some(None) => {
+                eprintln!("Error: --color must be auto, always or never");
+                std::process::exit(1);
+            }
+        }
+    };
+    // Every diagnostic goes to stderr
+    let color = color_choice.enabled(io::stderr().is_terminal());
+    // 0 lifts the limit
+    let max_errors = match flag_value(&args, "--max-errors").map(str::parse::<usize>) {
+        None => 20,
+        Some(Ok(0)) => usize::MAX,
+        Some(Ok(max)) => max,
+        Some(Err(_)) => {
+            eprintln!("Error: --max-errors needs a number");
+            std::process::exit(1);
+        }
+    };
+    // `symbols` and `metrics` dump the analysis instead of running a backend
+    let emit = options.emit.as_str();
+    let backend = if matches!(emit, "symbols" | "metrics") {
+        options
+            .validate()
+            .map_err(CompileError::Options)
+            .map(|()| None)
+    } else {
+        checked_backend(&options).map(Some)
+    };
+    let backend = match backend {
+        Ok(backend) => backend,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(1);
+        }
+    };
+    // `-` reads the program from stdin and writes the output to stdout
+    let from_stdin = args.last().unwrap() == "-";
+    // The map is written next to the C file
+    if sourcemap_flag && (emit != "c" || stdout_flag || from_stdin) {
+        eprintln!("Error: --sourcemap needs C output written to a file");
+        std::process::exit(1);
+    }
+    let file_path = if from_stdin {
+        "<stdin>"
+    } else {
+        args.last().unwrap().as_str()
+    };
 
-    // Tokenize
-    let lexer = Lexer::new(content);
-    let tokens: Vec<_> = lexer.collect();
+    // Only tokenizes, so includes aren't followed
+    if args.contains(&"--dump-tokens-with-spans".to_string()) {
+        let source = if from_stdin {
+            read_source(io::stdin())?
+        } else {
+            fs::read_to_string(file_path)?
+        };
+        println!("{}", tokens_to_json(&source));
+        return Ok(());
+    }
 
-    // Syntax analysis
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
+    // Tokenize, parse and resolve includes
+    let mut timings = Timings::default();
+    let loaded = if from_stdin {
+        let source = read_source(io::stdin())?;
+        load_source_timed(
+            Path::new(file_path),
+            source,
+            options.keep_comments,
+            &mut timings,
+        )
+    } else {
+        load_program_timed(Path::new(file_path), options.keep_comments, &mut timings)
+    };
+    let ast = match loaded {
+        Ok(ast) => ast,
+        Err(IncludeError::Parse { error, .. }) if json_errors_flag => {
+            eprintln!("{}", Diagnostic::from_parse_error(&error).to_json());
+            std::process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("{}: {}", Severity::Error.label(color), error);
+            std::process::exit(1);
+        }
+    };
     if print_ast_flag {
-        println!("{:?}", &ast);
+        print!("{}", ast);
     }
 
     // Semantic analysis
-    match SemanticAnalyzer::analyze(&ast) {
-        Ok(()) => {}
-        Err(errors) => {
-            eprintln!("Semantic analysis failed:");
-            for error in errors {
-                match error {
-                    SemanticError::UndeclaredVariable(name) => {
-                        eprintln!("  Error: Use of undeclared variable '{}'", name);
-                    }
+    timings.statements = count_statements(&ast.statement_list);
+    let report_warnings = |warnings: &[SemanticWarning]| {
+        for warning in warnings {
+            let diagnostic = Diagnostic::from_warning(warning);
+            if json_errors_flag {
+                eprintln!("{}", diagnostic.to_json());
+            } else {
+                eprintln!("{}", diagnostic.to_human(file_path, color));
+            }
+        }
+    };
+    let analysis = match check_timed(&ast, &options, &mut timings) {
+        Ok(analysis) => {
+            report_warnings(&analysis.warnings);
+            analysis
+        }
+        Err(CompileError::Semantic {
+            mut analysis,
+            int_width,
+        }) => {
+            report_warnings(&analysis.warnings);
+            let dropped = analysis.limit_errors(max_errors);
+            if !json_errors_flag {
+                eprintln!("Semantic analysis failed:");
+            }
+            for error in &analysis.errors {
+                let diagnostic = Diagnostic::from_error(error, int_width);
+                if json_errors_flag {
+                    eprintln!("{}", diagnostic.to_json());
+                } else {
+                    eprintln!("  {}", diagnostic.to_human(file_path, color));
                 }
             }
+            // JSON output stays one diagnostic per line
+            if dropped > 0 && !json_errors_flag {
+                eprintln!("  ... and {} more", dropped);
+            }
+            std::process::exit(1);
+        }
+        Err(CompileError::Warnings(warnings)) => {
+            report_warnings(&warnings);
+            eprintln!(
+                "{}: warnings are treated as errors (-Werror)",
+                Severity::Error.label(color)
+            );
             std::process::exit(1);
         }
+        Err(error) => {
+            eprintln!("{}: {}", Severity::Error.label(color), error);
+            std::process::exit(1);
+        }
+    };
+
+    if dump_cfg_flag {
+        print!("{}", ControlFlowGraph::build(&ast).to_dot());
+        return Ok(());
+    }
+
+    if predict_output_flag {
+        match predict_output(&ast, int_width, options.codegen.overflow) {
+            Ok(output) => {
+                eprintln!("Predicted output:");
+                eprint!("{}", output);
+                if !output.is_empty() && !output.ends_with('\n') {
+                    eprintln!();
+                }
+            }
+            Err(RuntimeError::InvalidInput { .. }) => {
+                eprintln!("Can't predict the output: the program reads input");
+            }
+            Err(error) => {
+                let span = error.span();
+                eprintln!(
+                    "Can't predict the output: {} at {}:{}:{}",
+                    error, file_path, span.line, span.column
+                );
+            }
+        }
     }
 
+    if options.run == Some(Run::Interpreter) {
+        let mut interpreter = Interpreter::new(int_width);
+        interpreter.set_trace(options.codegen.trace);
+        interpreter.set_overflow(options.codegen.overflow);
+        let result = interpreter.run(&ast);
+        // `exit` skips flushing, which would lose output after a `printc`.
+        io::stdout().flush()?;
+        match result {
+            Ok(code) => std::process::exit(code),
+            Err(error) => {
+                let span = error.span();
+                eprintln!(
+                    "{} at {}:{}:{}: {}",
+                    Severity::Error.label(color),
+                    file_path,
+                    span.line,
+                    span.column,
+                    error
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if options.run == Some(Run::Vm) {
+        let asm = generate_asm(&ast, int_width);
+        let program = Program::parse(&asm).expect("the asm backend writes valid assembly");
+        let result = Vm::new().run(&program);
+        io::stdout().flush()?;
+        match result {
+            Ok(code) => std::process::exit(code),
+            Err(error) => {
+                eprintln!(
+                    "{} at line {} of the assembly: {}",
+                    Severity::Error.label(color),
+                    error.line(),
+                    error
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let Some(backend) = backend else {
+        if emit == "metrics" {
+            println!("statements: {}", timings.statements);
+            println!("max loop depth: {}", analysis.max_loop_depth);
+            return Ok(());
+        }
+        for symbol in analysis.symbols {
+            println!(
+                "{}:{}:{}: {}: {}, depth {}",
+                file_path,
+                symbol.span.line,
+                symbol.span.column,
+                symbol.name,
+                symbol.ty,
+                symbol.depth
+            );
+        }
+        return Ok(());
+    };
+
     // Code generation
-    let generated_code = generate_c_code(&ast);
-    if stdout_flag {
+    let (generated_code, sourcemap) = time(&mut timings.codegen, || {
+        if sourcemap_flag {
+            let (code, map) = generate_c_code_with_sourcemap(&ast, &options.codegen);
+            (code, Some(map))
+        } else {
+            (backend.emit(&ast), None)
+        }
+    });
+    if verbose_flag {
+        eprintln!("{}", timings);
+    }
+    if stdout_flag || from_stdin {
         println!("{}", generated_code);
     } else {
-        let output_path = if file_path.ends_with(".hand") {
-            file_path.replace(".hand", ".c")
-        } else {
-            format!("{}.c", file_path)
+        let extension = backend.file_extension();
+        let output_path = match file_path.strip_suffix(".hand") {
+            Some(stem) => format!("{}.{}", stem, extension),
+            None => format!("{}.{}", file_path, extension),
         };
 
         fs::write(&output_path, generated_code)?;
-        eprintln!("Generated C code written to: {}", output_path);
+        eprintln!("Generated code written to: {}", output_path);
+        if let Some(map) = sourcemap {
+            let map_path = format!("{}.map", output_path);
+            fs::write(&map_path, map.to_json(file_path))?;
+            eprintln!("Source map written to: {}", map_path);
+        }
     }
     Ok(())
 }
+
+/// Reads the flags that change what gets compiled into `CompilerOptions`,
+/// exiting with an error on a bad value. Whether they fit together is
+/// checked with the backend. Flags that only change how results are
+/// presented, such as `--stdout`, are read by `main` itself.
+fn parse_options(args: &[String]) -> CompilerOptions {
+    let int_width = match flag_value(args, "--int-width") {
+        None | Some("32") => IntWidth::Bits32,
+        Some("64") => IntWidth::Bits64,
+        Some(other) => {
+            eprintln!("Error: unsupported integer width '{}'", other);
+            std::process::exit(1);
+        }
+    };
+    let overflow = match flag_value(args, "--overflow") {
+        None | Some("unchecked") => Overflow::Unchecked,
+        Some("wrap") => Overflow::Wrap,
+        Some("checked") => Overflow::Checked,
+        Some(other) => {
+            eprintln!("Error: unknown overflow mode '{}'", other);
+            std::process::exit(1);
+        }
+    };
+    let indent = match flag_value(args, "--indent").map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(indent)) => indent,
+        Some(Err(_)) => {
+            eprintln!("Error: --indent needs a number of spaces");
+            std::process::exit(1);
+        }
+    };
+    let brace_style = match flag_value(args, "--brace-style") {
+        None | Some("attach") => BraceStyle::Attach,
+        Some("allman") => BraceStyle::Allman,
+        Some(other) => {
+            eprintln!("Error: unknown brace style '{}'", other);
+            std::process::exit(1);
+        }
+    };
+    CompilerOptions {
+        codegen: CodegenOptions {
+            c89: args.contains(&"--c89".to_string()),
+            int_width,
+            overflow,
+            optimize: args.contains(&"--optimize".to_string()),
+            bool_words: args.contains(&"--bool-words".to_string()),
+            suppress_unused: args.contains(&"--suppress-unused".to_string()),
+            as_function: flag_value(args, "--as-function").map(str::to_string),
+            trace: args.contains(&"--trace".to_string()),
+            indent,
+            brace_style,
+        },
+        strict: args.contains(&"--strict".to_string()),
+        warnings_as_errors: args.contains(&"-Werror".to_string()),
+        keep_comments: args.contains(&"--keep-comments".to_string()),
+        emit: flag_value(args, "--emit").unwrap_or("c").to_string(),
+        run: if args.contains(&"--run".to_string()) {
+            Some(Run::Interpreter)
+        } else if args.contains(&"--run-vm".to_string()) {
+            Some(Run::Vm)
+        } else {
+            None
+        },
+    }
+}
+
+/// The argument following `flag`, if the flag was given.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    args.get(index + 1).map(String::as_str)
+}
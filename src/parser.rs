@@ -1,144 +1,1076 @@
+use std::collections::VecDeque;
+use std::fmt;
+
 use crate::ast::{
-    AbstractSyntaxTree, AssignmentStatement, Block, Expr, LetStatement, LoopStatement,
-    PrintStatement, Statement, StatementList, Term,
+    AbstractSyntaxTree, ArrayInitializer, ArrayLetStatement, AssertStatement, AssignmentStatement,
+    BinaryExpr, BinaryOperator, Block, BlockExpr, BreakStatement, CallExpr, CommentStatement,
+    ConstStatement, ElseBranch, ExitStatement, Expr, ExprKind, ExpressionStatement, Identifier,
+    IfStatement, IncludeStatement, LetStatement, LoopStatement, PrintArgument, PrintFormat,
+    PrintStatement, ReadStatement, RepeatStatement, Statement, StatementList, Term, Type,
+    UseStatement, WriteExpr,
 };
-use crate::lexer::Token;
+use crate::lexer::{SpannedToken, Token};
+use crate::span::Span;
+
+/// How deep expressions may nest, counting parentheses, operands of
+/// operators and blocks used as values. The parser and every later pass
+/// walk an expression recursively, so without a limit a long enough
+/// `1 + 1 + ...` would overflow the stack.
+pub const MAX_EXPRESSION_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// What the parser was looking for, e.g. "`;`" or "expression".
+    pub expected: String,
+    /// The token found instead, or `None` at the end of input.
+    pub found: Option<Token>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseErrorKind {
+    /// `found` isn't what `expected` describes.
+    Unexpected,
+    /// An expression nests deeper than `MAX_EXPRESSION_DEPTH`, at `found`.
+    ExpressionTooDeep,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.kind == ParseErrorKind::ExpressionTooDeep {
+            return write!(
+                f,
+                "expression nested more than {} levels deep",
+                MAX_EXPRESSION_DEPTH
+            );
+        }
+        match &self.found {
+            Some(Token::Error(error)) => write!(f, "{}", error),
+            Some(token) => write!(f, "expected {}, found `{}`", self.expected, token),
+            None => write!(f, "expected {}, found end of input", self.expected),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// The stable code `--explain` takes: `E0101` for text that isn't a
+    /// token, `E0102` for an expression nested too deeply and `E0100` for
+    /// any other syntax error.
+    pub fn code(&self) -> &'static str {
+        match (self.kind, &self.found) {
+            (ParseErrorKind::ExpressionTooDeep, _) => "E0102",
+            (_, Some(Token::Error(_))) => "E0101",
+            _ => "E0100",
+        }
+    }
+}
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    position: usize,
+/// Parses tokens as it reads them, so they needn't all be lexed up front.
+pub struct Parser<'a> {
+    /// The tokens not read yet.
+    tokens: Box<dyn Iterator<Item = SpannedToken> + 'a>,
+    /// The current token and any the parser has looked ahead at. Two are
+    /// kept whenever the input has them, so `peek` and `next_token` needn't
+    /// read more.
+    lookahead: VecDeque<SpannedToken>,
+    /// Span of the most recently consumed token.
+    previous: Span,
+    /// How many expressions the one being parsed is nested in.
+    depth: usize,
+    /// The deepest `depth` reached since `parse_operand` last reset it,
+    /// which tells how far below its level an operand reaches.
+    deepest: usize,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser {
-            tokens,
-            position: 0,
+/// The binary operator `token` stands for, other than `**`, and how
+/// tightly it binds.
+fn binary_operator(token: &Token) -> Option<(BinaryOperator, u8)> {
+    let operator = match token {
+        Token::PipePipe => (BinaryOperator::LogicalOr, 1),
+        Token::AmpersandAmpersand => (BinaryOperator::LogicalAnd, 2),
+        Token::Pipe => (BinaryOperator::BitwiseOr, 3),
+        Token::Caret => (BinaryOperator::BitwiseXor, 4),
+        Token::Ampersand => (BinaryOperator::BitwiseAnd, 5),
+        Token::EqualsEquals => (BinaryOperator::Equal, 6),
+        Token::NotEquals => (BinaryOperator::NotEqual, 6),
+        Token::Less => (BinaryOperator::Less, 7),
+        Token::LessEquals => (BinaryOperator::LessEqual, 7),
+        Token::Greater => (BinaryOperator::Greater, 7),
+        Token::GreaterEquals => (BinaryOperator::GreaterEqual, 7),
+        Token::ShiftLeft => (BinaryOperator::ShiftLeft, 8),
+        Token::ShiftRight => (BinaryOperator::ShiftRight, 8),
+        Token::Plus => (BinaryOperator::Add, 9),
+        Token::Minus => (BinaryOperator::Subtract, 9),
+        Token::Star => (BinaryOperator::Multiply, 10),
+        Token::Slash => (BinaryOperator::Divide, 10),
+        Token::Percent => (BinaryOperator::Modulo, 10),
+        _ => return None,
+    };
+    Some(operator)
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        Self::from_tokens(tokens)
+    }
+
+    /// A parser that reads `tokens` only as it needs them, such as straight
+    /// from a `Lexer`.
+    pub fn from_tokens(tokens: impl IntoIterator<Item = SpannedToken> + 'a) -> Self {
+        let mut parser = Parser {
+            tokens: Box::new(tokens.into_iter()),
+            lookahead: VecDeque::new(),
+            previous: Span::default(),
+            depth: 0,
+            deepest: 0,
+        };
+        parser.look_ahead(2);
+        parser
+    }
+
+    /// Reads tokens until `count` are buffered or the input runs out.
+    fn look_ahead(&mut self, count: usize) {
+        while self.lookahead.len() < count {
+            match self.tokens.next() {
+                Some(token) => self.lookahead.push_back(token),
+                None => break,
+            }
         }
     }
 
-    fn current_token(&self) -> Option<&Token> {
-        self.tokens.get(self.position)
+    fn peek(&self) -> Option<&Token> {
+        self.lookahead.front().map(|t| &t.token)
+    }
+
+    fn current_span(&self) -> Span {
+        self.lookahead.front().map(|t| t.span).unwrap_or_default()
+    }
+
+    /// Span of the most recently consumed token.
+    fn previous_span(&self) -> Span {
+        self.previous
+    }
+
+    /// Span from `start` to the end of the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span())
+    }
+
+    fn next_token(&self) -> Option<&Token> {
+        self.lookahead.get(1).map(|t| &t.token)
     }
 
     fn consume_token(&mut self) -> Option<Token> {
-        let token = self.tokens.get(self.position).cloned();
-        self.position += 1;
-        token
+        let token = self.lookahead.pop_front();
+        self.look_ahead(2);
+        self.previous = token.as_ref().map(|t| t.span).unwrap_or_default();
+        token.map(|t| t.token)
     }
 
-    pub fn parse(&mut self) -> AbstractSyntaxTree {
-        let statements = self.parse_statement_list();
-        AbstractSyntaxTree {
-            statement_list: statements,
+    fn error(&self, expected: &str) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::Unexpected,
+            expected: expected.to_string(),
+            found: self.peek().cloned(),
+            span: self.current_span(),
         }
     }
 
-    fn parse_statement_list(&mut self) -> StatementList {
-        let mut statements = Vec::new();
-        while self.current_token().is_some() {
-            statements.push(self.parse_statement());
+    /// Consumes the current token if it is the same kind of token as `kind`,
+    /// ignoring any payload.
+    fn expect(&mut self, kind: Token) -> Result<Token, ParseError> {
+        match self.peek() {
+            Some(token) if std::mem::discriminant(token) == std::mem::discriminant(&kind) => {
+                Ok(self.consume_token().unwrap())
+            }
+            _ => Err(self.error(&format!("`{}`", kind))),
+        }
+    }
+
+    /// Like `expect_semicolon`, but the last statement in the file may leave
+    /// its `;` out. Only the end of input counts, so a `;` missing before
+    /// another statement or a `}` is still an error.
+    fn expect_final_semicolon(&mut self) -> Result<(), ParseError> {
+        if self.peek().is_none() {
+            return Ok(());
         }
-        StatementList { statements }
+        self.expect_semicolon()
     }
 
-    fn parse_statement(&mut self) -> Statement {
-        match self.current_token() {
-            Some(Token::Let) => Statement::Let(self.parse_let_statement()),
-            Some(Token::Identifier(_)) => Statement::Assignment(self.parse_assignment_statement()),
-            Some(Token::Loop) => Statement::Loop(self.parse_loop_statement()),
-            Some(Token::Print) => Statement::Print(self.parse_print_statement()),
-            _ => panic!("Unexpected token"),
+    /// Consumes the `;` ending a statement. A missing `;` is reported right
+    /// after the previous token, where it should have been, rather than at
+    /// the start of whatever follows.
+    fn expect_semicolon(&mut self) -> Result<(), ParseError> {
+        if matches!(self.peek(), Some(Token::Semicolon)) {
+            _ = self.consume_token();
+            return Ok(());
         }
+        let previous = self.previous_span();
+        let width = previous.end - previous.start;
+        Err(ParseError {
+            kind: ParseErrorKind::Unexpected,
+            expected: "`;` after statement".to_string(),
+            found: self.peek().cloned(),
+            span: Span {
+                start: previous.end,
+                end: previous.end,
+                line: previous.line,
+                column: previous.column + width,
+            },
+        })
     }
 
-    fn parse_let_statement(&mut self) -> LetStatement {
-        let Some(Token::Let) = self.consume_token() else {
-            panic!("Unexpected token");
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let ty = match self.peek() {
+            Some(Token::Identifier(name)) => Type::from_name(name),
+            _ => None,
         };
-        let Some(Token::Identifier(identifier)) = self.consume_token() else {
-            panic!("Unexpected token");
+        let ty = ty.ok_or_else(|| self.error("type"))?;
+        _ = self.consume_token();
+        Ok(ty)
+    }
+
+    fn parse_identifier(&mut self) -> Result<Identifier, ParseError> {
+        let span = self.current_span();
+        let Some(Token::Identifier(_)) = self.peek() else {
+            return Err(self.error("identifier"));
         };
-        let Some(Token::Equals) = self.consume_token() else {
-            panic!("Unexpected token");
+        let Some(Token::Identifier(name)) = self.consume_token() else {
+            unreachable!();
         };
-        let value = self.parse_expression();
-        let Some(Token::Semicolon) = self.consume_token() else {
-            panic!("Unexpected token");
+        Ok(Identifier { name, span })
+    }
+
+    /// Parses a name in an expression, which may be qualified by a module
+    /// as in `math.PI`. The qualified name is kept whole, dot included.
+    fn parse_name(&mut self) -> Result<Identifier, ParseError> {
+        let identifier = self.parse_identifier()?;
+        if !matches!(self.peek(), Some(Token::Dot)) {
+            return Ok(identifier);
+        }
+        _ = self.consume_token();
+        let member = self.parse_identifier()?;
+        Ok(Identifier {
+            name: format!("{}.{}", identifier.name, member.name),
+            span: identifier.span.to(member.span),
+        })
+    }
+
+    pub fn parse(&mut self) -> AbstractSyntaxTree {
+        self.try_parse().unwrap_or_else(|error| {
+            panic!(
+                "{} at line {}, column {}",
+                error, error.span.line, error.span.column
+            )
+        })
+    }
+
+    pub fn try_parse(&mut self) -> Result<AbstractSyntaxTree, ParseError> {
+        let statements = self.parse_statement_list()?;
+        Ok(AbstractSyntaxTree {
+            statement_list: statements,
+        })
+    }
+
+    fn parse_statement_list(&mut self) -> Result<StatementList, ParseError> {
+        let mut statements = Vec::new();
+        while self.peek().is_some() {
+            if self.skip_empty_statement() {
+                continue;
+            }
+            statements.push(self.parse_statement()?);
+        }
+        Ok(StatementList { statements })
+    }
+
+    /// A lone `;` is an empty statement. It's consumed without producing a
+    /// node, so stray semicolons like `let x = 1;;` are harmless.
+    fn skip_empty_statement(&mut self) -> bool {
+        if matches!(self.peek(), Some(Token::Semicolon)) {
+            _ = self.consume_token();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        let at_assignment =
+            matches!(self.peek(), Some(Token::Identifier(_))) && self.at_assignment();
+        let statement = match self.peek() {
+            Some(Token::Let) => self.parse_let_statement()?,
+            Some(Token::Const) => Statement::Const(self.parse_const_statement()?),
+            Some(Token::Identifier(_)) if at_assignment => {
+                Statement::Assignment(self.parse_assignment_statement()?)
+            }
+            Some(Token::Identifier(_) | Token::Number(_) | Token::OpenParen | Token::Minus) => {
+                Statement::Expression(self.parse_expression_statement()?)
+            }
+            Some(Token::Loop) => Statement::Loop(self.parse_loop_statement()?),
+            Some(Token::Repeat) => Statement::Repeat(self.parse_repeat_statement()?),
+            Some(Token::If) => Statement::If(self.parse_if_statement()?),
+            Some(Token::Print | Token::PrintHex | Token::PrintChar) => {
+                Statement::Print(self.parse_print_statement()?)
+            }
+            Some(Token::Assert) => Statement::Assert(self.parse_assert_statement()?),
+            Some(Token::Read) => Statement::Read(self.parse_read_statement()?),
+            Some(Token::Include) => Statement::Include(self.parse_include_statement()?),
+            Some(Token::Use) => Statement::Use(self.parse_use_statement()?),
+            Some(Token::Exit) => Statement::Exit(self.parse_exit_statement()?),
+            Some(Token::Break) => self.parse_break_statement()?,
+            Some(Token::Comment(_)) => {
+                let span = self.current_span();
+                let Some(Token::Comment(text)) = self.consume_token() else {
+                    unreachable!();
+                };
+                Statement::Comment(CommentStatement { text, span })
+            }
+            _ => return Err(self.error("statement")),
         };
-        LetStatement { identifier, value }
+        Ok(statement)
     }
 
-    fn parse_assignment_statement(&mut self) -> AssignmentStatement {
-        let Some(Token::Identifier(identifier)) = self.consume_token() else {
-            panic!("Unexpected token");
+    /// Parses `let x = v;` or `let x;`, or one of the array forms
+    /// `let a[3] = v;` and `let a = [v, ...];`, each optionally with `mut`
+    /// after `let`.
+    fn parse_let_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Let)?;
+        let mutable = matches!(self.peek(), Some(Token::Mut));
+        if mutable {
+            _ = self.consume_token();
+        }
+        let identifier = self.parse_identifier()?;
+        let length = if matches!(self.peek(), Some(Token::OpenSquareBracket)) {
+            _ = self.consume_token();
+            let length = self.parse_array_length()?;
+            self.expect(Token::CloseSquareBracket)?;
+            Some(length)
+        } else {
+            None
         };
-        let Some(Token::Equals) = self.consume_token() else {
-            panic!("Unexpected token");
+        let declared_type = if length.is_none() && matches!(self.peek(), Some(Token::Colon)) {
+            _ = self.consume_token();
+            Some(self.parse_type()?)
+        } else {
+            None
         };
-        let value = self.parse_expression();
-        let Some(Token::Semicolon) = self.consume_token() else {
-            panic!("Unexpected token");
+        if length.is_none() && matches!(self.peek(), Some(Token::Semicolon) | None) {
+            self.expect_final_semicolon()?;
+            return Ok(Statement::Let(LetStatement {
+                identifier,
+                mutable,
+                declared_type,
+                value: None,
+                span: self.span_from(start),
+            }));
+        }
+        self.expect(Token::Equals)?;
+        let statement = match length {
+            Some(length) => {
+                let value = self.parse_expression()?;
+                self.expect_final_semicolon()?;
+                Statement::ArrayLet(ArrayLetStatement {
+                    identifier,
+                    mutable,
+                    length,
+                    initializer: ArrayInitializer::Fill(value),
+                    span: self.span_from(start),
+                })
+            }
+            None if matches!(self.peek(), Some(Token::OpenSquareBracket)) => {
+                let elements = self.parse_array_literal()?;
+                self.expect_final_semicolon()?;
+                Statement::ArrayLet(ArrayLetStatement {
+                    identifier,
+                    mutable,
+                    length: elements.len(),
+                    initializer: ArrayInitializer::List(elements),
+                    span: self.span_from(start),
+                })
+            }
+            None => {
+                let value = self.parse_expression()?;
+                self.expect_final_semicolon()?;
+                Statement::Let(LetStatement {
+                    identifier,
+                    mutable,
+                    declared_type,
+                    value: Some(value),
+                    span: self.span_from(start),
+                })
+            }
         };
-        AssignmentStatement { identifier, value }
+        Ok(statement)
     }
 
-    fn parse_block(&mut self) -> Block {
-        let Some(Token::OpenBracket) = self.consume_token() else {
-            panic!("Unexpected token");
+    fn parse_array_length(&mut self) -> Result<usize, ParseError> {
+        match self.peek() {
+            Some(&Token::Number(n)) if n > 0 => {
+                _ = self.consume_token();
+                Ok(n as usize)
+            }
+            _ => Err(self.error("positive array length")),
+        }
+    }
+
+    /// Parses `[v, ...]` with at least one element.
+    fn parse_array_literal(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.expect(Token::OpenSquareBracket)?;
+        let mut elements = vec![self.parse_expression()?];
+        while matches!(self.peek(), Some(Token::Comma)) {
+            _ = self.consume_token();
+            elements.push(self.parse_expression()?);
+        }
+        self.expect(Token::CloseSquareBracket)?;
+        Ok(elements)
+    }
+
+    fn parse_assignment_statement(&mut self) -> Result<AssignmentStatement, ParseError> {
+        let start = self.current_span();
+        let identifier = self.parse_identifier()?;
+        let index = self.parse_index()?;
+        self.expect(Token::Equals)?;
+        let value = self.parse_expression()?;
+        self.expect_final_semicolon()?;
+        Ok(AssignmentStatement {
+            identifier,
+            index,
+            value,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Whether the tokens ahead are `name =` or `name[...] =`, telling an
+    /// assignment apart from an expression that starts with a name.
+    fn at_assignment(&mut self) -> bool {
+        let mut position = 1;
+        let mut depth = 0;
+        loop {
+            self.look_ahead(position + 1);
+            let Some(spanned) = self.lookahead.get(position) else {
+                return false;
+            };
+            match spanned.token {
+                Token::OpenSquareBracket => depth += 1,
+                Token::CloseSquareBracket if depth > 0 => depth -= 1,
+                Token::Equals => return depth == 0,
+                _ if depth == 0 => return false,
+                _ => {}
+            }
+            position += 1;
+        }
+    }
+
+    fn parse_expression_statement(&mut self) -> Result<ExpressionStatement, ParseError> {
+        let start = self.current_span();
+        let value = self.parse_expression()?;
+        self.expect_final_semicolon()?;
+        Ok(ExpressionStatement {
+            value,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_if_statement(&mut self) -> Result<IfStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::If)?;
+        let condition = self.parse_expression()?;
+        let then_block = self.parse_block()?;
+        let else_branch = if matches!(self.peek(), Some(Token::Else)) {
+            _ = self.consume_token();
+            let branch = if matches!(self.peek(), Some(Token::If)) {
+                ElseBranch::If(self.parse_if_statement()?)
+            } else {
+                ElseBranch::Block(self.parse_block()?)
+            };
+            Some(Box::new(branch))
+        } else {
+            None
         };
+        // As with loops, the `;` after the last block is optional.
+        if matches!(self.peek(), Some(Token::Semicolon)) {
+            _ = self.consume_token();
+        }
+        Ok(IfStatement {
+            condition,
+            then_block,
+            else_branch,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_block(&mut self) -> Result<Block, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::OpenBracket)?;
         let mut statements = Vec::new();
-        while !matches!(self.current_token(), Some(Token::CloseBracket)) {
-            statements.push(self.parse_statement());
+        while !matches!(self.peek(), Some(Token::CloseBracket)) {
+            // Pointing at the `{` that was never closed helps more than
+            // pointing at the end of the file.
+            if self.peek().is_none() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::Unexpected,
+                    expected: "`}` to close this block".to_string(),
+                    found: None,
+                    span: start,
+                });
+            }
+            if self.skip_empty_statement() {
+                continue;
+            }
+            statements.push(self.parse_statement()?);
         }
-        let Some(Token::CloseBracket) = self.consume_token() else {
-            panic!("Unexpected token");
-        };
-        Block {
+        self.expect(Token::CloseBracket)?;
+        Ok(Block {
             statements: Box::new(StatementList { statements }),
-        }
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_loop_statement(&mut self) -> LoopStatement {
-        let Some(Token::Loop) = self.consume_token() else {
-            panic!("Unexpected token");
+    fn parse_loop_statement(&mut self) -> Result<LoopStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Loop)?;
+        let mut from = None;
+        let counter = match (self.peek(), self.next_token()) {
+            (Some(Token::Identifier(_)), Some(Token::In)) => {
+                let counter = self.parse_identifier()?;
+                self.expect(Token::In)?;
+                Some(counter)
+            }
+            (Some(Token::Identifier(_)), Some(Token::From)) => {
+                let counter = self.parse_identifier()?;
+                self.expect(Token::From)?;
+                from = Some(self.parse_expression()?);
+                self.expect(Token::To)?;
+                Some(counter)
+            }
+            _ => None,
         };
-        let condition = self.parse_expression();
-        let body = self.parse_block();
-        let Some(Token::Semicolon) = self.consume_token() else {
-            panic!("Unexpected token");
+        let condition = self.parse_expression()?;
+        let step = if matches!(self.peek(), Some(Token::Step)) {
+            _ = self.consume_token();
+            Some(self.parse_expression()?)
+        } else {
+            None
         };
-        LoopStatement {
+        let body = self.parse_block()?;
+        // The `;` after the block is optional.
+        if matches!(self.peek(), Some(Token::Semicolon)) {
+            _ = self.consume_token();
+        }
+        Ok(LoopStatement {
+            counter,
+            start: from,
             count: condition,
+            step,
             body: Box::new(body),
-        }
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_print_statement(&mut self) -> PrintStatement {
-        let Some(Token::Print) = self.consume_token() else {
-            panic!("Unexpected token");
-        };
-        let value = self.parse_expression();
-        let Some(Token::Semicolon) = self.consume_token() else {
-            panic!("Unexpected token");
-        };
-        PrintStatement { value }
+    fn parse_repeat_statement(&mut self) -> Result<RepeatStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Repeat)?;
+        let body = self.parse_block()?;
+        self.expect(Token::Until)?;
+        let condition = self.parse_expression()?;
+        self.expect_final_semicolon()?;
+        Ok(RepeatStatement {
+            body: Box::new(body),
+            condition,
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_expression(&mut self) -> Expr {
-        let lhs = match self.consume_token() {
-            Some(Token::Identifier(name)) => Term::Identifier(name.clone()),
-            Some(Token::Number(n)) => Term::Number(n),
-            _ => panic!("Unexpected token"),
+    fn parse_print_statement(&mut self) -> Result<PrintStatement, ParseError> {
+        let start = self.current_span();
+        let format = match self.peek() {
+            Some(Token::Print) => PrintFormat::Decimal,
+            Some(Token::PrintHex) => PrintFormat::Hex,
+            Some(Token::PrintChar) => PrintFormat::Char,
+            _ => return Err(self.error("`print`")),
         };
-        let rhs = if matches!(self.current_token(), Some(Token::Plus)) {
+        _ = self.consume_token();
+        // A bare `print;` prints an empty line. `printx` and `printc` always
+        // need something to print.
+        let mut arguments = Vec::new();
+        if format != PrintFormat::Decimal || !matches!(self.peek(), Some(Token::Semicolon) | None) {
+            arguments.push(self.parse_print_argument()?);
+            while matches!(self.peek(), Some(Token::Comma)) {
+                _ = self.consume_token();
+                arguments.push(self.parse_print_argument()?);
+            }
+        }
+        self.expect_final_semicolon()?;
+        Ok(PrintStatement {
+            arguments,
+            format,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parses one part of a `print` or `write`. String literals joined with
+    /// `+`, as in `"a" + "b"`, are folded into a single text here, since C
+    /// can't add strings; joining one with anything else is an error.
+    fn parse_print_argument(&mut self) -> Result<PrintArgument, ParseError> {
+        if let Some(Token::StringLiteral(_)) = self.peek() {
+            let Some(Token::StringLiteral(mut text)) = self.consume_token() else {
+                unreachable!();
+            };
+            while matches!(self.peek(), Some(Token::Plus)) {
+                _ = self.consume_token();
+                let Some(Token::StringLiteral(_)) = self.peek() else {
+                    return Err(self.error("string literal after `+`"));
+                };
+                let Some(Token::StringLiteral(next)) = self.consume_token() else {
+                    unreachable!();
+                };
+                text.push_str(&next);
+            }
+            return Ok(PrintArgument::Text(text));
+        }
+        Ok(PrintArgument::Value(self.parse_expression()?))
+    }
+
+    fn parse_assert_statement(&mut self) -> Result<AssertStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Assert)?;
+        let condition = self.parse_expression()?;
+        let message = if matches!(self.peek(), Some(Token::Comma)) {
             _ = self.consume_token();
-            Some(Box::new(self.parse_expression()))
+            if !matches!(self.peek(), Some(Token::StringLiteral(_))) {
+                return Err(self.error("string message"));
+            }
+            let Some(Token::StringLiteral(text)) = self.consume_token() else {
+                unreachable!();
+            };
+            Some(text)
         } else {
             None
         };
+        self.expect_final_semicolon()?;
+        Ok(AssertStatement {
+            condition,
+            message,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_read_statement(&mut self) -> Result<ReadStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Read)?;
+        let identifier = self.parse_identifier()?;
+        self.expect_final_semicolon()?;
+        Ok(ReadStatement {
+            identifier,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_include_statement(&mut self) -> Result<IncludeStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Include)?;
+        let Some(Token::StringLiteral(_)) = self.peek() else {
+            return Err(self.error("string literal"));
+        };
+        let Some(Token::StringLiteral(path)) = self.consume_token() else {
+            unreachable!();
+        };
+        self.expect_final_semicolon()?;
+        Ok(IncludeStatement {
+            path,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_use_statement(&mut self) -> Result<UseStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Use)?;
+        let module = self.parse_identifier()?;
+        self.expect_final_semicolon()?;
+        Ok(UseStatement {
+            module,
+            resolved: false,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_const_statement(&mut self) -> Result<ConstStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Const)?;
+        let identifier = self.parse_identifier()?;
+        self.expect(Token::Equals)?;
+        let value = self.parse_expression()?;
+        self.expect_final_semicolon()?;
+        Ok(ConstStatement {
+            identifier,
+            value,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_exit_statement(&mut self) -> Result<ExitStatement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Exit)?;
+        let code = self.parse_expression()?;
+        self.expect_final_semicolon()?;
+        Ok(ExitStatement {
+            code,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parses `break;`, or `break if <condition>;`, which becomes the same
+    /// `if` statement as `if <condition> { break; }`.
+    fn parse_break_statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::Break)?;
+        if !matches!(self.peek(), Some(Token::If)) {
+            self.expect_final_semicolon()?;
+            return Ok(Statement::Break(BreakStatement {
+                span: self.span_from(start),
+            }));
+        }
+        _ = self.consume_token();
+        let condition = self.parse_expression()?;
+        self.expect_final_semicolon()?;
+        // The `break` and the block made up for it have no source of their
+        // own, so they cover the whole statement.
+        let span = self.span_from(start);
+        Ok(Statement::If(IfStatement {
+            condition,
+            then_block: Block {
+                statements: Box::new(StatementList {
+                    statements: vec![Statement::Break(BreakStatement { span })],
+                }),
+                span,
+            },
+            else_branch: None,
+            span,
+        }))
+    }
+
+    pub(crate) fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        self.nested(Self::parse_binary)
+    }
+
+    /// Runs `parse` one level of nesting deeper, failing instead once that's
+    /// deeper than `MAX_EXPRESSION_DEPTH`.
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        if self.depth == MAX_EXPRESSION_DEPTH {
+            return Err(self.too_deep());
+        }
+        self.depth += 1;
+        self.deepest = self.deepest.max(self.depth);
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn too_deep(&self) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::ExpressionTooDeep,
+            ..self.error("less deeply nested expression")
+        }
+    }
+
+    /// Parses operands joined by binary operators other than `**`, from
+    /// `||`, which binds loosest, to `*`, `/` and `%`, which bind tightest,
+    /// all left-associative. Operands and operators waiting for a tighter
+    /// operator to their right are kept on stacks rather than the call
+    /// stack, so a long `1 + 1 + ...` doesn't recurse. Every operator still
+    /// pushes its operands one level deeper into the tree, though, so the
+    /// height counts towards `MAX_EXPRESSION_DEPTH`.
+    fn parse_binary(&mut self) -> Result<Expr, ParseError> {
+        let outer_deepest = self.deepest;
+        let mut operands = vec![self.parse_operand()?];
+        let mut operators: Vec<(BinaryOperator, u8)> = Vec::new();
+        while let Some((operator, precedence)) = self.peek().and_then(binary_operator) {
+            while operators
+                .last()
+                .is_some_and(|&(_, previous)| previous >= precedence)
+            {
+                self.reduce(&mut operands, &mut operators)?;
+            }
+            _ = self.consume_token();
+            operators.push((operator, precedence));
+            operands.push(self.parse_operand()?);
+        }
+        while !operators.is_empty() {
+            self.reduce(&mut operands, &mut operators)?;
+        }
+        let (expr, height) = operands.pop().unwrap();
+        self.deepest = outer_deepest.max(self.depth + height);
+        Ok(expr)
+    }
+
+    /// An operand of `parse_binary` and how far below the current depth it
+    /// reaches.
+    fn parse_operand(&mut self) -> Result<(Expr, usize), ParseError> {
+        self.deepest = self.depth;
+        let operand = self.parse_unary()?;
+        Ok((operand, self.deepest - self.depth))
+    }
+
+    /// Joins the top two `operands` with the top operator.
+    fn reduce(
+        &self,
+        operands: &mut Vec<(Expr, usize)>,
+        operators: &mut Vec<(BinaryOperator, u8)>,
+    ) -> Result<(), ParseError> {
+        let (operator, _) = operators.pop().unwrap();
+        let (rhs, rhs_height) = operands.pop().unwrap();
+        let (lhs, lhs_height) = operands.pop().unwrap();
+        let height = lhs_height.max(rhs_height) + 1;
+        if self.depth + height > MAX_EXPRESSION_DEPTH {
+            return Err(self.too_deep());
+        }
+        let span = lhs.span.to(rhs.span);
+        let expr = Expr {
+            kind: ExprKind::Binary(BinaryExpr {
+                operator,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            }),
+            span,
+        };
+        operands.push((expr, height));
+        Ok(())
+    }
+
+    /// A prefix `-` binds looser than `**`, so `-2 ** 2` is `-(2 ** 2)`.
+    /// Negating a literal folds into it before anything range-checks the
+    /// value, which keeps `-2147483648` in range for 32-bit ints even
+    /// though `2147483648` isn't.
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        if !matches!(self.peek(), Some(Token::Minus)) {
+            return self.parse_power();
+        }
+        let span = self.current_span();
+        _ = self.consume_token();
+        let operand = self.nested(Self::parse_unary)?;
+        let span = span.to(operand.span);
+        let kind = match operand.kind {
+            ExprKind::Term(Term::Number(n)) => ExprKind::Term(Term::Number(n.wrapping_neg())),
+            _ => ExprKind::Negate(Box::new(operand)),
+        };
+        Ok(Expr { kind, span })
+    }
+
+    /// `**` binds tighter than `*` and is right-associative, so
+    /// `2 ** 3 ** 2` is `2 ** (3 ** 2)`. Its exponent may be negated.
+    fn parse_power(&mut self) -> Result<Expr, ParseError> {
+        let lhs = self.parse_primary()?;
+        if !matches!(self.peek(), Some(Token::StarStar)) {
+            return Ok(lhs);
+        }
+        _ = self.consume_token();
+        let rhs = self.nested(Self::parse_unary)?;
+        let span = lhs.span.to(rhs.span);
+        Ok(Expr {
+            kind: ExprKind::Binary(BinaryExpr {
+                operator: BinaryOperator::Power,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            }),
+            span,
+        })
+    }
+
+    /// Parses an optional `[index]` after an identifier.
+    fn parse_index(&mut self) -> Result<Option<Expr>, ParseError> {
+        if !matches!(self.peek(), Some(Token::OpenSquareBracket)) {
+            return Ok(None);
+        }
+        _ = self.consume_token();
+        let index = self.parse_expression()?;
+        self.expect(Token::CloseSquareBracket)?;
+        Ok(Some(index))
+    }
+
+    /// Parses a parenthesized, comma-separated argument list, which may be
+    /// empty.
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.expect(Token::OpenParen)?;
+        let mut arguments = Vec::new();
+        if !matches!(self.peek(), Some(Token::CloseParen)) {
+            arguments.push(self.parse_expression()?);
+            while matches!(self.peek(), Some(Token::Comma)) {
+                _ = self.consume_token();
+                arguments.push(self.parse_expression()?);
+            }
+        }
+        self.expect(Token::CloseParen)?;
+        Ok(arguments)
+    }
+
+    /// Parses the parenthesized arguments of `write`, each a string or an
+    /// expression as in `print`.
+    fn parse_write_arguments(&mut self) -> Result<Vec<PrintArgument>, ParseError> {
+        self.expect(Token::OpenParen)?;
+        let mut arguments = Vec::new();
+        if !matches!(self.peek(), Some(Token::CloseParen)) {
+            arguments.push(self.parse_print_argument()?);
+            while matches!(self.peek(), Some(Token::Comma)) {
+                _ = self.consume_token();
+                arguments.push(self.parse_print_argument()?);
+            }
+        }
+        self.expect(Token::CloseParen)?;
+        Ok(arguments)
+    }
+
+    /// Parses `{ statements... value }`. Blocks only stand for values where an
+    /// expression is expected; the blocks of `if` and loops go through
+    /// `parse_block`. An expression is the block's value if `}` follows it,
+    /// and an expression statement if `;` does.
+    fn parse_block_expression(&mut self) -> Result<Expr, ParseError> {
+        let start = self.current_span();
+        self.expect(Token::OpenBracket)?;
+        let mut statements = Vec::new();
+        let value = loop {
+            if self.peek().is_none() {
+                return Err(ParseError {
+                    kind: ParseErrorKind::Unexpected,
+                    expected: "`}` to close this block".to_string(),
+                    found: None,
+                    span: start,
+                });
+            }
+            if self.skip_empty_statement() {
+                continue;
+            }
+            let at_assignment =
+                matches!(self.peek(), Some(Token::Identifier(_))) && self.at_assignment();
+            let at_expression = match self.peek() {
+                Some(Token::Identifier(_)) => !at_assignment,
+                Some(Token::Number(_) | Token::OpenParen | Token::Minus | Token::OpenBracket) => {
+                    true
+                }
+                Some(Token::CloseBracket) => return Err(self.error("expression ending the block")),
+                // Includes and modules are resolved before analysis, which
+                // only looks for them among statements.
+                Some(Token::Include) => {
+                    return Err(self.error("statement other than `include` in a block expression"));
+                }
+                Some(Token::Use) => {
+                    return Err(self.error("statement other than `use` in a block expression"));
+                }
+                _ => false,
+            };
+            if !at_expression {
+                statements.push(self.parse_statement()?);
+                continue;
+            }
+            let statement_start = self.current_span();
+            let value = self.parse_expression()?;
+            if matches!(self.peek(), Some(Token::CloseBracket)) {
+                break value;
+            }
+            self.expect_semicolon()?;
+            statements.push(Statement::Expression(ExpressionStatement {
+                value,
+                span: self.span_from(statement_start),
+            }));
+        };
+        self.expect(Token::CloseBracket)?;
+        Ok(Expr {
+            kind: ExprKind::Block(BlockExpr {
+                statements: Box::new(StatementList { statements }),
+                value: Box::new(value),
+            }),
+            span: self.span_from(start),
+        })
+    }
 
-        Expr { lhs, rhs }
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.current_span();
+        let kind = match self.peek() {
+            Some(Token::Identifier(_)) => {
+                let identifier = self.parse_name()?;
+                // `write` takes text as well as values, so it isn't an
+                // ordinary call.
+                if identifier.name == "write" && matches!(self.peek(), Some(Token::OpenParen)) {
+                    let arguments = self.parse_write_arguments()?;
+                    return Ok(Expr {
+                        kind: ExprKind::Write(WriteExpr { arguments }),
+                        span: self.span_from(span),
+                    });
+                }
+                // `len` of a string literal; other arguments are left to the
+                // analyzer to reject.
+                if identifier.name == "len"
+                    && matches!(
+                        (self.peek(), self.next_token()),
+                        (Some(Token::OpenParen), Some(Token::StringLiteral(_)))
+                    )
+                {
+                    _ = self.consume_token();
+                    let Some(Token::StringLiteral(text)) = self.consume_token() else {
+                        unreachable!();
+                    };
+                    self.expect(Token::CloseParen)?;
+                    return Ok(Expr {
+                        kind: ExprKind::StringLength(text),
+                        span: self.span_from(span),
+                    });
+                }
+                if matches!(self.peek(), Some(Token::OpenParen)) {
+                    let arguments = self.parse_arguments()?;
+                    return Ok(Expr {
+                        kind: ExprKind::Call(CallExpr {
+                            function: identifier,
+                            arguments,
+                        }),
+                        span: self.span_from(span),
+                    });
+                }
+                match self.parse_index()? {
+                    Some(index) => {
+                        return Ok(Expr {
+                            kind: ExprKind::Term(Term::Index {
+                                base: identifier,
+                                index: Box::new(index),
+                            }),
+                            span: self.span_from(span),
+                        });
+                    }
+                    None => {
+                        return Ok(Expr {
+                            kind: ExprKind::Term(Term::Identifier(identifier)),
+                            span: self.span_from(span),
+                        });
+                    }
+                }
+            }
+            Some(&Token::Number(n)) => {
+                _ = self.consume_token();
+                ExprKind::Term(Term::Number(n))
+            }
+            Some(Token::True | Token::False) => {
+                let value = self.consume_token() == Some(Token::True);
+                ExprKind::Term(Term::Bool(value))
+            }
+            Some(Token::OpenBracket) => return self.parse_block_expression(),
+            Some(Token::OpenParen) => {
+                _ = self.consume_token();
+                let expr = self.parse_expression()?;
+                let close_span = self.current_span();
+                self.expect(Token::CloseParen)?;
+                return Ok(Expr {
+                    span: span.to(close_span),
+                    ..expr
+                });
+            }
+            _ => return Err(self.error("expression")),
+        };
+        Ok(Expr { kind, span })
     }
 }
 
@@ -146,6 +1078,40 @@ impl Parser {
 mod tests {
     use super::*;
 
+    fn parser_for(tokens: Vec<Token>) -> Parser<'static> {
+        Parser::new(tokens.into_iter().map(SpannedToken::from).collect())
+    }
+
+    fn identifier(name: &str) -> Identifier {
+        Identifier {
+            name: name.to_string(),
+            span: Span::default(),
+        }
+    }
+
+    fn expr(kind: ExprKind) -> Expr {
+        Expr {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn number(n: i64) -> Expr {
+        expr(ExprKind::Term(Term::Number(n)))
+    }
+
+    fn variable(name: &str) -> Expr {
+        expr(ExprKind::Term(Term::Identifier(identifier(name))))
+    }
+
+    fn binary(operator: BinaryOperator, lhs: Expr, rhs: Expr) -> Expr {
+        expr(ExprKind::Binary(BinaryExpr {
+            operator,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }))
+    }
+
     #[test]
     fn test_valid_program() {
         // let x = 5; loop 3 { x = x + 1; print x; }
@@ -171,10 +1137,247 @@ mod tests {
             Token::Semicolon,
         ];
 
-        let mut parser = Parser::new(tokens);
+        let mut parser = parser_for(tokens);
         parser.parse();
     }
 
+    #[test]
+    fn test_parses_expected_ast() {
+        // let x = 1; loop x { print x + 2; };
+        let tokens = vec![
+            Token::Let,
+            Token::Identifier("x".to_string()),
+            Token::Equals,
+            Token::Number(1),
+            Token::Semicolon,
+            Token::Loop,
+            Token::Identifier("x".to_string()),
+            Token::OpenBracket,
+            Token::Print,
+            Token::Identifier("x".to_string()),
+            Token::Plus,
+            Token::Number(2),
+            Token::Semicolon,
+            Token::CloseBracket,
+            Token::Semicolon,
+        ];
+
+        let expected = AbstractSyntaxTree {
+            statement_list: StatementList {
+                statements: vec![
+                    Statement::Let(LetStatement {
+                        identifier: identifier("x"),
+                        mutable: false,
+                        declared_type: None,
+                        value: Some(number(1)),
+                        span: Span::default(),
+                    }),
+                    Statement::Loop(LoopStatement {
+                        counter: None,
+                        start: None,
+                        count: variable("x"),
+                        step: None,
+                        body: Box::new(Block {
+                            statements: Box::new(StatementList {
+                                statements: vec![Statement::Print(PrintStatement {
+                                    arguments: vec![PrintArgument::Value(binary(
+                                        BinaryOperator::Add,
+                                        variable("x"),
+                                        number(2),
+                                    ))],
+                                    format: PrintFormat::Decimal,
+                                    span: Span::default(),
+                                })],
+                            }),
+                            span: Span::default(),
+                        }),
+                        span: Span::default(),
+                    }),
+                ],
+            },
+        };
+
+        let mut parser = parser_for(tokens);
+        assert_eq!(parser.parse(), expected);
+    }
+
+    #[test]
+    fn test_named_loop_counter() {
+        // loop i in 3 { print i; };
+        let tokens = vec![
+            Token::Loop,
+            Token::Identifier("i".to_string()),
+            Token::In,
+            Token::Number(3),
+            Token::OpenBracket,
+            Token::Print,
+            Token::Identifier("i".to_string()),
+            Token::Semicolon,
+            Token::CloseBracket,
+            Token::Semicolon,
+        ];
+
+        let mut parser = parser_for(tokens);
+        let ast = parser.parse();
+
+        let Statement::Loop(loop_stmt) = &ast.statement_list.statements[0] else {
+            panic!("expected loop statement");
+        };
+        assert_eq!(loop_stmt.counter, Some(identifier("i")));
+        assert_eq!(loop_stmt.count, number(3));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        // 1 + 2 * (3 - 4)
+        let tokens = vec![
+            Token::Number(1),
+            Token::Plus,
+            Token::Number(2),
+            Token::Star,
+            Token::OpenParen,
+            Token::Number(3),
+            Token::Minus,
+            Token::Number(4),
+            Token::CloseParen,
+        ];
+
+        let mut parser = parser_for(tokens);
+        let expr = parser.parse_expression().unwrap();
+
+        let ExprKind::Binary(add) = expr.kind else {
+            panic!("expected binary expression");
+        };
+        assert!(matches!(add.operator, BinaryOperator::Add));
+        assert!(matches!(add.lhs.kind, ExprKind::Term(Term::Number(1))));
+        let ExprKind::Binary(mul) = add.rhs.kind else {
+            panic!("expected binary expression");
+        };
+        assert!(matches!(mul.operator, BinaryOperator::Multiply));
+        let ExprKind::Binary(sub) = mul.rhs.kind else {
+            panic!("expected binary expression");
+        };
+        assert!(matches!(sub.operator, BinaryOperator::Subtract));
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        // -2 ** 2 - -x
+        let tokens = vec![
+            Token::Minus,
+            Token::Number(2),
+            Token::StarStar,
+            Token::Number(2),
+            Token::Minus,
+            Token::Minus,
+            Token::Identifier("x".to_string()),
+        ];
+
+        let mut parser = parser_for(tokens);
+        let expr = parser.parse_expression().unwrap();
+
+        let ExprKind::Binary(sub) = expr.kind else {
+            panic!("expected binary expression");
+        };
+        assert!(matches!(sub.operator, BinaryOperator::Subtract));
+        let ExprKind::Negate(power) = sub.lhs.kind else {
+            panic!("expected negation");
+        };
+        assert!(matches!(power.kind, ExprKind::Binary(_)));
+        let ExprKind::Negate(x) = sub.rhs.kind else {
+            panic!("expected negation");
+        };
+        assert!(matches!(x.kind, ExprKind::Term(Term::Identifier(_))));
+
+        // A negated literal is folded, even past the positive range.
+        let mut parser = parser_for(vec![Token::Minus, Token::Number(2147483648)]);
+        assert!(matches!(
+            parser.parse_expression().unwrap().kind,
+            ExprKind::Term(Term::Number(-2147483648))
+        ));
+    }
+
+    #[test]
+    fn test_comparison_precedence() {
+        // assert 1 + 1 < 3 == 2 > 1;
+        let tokens = vec![
+            Token::Assert,
+            Token::Number(1),
+            Token::Plus,
+            Token::Number(1),
+            Token::Less,
+            Token::Number(3),
+            Token::EqualsEquals,
+            Token::Number(2),
+            Token::Greater,
+            Token::Number(1),
+            Token::Semicolon,
+        ];
+
+        let mut parser = parser_for(tokens);
+        let ast = parser.parse();
+
+        let Statement::Assert(assert_stmt) = &ast.statement_list.statements[0] else {
+            panic!("expected assert statement");
+        };
+        let ExprKind::Binary(equal) = &assert_stmt.condition.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(equal.operator, BinaryOperator::Equal);
+        let ExprKind::Binary(less) = &equal.lhs.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(less.operator, BinaryOperator::Less);
+        let ExprKind::Binary(greater) = &equal.rhs.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(greater.operator, BinaryOperator::Greater);
+    }
+
+    #[test]
+    fn test_bitwise_and_binds_tighter_than_or() {
+        // 1 | 2 & 3
+        let tokens = vec![
+            Token::Number(1),
+            Token::Pipe,
+            Token::Number(2),
+            Token::Ampersand,
+            Token::Number(3),
+        ];
+
+        let mut parser = parser_for(tokens);
+        assert_eq!(
+            parser.parse_expression().unwrap(),
+            binary(
+                BinaryOperator::BitwiseOr,
+                number(1),
+                binary(BinaryOperator::BitwiseAnd, number(2), number(3))
+            )
+        );
+    }
+
+    #[test]
+    fn test_shift_binds_looser_than_addition() {
+        // 1 << 2 + 3
+        let tokens = vec![
+            Token::Number(1),
+            Token::ShiftLeft,
+            Token::Number(2),
+            Token::Plus,
+            Token::Number(3),
+        ];
+
+        let mut parser = parser_for(tokens);
+        assert_eq!(
+            parser.parse_expression().unwrap(),
+            binary(
+                BinaryOperator::ShiftLeft,
+                number(1),
+                binary(BinaryOperator::Add, number(2), number(3))
+            )
+        );
+    }
+
     #[test]
     #[should_panic]
     fn test_invalid_program() {
@@ -193,7 +1396,551 @@ mod tests {
             Token::Semicolon,
         ];
 
-        let mut parser = Parser::new(tokens);
+        let mut parser = parser_for(tokens);
         parser.parse();
     }
+
+    #[test]
+    fn test_expect_reports_expected_and_found() {
+        let span = Span {
+            start: 10,
+            end: 15,
+            line: 1,
+            column: 11,
+        };
+        let mut parser = Parser::new(vec![SpannedToken {
+            token: Token::Print,
+            span,
+        }]);
+
+        let error = parser.expect(Token::Semicolon).unwrap_err();
+        assert_eq!(
+            error,
+            ParseError {
+                kind: ParseErrorKind::Unexpected,
+                expected: "`;`".to_string(),
+                found: Some(Token::Print),
+                span,
+            }
+        );
+        assert_eq!(error.to_string(), "expected `;`, found `print`");
+        assert_eq!(parser.expect(Token::Print), Ok(Token::Print));
+        assert_eq!(
+            parser.expect(Token::Semicolon).unwrap_err().to_string(),
+            "expected `;`, found end of input"
+        );
+    }
+
+    #[test]
+    fn test_power_is_right_associative_and_binds_tighter_than_multiply() {
+        // 2 * 3 ** 2 ** 1
+        let tokens = vec![
+            Token::Number(2),
+            Token::Star,
+            Token::Number(3),
+            Token::StarStar,
+            Token::Number(2),
+            Token::StarStar,
+            Token::Number(1),
+        ];
+
+        let mut parser = parser_for(tokens);
+        assert_eq!(
+            parser.parse_expression().unwrap(),
+            binary(
+                BinaryOperator::Multiply,
+                number(2),
+                binary(
+                    BinaryOperator::Power,
+                    number(3),
+                    binary(BinaryOperator::Power, number(2), number(1))
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let source = "let x = 1;\nloop i in 2 {\n  print i;\n};";
+        let lexer = crate::lexer::Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+
+        let statements = &ast.statement_list.statements;
+        assert_eq!(
+            statements[0].span(),
+            Span {
+                start: 0,
+                end: 10,
+                line: 1,
+                column: 1
+            }
+        );
+        // From `loop` through the `;` after the closing `}`.
+        let Statement::Loop(loop_stmt) = &statements[1] else {
+            panic!("expected a loop statement");
+        };
+        assert_eq!(
+            &source[loop_stmt.span.start..loop_stmt.span.end],
+            &source[11..]
+        );
+        assert_eq!((loop_stmt.span.line, loop_stmt.span.column), (2, 1));
+        assert_eq!(
+            &source[loop_stmt.body.span.start..loop_stmt.body.span.end],
+            "{\n  print i;\n}"
+        );
+    }
+
+    #[test]
+    fn test_reads_tokens_only_as_needed() {
+        let source = "let a[2] = 0; a[1] = 5; print a[1];\nlet x = ; print 1; print 2;";
+        let expected =
+            Parser::new(crate::lexer::Lexer::new(source.to_string()).collect()).try_parse();
+
+        let mut read = 0;
+        let tokens = crate::lexer::Lexer::new(source.to_string()).inspect(|_| read += 1);
+        let error = Parser::from_tokens(tokens).try_parse().unwrap_err();
+        assert_eq!(Err(error), expected);
+        // Of 31 tokens, the parser read up to the `;` after `let x =` and
+        // the one token it keeps looked ahead at.
+        assert_eq!(read, 26);
+    }
+
+    #[test]
+    fn test_expression_too_deep() {
+        let parse =
+            |source: String| Parser::new(crate::lexer::Lexer::new(source).collect()).try_parse();
+
+        // The chain is parsed in a loop, and stops with an error once the
+        // tree it builds gets too deep for the passes that walk it.
+        let terms = |count| vec!["1"; count].join(" + ");
+        let error = parse(format!("print {};", terms(100_000))).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::ExpressionTooDeep);
+        assert_eq!(error.code(), "E0102");
+        assert_eq!(
+            error.to_string(),
+            "expression nested more than 256 levels deep"
+        );
+        assert!(parse(format!("print {};", terms(MAX_EXPRESSION_DEPTH))).is_ok());
+
+        // A chain in parentheses sits deeper in the tree for every operator
+        // in the chain around it.
+        let nested = format!("print ({}) + {};", terms(200), terms(100));
+        assert_eq!(
+            parse(nested).unwrap_err().kind,
+            ParseErrorKind::ExpressionTooDeep
+        );
+    }
+
+    #[test]
+    fn test_stray_semicolons_are_skipped() {
+        let source = "let x = 1;;; print x; loop 2 { ; print x;; };;";
+        let lexer = crate::lexer::Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+
+        assert_eq!(ast.statement_list.statements.len(), 3);
+        let Statement::Loop(loop_stmt) = &ast.statement_list.statements[2] else {
+            panic!("expected a loop statement");
+        };
+        assert_eq!(loop_stmt.body.statements.statements.len(), 1);
+    }
+
+    #[test]
+    fn test_array_declarations_and_indexing() {
+        let source = "let a[2] = 0; let b = [1, 2]; a[1] = b[0];";
+        let lexer = crate::lexer::Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+
+        let [
+            Statement::ArrayLet(a),
+            Statement::ArrayLet(b),
+            Statement::Assignment(assign),
+        ] = ast.statement_list.statements.as_slice()
+        else {
+            panic!("unexpected statements: {:?}", ast.statement_list.statements);
+        };
+        assert_eq!(a.length, 2);
+        assert!(matches!(&a.initializer, ArrayInitializer::Fill(_)));
+        assert_eq!(b.length, 2);
+        assert!(matches!(&b.initializer, ArrayInitializer::List(elements) if elements.len() == 2));
+        assert!(assign.index.is_some());
+        assert!(matches!(
+            assign.value.kind,
+            ExprKind::Term(Term::Index { .. })
+        ));
+
+        let lexer = crate::lexer::Lexer::new("let a[0] = 1;".to_string());
+        let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected positive array length, found `0`"
+        );
+    }
+
+    #[test]
+    fn test_loop_semicolon_is_optional() {
+        for source in [
+            "loop 3 { print 1; } print 2;",
+            "loop 3 { print 1; }; print 2;",
+        ] {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+
+            let [Statement::Loop(loop_stmt), Statement::Print(_)] =
+                ast.statement_list.statements.as_slice()
+            else {
+                panic!("unexpected statements for {:?}", source);
+            };
+            assert_eq!(loop_stmt.body.statements.statements.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_missing_semicolon_points_after_previous_token() {
+        let source = "let a = 1 + 22 print a;";
+        let lexer = crate::lexer::Lexer::new(source.to_string());
+        let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "expected `;` after statement, found `print`"
+        );
+        // Right after `22`, not at `print`.
+        assert_eq!((error.span.line, error.span.column), (1, 15));
+        assert_eq!(error.span.start, 14);
+    }
+
+    #[test]
+    fn test_final_semicolon_is_optional() {
+        let parse = |source: &str| {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            Parser::new(lexer.collect()).try_parse()
+        };
+
+        let ast = parse("let x = 1;\nprint x").unwrap();
+        assert!(matches!(
+            ast.statement_list.statements[..],
+            [Statement::Let(_), Statement::Print(_)]
+        ));
+        for source in [
+            "let x",
+            "let a[2] = 0",
+            "let x = 1; x = 2",
+            "printx 1",
+            "1 + 2",
+            "repeat { } until 1 > 2",
+            "exit 0",
+            "const N = 1",
+            "assert 1 < 2, \"ok\"",
+            "let mut x; read x",
+            "include \"lib.hand\"",
+            "break",
+            "break if 1 < 2",
+        ] {
+            assert!(parse(source).is_ok(), "{}", source);
+        }
+
+        // Only the end of the file can stand in for the `;`.
+        let error = parse("let x = 1\nprint x").unwrap_err();
+        assert_eq!(error.found, Some(Token::Print));
+        let error = parse("loop 2 { print 1 }").unwrap_err();
+        assert_eq!(error.found, Some(Token::CloseBracket));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected `;` after statement, found `print` at line 1, column 15")]
+    fn test_parse_reports_missing_semicolon_location() {
+        let lexer = crate::lexer::Lexer::new("let a = 1 + 22 print a;".to_string());
+        Parser::new(lexer.collect()).parse();
+    }
+
+    #[test]
+    fn test_let_type_annotation() {
+        let lexer = crate::lexer::Lexer::new("let x: int = 5; let b: bool = x < 3;".to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+        let declared: Vec<_> = ast
+            .statement_list
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Let(let_stmt) => let_stmt.declared_type,
+                _ => panic!("expected let statement"),
+            })
+            .collect();
+        assert_eq!(declared, vec![Some(Type::Int), Some(Type::Bool)]);
+
+        let lexer = crate::lexer::Lexer::new("let x; let b: bool;".to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+        assert!(
+            ast.statement_list
+                .statements
+                .iter()
+                .all(|statement| matches!(
+                    statement,
+                    Statement::Let(LetStatement { value: None, .. })
+                ))
+        );
+
+        let lexer = crate::lexer::Lexer::new("let x: float = 5;".to_string());
+        let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+        assert_eq!(error.to_string(), "expected type, found `float`");
+    }
+
+    #[test]
+    fn test_let_mut() {
+        let lexer =
+            crate::lexer::Lexer::new("let mut x: int = 5; let y; let mut a[2] = 0;".to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+        let mutable: Vec<_> = ast
+            .statement_list
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Let(let_stmt) => let_stmt.mutable,
+                Statement::ArrayLet(array_let_stmt) => array_let_stmt.mutable,
+                _ => panic!("expected let statement"),
+            })
+            .collect();
+        assert_eq!(mutable, vec![true, false, true]);
+
+        // `mut` is a keyword, so it can't name a variable.
+        let lexer = crate::lexer::Lexer::new("let mut = 1;".to_string());
+        let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+        assert_eq!(error.to_string(), "expected identifier, found `=`");
+    }
+
+    #[test]
+    fn test_break_if() {
+        let parse = |source: &str| {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            Parser::new(lexer.collect()).try_parse().unwrap()
+        };
+        let sugar = parse("loop 3 { break if c; }");
+        let explicit = parse("loop 3 { if c { break; } }");
+        assert_eq!(sugar.to_string(), explicit.to_string());
+        let Statement::Loop(loop_stmt) = &sugar.statement_list.statements[0] else {
+            panic!("expected a loop");
+        };
+        let [
+            Statement::If(IfStatement {
+                then_block,
+                else_branch: None,
+                span,
+                ..
+            }),
+        ] = &loop_stmt.body.statements.statements[..]
+        else {
+            panic!("expected an if");
+        };
+        let [Statement::Break(break_stmt)] = &then_block.statements.statements[..] else {
+            panic!("expected a break");
+        };
+        // Both cover all of `break if c;`.
+        assert_eq!((span.start, span.end), (9, 20));
+        assert_eq!(then_block.span, *span);
+        assert_eq!(break_stmt.span, *span);
+    }
+
+    #[test]
+    fn test_unclosed_block() {
+        for source in [
+            "loop 3 { print x;",
+            "loop 3 { print x",
+            "if x < 1 {\n  print x;\n",
+        ] {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "expected `}` to close this block, found end of input"
+            );
+            // The error points at the `{` that's missing its `}`.
+            assert_eq!(&source[error.span.start..error.span.end], "{");
+        }
+    }
+
+    #[test]
+    fn test_else_if_chain() {
+        let source = "if x < 0 { print 0; } else if x < 10 { print 1; } else { print 2; } print 3;";
+        let lexer = crate::lexer::Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+
+        let [Statement::If(if_stmt), Statement::Print(_)] =
+            ast.statement_list.statements.as_slice()
+        else {
+            panic!("expected an if statement followed by a print");
+        };
+        let Some(ElseBranch::If(else_if)) = if_stmt.else_branch.as_deref() else {
+            panic!("expected an else if");
+        };
+        assert!(matches!(
+            else_if.else_branch.as_deref(),
+            Some(ElseBranch::Block(_))
+        ));
+        assert_eq!(if_stmt.span.end, source.find(" print 3").unwrap());
+    }
+
+    #[test]
+    fn test_loop_count_forms() {
+        let parse_loop = |source: &str| {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+            let [Statement::Loop(loop_stmt)] = ast.statement_list.statements.as_slice() else {
+                panic!("expected a single loop in {:?}", source);
+            };
+            loop_stmt.clone()
+        };
+
+        let loop_stmt = parse_loop("loop n { print n; }");
+        assert!(matches!(
+            &loop_stmt.count.kind,
+            ExprKind::Term(Term::Identifier(identifier)) if identifier.name == "n"
+        ));
+        // The `{` starts the body; it is never read as part of the count.
+        assert_eq!(loop_stmt.body.statements.statements.len(), 1);
+
+        let loop_stmt = parse_loop("loop (n * 2) { print n; }");
+        assert!(matches!(
+            &loop_stmt.count.kind,
+            ExprKind::Binary(BinaryExpr {
+                operator: BinaryOperator::Multiply,
+                ..
+            })
+        ));
+        assert_eq!(loop_stmt.body.statements.statements.len(), 1);
+
+        let loop_stmt = parse_loop("loop i in (n + 1) step (2) { }");
+        assert!(loop_stmt.counter.is_some());
+        assert!(loop_stmt.step.is_some());
+
+        let loop_stmt = parse_loop("loop i from 1 to n + 1 { }");
+        assert!(matches!(
+            loop_stmt.start,
+            Some(Expr {
+                kind: ExprKind::Term(Term::Number(1)),
+                ..
+            })
+        ));
+        assert!(matches!(loop_stmt.count.kind, ExprKind::Binary(_)));
+
+        let lexer = crate::lexer::Lexer::new("loop n print n;".to_string());
+        let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+        assert_eq!(error.to_string(), "expected `{`, found `print`");
+    }
+
+    #[test]
+    fn test_const_in_block() {
+        let lexer = crate::lexer::Lexer::new("const N = 3; print N;".to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+        assert!(matches!(
+            &ast.statement_list.statements[0],
+            Statement::Const(const_stmt) if const_stmt.identifier.name == "N"
+        ));
+
+        let lexer = crate::lexer::Lexer::new("loop 2 { const N = 3; }".to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+        let Statement::Loop(loop_stmt) = &ast.statement_list.statements[0] else {
+            panic!("expected a loop statement");
+        };
+        assert!(matches!(
+            &loop_stmt.body.statements.statements[0],
+            Statement::Const(_)
+        ));
+    }
+
+    #[test]
+    fn test_expression_statement() {
+        let parse = |source: &str| {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            Parser::new(lexer.collect()).try_parse().unwrap()
+        };
+
+        let ast = parse("x + 3; a[i + 1] = 2; a[0] == 1; (1);");
+        let statements = &ast.statement_list.statements;
+        assert!(matches!(statements[0], Statement::Expression(_)));
+        assert!(matches!(statements[1], Statement::Assignment(_)));
+        assert!(matches!(statements[2], Statement::Expression(_)));
+        assert!(matches!(statements[3], Statement::Expression(_)));
+    }
+
+    #[test]
+    fn test_block_expression() {
+        let parse = |source: &str| {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            Parser::new(lexer.collect()).try_parse()
+        };
+
+        let ast = parse("let x = { let t = 1; t + 2 };").unwrap();
+        let Statement::Let(let_stmt) = &ast.statement_list.statements[0] else {
+            panic!("expected a let statement");
+        };
+        let Some(ExprKind::Block(block)) = let_stmt.value.as_ref().map(|value| &value.kind) else {
+            panic!("expected a block expression");
+        };
+        assert!(matches!(
+            block.statements.statements[..],
+            [Statement::Let(_)]
+        ));
+        assert!(matches!(block.value.kind, ExprKind::Binary(_)));
+
+        // An expression followed by `;` is a statement, not the value.
+        let ast = parse("let x = { t; t = 2; 3 } * 2;").unwrap();
+        let Statement::Let(let_stmt) = &ast.statement_list.statements[0] else {
+            panic!("expected a let statement");
+        };
+        let ExprKind::Binary(product) = &let_stmt.value.as_ref().unwrap().kind else {
+            panic!("expected a product");
+        };
+        let ExprKind::Block(block) = &product.lhs.kind else {
+            panic!("expected a block expression");
+        };
+        assert!(matches!(
+            block.statements.statements[..],
+            [Statement::Expression(_), Statement::Assignment(_)]
+        ));
+
+        let error = parse("let x = { let t = 1; };").unwrap_err();
+        assert_eq!(error.expected, "expression ending the block");
+    }
+
+    #[test]
+    fn test_print_arguments() {
+        let lexer = crate::lexer::Lexer::new("print \"x =\", x, 1;".to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+        let Statement::Print(print_stmt) = &ast.statement_list.statements[0] else {
+            panic!("expected a print statement");
+        };
+        assert_eq!(print_stmt.arguments.len(), 3);
+        assert_eq!(
+            print_stmt.arguments[0],
+            PrintArgument::Text("x =".to_string())
+        );
+        assert!(matches!(print_stmt.arguments[1], PrintArgument::Value(_)));
+
+        let lexer = crate::lexer::Lexer::new("print \"x =\" x;".to_string());
+        let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+        assert_eq!(error.expected, "`;` after statement");
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        let lexer = crate::lexer::Lexer::new("print \"a\" + \"b\" + \"c\", 1;".to_string());
+        let ast = Parser::new(lexer.collect()).try_parse().unwrap();
+        let Statement::Print(print_stmt) = &ast.statement_list.statements[0] else {
+            panic!("expected a print statement");
+        };
+        assert_eq!(
+            print_stmt.arguments[0],
+            PrintArgument::Text("abc".to_string())
+        );
+        assert_eq!(print_stmt.arguments.len(), 2);
+
+        for source in ["print \"a\" + 1;", "print \"a\" + x;"] {
+            let lexer = crate::lexer::Lexer::new(source.to_string());
+            let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+            assert_eq!(error.expected, "string literal after `+`");
+        }
+        let lexer = crate::lexer::Lexer::new("print 1 + \"a\";".to_string());
+        let error = Parser::new(lexer.collect()).try_parse().unwrap_err();
+        assert_eq!(error.found, Some(Token::StringLiteral("a".to_string())));
+    }
 }
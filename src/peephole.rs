@@ -0,0 +1,299 @@
+use crate::ast::{
+    AbstractSyntaxTree, ArrayInitializer, ArrayLetStatement, AssertStatement, AssignmentStatement,
+    BinaryExpr, BinaryOperator, Block, BlockExpr, CallExpr, ConstStatement, ElseBranch,
+    ExitStatement, Expr, ExprKind, ExpressionStatement, IfStatement, LetStatement, LoopStatement,
+    PrintArgument, PrintStatement, RepeatStatement, Statement, StatementList, Term, WriteExpr,
+};
+use crate::span::Span;
+
+/// A copy of `ast` with algebraic identities simplified away, as
+/// `--optimize` does before generating C: `x + 0` and `x * 1` become `x`,
+/// and `x * 0` and `x - x` become `0`. Rewrites apply bottom-up, so they
+/// compose: `x + 0 * y` becomes `x`. An operand is only dropped if it has no
+/// `write` in it.
+pub fn simplify(ast: &AbstractSyntaxTree) -> AbstractSyntaxTree {
+    AbstractSyntaxTree {
+        statement_list: simplify_statement_list(ast.statement_list.clone()),
+    }
+}
+
+fn simplify_statement_list(statement_list: StatementList) -> StatementList {
+    StatementList {
+        statements: statement_list
+            .statements
+            .into_iter()
+            .map(simplify_statement)
+            .collect(),
+    }
+}
+
+fn simplify_block(block: Block) -> Block {
+    Block {
+        statements: Box::new(simplify_statement_list(*block.statements)),
+        span: block.span,
+    }
+}
+
+fn simplify_statement(statement: Statement) -> Statement {
+    match statement {
+        Statement::Let(let_stmt) => Statement::Let(LetStatement {
+            value: let_stmt.value.map(simplify_expression),
+            ..let_stmt
+        }),
+        Statement::Const(const_stmt) => Statement::Const(ConstStatement {
+            value: simplify_expression(const_stmt.value),
+            ..const_stmt
+        }),
+        Statement::ArrayLet(array_let_stmt) => {
+            let initializer = match array_let_stmt.initializer {
+                ArrayInitializer::Fill(value) => ArrayInitializer::Fill(simplify_expression(value)),
+                ArrayInitializer::List(elements) => {
+                    ArrayInitializer::List(elements.into_iter().map(simplify_expression).collect())
+                }
+            };
+            Statement::ArrayLet(ArrayLetStatement {
+                initializer,
+                ..array_let_stmt
+            })
+        }
+        Statement::Assignment(assign_stmt) => Statement::Assignment(AssignmentStatement {
+            index: assign_stmt.index.map(simplify_expression),
+            value: simplify_expression(assign_stmt.value),
+            ..assign_stmt
+        }),
+        Statement::Loop(loop_stmt) => Statement::Loop(LoopStatement {
+            start: loop_stmt.start.map(simplify_expression),
+            count: simplify_expression(loop_stmt.count),
+            step: loop_stmt.step.map(simplify_expression),
+            body: Box::new(simplify_block(*loop_stmt.body)),
+            ..loop_stmt
+        }),
+        Statement::Repeat(repeat_stmt) => Statement::Repeat(RepeatStatement {
+            body: Box::new(simplify_block(*repeat_stmt.body)),
+            condition: simplify_expression(repeat_stmt.condition),
+            ..repeat_stmt
+        }),
+        Statement::If(if_stmt) => Statement::If(simplify_if_statement(if_stmt)),
+        Statement::Print(print_stmt) => Statement::Print(PrintStatement {
+            arguments: simplify_print_arguments(print_stmt.arguments),
+            ..print_stmt
+        }),
+        Statement::Assert(assert_stmt) => Statement::Assert(AssertStatement {
+            condition: simplify_expression(assert_stmt.condition),
+            ..assert_stmt
+        }),
+        Statement::Exit(exit_stmt) => Statement::Exit(ExitStatement {
+            code: simplify_expression(exit_stmt.code),
+            ..exit_stmt
+        }),
+        Statement::Expression(expr_stmt) => Statement::Expression(ExpressionStatement {
+            value: simplify_expression(expr_stmt.value),
+            ..expr_stmt
+        }),
+        statement @ (Statement::Read(_)
+        | Statement::Include(_)
+        | Statement::Use(_)
+        | Statement::Break(_)
+        | Statement::Comment(_)) => statement,
+    }
+}
+
+fn simplify_if_statement(if_stmt: IfStatement) -> IfStatement {
+    let else_branch = if_stmt.else_branch.map(|branch| {
+        Box::new(match *branch {
+            ElseBranch::Block(block) => ElseBranch::Block(simplify_block(block)),
+            ElseBranch::If(next) => ElseBranch::If(simplify_if_statement(next)),
+        })
+    });
+    IfStatement {
+        condition: simplify_expression(if_stmt.condition),
+        then_block: simplify_block(if_stmt.then_block),
+        else_branch,
+        span: if_stmt.span,
+    }
+}
+
+fn simplify_print_arguments(arguments: Vec<PrintArgument>) -> Vec<PrintArgument> {
+    arguments
+        .into_iter()
+        .map(|argument| match argument {
+            PrintArgument::Value(value) => PrintArgument::Value(simplify_expression(value)),
+            text => text,
+        })
+        .collect()
+}
+
+fn simplify_expression(expr: Expr) -> Expr {
+    let span = expr.span;
+    let kind = match expr.kind {
+        ExprKind::Binary(binary) => {
+            let lhs = simplify_expression(*binary.lhs);
+            let rhs = simplify_expression(*binary.rhs);
+            return simplify_binary(binary.operator, lhs, rhs, span);
+        }
+        ExprKind::Term(Term::Index { base, index }) => ExprKind::Term(Term::Index {
+            base,
+            index: Box::new(simplify_expression(*index)),
+        }),
+        ExprKind::Call(call) => ExprKind::Call(CallExpr {
+            arguments: call
+                .arguments
+                .into_iter()
+                .map(simplify_expression)
+                .collect(),
+            ..call
+        }),
+        ExprKind::Negate(operand) => ExprKind::Negate(Box::new(simplify_expression(*operand))),
+        ExprKind::Write(write) => ExprKind::Write(WriteExpr {
+            arguments: simplify_print_arguments(write.arguments),
+        }),
+        ExprKind::Block(block) => ExprKind::Block(BlockExpr {
+            statements: Box::new(simplify_statement_list(*block.statements)),
+            value: Box::new(simplify_expression(*block.value)),
+        }),
+        kind @ (ExprKind::Term(_) | ExprKind::StringLength(_)) => kind,
+    };
+    Expr { kind, span }
+}
+
+/// `lhs operator rhs`, whose operands are already simplified.
+fn simplify_binary(operator: BinaryOperator, lhs: Expr, rhs: Expr, span: Span) -> Expr {
+    let zero = Expr {
+        kind: ExprKind::Term(Term::Number(0)),
+        span,
+    };
+    match operator {
+        BinaryOperator::Add if is_number(&lhs, 0) => return rhs,
+        BinaryOperator::Add | BinaryOperator::Subtract if is_number(&rhs, 0) => return lhs,
+        BinaryOperator::Subtract if same_expression(&lhs, &rhs) && is_pure(&lhs) => return zero,
+        BinaryOperator::Multiply if is_number(&lhs, 1) => return rhs,
+        BinaryOperator::Multiply if is_number(&rhs, 1) => return lhs,
+        BinaryOperator::Multiply
+            if (is_number(&lhs, 0) && is_pure(&rhs)) || (is_number(&rhs, 0) && is_pure(&lhs)) =>
+        {
+            return zero;
+        }
+        _ => {}
+    }
+    Expr {
+        kind: ExprKind::Binary(BinaryExpr {
+            operator,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }),
+        span,
+    }
+}
+
+fn is_number(expr: &Expr, n: i64) -> bool {
+    matches!(expr.kind, ExprKind::Term(Term::Number(value)) if value == n)
+}
+
+/// Whether evaluating `expr` can't print anything, so it may be left out.
+fn is_pure(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Term(Term::Index { index, .. }) => is_pure(index),
+        ExprKind::Term(_) | ExprKind::StringLength(_) => true,
+        ExprKind::Binary(binary) => is_pure(&binary.lhs) && is_pure(&binary.rhs),
+        ExprKind::Call(call) => call.arguments.iter().all(is_pure),
+        ExprKind::Negate(operand) => is_pure(operand),
+        ExprKind::Write(_) => false,
+        // Leaving out a statement could skip an assignment as well as a print.
+        ExprKind::Block(block) => block.statements.statements.is_empty() && is_pure(&block.value),
+    }
+}
+
+/// Whether `a` and `b` are written the same, wherever they are.
+fn same_expression(a: &Expr, b: &Expr) -> bool {
+    match (&a.kind, &b.kind) {
+        (ExprKind::Term(Term::Number(a)), ExprKind::Term(Term::Number(b))) => a == b,
+        (ExprKind::Term(Term::Bool(a)), ExprKind::Term(Term::Bool(b))) => a == b,
+        (ExprKind::Term(Term::Identifier(a)), ExprKind::Term(Term::Identifier(b))) => {
+            a.name == b.name
+        }
+        (
+            ExprKind::Term(Term::Index { base, index }),
+            ExprKind::Term(Term::Index {
+                base: other_base,
+                index: other_index,
+            }),
+        ) => base.name == other_base.name && same_expression(index, other_index),
+        (ExprKind::Binary(a), ExprKind::Binary(b)) => {
+            a.operator == b.operator
+                && same_expression(&a.lhs, &b.lhs)
+                && same_expression(&a.rhs, &b.rhs)
+        }
+        (ExprKind::Call(a), ExprKind::Call(b)) => {
+            a.function.name == b.function.name
+                && a.arguments.len() == b.arguments.len()
+                && a.arguments
+                    .iter()
+                    .zip(&b.arguments)
+                    .all(|(a, b)| same_expression(a, b))
+        }
+        (ExprKind::Negate(a), ExprKind::Negate(b)) => same_expression(a, b),
+        (ExprKind::StringLength(a), ExprKind::StringLength(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_generator::{CodegenOptions, generate_c_code, generate_c_code_with_options};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The C for `let r = <expr>;` after simplifying, with `x` and `y`
+    /// declared first.
+    fn simplified(expr: &str) -> String {
+        let source = format!("let x = 1; let y = 2; let r = {};", expr);
+        let ast = Parser::new(Lexer::new(source).collect()).parse();
+        let c = generate_c_code(&simplify(&ast));
+        let line = c.lines().find(|line| line.starts_with("int r = ")).unwrap();
+        line["int r = ".len()..line.len() - 1].to_string()
+    }
+
+    #[test]
+    fn test_each_rewrite() {
+        assert_eq!(simplified("x + 0"), "x");
+        assert_eq!(simplified("0 + x"), "x");
+        assert_eq!(simplified("x - 0"), "x");
+        assert_eq!(simplified("x * 1"), "x");
+        assert_eq!(simplified("1 * x"), "x");
+        assert_eq!(simplified("x * 0"), "0");
+        assert_eq!(simplified("0 * x"), "0");
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("(x + y) - (x + y)"), "0");
+    }
+
+    #[test]
+    fn test_rewrites_compose() {
+        assert_eq!(simplified("x + 0 * y"), "x");
+        assert_eq!(simplified("(x - x) * y + y * 1"), "y");
+        assert_eq!(simplified("abs(x * 1 - (y - y))"), simplified("abs(x)"));
+    }
+
+    #[test]
+    fn test_only_with_optimize() {
+        let ast = Parser::new(Lexer::new("let x = 1; print x * 1;".to_string()).collect()).parse();
+        let compile = |optimize| {
+            let options = CodegenOptions {
+                optimize,
+                ..Default::default()
+            };
+            generate_c_code_with_options(&ast, &options)
+        };
+        assert!(compile(true).contains("printf(\"%d\\n\", x);"));
+        assert!(compile(false).contains("printf(\"%d\\n\", x * 1);"));
+    }
+
+    #[test]
+    fn test_keeps_what_it_cant_simplify() {
+        assert_eq!(simplified("x - y"), "x - y");
+        assert_eq!(simplified("0 - x"), "0 - x");
+        assert_eq!(simplified("x * 2 + 1"), "x * 2 + 1");
+        // The `write` still has to print.
+        assert!(simplified("write(x) * 0").contains("printf"));
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+/// Nested scopes mapping each declared name to what a pass tracks about it:
+/// its type during analysis, its storage in a backend, its value in the
+/// interpreter. Every pass enters a scope for the same constructs (a block,
+/// and a loop's counter) so they agree on which declaration a name means.
+pub struct ScopeStack<T> {
+    scopes: Vec<HashMap<String, T>>,
+}
+
+impl<T> ScopeStack<T> {
+    /// A stack holding just the top-level scope.
+    pub fn new() -> Self {
+        ScopeStack {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Declares `name` in the innermost scope, shadowing any outer `name`.
+    pub fn declare(&mut self, name: String, value: T) {
+        if let Some(current_scope) = self.scopes.last_mut() {
+            current_scope.insert(name, value);
+        }
+    }
+
+    /// Number of scopes entered; 0 at the top level.
+    pub fn depth(&self) -> usize {
+        self.scopes.len() - 1
+    }
+
+    pub fn declared(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    /// The innermost declaration of `name`.
+    pub fn lookup(&self, name: &str) -> Option<&T> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    pub fn lookup_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+}
+
+impl<T> Default for ScopeStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shadowing_ends_with_its_scope() {
+        let mut scopes = ScopeStack::new();
+        scopes.declare("x".to_string(), 1);
+        scopes.enter_scope();
+        assert_eq!(scopes.lookup("x"), Some(&1));
+        scopes.declare("x".to_string(), 2);
+        *scopes.lookup_mut("x").unwrap() += 1;
+        assert_eq!(scopes.lookup("x"), Some(&3));
+        assert_eq!(scopes.depth(), 1);
+        scopes.exit_scope();
+
+        assert_eq!(scopes.lookup("x"), Some(&1));
+        assert!(!scopes.declared("y"));
+    }
+}
@@ -1,75 +1,374 @@
+use crate::CompilerOptions;
+pub use crate::ast::Type;
 use crate::ast::{
-    AbstractSyntaxTree, AssignmentStatement, Expr, LetStatement, LoopStatement, PrintStatement,
-    Statement, StatementList, Term,
+    AbstractSyntaxTree, ArrayInitializer, ArrayLetStatement, AssertStatement, AssignmentStatement,
+    BinaryExpr, BinaryOperator, Block, BlockExpr, CallExpr, ConstStatement, ElseBranch,
+    ExitStatement, Expr, ExprKind, Identifier, IfStatement, Intrinsic, LetStatement, LoopStatement,
+    PrintArgument, PrintFormat, PrintStatement, ReadStatement, RepeatStatement, Statement,
+    StatementList, Term, WriteExpr,
 };
+use crate::code_generator::CodegenOptions;
+use crate::const_eval::{IntWidth, eval_const, eval_const_with};
+use crate::scope::ScopeStack;
+use crate::span::Span;
 use std::collections::HashSet;
 
 #[derive(Debug, Clone)]
 pub enum SemanticError {
-    UndeclaredVariable(String),
+    UndeclaredVariable {
+        name: String,
+        span: Span,
+    },
+    DivisionByZero {
+        span: Span,
+    },
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    UnresolvedInclude {
+        path: String,
+        span: Span,
+    },
+    IntegerOutOfRange {
+        value: i64,
+        span: Span,
+    },
+    NotAnArray {
+        name: String,
+        span: Span,
+    },
+    UnknownFunction {
+        name: String,
+        span: Span,
+    },
+    WrongArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// A loop `step` that is constant and not positive would never finish.
+    NonPositiveStep {
+        value: i64,
+        span: Span,
+    },
+    /// `a < b < c`, which C would read as `(a < b) < c`; `span` covers the
+    /// whole chain.
+    ChainedComparison {
+        span: Span,
+    },
+    /// A constant is assigned to, or its name declared again; `span` is
+    /// that of the offending name.
+    AssignToConst {
+        name: String,
+        span: Span,
+    },
+    /// A `const` whose value can't be computed at compile time.
+    NotConstant {
+        name: String,
+        span: Span,
+    },
+    /// A `write` inside the arguments of `function`, which C evaluates more
+    /// than once.
+    WriteInArgument {
+        function: String,
+        span: Span,
+    },
+    /// A block expression inside the arguments of `function` with a
+    /// statement that does more than declare something, which C would run
+    /// more than once.
+    StatementInArgument {
+        function: String,
+        span: Span,
+    },
+    /// A loop whose constant bounds or step take the counter past the
+    /// largest int, where C's `for` would overflow it.
+    LoopBoundOverflow {
+        span: Span,
+    },
+    /// An assignment or `read` into a variable not declared `mut`, such as
+    /// a loop counter; `span` is that of the name.
+    AssignToImmutable {
+        name: String,
+        span: Span,
+    },
+    /// A `break` outside any loop body. A block used as a value counts as
+    /// outside, even in a loop.
+    BreakOutsideLoop {
+        span: Span,
+    },
+    /// A constant shift amount that is negative or not less than the int
+    /// width, which C leaves undefined.
+    ShiftOutOfRange {
+        amount: i64,
+        span: Span,
+    },
+    /// A `use` with no module file to import, or a qualified name such as
+    /// `math.PI` whose module no `use` imports.
+    UnknownModule {
+        name: String,
+        span: Span,
+    },
+    /// A qualified name whose module is imported but has no such const.
+    UnknownMember {
+        module: String,
+        member: String,
+        span: Span,
+    },
 }
 
-pub struct ScopeStack {
-    scopes: Vec<HashSet<String>>,
-}
-
-impl ScopeStack {
-    pub fn new() -> Self {
-        ScopeStack {
-            scopes: vec![HashSet::new()],
+impl SemanticError {
+    pub fn span(&self) -> Span {
+        match self {
+            SemanticError::UndeclaredVariable { span, .. }
+            | SemanticError::DivisionByZero { span }
+            | SemanticError::TypeMismatch { span, .. }
+            | SemanticError::UnresolvedInclude { span, .. }
+            | SemanticError::IntegerOutOfRange { span, .. }
+            | SemanticError::NotAnArray { span, .. }
+            | SemanticError::UnknownFunction { span, .. }
+            | SemanticError::WrongArgumentCount { span, .. }
+            | SemanticError::NonPositiveStep { span, .. }
+            | SemanticError::ChainedComparison { span }
+            | SemanticError::AssignToConst { span, .. }
+            | SemanticError::NotConstant { span, .. }
+            | SemanticError::WriteInArgument { span, .. }
+            | SemanticError::StatementInArgument { span, .. }
+            | SemanticError::LoopBoundOverflow { span }
+            | SemanticError::AssignToImmutable { span, .. }
+            | SemanticError::ShiftOutOfRange { span, .. }
+            | SemanticError::UnknownModule { span, .. }
+            | SemanticError::UnknownMember { span, .. } => *span,
+            SemanticError::BreakOutsideLoop { span } => *span,
         }
     }
 
-    pub fn enter_scope(&mut self) {
-        self.scopes.push(HashSet::new());
+    /// The stable code `--explain` takes, such as `E0001`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SemanticError::UndeclaredVariable { .. } => "E0001",
+            SemanticError::DivisionByZero { .. } => "E0002",
+            SemanticError::TypeMismatch { .. } => "E0003",
+            SemanticError::UnresolvedInclude { .. } => "E0004",
+            SemanticError::IntegerOutOfRange { .. } => "E0005",
+            SemanticError::NotAnArray { .. } => "E0006",
+            SemanticError::UnknownFunction { .. } => "E0007",
+            SemanticError::WrongArgumentCount { .. } => "E0008",
+            SemanticError::NonPositiveStep { .. } => "E0009",
+            SemanticError::ChainedComparison { .. } => "E0010",
+            SemanticError::AssignToConst { .. } => "E0011",
+            SemanticError::NotConstant { .. } => "E0012",
+            SemanticError::WriteInArgument { .. } => "E0013",
+            SemanticError::StatementInArgument { .. } => "E0014",
+            SemanticError::LoopBoundOverflow { .. } => "E0015",
+            SemanticError::AssignToImmutable { .. } => "E0016",
+            SemanticError::BreakOutsideLoop { .. } => "E0017",
+            SemanticError::ShiftOutOfRange { .. } => "E0018",
+            SemanticError::UnknownModule { .. } => "E0019",
+            SemanticError::UnknownMember { .. } => "E0020",
+        }
     }
+}
 
-    pub fn exit_scope(&mut self) {
-        self.scopes.pop();
-    }
+#[derive(Debug, Clone)]
+pub enum SemanticWarning {
+    /// Statements follow an `exit` or a `break`, named by `after`, in the
+    /// same block; `span` covers the first of them.
+    UnreachableCode { after: &'static str, span: Span },
+    /// A constant index outside `0..length`; C doesn't check this at runtime.
+    IndexOutOfBounds {
+        index: i64,
+        length: usize,
+        span: Span,
+    },
+    /// An assignment such as `x = x;` or `x = x + 0;` that leaves the
+    /// variable unchanged; `span` covers the statement.
+    SelfAssignment { name: String, span: Span },
+    /// A variable declared without an initializer is read before anything
+    /// is assigned to it; `span` covers the read.
+    UseOfUninitialized { name: String, span: Span },
+    /// `loop i from a to b` with constant bounds where `a > b`, so the body
+    /// never runs; `span` covers both bounds.
+    EmptyRange { from: i64, to: i64, span: Span },
+    /// `printc` of a constant outside ASCII, 0 to 127, which prints
+    /// differently depending on the platform's character set.
+    CharOutOfRange { value: i64, span: Span },
+    /// A `repeat` whose condition is constant and false, with no `break`
+    /// out of it and no `exit` in it, so it runs forever; `span` covers the
+    /// condition.
+    PossibleInfiniteLoop { span: Span },
+}
 
-    pub fn declare(&mut self, name: String) {
-        if let Some(current_scope) = self.scopes.last_mut() {
-            current_scope.insert(name);
+impl SemanticWarning {
+    pub fn span(&self) -> Span {
+        match self {
+            SemanticWarning::UnreachableCode { span, .. }
+            | SemanticWarning::IndexOutOfBounds { span, .. }
+            | SemanticWarning::SelfAssignment { span, .. }
+            | SemanticWarning::UseOfUninitialized { span, .. }
+            | SemanticWarning::EmptyRange { span, .. }
+            | SemanticWarning::CharOutOfRange { span, .. }
+            | SemanticWarning::PossibleInfiniteLoop { span } => *span,
         }
     }
+}
 
-    pub fn declared(&self, name: &str) -> bool {
-        for scope in self.scopes.iter().rev() {
-            if scope.contains(name) {
-                return true;
-            }
-        }
-        false
+/// A declared variable, as recorded for `--emit symbols`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub ty: Type,
+    /// Number of enclosing scopes; top-level declarations are at depth 0.
+    pub depth: usize,
+    pub span: Span,
+    /// Whether the variable's value is used anywhere. Assigning to it, or to
+    /// one of its elements, doesn't count.
+    pub read: bool,
+}
+
+/// Everything the analyzer reports. Errors and warnings are sorted by source
+/// position; symbols are in declaration order.
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub errors: Vec<SemanticError>,
+    pub warnings: Vec<SemanticWarning>,
+    pub symbols: Vec<Symbol>,
+    /// Deepest nesting of loops inside one another; 0 without loops.
+    pub max_loop_depth: usize,
+}
+
+impl Analysis {
+    /// Keeps only the first `max` errors in source order and returns how
+    /// many were dropped.
+    pub fn limit_errors(&mut self, max: usize) -> usize {
+        let dropped = self.errors.len().saturating_sub(max);
+        self.errors.truncate(max);
+        dropped
     }
 }
 
+/// What the analyzer knows about a variable in scope.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VarInfo {
+    pub ty: Type,
+    /// Index of the variable's entry in `Analysis::symbols`.
+    pub symbol: usize,
+    /// Declared with `let mut`, so it can be assigned.
+    pub mutable: bool,
+}
+
 pub struct SemanticAnalyzer {
-    scope_stack: ScopeStack,
+    scope_stack: ScopeStack<VarInfo>,
     errors: Vec<SemanticError>,
+    warnings: Vec<SemanticWarning>,
+    symbols: Vec<Symbol>,
+    int_width: IntWidth,
+    /// Whether `--strict` forbids the implicit int/bool coercions C allows.
+    strict: bool,
+    loop_depth: usize,
+    max_loop_depth: usize,
+    /// Whether a `break` here would leave a loop.
+    in_loop_body: bool,
+    /// Values of the `const`s in scope, entered and exited with
+    /// `scope_stack`.
+    constants: ScopeStack<i64>,
+    /// Symbols of the `let x;` variables that some path to the current
+    /// statement leaves unassigned.
+    unassigned: HashSet<usize>,
+    /// The function whose arguments are being analyzed, if any.
+    calling: Option<String>,
+    /// Names of the modules a `use` has imported so far.
+    modules: HashSet<String>,
 }
 
 impl SemanticAnalyzer {
-    fn new() -> Self {
+    fn new(int_width: IntWidth, strict: bool) -> Self {
         SemanticAnalyzer {
             scope_stack: ScopeStack::new(),
             errors: Vec::new(),
+            warnings: Vec::new(),
+            symbols: Vec::new(),
+            int_width,
+            strict,
+            loop_depth: 0,
+            max_loop_depth: 0,
+            in_loop_body: false,
+            constants: ScopeStack::new(),
+            unassigned: HashSet::new(),
+            calling: None,
+            modules: HashSet::new(),
         }
     }
 
     pub fn analyze(ast: &AbstractSyntaxTree) -> Result<(), Vec<SemanticError>> {
-        let mut analyzer = SemanticAnalyzer::new();
-        analyzer.analyze_statement_list(&ast.statement_list);
+        Self::analyze_with_width(ast, IntWidth::default())
+    }
 
-        if analyzer.errors.is_empty() {
+    pub fn analyze_with_width(
+        ast: &AbstractSyntaxTree,
+        int_width: IntWidth,
+    ) -> Result<(), Vec<SemanticError>> {
+        let analysis = Self::check(ast, int_width);
+        if analysis.errors.is_empty() {
             Ok(())
         } else {
-            Err(analyzer.errors.clone())
+            Err(analysis.errors)
+        }
+    }
+
+    /// Analyzes `ast` and returns warnings alongside any errors.
+    pub fn check(ast: &AbstractSyntaxTree, int_width: IntWidth) -> Analysis {
+        let options = CompilerOptions {
+            codegen: CodegenOptions {
+                int_width,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        Self::check_with(ast, &options)
+    }
+
+    /// Like `check`, but with `strict` an int is never accepted where a bool
+    /// belongs or the other way around, even where C would coerce it:
+    /// arithmetic and ordering operands, `==` between an int and a bool, loop
+    /// counts and assignments.
+    pub fn check_with(ast: &AbstractSyntaxTree, options: &CompilerOptions) -> Analysis {
+        let mut analyzer = SemanticAnalyzer::new(options.int_width(), options.strict);
+        analyzer.analyze_statement_list(&ast.statement_list);
+        // Errors are pushed in traversal order, which isn't always source
+        // order (an operator's type error comes after its operands' errors).
+        analyzer.errors.sort_by_key(|error| error.span().start);
+        analyzer
+            .warnings
+            .sort_by_key(|warning| warning.span().start);
+
+        Analysis {
+            errors: analyzer.errors,
+            warnings: analyzer.warnings,
+            symbols: analyzer.symbols,
+            max_loop_depth: analyzer.max_loop_depth,
         }
     }
 
     fn analyze_statement_list(&mut self, statement_list: &StatementList) {
+        let mut after_jump = None;
         for statement in &statement_list.statements {
+            if let Some(after) = after_jump
+                && !matches!(statement, Statement::Comment(_))
+            {
+                self.warnings.push(SemanticWarning::UnreachableCode {
+                    after,
+                    span: statement.span(),
+                });
+                // One warning per block is enough.
+                after_jump = None;
+            } else if matches!(statement, Statement::Exit(_)) {
+                after_jump = Some("exit");
+            } else if matches!(statement, Statement::Break(_)) {
+                after_jump = Some("break");
+            }
             self.analyze_statement(statement);
         }
     }
@@ -77,59 +376,925 @@ impl SemanticAnalyzer {
     fn analyze_statement(&mut self, statement: &Statement) {
         match statement {
             Statement::Let(let_stmt) => self.analyze_let_statement(let_stmt),
+            Statement::Const(const_stmt) => self.analyze_const_statement(const_stmt),
+            Statement::ArrayLet(array_let_stmt) => self.analyze_array_let_statement(array_let_stmt),
             Statement::Assignment(assign_stmt) => self.analyze_assignment_statement(assign_stmt),
             Statement::Loop(loop_stmt) => self.analyze_loop_statement(loop_stmt),
+            Statement::Repeat(repeat_stmt) => self.analyze_repeat_statement(repeat_stmt),
+            Statement::If(if_stmt) => self.analyze_if_statement(if_stmt),
             Statement::Print(print_stmt) => self.analyze_print_statement(print_stmt),
+            Statement::Assert(assert_stmt) => self.analyze_assert_statement(assert_stmt),
+            Statement::Read(read_stmt) => self.analyze_read_statement(read_stmt),
+            Statement::Include(include_stmt) => {
+                // Includes are spliced in by `include::load_program`; one that
+                // is still here came from source without a file to resolve from.
+                self.errors.push(SemanticError::UnresolvedInclude {
+                    path: include_stmt.path.clone(),
+                    span: include_stmt.span,
+                });
+            }
+            Statement::Use(use_stmt) => {
+                // Like includes, modules are resolved by `include`, which
+                // leaves a `use` unresolved if it finds no module file.
+                if !use_stmt.resolved {
+                    self.errors.push(SemanticError::UnknownModule {
+                        name: use_stmt.module.name.clone(),
+                        span: use_stmt.module.span,
+                    });
+                }
+                self.modules.insert(use_stmt.module.name.clone());
+            }
+            Statement::Exit(exit_stmt) => self.analyze_exit_statement(exit_stmt),
+            Statement::Break(break_stmt) => {
+                if !self.in_loop_body {
+                    self.errors.push(SemanticError::BreakOutsideLoop {
+                        span: break_stmt.span,
+                    });
+                }
+            }
+            Statement::Expression(expr_stmt) => {
+                self.analyze_expression(&expr_stmt.value);
+            }
+            Statement::Comment(_) => {}
         }
     }
 
     fn analyze_let_statement(&mut self, let_stmt: &LetStatement) {
-        self.scope_stack.declare(let_stmt.identifier.clone());
-        self.analyze_expression(&let_stmt.value);
+        let Some(value) = &let_stmt.value else {
+            let ty = let_stmt.declared_type.unwrap_or(Type::Int);
+            self.declare_uninitialized(&let_stmt.identifier, ty, let_stmt.mutable);
+            return;
+        };
+        let ty = self.analyze_expression(value);
+        let ty = match let_stmt.declared_type {
+            Some(declared) => {
+                self.expect_type(declared, ty, value.span);
+                declared
+            }
+            None => ty,
+        };
+        self.declare(&let_stmt.identifier, ty, let_stmt.mutable);
+    }
+
+    fn analyze_const_statement(&mut self, const_stmt: &ConstStatement) {
+        let identifier = &const_stmt.identifier;
+        let ty = self.analyze_expression(&const_stmt.value);
+        if self.scope_stack.declared(&identifier.name) && !self.constants.declared(&identifier.name)
+        {
+            self.errors.push(SemanticError::AssignToConst {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            });
+        }
+        self.declare(identifier, ty, false);
+        match eval_const(&const_stmt.value, self.int_width) {
+            Some(value) => {
+                self.constants.declare(identifier.name.clone(), value);
+            }
+            None => self.errors.push(SemanticError::NotConstant {
+                name: identifier.name.clone(),
+                span: const_stmt.value.span,
+            }),
+        }
+    }
+
+    fn analyze_array_let_statement(&mut self, array_let_stmt: &ArrayLetStatement) {
+        let elements = match &array_let_stmt.initializer {
+            ArrayInitializer::Fill(value) => std::slice::from_ref(value),
+            ArrayInitializer::List(elements) => elements.as_slice(),
+        };
+        for element in elements {
+            let ty = self.analyze_expression(element);
+            self.expect_type(Type::Int, ty, element.span);
+        }
+        self.declare(
+            &array_let_stmt.identifier,
+            Type::Array(array_let_stmt.length),
+            array_let_stmt.mutable,
+        );
     }
 
     fn analyze_assignment_statement(&mut self, assign_stmt: &AssignmentStatement) {
-        if !self.scope_stack.declared(&assign_stmt.identifier) {
-            self.errors.push(SemanticError::UndeclaredVariable(
-                assign_stmt.identifier.clone(),
-            ));
+        let identifier = &assign_stmt.identifier;
+        if self.is_self_assignment(assign_stmt) {
+            self.warnings.push(SemanticWarning::SelfAssignment {
+                name: identifier.name.clone(),
+                span: assign_stmt.span,
+            });
+        }
+        match &assign_stmt.index {
+            Some(index) => {
+                if let Some(Type::Array(_)) = self.variable_type(&identifier.name) {
+                    self.check_mutable(identifier);
+                }
+                self.analyze_index(identifier, index);
+                let ty = self.analyze_expression(&assign_stmt.value);
+                self.expect_type(Type::Int, ty, assign_stmt.value.span);
+            }
+            None => {
+                if self.constants.declared(&identifier.name) {
+                    self.errors.push(SemanticError::AssignToConst {
+                        name: identifier.name.clone(),
+                        span: identifier.span,
+                    });
+                }
+                match self.variable_type(&identifier.name) {
+                    None => self.errors.push(SemanticError::UndeclaredVariable {
+                        name: identifier.name.clone(),
+                        span: identifier.span,
+                    }),
+                    // Arrays can only be assigned one element at a time.
+                    Some(ty @ Type::Array(_)) => self.expect_type(Type::Int, ty, identifier.span),
+                    Some(_) => self.check_mutable(identifier),
+                }
+                let value_type = self.analyze_expression(&assign_stmt.value);
+                if let Some(ty @ (Type::Int | Type::Bool)) = self.variable_type(&identifier.name) {
+                    self.expect_strict(ty, value_type, assign_stmt.value.span);
+                }
+                self.mark_initialized(&identifier.name);
+            }
+        }
+    }
+
+    /// Checks `base[index]`, for both reads and writes.
+    fn analyze_index(&mut self, base: &Identifier, index: &Expr) {
+        let index_type = self.analyze_expression(index);
+        self.expect_type(Type::Int, index_type, index.span);
+        match self.variable_type(&base.name) {
+            Some(Type::Array(length)) => {
+                if let Some(value) = self.eval_const(index, self.int_width)
+                    && !usize::try_from(value).is_ok_and(|value| value < length)
+                {
+                    self.warnings.push(SemanticWarning::IndexOutOfBounds {
+                        index: value,
+                        length,
+                        span: index.span,
+                    });
+                }
+            }
+            Some(_) => self.errors.push(SemanticError::NotAnArray {
+                name: base.name.clone(),
+                span: base.span,
+            }),
+            None => self.report_undeclared(base),
         }
-        self.analyze_expression(&assign_stmt.value);
+    }
+
+    /// Reports a name that isn't in scope. For a qualified name such as
+    /// `math.PI`, that's an unknown module unless a `use` imported it, and
+    /// otherwise an unknown member.
+    fn report_undeclared(&mut self, identifier: &Identifier) {
+        let error = match identifier.name.split_once('.') {
+            Some((module, member)) if self.modules.contains(module) => {
+                SemanticError::UnknownMember {
+                    module: module.to_string(),
+                    member: member.to_string(),
+                    span: identifier.span,
+                }
+            }
+            Some((module, _)) => SemanticError::UnknownModule {
+                name: module.to_string(),
+                span: identifier.span,
+            },
+            None => SemanticError::UndeclaredVariable {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            },
+        };
+        self.errors.push(error);
     }
 
     fn analyze_loop_statement(&mut self, loop_stmt: &LoopStatement) {
-        self.analyze_expression(&loop_stmt.count);
-        self.scope_stack.enter_scope();
+        if let Some(start) = &loop_stmt.start {
+            let ty = self.analyze_expression(start);
+            self.expect_strict(Type::Int, ty, start.span);
+            if let (Some(from), Some(to)) = (
+                self.eval_const(start, self.int_width),
+                self.eval_const(&loop_stmt.count, self.int_width),
+            ) && from > to
+            {
+                self.warnings.push(SemanticWarning::EmptyRange {
+                    from,
+                    to,
+                    span: start.span.to(loop_stmt.count.span),
+                });
+            }
+        }
+        let count_type = self.analyze_expression(&loop_stmt.count);
+        self.expect_strict(Type::Int, count_type, loop_stmt.count.span);
+        if let Some(step) = &loop_stmt.step {
+            let ty = self.analyze_expression(step);
+            self.expect_type(Type::Int, ty, step.span);
+            if let Some(value) = self.eval_const(step, self.int_width)
+                && value <= 0
+            {
+                self.errors.push(SemanticError::NonPositiveStep {
+                    value,
+                    span: step.span,
+                });
+            }
+        }
+        self.check_loop_bounds(loop_stmt);
+        self.loop_depth += 1;
+        self.max_loop_depth = self.max_loop_depth.max(self.loop_depth);
+        self.enter_scope();
+        if let Some(counter) = &loop_stmt.counter {
+            // The loop itself advances the counter.
+            self.declare(counter, Type::Int, false);
+        }
+        // The body may run zero times, so nothing it assigns counts after
+        // the loop.
+        let unassigned = self.unassigned.clone();
+        let in_loop_body = std::mem::replace(&mut self.in_loop_body, true);
         self.analyze_statement_list(&loop_stmt.body.statements);
+        self.in_loop_body = in_loop_body;
+        self.unassigned = unassigned;
+        self.exit_scope();
+        self.loop_depth -= 1;
+    }
+
+    /// Reports constant bounds that don't fit the int width, or that make the
+    /// counter step past the largest int before the loop ends. Counts under
+    /// `loop N` stop at the first multiple of the step not below `N`. A
+    /// `loop i from a to b` stops after its last value in range without
+    /// stepping again, so only `a` and `b` need to fit. Bounds are folded in
+    /// 64 bits, so a 32-bit overflow still has a value to check.
+    fn check_loop_bounds(&mut self, loop_stmt: &LoopStatement) {
+        let fold = |expr: &Expr| self.eval_const(expr, IntWidth::Bits64).map(i128::from);
+        let step = match &loop_stmt.step {
+            Some(step) => match fold(step) {
+                Some(step) if step > 0 => step,
+                // Unknown, or already reported as not positive.
+                _ => return,
+            },
+            None => 1,
+        };
+        let Some(end) = fold(&loop_stmt.count) else {
+            return;
+        };
+        let start = match &loop_stmt.start {
+            Some(start) => match fold(start) {
+                Some(start) => Some(start),
+                None => return,
+            },
+            None => None,
+        };
+        // For a range loop this is the start, the one bound left to check.
+        let last = match start {
+            Some(start) => start,
+            None if end > 0 => (end + step - 1) / step * step,
+            None => 0,
+        };
+        let (min, max) = (
+            i128::from(self.int_width.min()),
+            i128::from(self.int_width.max()),
+        );
+        let fits = |value: i128| (min..=max).contains(&value);
+        if !fits(end) || !fits(last) {
+            let first = loop_stmt.start.as_ref().unwrap_or(&loop_stmt.count);
+            let last = loop_stmt.step.as_ref().unwrap_or(&loop_stmt.count);
+            self.errors.push(SemanticError::LoopBoundOverflow {
+                span: first.span.to(last.span),
+            });
+        }
+    }
+
+    fn analyze_repeat_statement(&mut self, repeat_stmt: &RepeatStatement) {
+        self.loop_depth += 1;
+        self.max_loop_depth = self.max_loop_depth.max(self.loop_depth);
+        // The body always runs, so what it assigns counts in the condition,
+        // and after the loop unless a `break` may skip the rest of it. As in
+        // C's `do`/`while`, the condition can't see the body's declarations.
+        let unassigned = self.unassigned.clone();
+        let in_loop_body = std::mem::replace(&mut self.in_loop_body, true);
+        self.analyze_block(&repeat_stmt.body);
+        self.in_loop_body = in_loop_body;
+        self.loop_depth -= 1;
+        let ty = self.analyze_expression(&repeat_stmt.condition);
+        self.expect_type(Type::Bool, ty, repeat_stmt.condition.span);
+        let breaks = breaks_out(&repeat_stmt.body.statements);
+        if breaks {
+            self.unassigned = unassigned;
+        }
+        if !breaks
+            && !may_exit(&repeat_stmt.body.statements)
+            && self.eval_const(&repeat_stmt.condition, self.int_width) == Some(0)
+        {
+            self.warnings.push(SemanticWarning::PossibleInfiniteLoop {
+                span: repeat_stmt.condition.span,
+            });
+        }
+    }
+
+    fn declare(&mut self, identifier: &Identifier, ty: Type, mutable: bool) {
+        self.declare_with(identifier, ty, true, mutable);
+    }
+
+    /// Declares a variable from `let x;`, which starts out as 0.
+    fn declare_uninitialized(&mut self, identifier: &Identifier, ty: Type, mutable: bool) {
+        self.declare_with(identifier, ty, false, mutable);
+    }
+
+    fn declare_with(
+        &mut self,
+        identifier: &Identifier,
+        ty: Type,
+        initialized: bool,
+        mutable: bool,
+    ) {
+        // Codegen substitutes constants by name, so a name can't be both.
+        if self.constants.declared(&identifier.name) {
+            self.errors.push(SemanticError::AssignToConst {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            });
+        }
+        let info = VarInfo {
+            ty,
+            symbol: self.symbols.len(),
+            mutable,
+        };
+        if !initialized {
+            self.unassigned.insert(info.symbol);
+        }
+        self.symbols.push(Symbol {
+            name: identifier.name.clone(),
+            ty,
+            depth: self.scope_stack.depth(),
+            span: identifier.span,
+            read: false,
+        });
+        self.scope_stack.declare(identifier.name.clone(), info);
+    }
+
+    fn variable_type(&self, name: &str) -> Option<Type> {
+        self.scope_stack.lookup(name).map(|info| info.ty)
+    }
+
+    /// Reports assigning to `identifier` unless it's declared `mut`.
+    /// Constants and undeclared names are reported elsewhere.
+    fn check_mutable(&mut self, identifier: &Identifier) {
+        if let Some(info) = self.scope_stack.lookup(&identifier.name)
+            && !info.mutable
+            && !self.constants.declared(&identifier.name)
+        {
+            self.errors.push(SemanticError::AssignToImmutable {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            });
+        }
+    }
+
+    /// Records an assignment to the innermost variable called `name`.
+    fn mark_initialized(&mut self, name: &str) {
+        if let Some(info) = self.scope_stack.lookup(name) {
+            self.unassigned.remove(&info.symbol);
+        }
+    }
+
+    /// A variable is assigned after the `if` only if every branch that
+    /// falls through to the next statement assigns it.
+    fn analyze_if_statement(&mut self, if_stmt: &IfStatement) {
+        let ty = self.analyze_expression(&if_stmt.condition);
+        self.expect_type(Type::Bool, ty, if_stmt.condition.span);
+        let before = self.unassigned.clone();
+        self.analyze_block(&if_stmt.then_block);
+        let after_then = std::mem::replace(&mut self.unassigned, before);
+        match if_stmt.else_branch.as_deref() {
+            None => {}
+            Some(ElseBranch::Block(block)) => self.analyze_block(block),
+            Some(ElseBranch::If(next)) => self.analyze_if_statement(next),
+        }
+        let else_exits = match if_stmt.else_branch.as_deref() {
+            None => false,
+            Some(ElseBranch::Block(block)) => always_exits(&block.statements),
+            Some(ElseBranch::If(next)) => if_always_exits(next),
+        };
+        if else_exits {
+            self.unassigned = after_then;
+        } else if !always_exits(&if_stmt.then_block.statements) {
+            self.unassigned.extend(after_then);
+        }
+    }
+
+    /// Analyzes `block` in a scope of its own.
+    fn analyze_block(&mut self, block: &Block) {
+        self.enter_scope();
+        self.analyze_statement_list(&block.statements);
+        self.exit_scope();
+    }
+
+    /// A `const` declared in a block is only folded within it.
+    fn enter_scope(&mut self) {
+        self.scope_stack.enter_scope();
+        self.constants.enter_scope();
+    }
+
+    fn exit_scope(&mut self) {
         self.scope_stack.exit_scope();
+        self.constants.exit_scope();
     }
 
     fn analyze_print_statement(&mut self, print_stmt: &PrintStatement) {
-        self.analyze_expression(&print_stmt.value);
+        for argument in &print_stmt.arguments {
+            match argument {
+                // `print a;` prints every element of an array, but a whole
+                // array is still no value anywhere else.
+                PrintArgument::Value(Expr {
+                    kind: ExprKind::Term(Term::Identifier(identifier)),
+                    ..
+                }) if matches!(self.variable_type(&identifier.name), Some(Type::Array(_))) => {
+                    if let Some(info) = self.scope_stack.lookup(&identifier.name) {
+                        self.symbols[info.symbol].read = true;
+                    }
+                }
+                PrintArgument::Value(value) => {
+                    self.analyze_expression(value);
+                    if print_stmt.format == PrintFormat::Char
+                        && let Some(code) = self.eval_const(value, self.int_width)
+                        && !(0..=127).contains(&code)
+                    {
+                        self.warnings.push(SemanticWarning::CharOutOfRange {
+                            value: code,
+                            span: value.span,
+                        });
+                    }
+                }
+                PrintArgument::Text(_) => {}
+            }
+        }
+    }
+
+    fn analyze_assert_statement(&mut self, assert_stmt: &AssertStatement) {
+        let ty = self.analyze_expression(&assert_stmt.condition);
+        self.expect_type(Type::Bool, ty, assert_stmt.condition.span);
+    }
+
+    fn analyze_read_statement(&mut self, read_stmt: &ReadStatement) {
+        let identifier = &read_stmt.identifier;
+        if self.constants.declared(&identifier.name) {
+            self.errors.push(SemanticError::AssignToConst {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            });
+            return;
+        }
+        match self.variable_type(&identifier.name) {
+            None => self.errors.push(SemanticError::UndeclaredVariable {
+                name: identifier.name.clone(),
+                span: identifier.span,
+            }),
+            // Input is only ever read as an int.
+            Some(ty) => {
+                self.expect_type(Type::Int, ty, identifier.span);
+                self.check_mutable(identifier);
+            }
+        }
+        self.mark_initialized(&identifier.name);
+    }
+
+    fn analyze_exit_statement(&mut self, exit_stmt: &ExitStatement) {
+        let ty = self.analyze_expression(&exit_stmt.code);
+        self.expect_type(Type::Int, ty, exit_stmt.code.span);
+    }
+
+    /// Whether `assign_stmt` stores back the value already in its target.
+    fn is_self_assignment(&self, assign_stmt: &AssignmentStatement) -> bool {
+        let value = self.strip_identity_operations(&assign_stmt.value);
+        match (&assign_stmt.index, &value.kind) {
+            (None, ExprKind::Term(Term::Identifier(source))) => {
+                source.name == assign_stmt.identifier.name
+            }
+            (
+                Some(index),
+                ExprKind::Term(Term::Index {
+                    base,
+                    index: source,
+                }),
+            ) => base.name == assign_stmt.identifier.name && self.same_index(index, source),
+            _ => false,
+        }
+    }
+
+    /// Whether two index expressions certainly pick the same element: both
+    /// the same variable or both the same constant.
+    fn same_index(&self, a: &Expr, b: &Expr) -> bool {
+        match (&a.kind, &b.kind) {
+            (ExprKind::Term(Term::Identifier(a)), ExprKind::Term(Term::Identifier(b))) => {
+                a.name == b.name
+            }
+            _ => match eval_const(a, self.int_width) {
+                Some(value) => eval_const(b, self.int_width) == Some(value),
+                None => false,
+            },
+        }
+    }
+
+    /// `expr` with operations that return their other operand unchanged,
+    /// such as `+ 0` or `* 1`, stripped off.
+    fn strip_identity_operations<'e>(&self, expr: &'e Expr) -> &'e Expr {
+        let ExprKind::Binary(binary) = &expr.kind else {
+            return expr;
+        };
+        let lhs = eval_const(&binary.lhs, self.int_width);
+        let rhs = eval_const(&binary.rhs, self.int_width);
+        let operand = match (&binary.operator, lhs, rhs) {
+            (
+                BinaryOperator::Add | BinaryOperator::BitwiseOr | BinaryOperator::BitwiseXor,
+                Some(0),
+                _,
+            )
+            | (BinaryOperator::Multiply, Some(1), _) => &binary.rhs,
+            (
+                BinaryOperator::Add
+                | BinaryOperator::Subtract
+                | BinaryOperator::BitwiseOr
+                | BinaryOperator::BitwiseXor
+                | BinaryOperator::ShiftLeft
+                | BinaryOperator::ShiftRight,
+                _,
+                Some(0),
+            )
+            | (
+                BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Power,
+                _,
+                Some(1),
+            ) => &binary.lhs,
+            _ => return expr,
+        };
+        self.strip_identity_operations(operand)
+    }
+
+    /// `expr` evaluated at compile time in `width`, with every `const` in
+    /// scope folded in, as codegen folds them.
+    fn eval_const(&self, expr: &Expr, width: IntWidth) -> Option<i64> {
+        eval_const_with(expr, width, &|name| self.constants.lookup(name).copied())
+    }
+
+    fn expect_type(&mut self, expected: Type, found: Type, span: Span) {
+        if expected != found {
+            self.errors.push(SemanticError::TypeMismatch {
+                expected,
+                found,
+                span,
+            });
+        }
+    }
+
+    /// `expect_type` in `--strict` mode; otherwise C's coercions apply.
+    fn expect_strict(&mut self, expected: Type, found: Type, span: Span) {
+        if self.strict {
+            self.expect_type(expected, found, span);
+        }
     }
 
-    fn analyze_expression(&mut self, expr: &Expr) {
-        self.analyze_term(&expr.lhs);
-        if let Some(rhs_expr) = &expr.rhs {
-            self.analyze_expression(rhs_expr);
+    fn analyze_expression(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Term(term) => self.analyze_term(term, expr.span),
+            ExprKind::Binary(binary) => self.analyze_binary_expression(binary),
+            ExprKind::Call(call) => self.analyze_call(call, expr.span),
+            ExprKind::Negate(operand) => {
+                let ty = self.analyze_expression(operand);
+                self.expect_strict(Type::Int, ty, operand.span);
+                Type::Int
+            }
+            ExprKind::Write(write) => self.analyze_write(write, expr.span),
+            ExprKind::StringLength(_) => Type::Int,
+            ExprKind::Block(block) => self.analyze_block_expression(block),
+        }
+    }
+
+    /// A block expression has the type of its value, and its own scope.
+    fn analyze_block_expression(&mut self, block: &BlockExpr) -> Type {
+        if let Some(function) = &self.calling {
+            let effect = block.statements.statements.iter().find(|statement| {
+                !matches!(
+                    statement,
+                    Statement::Let(_)
+                        | Statement::ArrayLet(_)
+                        | Statement::Const(_)
+                        | Statement::Comment(_)
+                )
+            });
+            if let Some(statement) = effect {
+                self.errors.push(SemanticError::StatementInArgument {
+                    function: function.clone(),
+                    span: statement.span(),
+                });
+            }
+        }
+        let in_loop_body = std::mem::replace(&mut self.in_loop_body, false);
+        self.enter_scope();
+        self.analyze_statement_list(&block.statements);
+        let ty = self.analyze_expression(&block.value);
+        self.exit_scope();
+        self.in_loop_body = in_loop_body;
+        ty
+    }
+
+    /// `write` evaluates to an int, the number of bytes it wrote.
+    fn analyze_write(&mut self, write: &WriteExpr, span: Span) -> Type {
+        if let Some(function) = &self.calling {
+            self.errors.push(SemanticError::WriteInArgument {
+                function: function.clone(),
+                span,
+            });
+        }
+        for argument in &write.arguments {
+            if let PrintArgument::Value(value) = argument {
+                self.analyze_expression(value);
+            }
+        }
+        Type::Int
+    }
+
+    fn analyze_call(&mut self, call: &CallExpr, span: Span) -> Type {
+        let name = &call.function.name;
+        let outer = self.calling.replace(name.clone());
+        for argument in &call.arguments {
+            let ty = self.analyze_expression(argument);
+            self.expect_type(Type::Int, ty, argument.span);
+        }
+        self.calling = outer;
+        match Intrinsic::from_name(name) {
+            Some(intrinsic) if !intrinsic.accepts(call.arguments.len()) => {
+                self.errors.push(SemanticError::WrongArgumentCount {
+                    name: name.clone(),
+                    expected: intrinsic.arity(),
+                    found: call.arguments.len(),
+                    span,
+                });
+            }
+            Some(_) => {}
+            None => self.errors.push(SemanticError::UnknownFunction {
+                name: name.clone(),
+                span: call.function.span,
+            }),
+        }
+        Type::Int
+    }
+
+    fn analyze_binary_expression(&mut self, binary: &BinaryExpr) -> Type {
+        let lhs_type = self.analyze_expression(&binary.lhs);
+        let rhs_type = self.analyze_expression(&binary.rhs);
+        if matches!(
+            binary.operator,
+            BinaryOperator::Divide | BinaryOperator::Modulo
+        ) && self.eval_const(&binary.rhs, self.int_width) == Some(0)
+        {
+            self.errors.push(SemanticError::DivisionByZero {
+                span: binary.rhs.span,
+            });
+        }
+        if matches!(
+            binary.operator,
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight
+        ) && let Some(amount) = self.eval_const(&binary.rhs, self.int_width)
+            && !(0..i64::from(self.int_width.bits())).contains(&amount)
+        {
+            self.errors.push(SemanticError::ShiftOutOfRange {
+                amount,
+                span: binary.rhs.span,
+            });
+        }
+        match binary.operator {
+            BinaryOperator::Add
+            | BinaryOperator::Subtract
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::Modulo
+            | BinaryOperator::Power => {
+                self.expect_strict(Type::Int, lhs_type, binary.lhs.span);
+                self.expect_strict(Type::Int, rhs_type, binary.rhs.span);
+                Type::Int
+            }
+            BinaryOperator::BitwiseAnd
+            | BinaryOperator::BitwiseOr
+            | BinaryOperator::BitwiseXor
+            | BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight => {
+                self.expect_type(Type::Int, lhs_type, binary.lhs.span);
+                self.expect_type(Type::Int, rhs_type, binary.rhs.span);
+                Type::Int
+            }
+            BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                self.expect_strict(lhs_type, rhs_type, binary.rhs.span);
+                Type::Bool
+            }
+            BinaryOperator::Less
+            | BinaryOperator::LessEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual => {
+                if is_unparenthesized_ordering(&binary.lhs) {
+                    self.errors.push(SemanticError::ChainedComparison {
+                        span: binary.lhs.span.to(binary.rhs.span),
+                    });
+                }
+                self.expect_strict(Type::Int, lhs_type, binary.lhs.span);
+                self.expect_strict(Type::Int, rhs_type, binary.rhs.span);
+                Type::Bool
+            }
+            BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+                self.expect_type(Type::Bool, lhs_type, binary.lhs.span);
+                self.expect_type(Type::Bool, rhs_type, binary.rhs.span);
+                Type::Bool
+            }
         }
     }
 
-    fn analyze_term(&mut self, term: &Term) {
+    fn analyze_term(&mut self, term: &Term, span: Span) -> Type {
         match term {
-            Term::Identifier(name) => {
-                if !self.scope_stack.declared(name) {
-                    self.errors
-                        .push(SemanticError::UndeclaredVariable(name.clone()));
+            Term::Identifier(identifier) => {
+                match self.scope_stack.lookup(&identifier.name).copied() {
+                    // A whole array can't be used as a value, only its elements.
+                    Some(VarInfo {
+                        ty: ty @ Type::Array(_),
+                        ..
+                    }) => {
+                        self.expect_type(Type::Int, ty, identifier.span);
+                        Type::Int
+                    }
+                    Some(info) => {
+                        self.symbols[info.symbol].read = true;
+                        if self.unassigned.contains(&info.symbol) {
+                            self.warnings.push(SemanticWarning::UseOfUninitialized {
+                                name: identifier.name.clone(),
+                                span: identifier.span,
+                            });
+                        }
+                        info.ty
+                    }
+                    None => {
+                        self.report_undeclared(identifier);
+                        // Assume int so one missing declaration doesn't cascade
+                        // into type errors.
+                        Type::Int
+                    }
                 }
             }
-            Term::Number(_) => {
-                // Numbers are always valid
+            Term::Number(value) => {
+                if !self.int_width.fits(*value) {
+                    self.errors.push(SemanticError::IntegerOutOfRange {
+                        value: *value,
+                        span,
+                    });
+                }
+                Type::Int
+            }
+            Term::Bool(_) => Type::Bool,
+            Term::Index { base, index } => {
+                if let Some(info) = self.scope_stack.lookup(&base.name) {
+                    self.symbols[info.symbol].read = true;
+                }
+                self.analyze_index(base, index);
+                Type::Int
             }
         }
     }
 }
 
+/// Whether running `statement_list` always ends the program: it reaches an
+/// `exit`, an `if` every branch of which does, or a `repeat` whose body
+/// does and never breaks out.
+fn always_exits(statement_list: &StatementList) -> bool {
+    statement_list
+        .statements
+        .iter()
+        .any(|statement| match statement {
+            Statement::Exit(_) => true,
+            Statement::If(if_stmt) => if_always_exits(if_stmt),
+            Statement::Repeat(repeat_stmt) => {
+                always_exits(&repeat_stmt.body.statements)
+                    && !breaks_out(&repeat_stmt.body.statements)
+            }
+            _ => false,
+        })
+}
+
+/// Whether `statement_list` has a `break` out of the loop it's the body of,
+/// directly or in an `if`, rather than in a loop of its own. A block used
+/// as a value can't hold a `break`, so expressions aren't searched.
+fn breaks_out(statement_list: &StatementList) -> bool {
+    statement_list
+        .statements
+        .iter()
+        .any(|statement| match statement {
+            Statement::Break(_) => true,
+            Statement::If(if_stmt) => if_breaks_out(if_stmt),
+            _ => false,
+        })
+}
+
+/// Whether `statement_list` has an `exit` anywhere in it, even in a loop,
+/// a branch that may never run or a block used as a value.
+fn may_exit(statement_list: &StatementList) -> bool {
+    statement_list
+        .statements
+        .iter()
+        .any(|statement| match statement {
+            Statement::Exit(_) => true,
+            Statement::If(if_stmt) => if_may_exit(if_stmt),
+            Statement::Loop(loop_stmt) => {
+                [
+                    loop_stmt.start.as_ref(),
+                    Some(&loop_stmt.count),
+                    loop_stmt.step.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(expr_may_exit)
+                    || may_exit(&loop_stmt.body.statements)
+            }
+            Statement::Repeat(repeat_stmt) => {
+                may_exit(&repeat_stmt.body.statements) || expr_may_exit(&repeat_stmt.condition)
+            }
+            Statement::Let(let_stmt) => let_stmt.value.as_ref().is_some_and(expr_may_exit),
+            Statement::Const(const_stmt) => expr_may_exit(&const_stmt.value),
+            Statement::ArrayLet(array_let_stmt) => match &array_let_stmt.initializer {
+                ArrayInitializer::Fill(value) => expr_may_exit(value),
+                ArrayInitializer::List(elements) => elements.iter().any(expr_may_exit),
+            },
+            Statement::Assignment(assign_stmt) => {
+                assign_stmt.index.as_ref().is_some_and(expr_may_exit)
+                    || expr_may_exit(&assign_stmt.value)
+            }
+            Statement::Print(print_stmt) => arguments_may_exit(&print_stmt.arguments),
+            Statement::Assert(assert_stmt) => expr_may_exit(&assert_stmt.condition),
+            Statement::Expression(expr_stmt) => expr_may_exit(&expr_stmt.value),
+            Statement::Read(_)
+            | Statement::Include(_)
+            | Statement::Use(_)
+            | Statement::Break(_)
+            | Statement::Comment(_) => false,
+        })
+}
+
+/// Whether `expr` has a block in it, used as a value, with an `exit`.
+fn expr_may_exit(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Block(block) => may_exit(&block.statements) || expr_may_exit(&block.value),
+        ExprKind::Binary(binary) => expr_may_exit(&binary.lhs) || expr_may_exit(&binary.rhs),
+        ExprKind::Call(call) => call.arguments.iter().any(expr_may_exit),
+        ExprKind::Negate(operand) => expr_may_exit(operand),
+        ExprKind::Write(write) => arguments_may_exit(&write.arguments),
+        ExprKind::Term(Term::Index { index, .. }) => expr_may_exit(index),
+        ExprKind::Term(_) | ExprKind::StringLength(_) => false,
+    }
+}
+
+fn arguments_may_exit(arguments: &[PrintArgument]) -> bool {
+    arguments.iter().any(|argument| match argument {
+        PrintArgument::Value(value) => expr_may_exit(value),
+        PrintArgument::Text(_) => false,
+    })
+}
+
+fn if_may_exit(if_stmt: &IfStatement) -> bool {
+    expr_may_exit(&if_stmt.condition)
+        || may_exit(&if_stmt.then_block.statements)
+        || match if_stmt.else_branch.as_deref() {
+            None => false,
+            Some(ElseBranch::Block(block)) => may_exit(&block.statements),
+            Some(ElseBranch::If(next)) => if_may_exit(next),
+        }
+}
+
+fn if_breaks_out(if_stmt: &IfStatement) -> bool {
+    breaks_out(&if_stmt.then_block.statements)
+        || match if_stmt.else_branch.as_deref() {
+            None => false,
+            Some(ElseBranch::Block(block)) => breaks_out(&block.statements),
+            Some(ElseBranch::If(next)) => if_breaks_out(next),
+        }
+}
+
+fn if_always_exits(if_stmt: &IfStatement) -> bool {
+    always_exits(&if_stmt.then_block.statements)
+        && match if_stmt.else_branch.as_deref() {
+            None => false,
+            Some(ElseBranch::Block(block)) => always_exits(&block.statements),
+            Some(ElseBranch::If(next)) => if_always_exits(next),
+        }
+}
+
+/// Whether `expr` is a `<`, `<=`, `>` or `>=` comparison written without
+/// parentheses around it, making it the start of a chain like `a < b < c`.
+fn is_unparenthesized_ordering(expr: &Expr) -> bool {
+    let ExprKind::Binary(binary) = &expr.kind else {
+        return false;
+    };
+    let is_ordering = matches!(
+        binary.operator,
+        BinaryOperator::Less
+            | BinaryOperator::LessEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual
+    );
+    // A parenthesized expression's span includes the `(`, so it starts
+    // before its left operand does.
+    is_ordering && expr.span.start == binary.lhs.span.start
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,7 +1328,19 @@ mod tests {
         let errors = result.unwrap_err();
         assert_eq!(errors.len(), 1);
         match &errors[0] {
-            SemanticError::UndeclaredVariable(name) => assert_eq!(name, "x"),
+            SemanticError::UndeclaredVariable { name, span } => {
+                assert_eq!(name, "x");
+                assert_eq!(
+                    *span,
+                    Span {
+                        start: 6,
+                        end: 7,
+                        line: 1,
+                        column: 7
+                    }
+                );
+            }
+            other => panic!("unexpected error {:?}", other),
         }
     }
 
@@ -181,7 +1358,19 @@ mod tests {
         let errors = result.unwrap_err();
         assert_eq!(errors.len(), 1);
         match &errors[0] {
-            SemanticError::UndeclaredVariable(name) => assert_eq!(name, "x"),
+            SemanticError::UndeclaredVariable { name, span } => {
+                assert_eq!(name, "x");
+                assert_eq!(
+                    *span,
+                    Span {
+                        start: 0,
+                        end: 1,
+                        line: 1,
+                        column: 1
+                    }
+                );
+            }
+            other => panic!("unexpected error {:?}", other),
         }
     }
 
@@ -199,7 +1388,1006 @@ mod tests {
         let errors = result.unwrap_err();
         assert_eq!(errors.len(), 1);
         match &errors[0] {
-            SemanticError::UndeclaredVariable(name) => assert_eq!(name, "x"),
+            SemanticError::UndeclaredVariable { name, span } => {
+                assert_eq!(name, "x");
+                assert_eq!(
+                    *span,
+                    Span {
+                        start: 30,
+                        end: 31,
+                        line: 1,
+                        column: 31
+                    }
+                );
+            }
+            other => panic!("unexpected error {:?}", other),
         }
     }
+
+    fn analyze_source(input: &str) -> Result<(), Vec<SemanticError>> {
+        let lexer = Lexer::new(input.to_string());
+        let tokens: Vec<_> = lexer.collect();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse();
+        SemanticAnalyzer::analyze(&ast)
+    }
+
+    #[test]
+    fn test_named_loop_counter_scope() {
+        assert!(analyze_source("loop i in 3 { print i; };").is_ok());
+
+        let errors = analyze_source("loop i in 3 { print i; }; print i;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            SemanticError::UndeclaredVariable { name, span } => {
+                assert_eq!(name, "i");
+                assert_eq!(
+                    *span,
+                    Span {
+                        start: 32,
+                        end: 33,
+                        line: 1,
+                        column: 33
+                    }
+                );
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        // The error points at the use after the loop, whatever form the
+        // loop takes.
+        for (input, line, column) in [
+            ("loop i in 3 { }; print i;", 1, 24),
+            ("loop i from 1 to 3 step 2 { }\nprint i;", 2, 7),
+            (
+                "let n = 2;\nloop i in n {\n  print i;\n}\nprint n + i;",
+                5,
+                11,
+            ),
+        ] {
+            let errors = analyze_source(input).unwrap_err();
+            match &errors[..] {
+                [SemanticError::UndeclaredVariable { name, span }] => {
+                    assert_eq!(name, "i");
+                    assert_eq!((span.line, span.column), (line, column), "{}", input);
+                    assert_eq!(&input[span.start..span.end], "i");
+                }
+                other => panic!("unexpected errors {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let errors = analyze_source("let x = 10 / 0;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], SemanticError::DivisionByZero { .. }));
+    }
+
+    #[test]
+    fn test_modulo_by_folded_zero() {
+        let errors = analyze_source("let x = 10 % (1 - 1);").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], SemanticError::DivisionByZero { .. }));
+    }
+
+    #[test]
+    fn test_division_by_const_zero() {
+        let errors = analyze_source("const Z = 0; print 10 / Z;").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::DivisionByZero { span }] if span.column == 25
+        ));
+    }
+
+    #[test]
+    fn test_shift_out_of_range() {
+        let errors =
+            analyze_source("const W = 32; print 1 << W, 1 >> 0 - 1, 1 << 31;").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [
+                error @ SemanticError::ShiftOutOfRange { amount: 32, span },
+                SemanticError::ShiftOutOfRange { amount: -1, .. },
+            ] if span.column == 26 && error.code() == "E0018"
+        ));
+        // A shift amount that isn't constant is masked to the width instead.
+        assert!(analyze_source("let n = 32; print 1 << n;").is_ok());
+
+        let ast = Parser::new(Lexer::new("print 1 << 32;".to_string()).collect()).parse();
+        assert!(
+            SemanticAnalyzer::check(&ast, IntWidth::Bits64)
+                .errors
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_division_by_variable() {
+        assert!(analyze_source("let x = 0; let y = 10 / x;").is_ok());
+    }
+
+    #[test]
+    fn test_assert_condition() {
+        assert!(analyze_source("let x = 5; assert x == 5;").is_ok());
+        assert!(analyze_source("let b = 1 < 2; assert b;").is_ok());
+    }
+
+    #[test]
+    fn test_assert_requires_bool() {
+        let errors = analyze_source("let x = 5; assert x + 1;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            SemanticError::TypeMismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_bitwise_requires_int() {
+        assert!(analyze_source("let x = 6; let y = x & 3 | x << 1;").is_ok());
+
+        let errors = analyze_source("let x = (1 < 2) | 4;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            SemanticError::TypeMismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_errors_sorted_by_position() {
+        let input = "print a; let x = (1 < 2) | b; c = 10 / 0;";
+
+        let first = analyze_source(input).unwrap_err();
+        let second = analyze_source(input).unwrap_err();
+
+        let starts: Vec<usize> = first.iter().map(|error| error.span().start).collect();
+        assert_eq!(
+            starts,
+            second
+                .iter()
+                .map(|error| error.span().start)
+                .collect::<Vec<_>>()
+        );
+        assert!(starts.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        assert_eq!(first.len(), 5);
+        assert!(matches!(first[0], SemanticError::UndeclaredVariable { .. }));
+        assert!(matches!(first[1], SemanticError::TypeMismatch { .. }));
+        assert!(matches!(first[2], SemanticError::UndeclaredVariable { .. }));
+        assert!(matches!(first[3], SemanticError::UndeclaredVariable { .. }));
+        assert!(matches!(first[4], SemanticError::DivisionByZero { .. }));
+    }
+
+    #[test]
+    fn test_integer_width() {
+        let input = "let x = 3000000000; print x;";
+        let lexer = Lexer::new(input.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+
+        assert!(SemanticAnalyzer::analyze_with_width(&ast, IntWidth::Bits64).is_ok());
+
+        let errors = SemanticAnalyzer::analyze(&ast).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            SemanticError::IntegerOutOfRange {
+                value: 3000000000,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_negative_literal_range() {
+        let check = |input: &str| {
+            let ast = Parser::new(Lexer::new(input.to_string()).collect()).parse();
+            SemanticAnalyzer::analyze(&ast)
+        };
+
+        assert!(check("let x = -2147483648; print x;").is_ok());
+        let errors = check("let x = 2147483648; print x;").unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [SemanticError::IntegerOutOfRange {
+                value: 2147483648,
+                ..
+            }]
+        ));
+        assert!(check("let x = -2147483649; print x;").is_err());
+    }
+
+    #[test]
+    fn test_unreachable_after_exit() {
+        let check = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            SemanticAnalyzer::check(&ast, IntWidth::default())
+        };
+
+        let analysis = check("print 1; exit 2; print 3; print 4;");
+        assert!(analysis.errors.is_empty());
+        assert_eq!(analysis.warnings.len(), 1);
+        assert!(matches!(
+            analysis.warnings[0],
+            SemanticWarning::UnreachableCode { .. }
+        ));
+        let span = analysis.warnings[0].span();
+        assert_eq!((span.line, span.column), (1, 18));
+
+        assert!(check("print 1; exit 2;").warnings.is_empty());
+
+        // The warning is per block: code after the loop is still reachable.
+        let analysis = check("loop 3 { exit 1; print 2; }; print 3;");
+        assert_eq!(analysis.warnings.len(), 1);
+
+        let analysis = check("exit 1 == 1;");
+        assert!(matches!(
+            analysis.errors[0],
+            SemanticError::TypeMismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_symbols_record_scope_depth() {
+        let input = "let x = 1;\nloop i in 2 {\n  loop 3 {\n    let y = x < i;\n  };\n};";
+        let lexer = Lexer::new(input.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+
+        let symbols: Vec<_> = SemanticAnalyzer::check(&ast, IntWidth::default())
+            .symbols
+            .into_iter()
+            .map(|symbol| {
+                (
+                    symbol.name,
+                    symbol.ty,
+                    symbol.depth,
+                    symbol.span.line,
+                    symbol.span.column,
+                )
+            })
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("x".to_string(), Type::Int, 0, 1, 5),
+                ("i".to_string(), Type::Int, 1, 2, 6),
+                ("y".to_string(), Type::Bool, 2, 4, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arrays() {
+        let check = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            SemanticAnalyzer::check(&ast, IntWidth::default())
+        };
+
+        let analysis = check("let mut a[3] = 0; let b = [1, 2]; a[b[1]] = a[0] + 1; print a[2];");
+        assert!(analysis.errors.is_empty());
+        assert!(analysis.warnings.is_empty());
+        assert_eq!(analysis.symbols[0].ty, Type::Array(3));
+
+        let analysis = check("let mut a = [1, 2]; print a[2]; a[0 - 1] = 1;");
+        assert!(analysis.errors.is_empty());
+        assert!(matches!(
+            analysis.warnings.as_slice(),
+            [
+                SemanticWarning::IndexOutOfBounds {
+                    index: 2,
+                    length: 2,
+                    ..
+                },
+                SemanticWarning::IndexOutOfBounds {
+                    index: -1,
+                    length: 2,
+                    ..
+                },
+            ]
+        ));
+
+        let analysis = check("const I = 5; let a[3] = 0; print a[I];");
+        assert!(matches!(
+            analysis.warnings.as_slice(),
+            [SemanticWarning::IndexOutOfBounds {
+                index: 5,
+                length: 3,
+                ..
+            }]
+        ));
+
+        let analysis = check("let x = 1; let mut a[2] = 0; print x[0]; print a + 1; a[1 < 2] = 0;");
+        assert!(matches!(
+            analysis.errors.as_slice(),
+            [
+                SemanticError::NotAnArray { .. },
+                SemanticError::TypeMismatch {
+                    expected: Type::Int,
+                    found: Type::Array(2),
+                    ..
+                },
+                SemanticError::TypeMismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    ..
+                },
+            ]
+        ));
+
+        // Only `print` takes a whole array.
+        let analysis = check("let a = [1, 2]; print \"a = \", a; let n = write(a);");
+        assert!(matches!(
+            analysis.errors.as_slice(),
+            [SemanticError::TypeMismatch {
+                expected: Type::Int,
+                found: Type::Array(2),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_loop_step_must_be_positive() {
+        let analyze = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            SemanticAnalyzer::analyze(&Parser::new(lexer.collect()).parse())
+        };
+
+        assert!(analyze("let s = 0; loop 10 step s { };").is_ok());
+        assert!(analyze("loop 10 step 3 { };").is_ok());
+        for step in ["0", "1 - 2"] {
+            let errors = analyze(&format!("loop 10 step {} {{ }};", step)).unwrap_err();
+            assert!(matches!(
+                errors.as_slice(),
+                [SemanticError::NonPositiveStep { .. }]
+            ));
+            let errors = analyze(&format!("const S = {}; loop 3 step S {{ }};", step)).unwrap_err();
+            assert!(matches!(
+                errors.as_slice(),
+                [SemanticError::NonPositiveStep { .. }]
+            ));
+        }
+    }
+
+    #[test]
+    fn test_loop_bound_overflow() {
+        let check = |input: &str, int_width: IntWidth| {
+            let ast = Parser::new(Lexer::new(input.to_string()).collect()).parse();
+            SemanticAnalyzer::check(&ast, int_width).errors
+        };
+        let overflows = |input: &str, int_width: IntWidth| {
+            matches!(
+                check(input, int_width)[..],
+                [SemanticError::LoopBoundOverflow { .. }]
+            )
+        };
+
+        // The counter stops at the count, so the largest int is fine.
+        assert!(check("loop 2147483647 { }", IntWidth::Bits32).is_empty());
+        assert!(overflows("loop 2147483647 + 1 { }", IntWidth::Bits32));
+        assert!(check("loop 2147483647 + 1 { }", IntWidth::Bits64).is_empty());
+
+        // A range loop stops on its last value, so only the bounds must fit.
+        assert!(check("loop i from 0 to 2147483647 { }", IntWidth::Bits32).is_empty());
+        assert!(
+            check(
+                "loop i from 2147483640 to 2147483647 step 4 { }",
+                IntWidth::Bits32
+            )
+            .is_empty()
+        );
+        assert!(overflows(
+            "loop i from 0 to 2147483647 + 1 { }",
+            IntWidth::Bits32
+        ));
+        assert!(check("loop i in 2147483646 step 2 { }", IntWidth::Bits32).is_empty());
+        assert!(overflows(
+            "loop i in 2147483647 step 2 { }",
+            IntWidth::Bits32
+        ));
+        assert!(overflows(
+            "loop i from 0 - 2147483647 - 2 to 0 { }",
+            IntWidth::Bits32
+        ));
+
+        // Bounds that aren't constant can't be checked.
+        assert!(
+            check(
+                "let n = 2147483647; loop i from 0 to n { }",
+                IntWidth::Bits32
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_intrinsic_calls() {
+        let analyze = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            SemanticAnalyzer::analyze(&Parser::new(lexer.collect()).parse())
+        };
+
+        assert!(analyze("print min(1, 2) + max(3, 4) + abs(0 - 5);").is_ok());
+        assert!(analyze("print min_of(1) + max_of(1, 2, 3, 4);").is_ok());
+
+        let errors = analyze("print abs(1, 2);").unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [SemanticError::WrongArgumentCount {
+                expected: 1,
+                found: 2,
+                ..
+            }]
+        ));
+
+        let errors = analyze("print max_of();").unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [SemanticError::WrongArgumentCount {
+                expected: 1,
+                found: 0,
+                ..
+            }]
+        ));
+
+        let errors = analyze("print sqrt(4); print min(1 < 2, 3);").unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                SemanticError::UnknownFunction { .. },
+                SemanticError::TypeMismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    ..
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_let_type_annotation() {
+        assert!(analyze_source("let x: int = 5; let b: bool = x < 3; assert b;").is_ok());
+
+        let errors = analyze_source("let x: bool = 5;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            SemanticError::TypeMismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                ..
+            }
+        ));
+        assert_eq!(errors[0].span().column, 15);
+
+        // The annotation, not the initializer, decides the variable's type.
+        let errors = analyze_source("let x: int = 1 < 2; print x + 1;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+
+        assert!(
+            analyze_source("let b: bool = true; let mut done = false; done = b; assert done;")
+                .is_ok()
+        );
+        let errors = analyze_source("let x: int = true;").unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [SemanticError::TypeMismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_self_assignment_warning() {
+        let warnings = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            let analysis = SemanticAnalyzer::check(&ast, IntWidth::default());
+            assert!(analysis.errors.is_empty());
+            analysis.warnings
+        };
+
+        let found = warnings("let mut x = 1; x = x;");
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            &found[0],
+            SemanticWarning::SelfAssignment { name, .. } if name == "x"
+        ));
+        assert_eq!(found[0].span().column, 16);
+
+        assert_eq!(warnings("let mut x = 1; x = x + 0;").len(), 1);
+        assert_eq!(warnings("let mut x = 1; x = 1 * (x - 0);").len(), 1);
+        assert_eq!(warnings("let mut a = [1, 2]; a[1] = a[2 - 1];").len(), 1);
+
+        assert!(warnings("let mut x = 1; let y = 2; x = y;").is_empty());
+        assert!(warnings("let mut x = 1; x = x + 1;").is_empty());
+        assert!(warnings("let mut x = 1; x = x * 0;").is_empty());
+        assert!(warnings("let mut a = [1, 2]; let i = 0; a[i] = a[1];").is_empty());
+    }
+
+    #[test]
+    fn test_write_arguments() {
+        let check = |input: &str| {
+            let ast = Parser::new(Lexer::new(input.to_string()).collect()).parse();
+            SemanticAnalyzer::check(&ast, IntWidth::default()).errors
+        };
+
+        assert!(check("let a = 1; let n = write(\"a=\", a, a == 1) + 1;").is_empty());
+        assert!(matches!(
+            &check("let a = [1, 2]; let n = write(a);")[..],
+            [SemanticError::TypeMismatch { .. }]
+        ));
+        assert!(matches!(
+            &check("let n = abs(1 + write(\"x\"));")[..],
+            [SemanticError::WriteInArgument { function, .. }] if function == "abs"
+        ));
+    }
+
+    #[test]
+    fn test_block_expression() {
+        let check = |input: &str| {
+            let ast = Parser::new(Lexer::new(input.to_string()).collect()).parse();
+            SemanticAnalyzer::check(&ast, IntWidth::default()).errors
+        };
+
+        assert!(check("let x = { let t = 1; t + 2 }; print x;").is_empty());
+        // The block's declarations don't leak out of it.
+        assert!(matches!(
+            &check("let x = { let t = 1; t }; print t;")[..],
+            [SemanticError::UndeclaredVariable { name, .. }] if name == "t"
+        ));
+        assert!(matches!(
+            &check("let b: int = { 1 < 2 };")[..],
+            [SemanticError::TypeMismatch { .. }]
+        ));
+        assert!(check("let m = min({ let t = 1; t }, 2);").is_empty());
+        assert!(matches!(
+            &check("let m = min({ print 1; 1 }, 2);")[..],
+            [SemanticError::StatementInArgument { function, .. }] if function == "min"
+        ));
+    }
+
+    #[test]
+    fn test_definite_assignment() {
+        let warnings = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            SemanticAnalyzer::check(&ast, IntWidth::default()).warnings
+        };
+
+        let found = warnings("let c = 1 < 2; let x; if c { x = 1; } print x;");
+        assert!(matches!(
+            &found[..],
+            [SemanticWarning::UseOfUninitialized { name, .. }] if name == "x"
+        ));
+        assert!(
+            warnings("let c = 1 < 2; let x; if c { x = 1; } else { x = 2; } print x;").is_empty()
+        );
+        assert_eq!(
+            warnings("let c = 1 < 2; let x; if c { x = 1; } else if c { x = 2; } print x;").len(),
+            1
+        );
+        assert!(
+            warnings("let c = 1 < 2; let x; if c { x = 1; } else if c { x = 2; } else { x = 3; } print x;")
+                .is_empty()
+        );
+        // A branch that ends the program doesn't reach the `print`.
+        assert!(
+            warnings("let c = 1 < 2; let x; if c { x = 1; } else { exit 1; } print x;").is_empty()
+        );
+
+        // The body of a loop may never run.
+        assert_eq!(
+            warnings("let n = 0; let x; loop n { x = 1; } print x;").len(),
+            1
+        );
+        assert!(warnings("let x; loop 2 { x = 1; print x; }").is_empty());
+        // A `repeat` body always runs.
+        assert!(warnings("let x; repeat { x = 1; } until x > 0; print x;").is_empty());
+    }
+
+    #[test]
+    fn test_use_of_uninitialized_warning() {
+        let warnings = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            let analysis = SemanticAnalyzer::check(&ast, IntWidth::default());
+            assert!(analysis.errors.is_empty());
+            analysis.warnings
+        };
+
+        let found = warnings("let x; print x;");
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            &found[0],
+            SemanticWarning::UseOfUninitialized { name, .. } if name == "x"
+        ));
+        assert_eq!(found[0].span().column, 14);
+
+        assert!(warnings("let mut x; x = 1; print x;").is_empty());
+        assert_eq!(warnings("let mut x; x = x + 1;").len(), 1);
+        assert!(warnings("let mut done: bool; done = 1 < 2; assert done;").is_empty());
+    }
+
+    #[test]
+    fn test_read_assigns_an_int() {
+        let check = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            SemanticAnalyzer::check(&ast, IntWidth::default())
+        };
+
+        // A `read` counts as an assignment, and its int can be used at once.
+        for source in [
+            "let mut x; read x; if x > 0 { print x; };",
+            "let mut x; read x; let y = x * 2 + abs(x); assert y >= x;",
+            "let mut x; let c = 1 < 2; if c { read x; } else { x = 0; } print x;",
+        ] {
+            let analysis = check(source);
+            assert!(analysis.errors.is_empty(), "{}", source);
+            assert!(analysis.warnings.is_empty(), "{}", source);
+        }
+        // Unless it might not run.
+        assert!(matches!(
+            &check("let n = 0; let mut x; loop n { read x; } print x;").warnings[..],
+            [SemanticWarning::UseOfUninitialized { name, .. }] if name == "x"
+        ));
+
+        assert!(matches!(
+            &check("read x; print x;").errors[..],
+            [
+                SemanticError::UndeclaredVariable { name, .. },
+                SemanticError::UndeclaredVariable { .. },
+            ] if name == "x"
+        ));
+        assert!(matches!(
+            &check("let mut done = 1 < 2; read done;").errors[..],
+            [SemanticError::TypeMismatch {
+                expected: Type::Int,
+                found: Type::Bool,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_empty_range_warning() {
+        let warnings = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            let analysis = SemanticAnalyzer::check(&ast, IntWidth::default());
+            assert!(analysis.errors.is_empty());
+            analysis.warnings
+        };
+
+        let found = warnings("loop i from 5 to 1 { print i; }");
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            found[0],
+            SemanticWarning::EmptyRange { from: 5, to: 1, .. }
+        ));
+        assert_eq!(found[0].span().column, 13);
+
+        assert!(warnings("loop i from 1 to 1 { print i; }").is_empty());
+        // Bounds that aren't constant can't be checked.
+        assert!(warnings("let n = 5; loop i from n to 1 { print i; }").is_empty());
+        let found = warnings("const N = 5; loop i from N to 1 { print i; }");
+        assert!(matches!(
+            found[..],
+            [SemanticWarning::EmptyRange { from: 5, to: 1, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_strict_rejects_coercions() {
+        let errors = |input: &str, strict: bool| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            let options = CompilerOptions {
+                strict,
+                ..Default::default()
+            };
+            SemanticAnalyzer::check_with(&ast, &options).errors
+        };
+
+        for source in [
+            "let t = 1 < 2; print t + 1;",
+            "let t = 1 < 2; loop t { }",
+            "let t = 1 < 2; assert t == 1;",
+            "let t = 1 < 2; assert t > 0;",
+            "let mut x = 0; x = 1 < 2;",
+        ] {
+            assert!(errors(source, false).is_empty(), "{}", source);
+            let strict = errors(source, true);
+            assert_eq!(strict.len(), 1, "{}", source);
+            assert!(matches!(
+                strict[0],
+                SemanticError::TypeMismatch {
+                    expected: Type::Int,
+                    found: Type::Bool,
+                    ..
+                } | SemanticError::TypeMismatch {
+                    expected: Type::Bool,
+                    found: Type::Int,
+                    ..
+                }
+            ));
+        }
+
+        assert!(
+            errors(
+                "let mut x = 1; let t = x < 2; x = x + 1; assert t == (x > 0);",
+                true
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_if_statement() {
+        assert!(
+            analyze_source(
+                "let x = 5; if x < 0 { let y = 1; print y; } else if x < 10 { let y = 2; print y; } else { print x; }"
+            )
+            .is_ok()
+        );
+
+        // Each arm is its own scope.
+        let errors =
+            analyze_source("let x = 5; if x < 0 { let y = 1; } else { print y; }").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::UndeclaredVariable { name, .. }] if name == "y"
+        ));
+
+        let errors = analyze_source("let x = 5; if x { } else if x == 1 { }").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::TypeMismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_max_loop_depth() {
+        let depth = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            SemanticAnalyzer::check(&ast, IntWidth::default()).max_loop_depth
+        };
+
+        assert_eq!(depth("print 1;"), 0);
+        assert_eq!(depth("loop 2 { loop 3 { print 1; } }"), 2);
+        // Sibling loops don't add up, and ifs don't count.
+        assert_eq!(
+            depth("loop 2 { loop 3 { } } loop 1 { if 1 < 2 { loop 4 { } } }"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_const() {
+        assert!(analyze_source("const N = 3; loop i in N { print i * N; }").is_ok());
+
+        // A constant declared in a block is gone after it, so its name is
+        // free again.
+        assert!(analyze_source("loop 2 { const K = 2; print K; } let K = 1;").is_ok());
+        let errors = analyze_source("loop 2 { const K = 2; } print K;").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::UndeclaredVariable { name, .. }] if name == "K"
+        ));
+
+        let errors = analyze_source("const N = 3; N = 4;").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::AssignToConst { name, .. }] if name == "N"
+        ));
+
+        // Redeclaring either way round is an error too.
+        for source in [
+            "const N = 3; let N = 4;",
+            "const N = 3; loop N in 2 { }",
+            "let N = 3; const N = 4;",
+            "const N = 3; const N = 4;",
+        ] {
+            let errors = analyze_source(source).unwrap_err();
+            assert!(
+                matches!(&errors[..], [SemanticError::AssignToConst { .. }]),
+                "{}: {:?}",
+                source,
+                errors
+            );
+        }
+
+        let errors = analyze_source("let x = 1; const N = x + 1;").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::NotConstant { name, .. }] if name == "N"
+        ));
+    }
+
+    #[test]
+    fn test_assign_to_immutable() {
+        assert!(analyze_source("let mut x = 1; x = x + 1; read x; print x;").is_ok());
+        assert!(analyze_source("let mut a[2] = 0; a[1] = 5; print a[1];").is_ok());
+
+        let errors = analyze_source("let x = 1;\nx = 2;").unwrap_err();
+        match &errors[..] {
+            [error @ SemanticError::AssignToImmutable { name, span }] => {
+                assert_eq!(name, "x");
+                assert_eq!((span.line, span.column), (2, 1));
+                assert_eq!(error.code(), "E0016");
+            }
+            other => panic!("unexpected errors {:?}", other),
+        }
+
+        // Array elements, `read` and loop counters need `mut` too.
+        for source in [
+            "let a = [1, 2]; a[0] = 3;",
+            "let x; read x;",
+            "loop i in 3 { i = i + 1; }",
+            "let mut x = 1; loop 2 { let x = 0; x = 5; }",
+        ] {
+            let errors = analyze_source(source).unwrap_err();
+            assert!(
+                matches!(&errors[..], [SemanticError::AssignToImmutable { .. }]),
+                "{}: {:?}",
+                source,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        assert!(
+            analyze_source("loop 3 { break if 1 < 2; } repeat { break; } until 1 > 0;").is_ok()
+        );
+
+        for source in [
+            "break;",
+            "let c = 1 < 2; break if c;",
+            "loop 3 { let x = { break; 1 }; }",
+        ] {
+            let errors = analyze_source(source).unwrap_err();
+            assert!(
+                matches!(&errors[..], [error @ SemanticError::BreakOutsideLoop { .. }] if error.code() == "E0017"),
+                "{}: {:?}",
+                source,
+                errors
+            );
+        }
+
+        // A `break` may skip the rest of a `repeat` body.
+        let source = "let mut x; repeat { break; x = 1; } until x > 0; print x;";
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        let analysis = SemanticAnalyzer::check(&ast, IntWidth::default());
+        assert!(matches!(
+            analysis.warnings.as_slice(),
+            [
+                SemanticWarning::UnreachableCode { after: "break", .. },
+                SemanticWarning::UseOfUninitialized { .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_possible_infinite_loop_warning() {
+        let warnings = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            let analysis = SemanticAnalyzer::check(&ast, IntWidth::default());
+            assert!(analysis.errors.is_empty());
+            analysis.warnings
+        };
+
+        for source in [
+            "let x = 1; repeat { print x; } until false;",
+            "const DONE = 1 > 2; repeat { } until DONE;",
+            // The `break` only leaves the inner loop.
+            "repeat { loop 2 { break; } } until false;",
+        ] {
+            let found = warnings(source);
+            assert!(
+                matches!(found[..], [SemanticWarning::PossibleInfiniteLoop { .. }]),
+                "{}: {:?}",
+                source,
+                found
+            );
+        }
+        assert_eq!(warnings("repeat { } until false;")[0].span().column, 18);
+
+        for source in [
+            "repeat { break; } until false;",
+            "let x = 1; repeat { break if x > 0; } until false;",
+            "repeat { loop 2 { exit 0; } } until false;",
+            // An `exit` in a block used as a value still ends the program.
+            "let x = 1; repeat { let y = { if x > 0 { exit 0; } x }; print y; } until false;",
+            "repeat { print 1 + { exit 0; 1 }; } until false;",
+            "let x = 1; repeat { print x; } until x > 0;",
+            "repeat { } until true;",
+        ] {
+            assert!(warnings(source).is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_char_out_of_range_warning() {
+        let warnings = |input: &str| {
+            let lexer = Lexer::new(input.to_string());
+            let ast = Parser::new(lexer.collect()).parse();
+            let analysis = SemanticAnalyzer::check(&ast, IntWidth::default());
+            assert!(analysis.errors.is_empty());
+            analysis.warnings
+        };
+
+        let found = warnings("printc 65; printc 300;");
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            found[0],
+            SemanticWarning::CharOutOfRange { value: 300, .. }
+        ));
+        assert_eq!(found[0].span().column, 19);
+
+        assert!(matches!(
+            warnings("printc 2 * 100 - 201;")[..],
+            [SemanticWarning::CharOutOfRange { value: -1, .. }]
+        ));
+        assert!(matches!(
+            warnings("const C = 300; printc C;")[..],
+            [SemanticWarning::CharOutOfRange { value: 300, .. }]
+        ));
+        // Only `printc` prints a character, and a variable isn't checked.
+        assert!(warnings("print 300; let c = 300; printc c;").is_empty());
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        let errors = analyze_source("assert 1 < 2 < 3;").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::ChainedComparison { span }] if span.column == 8 && span.end == 16
+        ));
+        assert!(analyze_source("let x = 5; assert x >= 1 <= 9;").is_err());
+
+        assert!(analyze_source("assert 1 < 2 && 2 < 3;").is_ok());
+        assert!(analyze_source("assert 1 < 2 || 2 < 3 && 3 < 4;").is_ok());
+        // Explicit parentheses say the grouping is intended.
+        assert!(analyze_source("assert (1 < 2) < 3;").is_ok());
+        assert!(analyze_source("assert (1 < 2) == (3 < 4);").is_ok());
+    }
+
+    #[test]
+    fn test_logical_operators_require_bool() {
+        let errors = analyze_source("assert 1 && 2 < 3;").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [SemanticError::TypeMismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                ..
+            }]
+        ));
+    }
 }
@@ -0,0 +1,115 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::ast::{AbstractSyntaxTree, ElseBranch, IfStatement, Statement, StatementList};
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser};
+
+/// How long each compiler stage took, for `--verbose`.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub lexing: Duration,
+    pub parsing: Duration,
+    pub analysis: Duration,
+    pub codegen: Duration,
+    pub tokens: usize,
+    pub statements: usize,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "lexing:   {:?} ({} tokens)", self.lexing, self.tokens)?;
+        writeln!(
+            f,
+            "parsing:  {:?} ({} statements)",
+            self.parsing, self.statements
+        )?;
+        writeln!(f, "analysis: {:?}", self.analysis)?;
+        write!(f, "codegen:  {:?}", self.codegen)
+    }
+}
+
+/// Runs `stage`, adding the time it took to `total`.
+pub fn time<T>(total: &mut Duration, stage: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = stage();
+    *total += start.elapsed();
+    result
+}
+
+/// Parses the tokens `lexer` produces as the parser reads them, adding the
+/// time spent lexing and the number of tokens to `timings`, and the rest of
+/// the time to `timings.parsing`.
+pub fn lex_and_parse_timed(
+    mut lexer: Lexer,
+    timings: &mut Timings,
+) -> Result<AbstractSyntaxTree, ParseError> {
+    let mut lexing = Duration::ZERO;
+    let mut tokens = 0;
+    let tokens_read = std::iter::from_fn(|| {
+        let token = time(&mut lexing, || lexer.next());
+        tokens += usize::from(token.is_some());
+        token
+    });
+    let result = time(&mut timings.parsing, || {
+        Parser::from_tokens(tokens_read).try_parse()
+    });
+    // The lexing happened in between parsing.
+    timings.parsing -= lexing;
+    timings.lexing += lexing;
+    timings.tokens += tokens;
+    result
+}
+
+/// Number of statements in `statement_list`, including those nested in loops.
+pub fn count_statements(statement_list: &StatementList) -> usize {
+    statement_list
+        .statements
+        .iter()
+        .map(|statement| match statement {
+            Statement::Loop(loop_stmt) => 1 + count_statements(&loop_stmt.body.statements),
+            Statement::Repeat(repeat_stmt) => 1 + count_statements(&repeat_stmt.body.statements),
+            Statement::If(if_stmt) => 1 + count_if_branches(if_stmt),
+            _ => 1,
+        })
+        .sum()
+}
+
+fn count_if_branches(if_stmt: &IfStatement) -> usize {
+    let else_count = match if_stmt.else_branch.as_deref() {
+        None => 0,
+        Some(ElseBranch::Block(block)) => count_statements(&block.statements),
+        Some(ElseBranch::If(next)) => count_if_branches(next),
+    };
+    count_statements(&if_stmt.then_block.statements) + else_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompilerOptions, compile_timed};
+
+    #[test]
+    fn test_compile_timed_reports_every_stage() {
+        let (result, timings) = compile_timed(
+            "let x = 1; loop 2 { print x; };",
+            &CompilerOptions::default(),
+        );
+        assert!(result.is_ok());
+        assert_eq!(timings.tokens, 13);
+        assert_eq!(timings.statements, 3);
+
+        let report = timings.to_string();
+        for stage in ["lexing:", "parsing:", "analysis:", "codegen:"] {
+            assert!(report.contains(stage), "missing {} in {}", stage, report);
+        }
+        assert!(report.contains("(13 tokens)"));
+    }
+
+    #[test]
+    fn test_time_accumulates() {
+        let mut total = Duration::from_secs(1);
+        assert_eq!(time(&mut total, || 42), 42);
+        assert!(total >= Duration::from_secs(1));
+    }
+}
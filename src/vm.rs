@@ -0,0 +1,621 @@
+//! Runs the stack machine assembly `--emit asm` writes; `asm_generator`
+//! documents the instructions. The program is parsed once into a list of
+//! instructions with jumps resolved to indices, then executed with a value
+//! stack, a store of named slots and a program counter. Arithmetic wraps at
+//! the width given by `.bits`, and errors report the assembly line.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::ast::{BinaryOperator, PrintFormat};
+use crate::const_eval::IntWidth;
+use crate::interpreter::Input;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError {
+    /// The assembly can't be parsed.
+    Syntax {
+        message: String,
+        line: usize,
+    },
+    StackUnderflow {
+        line: usize,
+    },
+    /// A slot was read before anything was stored in it.
+    Unset {
+        name: String,
+        line: usize,
+    },
+    /// A slot holding a value was used as an array, or the other way round.
+    WrongSlotKind {
+        name: String,
+        line: usize,
+    },
+    DivisionByZero {
+        line: usize,
+    },
+    IndexOutOfBounds {
+        index: i64,
+        length: usize,
+        line: usize,
+    },
+    InvalidInput {
+        line: usize,
+    },
+    AssertionFailed {
+        message: Option<String>,
+        line: usize,
+    },
+}
+
+impl VmError {
+    /// The line of the assembly the error is on, counting from 1.
+    pub fn line(&self) -> usize {
+        match self {
+            VmError::Syntax { line, .. }
+            | VmError::StackUnderflow { line }
+            | VmError::Unset { line, .. }
+            | VmError::WrongSlotKind { line, .. }
+            | VmError::DivisionByZero { line }
+            | VmError::IndexOutOfBounds { line, .. }
+            | VmError::InvalidInput { line }
+            | VmError::AssertionFailed { line, .. } => *line,
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::Syntax { message, .. } => write!(f, "{}", message),
+            VmError::StackUnderflow { .. } => write!(f, "the stack is empty"),
+            VmError::Unset { name, .. } => write!(f, "'{}' has no value", name),
+            VmError::WrongSlotKind { name, .. } => {
+                write!(f, "'{}' is used both as a value and as an array", name)
+            }
+            VmError::DivisionByZero { .. } => write!(f, "division by zero"),
+            VmError::IndexOutOfBounds { index, length, .. } => write!(
+                f,
+                "index {} is out of bounds for an array of length {}",
+                index, length
+            ),
+            VmError::InvalidInput { .. } => write!(f, "invalid input"),
+            VmError::AssertionFailed {
+                message: Some(message),
+                ..
+            } => write!(f, "assertion failed: {}", message),
+            VmError::AssertionFailed { message: None, .. } => write!(f, "assertion failed"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {
+    Push(i64),
+    Pop,
+    Dup,
+    Load(String),
+    Store(String),
+    Array(String, usize),
+    LoadIndex(String),
+    StoreIndex(String),
+    Binary(BinaryOperator),
+    Min,
+    Max,
+    Negate,
+    Abs,
+    /// Continue at this index of the program.
+    Jump(usize),
+    JumpIfZero(usize),
+    Print(PrintFormat),
+    Text(String),
+    Digits,
+    Read,
+    Assert(Option<String>),
+    Exit,
+}
+
+/// Assembly parsed and ready to run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    int_width: IntWidth,
+    /// Each instruction with the line it came from.
+    instructions: Vec<(Instruction, usize)>,
+}
+
+impl Program {
+    /// Parses assembly in the format `generate_asm` writes. Without a
+    /// `.bits` line, values are 32 bits wide.
+    pub fn parse(text: &str) -> Result<Program, VmError> {
+        let mut int_width = IntWidth::Bits32;
+        let mut instructions = Vec::new();
+        let mut labels = HashMap::new();
+        // Jumps name labels that may come later, so they're resolved at the
+        // end.
+        let mut jumps = Vec::new();
+        for (i, text) in text.lines().enumerate() {
+            let line = i + 1;
+            let syntax = |message: String| VmError::Syntax { message, line };
+            let text = text.trim();
+            if text.is_empty() || text.starts_with(';') {
+                continue;
+            }
+            if let Some(label) = text.strip_suffix(':') {
+                if labels
+                    .insert(label.to_string(), instructions.len())
+                    .is_some()
+                {
+                    return Err(syntax(format!("label '{}' is defined twice", label)));
+                }
+                continue;
+            }
+            let (name, operand) = match text.split_once(' ') {
+                Some((name, operand)) => (name, operand.trim()),
+                None => (text, ""),
+            };
+            if name == ".bits" {
+                int_width = match operand {
+                    "32" => IntWidth::Bits32,
+                    "64" => IntWidth::Bits64,
+                    _ => return Err(syntax(format!("unsupported width '{}'", operand))),
+                };
+                continue;
+            }
+            let instruction = match (name, operand) {
+                ("PUSH", operand) => Instruction::Push(
+                    operand
+                        .parse()
+                        .map_err(|_| syntax(format!("'{}' isn't a number", operand)))?,
+                ),
+                ("ARRAY", operand) => {
+                    let length = operand
+                        .split_once(' ')
+                        .and_then(|(name, length)| Some((name, length.trim().parse().ok()?)));
+                    let Some((name, length)) = length else {
+                        return Err(syntax("ARRAY needs a name and a length".to_string()));
+                    };
+                    Instruction::Array(name.to_string(), length)
+                }
+                ("LOAD" | "STORE" | "LOADI" | "STOREI", "") => {
+                    return Err(syntax(format!("{} needs a name", name)));
+                }
+                ("LOAD", slot) => Instruction::Load(slot.to_string()),
+                ("STORE", slot) => Instruction::Store(slot.to_string()),
+                ("LOADI", slot) => Instruction::LoadIndex(slot.to_string()),
+                ("STOREI", slot) => Instruction::StoreIndex(slot.to_string()),
+                ("JMP" | "JZ", "") => return Err(syntax(format!("{} needs a label", name))),
+                ("JMP" | "JZ", label) => {
+                    jumps.push((instructions.len(), label.to_string(), line));
+                    // The target is filled in once every label is known.
+                    if name == "JMP" {
+                        Instruction::Jump(0)
+                    } else {
+                        Instruction::JumpIfZero(0)
+                    }
+                }
+                ("TEXT", text) => Instruction::Text(
+                    parse_string(text)
+                        .ok_or_else(|| syntax("TEXT needs a quoted string".to_string()))?,
+                ),
+                ("ASSERT", "") => Instruction::Assert(None),
+                ("ASSERT", message) => Instruction::Assert(Some(
+                    parse_string(message)
+                        .ok_or_else(|| syntax("ASSERT takes a quoted string".to_string()))?,
+                )),
+                (name, "") => match name {
+                    "POP" => Instruction::Pop,
+                    "DUP" => Instruction::Dup,
+                    "ADD" => Instruction::Binary(BinaryOperator::Add),
+                    "SUB" => Instruction::Binary(BinaryOperator::Subtract),
+                    "MUL" => Instruction::Binary(BinaryOperator::Multiply),
+                    "DIV" => Instruction::Binary(BinaryOperator::Divide),
+                    "MOD" => Instruction::Binary(BinaryOperator::Modulo),
+                    "POW" => Instruction::Binary(BinaryOperator::Power),
+                    "AND" => Instruction::Binary(BinaryOperator::BitwiseAnd),
+                    "OR" => Instruction::Binary(BinaryOperator::BitwiseOr),
+                    "XOR" => Instruction::Binary(BinaryOperator::BitwiseXor),
+                    "SHL" => Instruction::Binary(BinaryOperator::ShiftLeft),
+                    "SHR" => Instruction::Binary(BinaryOperator::ShiftRight),
+                    "EQ" => Instruction::Binary(BinaryOperator::Equal),
+                    "NE" => Instruction::Binary(BinaryOperator::NotEqual),
+                    "LT" => Instruction::Binary(BinaryOperator::Less),
+                    "LE" => Instruction::Binary(BinaryOperator::LessEqual),
+                    "GT" => Instruction::Binary(BinaryOperator::Greater),
+                    "GE" => Instruction::Binary(BinaryOperator::GreaterEqual),
+                    "MIN" => Instruction::Min,
+                    "MAX" => Instruction::Max,
+                    "NEG" => Instruction::Negate,
+                    "ABS" => Instruction::Abs,
+                    "PRINT" => Instruction::Print(PrintFormat::Decimal),
+                    "PRINTX" => Instruction::Print(PrintFormat::Hex),
+                    "PRINTC" => Instruction::Print(PrintFormat::Char),
+                    "DIGITS" => Instruction::Digits,
+                    "READ" => Instruction::Read,
+                    "EXIT" => Instruction::Exit,
+                    _ => return Err(syntax(format!("unknown instruction '{}'", name))),
+                },
+                (name, _) => {
+                    return Err(syntax(format!("{} takes no operand", name)));
+                }
+            };
+            instructions.push((instruction, line));
+        }
+        for (index, label, line) in jumps {
+            let Some(&target) = labels.get(&label) else {
+                return Err(VmError::Syntax {
+                    message: format!("no label named '{}'", label),
+                    line,
+                });
+            };
+            match &mut instructions[index].0 {
+                Instruction::Jump(to) | Instruction::JumpIfZero(to) => *to = target,
+                _ => unreachable!("only jumps are recorded"),
+            }
+        }
+        Ok(Program {
+            int_width,
+            instructions,
+        })
+    }
+}
+
+/// The text of a quoted string, undoing the escapes `generate_asm` writes:
+/// `\"`, `\\`, `\n` and `\xNN`.
+fn parse_string(quoted: &str) -> Option<String> {
+    let inner = quoted.strip_prefix('"')?.strip_suffix('"')?;
+    let mut bytes = Vec::new();
+    let mut rest = inner.bytes();
+    while let Some(byte) = rest.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest.next()? {
+            b'n' => bytes.push(b'\n'),
+            b'x' => {
+                let digits = [rest.next()?, rest.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&digits).ok()?, 16).ok()?);
+            }
+            escaped => bytes.push(escaped),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[derive(Debug, Clone)]
+enum Slot {
+    Value(i64),
+    Array(Vec<i64>),
+}
+
+pub struct Vm {
+    input: Input,
+    /// Printed output waiting to be returned by `run_capturing`, or `None`
+    /// to write it to stdout.
+    captured: Option<Vec<u8>>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    /// A machine that reads from stdin and prints to stdout.
+    pub fn new() -> Self {
+        Vm {
+            input: Input::stdin(),
+            captured: None,
+        }
+    }
+
+    /// A machine whose `READ`s take the values in `input` in order.
+    pub fn with_input(input: Vec<i64>) -> Self {
+        Vm {
+            input: Input::Queued(VecDeque::from(input)),
+            captured: None,
+        }
+    }
+
+    /// Like `run`, but returns everything the program printed instead of
+    /// writing it to stdout. The exit code is dropped.
+    pub fn run_capturing(&mut self, program: &Program) -> Result<String, VmError> {
+        self.captured = Some(Vec::new());
+        let result = self.run(program);
+        let output = self.captured.take().unwrap();
+        result.map(|_| String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Runs `program` until `EXIT` or its last instruction and returns the
+    /// exit code, 0 if it ran off the end.
+    pub fn run(&mut self, program: &Program) -> Result<i32, VmError> {
+        let width = program.int_width;
+        let mut stack: Vec<i64> = Vec::new();
+        let mut slots: HashMap<&str, Slot> = HashMap::new();
+        let mut pc = 0;
+        while let Some((instruction, line)) = program.instructions.get(pc) {
+            let line = *line;
+            pc += 1;
+            let mut pop = || stack.pop().ok_or(VmError::StackUnderflow { line });
+            match instruction {
+                Instruction::Push(value) => stack.push(*value),
+                Instruction::Pop => {
+                    pop()?;
+                }
+                Instruction::Dup => {
+                    let value = pop()?;
+                    stack.extend([value, value]);
+                }
+                Instruction::Load(name) => match slots.get(name.as_str()) {
+                    Some(Slot::Value(value)) => stack.push(*value),
+                    Some(Slot::Array(_)) => return Err(wrong_slot_kind(name, line)),
+                    None => return Err(unset(name, line)),
+                },
+                Instruction::Store(name) => {
+                    let value = pop()?;
+                    slots.insert(name, Slot::Value(value));
+                }
+                Instruction::Array(name, length) => {
+                    let value = pop()?;
+                    slots.insert(name, Slot::Array(vec![value; *length]));
+                }
+                Instruction::LoadIndex(name) => {
+                    let index = pop()?;
+                    let value = *element(&mut slots, name, index, line)?;
+                    stack.push(value);
+                }
+                Instruction::StoreIndex(name) => {
+                    let value = pop()?;
+                    let index = pop()?;
+                    *element(&mut slots, name, index, line)? = value;
+                }
+                Instruction::Binary(operator) => {
+                    let rhs = pop()?;
+                    let lhs = pop()?;
+                    let result = binary(operator, lhs, rhs, width)
+                        .ok_or(VmError::DivisionByZero { line })?;
+                    stack.push(result);
+                }
+                Instruction::Min | Instruction::Max => {
+                    let rhs = pop()?;
+                    let lhs = pop()?;
+                    stack.push(if *instruction == Instruction::Min {
+                        lhs.min(rhs)
+                    } else {
+                        lhs.max(rhs)
+                    });
+                }
+                Instruction::Negate => {
+                    let value = pop()?;
+                    stack.push(wrap(-i128::from(value), width));
+                }
+                Instruction::Abs => {
+                    let value = pop()?;
+                    stack.push(wrap(i128::from(value).abs(), width));
+                }
+                Instruction::Jump(target) => pc = *target,
+                Instruction::JumpIfZero(target) => {
+                    if pop()? == 0 {
+                        pc = *target;
+                    }
+                }
+                Instruction::Print(format) => {
+                    let value = pop()?;
+                    let bytes = match format {
+                        PrintFormat::Decimal => value.to_string().into_bytes(),
+                        // `%x` prints the two's complement bits.
+                        PrintFormat::Hex => match width {
+                            IntWidth::Bits32 => format!("{:x}", value as u32).into_bytes(),
+                            IntWidth::Bits64 => format!("{:x}", value as u64).into_bytes(),
+                        },
+                        // `%c` prints the low byte.
+                        PrintFormat::Char => vec![value as u8],
+                    };
+                    self.write(&bytes);
+                }
+                Instruction::Text(text) => self.write(text.as_bytes()),
+                Instruction::Digits => {
+                    let value = pop()?;
+                    stack.push(value.to_string().len() as i64);
+                }
+                Instruction::Read => {
+                    let value = self
+                        .input
+                        .next_value()
+                        .filter(|&value| width.fits(value))
+                        .ok_or(VmError::InvalidInput { line })?;
+                    stack.push(value);
+                }
+                Instruction::Assert(message) => {
+                    if pop()? == 0 {
+                        return Err(VmError::AssertionFailed {
+                            message: message.clone(),
+                            line,
+                        });
+                    }
+                }
+                // Like `return` from C's `main`, only the low bits reach the
+                // OS.
+                Instruction::Exit => return Ok(pop()? as i32),
+            }
+        }
+        Ok(0)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match &mut self.captured {
+            Some(output) => output.extend_from_slice(bytes),
+            // Like `printf`, a failed write to stdout is ignored.
+            None => _ = io::stdout().write_all(bytes),
+        }
+    }
+}
+
+fn unset(name: &str, line: usize) -> VmError {
+    VmError::Unset {
+        name: name.to_string(),
+        line,
+    }
+}
+
+fn wrong_slot_kind(name: &str, line: usize) -> VmError {
+    VmError::WrongSlotKind {
+        name: name.to_string(),
+        line,
+    }
+}
+
+/// The element of array `name` at `index`.
+fn element<'a>(
+    slots: &'a mut HashMap<&str, Slot>,
+    name: &str,
+    index: i64,
+    line: usize,
+) -> Result<&'a mut i64, VmError> {
+    let elements = match slots.get_mut(name) {
+        Some(Slot::Array(elements)) => elements,
+        Some(Slot::Value(_)) => return Err(wrong_slot_kind(name, line)),
+        None => return Err(unset(name, line)),
+    };
+    let length = elements.len();
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| elements.get_mut(i))
+        .ok_or(VmError::IndexOutOfBounds {
+            index,
+            length,
+            line,
+        })
+}
+
+/// `value` truncated to the integer width, wrapping around like two's
+/// complement arithmetic.
+fn wrap(value: i128, width: IntWidth) -> i64 {
+    match width {
+        IntWidth::Bits32 => value as i32 as i64,
+        IntWidth::Bits64 => value as i64,
+    }
+}
+
+/// `lhs operator rhs` with the interpreter's semantics, or `None` when
+/// dividing by zero.
+fn binary(operator: &BinaryOperator, lhs: i64, rhs: i64, width: IntWidth) -> Option<i64> {
+    let (wide_lhs, wide_rhs) = (i128::from(lhs), i128::from(rhs));
+    // Shift amounts wrap around the width, as they do in hardware.
+    let shift = (rhs as u32) % width.bits();
+    Some(match operator {
+        BinaryOperator::Add => wrap(wide_lhs + wide_rhs, width),
+        BinaryOperator::Subtract => wrap(wide_lhs - wide_rhs, width),
+        BinaryOperator::Multiply => wrap(wide_lhs * wide_rhs, width),
+        BinaryOperator::Divide | BinaryOperator::Modulo if rhs == 0 => return None,
+        BinaryOperator::Divide => wrap(wide_lhs / wide_rhs, width),
+        BinaryOperator::Modulo => wrap(wide_lhs % wide_rhs, width),
+        // A negative exponent gives 1. Wrapping at 64 bits keeps the low
+        // bits every narrower width needs.
+        BinaryOperator::Power => {
+            let (mut base, mut exponent, mut result) = (lhs, rhs.max(0), 1i64);
+            while exponent > 0 {
+                if exponent & 1 == 1 {
+                    result = result.wrapping_mul(base);
+                }
+                base = base.wrapping_mul(base);
+                exponent >>= 1;
+            }
+            wrap(result.into(), width)
+        }
+        BinaryOperator::BitwiseAnd => lhs & rhs,
+        BinaryOperator::BitwiseOr => lhs | rhs,
+        BinaryOperator::BitwiseXor => lhs ^ rhs,
+        BinaryOperator::ShiftLeft => wrap(wide_lhs << shift, width),
+        BinaryOperator::ShiftRight => lhs >> shift,
+        BinaryOperator::Equal => (lhs == rhs).into(),
+        BinaryOperator::NotEqual => (lhs != rhs).into(),
+        BinaryOperator::Less => (lhs < rhs).into(),
+        BinaryOperator::LessEqual => (lhs <= rhs).into(),
+        BinaryOperator::Greater => (lhs > rhs).into(),
+        BinaryOperator::GreaterEqual => (lhs >= rhs).into(),
+        // `generate_asm` turns these into jumps.
+        BinaryOperator::LogicalAnd | BinaryOperator::LogicalOr => {
+            unreachable!("no instruction maps to a logical operator")
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm_generator::generate_asm;
+    use crate::interpreter::Interpreter;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+
+    fn run_source(source: &str, input: Vec<i64>) -> Result<String, VmError> {
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        SemanticAnalyzer::analyze(&ast).unwrap();
+        let program = Program::parse(&generate_asm(&ast, IntWidth::Bits32)).unwrap();
+        Vm::with_input(input).run_capturing(&program)
+    }
+
+    #[test]
+    fn test_matches_interpreter() {
+        let source = "let mut a = [5, 6, 7]; a[1] = 2 ** 3 + min(a[0], 1); printx 0 - 1; printc 65; print a[1];
+             let x = 0; if x != 0 && 10 / x > 1 { print 1; } else if x == 0 { print 2147483647 + 1; }
+             loop i in 10 step 4 { print i; } loop j from 2 to 4 { print \"j=\", j > 2 || j == 0; }
+             let y = { let t = 4; t * t }; let w = write(\"y: \", y); print w, max_of(1, x, 3);
+             let mut r; read r; print -r % 4;
+             repeat { loop k in 9 { break if k > 1; print k; } break; } until 1 > 0;";
+        let ast = Parser::new(Lexer::new(source.to_string()).collect()).parse();
+        let expected = Interpreter::with_input(IntWidth::Bits32, vec![7])
+            .run_capturing(&ast)
+            .unwrap();
+
+        assert_eq!(run_source(source, vec![7]).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        assert_eq!(
+            run_source("let mut a[2] = 0; let i = 2; a[i] = 1;", Vec::new()),
+            Err(VmError::IndexOutOfBounds {
+                index: 2,
+                length: 2,
+                line: 8
+            })
+        );
+        assert!(matches!(
+            run_source("assert 1 > 2, \"no\";", Vec::new()),
+            Err(VmError::AssertionFailed {
+                message: Some(message),
+                ..
+            }) if message == "no"
+        ));
+        assert!(matches!(
+            run_source("let mut x; read x;", Vec::new()),
+            Err(VmError::InvalidInput { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = Program::parse(".bits 32\n    JMP nowhere\n").unwrap_err();
+        assert_eq!(error.to_string(), "no label named 'nowhere'");
+        assert_eq!(error.line(), 2);
+
+        let error = Program::parse("    PUSH x\n").unwrap_err();
+        assert_eq!(error.to_string(), "'x' isn't a number");
+
+        let error = Program::parse("    POP\n    HALT\n").unwrap_err();
+        assert_eq!(error.to_string(), "unknown instruction 'HALT'");
+        assert_eq!(error.line(), 2);
+
+        assert_eq!(
+            Vm::with_input(Vec::new()).run(&Program::parse("    ADD\n").unwrap()),
+            Err(VmError::StackUnderflow { line: 1 })
+        );
+    }
+}
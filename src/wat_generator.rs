@@ -0,0 +1,885 @@
+//! WebAssembly text backend. The program becomes an exported `main`
+//! function returning the exit code; printing calls functions imported from
+//! the host's `env` module, such as `print_i32`.
+
+use std::collections::BTreeSet;
+
+use crate::ast::*;
+use crate::const_eval::{IntWidth, eval_const};
+use crate::scope::ScopeStack;
+
+pub fn generate_wat(ast: &AbstractSyntaxTree, int_width: IntWidth) -> String {
+    let mut generator = WatGen {
+        ty: match int_width {
+            IntWidth::Bits32 => "i32",
+            IntWidth::Bits64 => "i64",
+        },
+        int_width,
+        scopes: ScopeStack::new(),
+        locals: Vec::new(),
+        constants: ScopeStack::new(),
+        memory_size: 0,
+        labels: 0,
+        breaks: Vec::new(),
+        imports: BTreeSet::new(),
+        helpers: BTreeSet::new(),
+        texts: Vec::new(),
+    };
+    // Printed text goes at the start of memory, before any array.
+    generator.place_texts(&ast.statement_list);
+    let mut body = Vec::new();
+    generator.generate_statement_list(&ast.statement_list, &mut body);
+
+    let ty = generator.ty;
+    let mut lines = vec!["(module".to_string()];
+    for import in &generator.imports {
+        let signature = match *import {
+            "print_str" => "(param i32 i32)".to_string(),
+            "read_i32" | "read_i64" => format!("(result {})", ty),
+            _ => format!("(param {})", ty),
+        };
+        lines.push(format!(
+            "  (import \"env\" \"{0}\" (func ${0} {1}))",
+            import, signature
+        ));
+    }
+    if generator.memory_size > 0 {
+        lines.push(format!(
+            "  (memory {})",
+            generator.memory_size.div_ceil(PAGE_SIZE)
+        ));
+    }
+    for (offset, text) in &generator.texts {
+        lines.push(format!(
+            "  (data (i32.const {}) {})",
+            offset,
+            wat_string(text)
+        ));
+    }
+    for helper in &generator.helpers {
+        lines.extend(
+            helper_function(*helper, ty)
+                .lines()
+                .map(|line| format!("  {}", line)),
+        );
+    }
+    lines.push("  (func $main (export \"main\") (result i32)".to_string());
+    for local in &generator.locals {
+        lines.push(format!("    (local ${} {})", local, ty));
+    }
+    lines.extend(body.iter().map(|line| format!("    {}", line)));
+    lines.push("    (i32.const 0)))".to_string());
+    lines.join("\n") + "\n"
+}
+
+const PAGE_SIZE: u32 = 65536;
+
+/// What a source name refers to in the current scope.
+#[derive(Clone)]
+enum Binding {
+    Local(String),
+    /// An array of the given length stored in linear memory from this byte
+    /// offset.
+    Array(u32, usize),
+}
+
+/// Functions emitted into the module only when the program needs them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Helper {
+    Pow,
+    Min,
+    Max,
+    Abs,
+    Width,
+}
+
+impl Helper {
+    fn name(self) -> &'static str {
+        match self {
+            Helper::Pow => "pow",
+            Helper::Min => "min",
+            Helper::Max => "max",
+            Helper::Abs => "abs",
+            Helper::Width => "width",
+        }
+    }
+}
+
+struct WatGen {
+    /// `i32` or `i64`, the type of every value.
+    ty: &'static str,
+    int_width: IntWidth,
+    scopes: ScopeStack<Binding>,
+    /// Every local of `main`. Wasm has no block scoping, so a shadowing
+    /// declaration gets a fresh local.
+    locals: Vec<String>,
+    constants: ScopeStack<i64>,
+    /// Bytes of linear memory used by arrays.
+    memory_size: u32,
+    /// Counter for unique loop labels and loop bookkeeping locals.
+    labels: usize,
+    /// The label number of every loop being generated, innermost last, for
+    /// `break` to leave its `$break_` block.
+    breaks: Vec<usize>,
+    imports: BTreeSet<&'static str>,
+    helpers: BTreeSet<Helper>,
+    /// Every distinct text printed, with the memory offset it's stored at.
+    texts: Vec<(u32, String)>,
+}
+
+impl WatGen {
+    fn element_size(&self) -> u32 {
+        self.int_width.bits() / 8
+    }
+
+    fn new_local(&mut self, name: &str) -> String {
+        let mut local = name.to_string();
+        let mut suffix = 1;
+        while self.locals.contains(&local) {
+            local = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        self.locals.push(local.clone());
+        local
+    }
+
+    fn declare_local(&mut self, name: &str) -> String {
+        let local = self.new_local(name);
+        self.scopes
+            .declare(name.to_string(), Binding::Local(local.clone()));
+        local
+    }
+
+    fn lookup(&self, name: &str) -> Binding {
+        self.scopes
+            .lookup(name)
+            .cloned()
+            .expect("undeclared variables are rejected by semantic analysis")
+    }
+
+    fn local(&self, identifier: &Identifier) -> String {
+        match self.lookup(&identifier.name) {
+            Binding::Local(local) => local,
+            Binding::Array(..) => unreachable!("arrays are only used through an index"),
+        }
+    }
+
+    /// Stores the text of every `print` in `statement_list` in memory.
+    fn place_texts(&mut self, statement_list: &StatementList) {
+        for statement in &statement_list.statements {
+            match statement {
+                Statement::Print(print_stmt) if print_stmt.arguments.is_empty() => {
+                    self.place_text("\n".to_string());
+                }
+                Statement::Print(print_stmt) => {
+                    for (i, argument) in print_stmt.arguments.iter().enumerate() {
+                        if let PrintArgument::Text(text) = argument {
+                            self.place_text(stored_text(print_stmt, i, text));
+                        }
+                    }
+                }
+                Statement::Loop(loop_stmt) => self.place_texts(&loop_stmt.body.statements),
+                Statement::Repeat(repeat_stmt) => self.place_texts(&repeat_stmt.body.statements),
+                Statement::If(if_stmt) => {
+                    let mut next = Some(if_stmt);
+                    while let Some(if_stmt) = next {
+                        self.place_texts(&if_stmt.then_block.statements);
+                        next = match if_stmt.else_branch.as_deref() {
+                            Some(ElseBranch::If(else_if)) => Some(else_if),
+                            Some(ElseBranch::Block(block)) => {
+                                self.place_texts(&block.statements);
+                                None
+                            }
+                            None => None,
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn place_text(&mut self, text: String) {
+        if self.text_offset(&text).is_none() {
+            let length = text.len() as u32;
+            self.texts.push((self.memory_size, text));
+            self.memory_size += length;
+        }
+    }
+
+    fn text_offset(&self, text: &str) -> Option<u32> {
+        self.texts
+            .iter()
+            .find(|(_, stored)| stored == text)
+            .map(|(offset, _)| *offset)
+    }
+
+    fn generate_print_statement(&mut self, print_stmt: &PrintStatement, out: &mut Vec<String>) {
+        let print_stmt = &print_stmt.expand_arrays(|name| match self.scopes.lookup(name) {
+            Some(Binding::Array(_, length)) => Some(*length),
+            _ => None,
+        });
+        if print_stmt.arguments.is_empty() {
+            out.push(self.print_text("\n"));
+        }
+        for (i, argument) in print_stmt.arguments.iter().enumerate() {
+            match argument {
+                PrintArgument::Text(text) => {
+                    out.push(self.print_text(&stored_text(print_stmt, i, text)));
+                }
+                PrintArgument::Value(value) => {
+                    let last = i + 1 == print_stmt.arguments.len();
+                    let function = self.print_function(&print_stmt.format, last);
+                    let value = self.generate_expression(value);
+                    out.push(format!("(call ${} {})", function, value));
+                }
+            }
+        }
+    }
+
+    /// The imported function printing a value in `format`. Only the `print_`
+    /// functions end the line, so every value but the `last` is written
+    /// without one.
+    fn print_function(&mut self, format: &PrintFormat, last: bool) -> &'static str {
+        let function = match (format, self.int_width, last) {
+            (PrintFormat::Decimal, IntWidth::Bits32, true) => "print_i32",
+            (PrintFormat::Decimal, IntWidth::Bits64, true) => "print_i64",
+            (PrintFormat::Decimal, IntWidth::Bits32, false) => "write_i32",
+            (PrintFormat::Decimal, IntWidth::Bits64, false) => "write_i64",
+            (PrintFormat::Hex, IntWidth::Bits32, true) => "print_hex_i32",
+            (PrintFormat::Hex, IntWidth::Bits64, true) => "print_hex_i64",
+            (PrintFormat::Hex, IntWidth::Bits32, false) => "write_hex_i32",
+            (PrintFormat::Hex, IntWidth::Bits64, false) => "write_hex_i64",
+            (PrintFormat::Char, IntWidth::Bits32, _) => "print_char_i32",
+            (PrintFormat::Char, IntWidth::Bits64, _) => "print_char_i64",
+        };
+        self.imports.insert(function);
+        function
+    }
+
+    /// Prints like `print`, then adds up the bytes written: text has a
+    /// known length, and `$width` counts a value's digits. Each value is
+    /// kept in a local of its own so it's only evaluated once.
+    fn generate_write(&mut self, write: &WriteExpr) -> String {
+        let ty = self.ty;
+        let mut instructions = Vec::new();
+        let mut lengths = Vec::new();
+        for (i, argument) in write.arguments.iter().enumerate() {
+            let last = i + 1 == write.arguments.len();
+            match argument {
+                PrintArgument::Text(text) => {
+                    let text = if last {
+                        format!("{}\n", text)
+                    } else {
+                        text.clone()
+                    };
+                    instructions.push(self.print_text(&text));
+                    lengths.push(format!("({}.const {})", ty, text.len()));
+                }
+                PrintArgument::Value(value) => {
+                    let function = self.print_function(&PrintFormat::Decimal, last);
+                    let value = self.generate_expression(value);
+                    let local = self.new_local("written");
+                    instructions.push(format!(
+                        "(call ${} (local.tee ${} {}))",
+                        function, local, value
+                    ));
+                    self.helpers.insert(Helper::Width);
+                    lengths.push(format!("(call $width (local.get ${}))", local));
+                    if last {
+                        lengths.push(format!("({}.const 1)", ty));
+                    }
+                }
+            }
+        }
+        if write.arguments.is_empty() {
+            instructions.push(self.print_text("\n"));
+            lengths.push(format!("({}.const 1)", ty));
+        }
+        let total = lengths
+            .into_iter()
+            .reduce(|sum, length| format!("({}.add {} {})", ty, sum, length))
+            .unwrap();
+        format!(
+            "(block (result {}) {} {})",
+            ty,
+            instructions.join(" "),
+            total
+        )
+    }
+
+    /// Prints `text`, storing it in memory first unless it's there already,
+    /// as the text of every `print` is.
+    fn print_text(&mut self, text: &str) -> String {
+        self.place_text(text.to_string());
+        self.imports.insert("print_str");
+        format!(
+            "(call $print_str (i32.const {}) (i32.const {}))",
+            self.text_offset(text).unwrap(),
+            text.len()
+        )
+    }
+
+    fn generate_statement_list(&mut self, statement_list: &StatementList, out: &mut Vec<String>) {
+        for statement in &statement_list.statements {
+            self.generate_statement(statement, out);
+        }
+    }
+
+    fn generate_block(&mut self, block: &Block, out: &mut Vec<String>) {
+        self.scopes.enter_scope();
+        self.constants.enter_scope();
+        let mut body = Vec::new();
+        self.generate_statement_list(&block.statements, &mut body);
+        self.constants.exit_scope();
+        self.scopes.exit_scope();
+        out.extend(body.into_iter().map(|line| format!("  {}", line)));
+    }
+
+    fn generate_statement(&mut self, statement: &Statement, out: &mut Vec<String>) {
+        match statement {
+            Statement::Let(let_stmt) => {
+                // Set even without an initializer, since a `let` in a loop
+                // body runs again on every iteration.
+                let value = match &let_stmt.value {
+                    Some(value) => self.generate_expression(value),
+                    None => format!("({}.const 0)", self.ty),
+                };
+                let local = self.declare_local(&let_stmt.identifier.name);
+                out.push(format!("(local.set ${} {})", local, value));
+            }
+            Statement::Const(const_stmt) => {
+                if let Some(value) = eval_const(&const_stmt.value, self.int_width) {
+                    self.constants
+                        .declare(const_stmt.identifier.name.clone(), value);
+                }
+            }
+            Statement::ArrayLet(array_let_stmt) => {
+                self.generate_array_let_statement(array_let_stmt, out)
+            }
+            Statement::Assignment(assign_stmt) => {
+                let value = self.generate_expression(&assign_stmt.value);
+                match &assign_stmt.index {
+                    Some(index) => {
+                        let address = self.generate_address(&assign_stmt.identifier, index);
+                        out.push(format!("({}.store {} {})", self.ty, address, value));
+                    }
+                    None => {
+                        let local = self.local(&assign_stmt.identifier);
+                        out.push(format!("(local.set ${} {})", local, value));
+                    }
+                }
+            }
+            Statement::Loop(loop_stmt) => self.generate_loop_statement(loop_stmt, out),
+            Statement::Repeat(repeat_stmt) => self.generate_repeat_statement(repeat_stmt, out),
+            Statement::If(if_stmt) => self.generate_if_statement(if_stmt, out),
+            Statement::Print(print_stmt) => self.generate_print_statement(print_stmt, out),
+            Statement::Read(read_stmt) => {
+                // The host decides what happens when there's no integer left.
+                let function = match self.int_width {
+                    IntWidth::Bits32 => "read_i32",
+                    IntWidth::Bits64 => "read_i64",
+                };
+                self.imports.insert(function);
+                let local = self.local(&read_stmt.identifier);
+                out.push(format!("(local.set ${} (call ${}))", local, function));
+            }
+            Statement::Assert(assert_stmt) => {
+                // Like the C backend, a failed assertion exits with code 1.
+                let condition = self.generate_condition(&assert_stmt.condition);
+                out.push(format!(
+                    "(if (i32.eqz {}) (then (return (i32.const 1))))",
+                    condition
+                ));
+            }
+            Statement::Break(_) => {
+                let label = self
+                    .breaks
+                    .last()
+                    .expect("analysis rejects `break` outside a loop");
+                out.push(format!("(br $break_{})", label));
+            }
+            Statement::Exit(exit_stmt) => {
+                let code = self.generate_expression(&exit_stmt.code);
+                let code = match self.int_width {
+                    IntWidth::Bits32 => code,
+                    IntWidth::Bits64 => format!("(i32.wrap_i64 {})", code),
+                };
+                out.push(format!("(return {})", code));
+            }
+            Statement::Expression(expr_stmt) => {
+                let value = self.generate_expression(&expr_stmt.value);
+                out.push(format!("(drop {})", value));
+            }
+            Statement::Comment(comment) => out.push(format!(";; {}", comment.text)),
+            // Rejected by semantic analysis.
+            Statement::Include(_) => {}
+            // The module's consts follow it.
+            Statement::Use(_) => {}
+        }
+    }
+
+    fn generate_array_let_statement(
+        &mut self,
+        array_let_stmt: &ArrayLetStatement,
+        out: &mut Vec<String>,
+    ) {
+        let base = self.memory_size;
+        let elements: Vec<String> = match &array_let_stmt.initializer {
+            ArrayInitializer::Fill(value) => {
+                vec![self.generate_expression(value); array_let_stmt.length]
+            }
+            ArrayInitializer::List(elements) => elements
+                .iter()
+                .map(|element| self.generate_expression(element))
+                .collect(),
+        };
+        for (i, element) in elements.iter().enumerate() {
+            out.push(format!(
+                "({}.store (i32.const {}) {})",
+                self.ty,
+                base + i as u32 * self.element_size(),
+                element
+            ));
+        }
+        self.memory_size += array_let_stmt.length as u32 * self.element_size();
+        self.scopes.declare(
+            array_let_stmt.identifier.name.clone(),
+            Binding::Array(base, array_let_stmt.length),
+        );
+    }
+
+    fn generate_loop_statement(&mut self, loop_stmt: &LoopStatement, out: &mut Vec<String>) {
+        let label = self.labels;
+        self.labels += 1;
+        let ty = self.ty;
+        // As in C, the bounds and step are evaluated once, before the counter
+        // is declared.
+        let start = match &loop_stmt.start {
+            Some(start) => {
+                let local = self.new_local(&format!("_start_{}", label));
+                out.push(format!(
+                    "(local.set ${} {})",
+                    local,
+                    self.generate_expression(start)
+                ));
+                format!("(local.get ${})", local)
+            }
+            None => format!("({}.const 0)", ty),
+        };
+        let count = self.new_local(&format!("_count_{}", label));
+        out.push(format!(
+            "(local.set ${} {})",
+            count,
+            self.generate_expression(&loop_stmt.count)
+        ));
+        let step = match &loop_stmt.step {
+            Some(step) => {
+                let local = self.new_local(&format!("_step_{}", label));
+                out.push(format!(
+                    "(local.set ${} {})",
+                    local,
+                    self.generate_expression(step)
+                ));
+                format!("(local.get ${})", local)
+            }
+            None => format!("({}.const 1)", ty),
+        };
+
+        self.scopes.enter_scope();
+        let counter = match &loop_stmt.counter {
+            Some(counter) => self.declare_local(&counter.name),
+            None => self.new_local(&format!("_counter_{}", label)),
+        };
+        out.push(format!("(local.set ${} {})", counter, start));
+        out.push(format!("(block $break_{}", label));
+        // A range loop is entered once if the start is at most the end, and
+        // left after the iteration that reaches the end, so the counter never
+        // steps past it.
+        let range = loop_stmt.start.is_some();
+        if range {
+            out.push(format!(
+                "  (br_if $break_{} ({}.gt_s (local.get ${}) (local.get ${})))",
+                label, ty, counter, count
+            ));
+        }
+        out.push(format!("  (loop $continue_{}", label));
+        if !range {
+            out.push(format!(
+                "    (br_if $break_{} ({}.ge_s (local.get ${}) (local.get ${})))",
+                label, ty, counter, count
+            ));
+        }
+        let mut body = Vec::new();
+        self.breaks.push(label);
+        self.generate_block(&loop_stmt.body, &mut body);
+        self.breaks.pop();
+        out.extend(body.into_iter().map(|line| format!("  {}", line)));
+        if range && loop_stmt.step.is_none() {
+            out.push(format!(
+                "    (br_if $break_{} ({}.eq (local.get ${}) (local.get ${})))",
+                label, ty, counter, count
+            ));
+        } else if range {
+            out.push(format!(
+                "    (br_if $break_{} ({1}.lt_u ({1}.sub (local.get ${2}) (local.get ${3})) {4}))",
+                label, ty, count, counter, step
+            ));
+        }
+        out.push(format!(
+            "    (local.set ${} ({}.add (local.get ${}) {}))",
+            counter, ty, counter, step
+        ));
+        out.push(format!("    (br $continue_{})))", label));
+        self.scopes.exit_scope();
+    }
+
+    /// The body falls through out of the `loop` once the condition holds.
+    /// The `block` around it is what `break` leaves.
+    fn generate_repeat_statement(&mut self, repeat_stmt: &RepeatStatement, out: &mut Vec<String>) {
+        let label = self.labels;
+        self.labels += 1;
+        out.push(format!("(block $break_{}", label));
+        out.push(format!("  (loop $continue_{}", label));
+        let mut body = Vec::new();
+        self.breaks.push(label);
+        self.generate_block(&repeat_stmt.body, &mut body);
+        self.breaks.pop();
+        out.extend(body.into_iter().map(|line| format!("  {}", line)));
+        let condition = self.generate_condition(&repeat_stmt.condition);
+        out.push(format!(
+            "    (br_if $continue_{} (i32.eqz {}))))",
+            label, condition
+        ));
+    }
+
+    fn generate_if_statement(&mut self, if_stmt: &IfStatement, out: &mut Vec<String>) {
+        let condition = self.generate_condition(&if_stmt.condition);
+        out.push(format!("(if {}", condition));
+        out.push("  (then".to_string());
+        let mut then_body = Vec::new();
+        self.generate_block(&if_stmt.then_block, &mut then_body);
+        out.extend(then_body.into_iter().map(|line| format!("  {}", line)));
+        let Some(else_branch) = if_stmt.else_branch.as_deref() else {
+            out.push("  ))".to_string());
+            return;
+        };
+        out.push("  )".to_string());
+        out.push("  (else".to_string());
+        let mut else_body = Vec::new();
+        match else_branch {
+            ElseBranch::Block(block) => self.generate_block(block, &mut else_body),
+            ElseBranch::If(next) => {
+                let mut nested = Vec::new();
+                self.generate_if_statement(next, &mut nested);
+                else_body.extend(nested.into_iter().map(|line| format!("  {}", line)));
+            }
+        }
+        out.extend(else_body.into_iter().map(|line| format!("  {}", line)));
+        out.push("  ))".to_string());
+    }
+
+    /// `expr` as an `i32` for `if` and `br_if`, which don't take `i64`.
+    fn generate_condition(&mut self, expr: &Expr) -> String {
+        let value = self.generate_expression(expr);
+        match self.int_width {
+            IntWidth::Bits32 => value,
+            IntWidth::Bits64 => format!("(i32.wrap_i64 {})", value),
+        }
+    }
+
+    /// Byte address of `base[index]`, as an `i32`.
+    fn generate_address(&mut self, base: &Identifier, index: &Expr) -> String {
+        let Binding::Array(offset, _) = self.lookup(&base.name) else {
+            unreachable!("indexing a non-array is rejected by semantic analysis");
+        };
+        let index = self.generate_expression(index);
+        let index = match self.int_width {
+            IntWidth::Bits32 => index,
+            IntWidth::Bits64 => format!("(i32.wrap_i64 {})", index),
+        };
+        format!(
+            "(i32.add (i32.const {}) (i32.mul {} (i32.const {})))",
+            offset,
+            index,
+            self.element_size()
+        )
+    }
+
+    fn generate_expression(&mut self, expr: &Expr) -> String {
+        let ty = self.ty;
+        match &expr.kind {
+            ExprKind::Term(Term::Number(n)) => format!("({}.const {})", ty, n),
+            ExprKind::Term(Term::Bool(value)) => format!("({}.const {})", ty, i64::from(*value)),
+            ExprKind::Term(Term::Identifier(identifier)) => {
+                match self.constants.lookup(&identifier.name) {
+                    Some(value) => format!("({}.const {})", ty, value),
+                    None => format!("(local.get ${})", self.local(identifier)),
+                }
+            }
+            ExprKind::Term(Term::Index { base, index }) => {
+                format!("({}.load {})", ty, self.generate_address(base, index))
+            }
+            ExprKind::Call(call) => {
+                let helper = match Intrinsic::from_name(&call.function.name) {
+                    Some(Intrinsic::Min) => Helper::Min,
+                    Some(Intrinsic::Max) => Helper::Max,
+                    Some(Intrinsic::Abs) => Helper::Abs,
+                    Some(Intrinsic::MinOf) => Helper::Min,
+                    Some(Intrinsic::MaxOf) => Helper::Max,
+                    None => unreachable!("unknown functions are rejected by semantic analysis"),
+                };
+                let arguments: Vec<String> = call
+                    .arguments
+                    .iter()
+                    .map(|argument| self.generate_expression(argument))
+                    .collect();
+                if helper == Helper::Abs {
+                    self.helpers.insert(helper);
+                    return format!("(call $abs {})", arguments[0]);
+                }
+                if arguments.len() > 1 {
+                    self.helpers.insert(helper);
+                }
+                // `min_of` and `max_of` nest the two-argument helper.
+                arguments
+                    .into_iter()
+                    .reduce(|result, argument| {
+                        format!("(call ${} {} {})", helper.name(), result, argument)
+                    })
+                    .unwrap()
+            }
+            ExprKind::Binary(binary) => self.generate_binary_expression(binary),
+            ExprKind::Negate(operand) => {
+                let value = self.generate_expression(operand);
+                format!("({0}.sub ({0}.const 0) {1})", ty, value)
+            }
+            ExprKind::Write(write) => self.generate_write(write),
+            ExprKind::StringLength(text) => format!("({}.const {})", ty, text.len()),
+            ExprKind::Block(block) => {
+                self.scopes.enter_scope();
+                self.constants.enter_scope();
+                let mut instructions = Vec::new();
+                self.generate_statement_list(&block.statements, &mut instructions);
+                instructions.push(self.generate_expression(&block.value));
+                self.constants.exit_scope();
+                self.scopes.exit_scope();
+                format!("(block (result {}) {})", ty, instructions.join(" "))
+            }
+        }
+    }
+
+    fn generate_binary_expression(&mut self, binary: &BinaryExpr) -> String {
+        let ty = self.ty;
+        let lhs = self.generate_expression(&binary.lhs);
+        let rhs = self.generate_expression(&binary.rhs);
+        let instruction = match binary.operator {
+            BinaryOperator::Add => "add",
+            BinaryOperator::Subtract => "sub",
+            BinaryOperator::Multiply => "mul",
+            BinaryOperator::Divide => "div_s",
+            BinaryOperator::Modulo => "rem_s",
+            BinaryOperator::BitwiseAnd => "and",
+            BinaryOperator::BitwiseOr => "or",
+            BinaryOperator::BitwiseXor => "xor",
+            BinaryOperator::ShiftLeft => "shl",
+            BinaryOperator::ShiftRight => "shr_s",
+            BinaryOperator::Power => {
+                self.helpers.insert(Helper::Pow);
+                return format!("(call $pow {} {})", lhs, rhs);
+            }
+            // Short-circuit like C, so `x != 0 && 10 / x > 1` can't trap.
+            BinaryOperator::LogicalAnd => {
+                return format!(
+                    "(if (result {0}) {1} (then {2}) (else ({0}.const 0)))",
+                    ty,
+                    self.as_condition(lhs),
+                    rhs
+                );
+            }
+            BinaryOperator::LogicalOr => {
+                return format!(
+                    "(if (result {0}) {1} (then ({0}.const 1)) (else {2}))",
+                    ty,
+                    self.as_condition(lhs),
+                    rhs
+                );
+            }
+            BinaryOperator::Equal => return self.comparison("eq", lhs, rhs),
+            BinaryOperator::NotEqual => return self.comparison("ne", lhs, rhs),
+            BinaryOperator::Less => return self.comparison("lt_s", lhs, rhs),
+            BinaryOperator::LessEqual => return self.comparison("le_s", lhs, rhs),
+            BinaryOperator::Greater => return self.comparison("gt_s", lhs, rhs),
+            BinaryOperator::GreaterEqual => return self.comparison("ge_s", lhs, rhs),
+        };
+        format!("({}.{} {} {})", ty, instruction, lhs, rhs)
+    }
+
+    /// Comparisons always produce an `i32`, so widen it in 64-bit mode.
+    fn comparison(&self, instruction: &str, lhs: String, rhs: String) -> String {
+        let result = format!("({}.{} {} {})", self.ty, instruction, lhs, rhs);
+        match self.int_width {
+            IntWidth::Bits32 => result,
+            IntWidth::Bits64 => format!("(i64.extend_i32_u {})", result),
+        }
+    }
+
+    fn as_condition(&self, value: String) -> String {
+        match self.int_width {
+            IntWidth::Bits32 => value,
+            IntWidth::Bits64 => format!("(i32.wrap_i64 {})", value),
+        }
+    }
+}
+
+fn helper_function(helper: Helper, ty: &str) -> String {
+    match helper {
+        // Matches the C backend's `_hand_pow`: a negative exponent gives 1.
+        Helper::Pow => format!(
+            "(func $pow (param $base {0}) (param $exp {0}) (result {0})
+  (local $result {0})
+  (local.set $result ({0}.const 1))
+  (block $done
+    (loop $next
+      (br_if $done ({0}.le_s (local.get $exp) ({0}.const 0)))
+      (local.set $result ({0}.mul (local.get $result) (local.get $base)))
+      (local.set $exp ({0}.sub (local.get $exp) ({0}.const 1)))
+      (br $next)))
+  (local.get $result))",
+            ty
+        ),
+        Helper::Min => format!(
+            "(func $min (param $a {0}) (param $b {0}) (result {0})
+  (select (local.get $a) (local.get $b) ({0}.lt_s (local.get $a) (local.get $b))))",
+            ty
+        ),
+        Helper::Max => format!(
+            "(func $max (param $a {0}) (param $b {0}) (result {0})
+  (select (local.get $a) (local.get $b) ({0}.gt_s (local.get $a) (local.get $b))))",
+            ty
+        ),
+        Helper::Abs => format!(
+            "(func $abs (param $x {0}) (result {0})
+  (select ({0}.sub ({0}.const 0) (local.get $x)) (local.get $x) ({0}.lt_s (local.get $x) ({0}.const 0))))",
+            ty
+        ),
+        // The number of characters `%d` prints for `$x`, sign included.
+        Helper::Width => format!(
+            "(func $width (param $x {0}) (result {0})
+  (local $width {0})
+  (local.set $width (select ({0}.const 2) ({0}.const 1) ({0}.lt_s (local.get $x) ({0}.const 0))))
+  (block $done
+    (loop $next
+      (local.set $x ({0}.div_s (local.get $x) ({0}.const 10)))
+      (br_if $done ({0}.eqz (local.get $x)))
+      (local.set $width ({0}.add (local.get $width) ({0}.const 1)))
+      (br $next)))
+  (local.get $width))",
+            ty
+        ),
+    }
+}
+
+/// The bytes stored for the text argument at `index`. Text that ends a
+/// line-terminated `print` carries the newline itself.
+fn stored_text(print_stmt: &PrintStatement, index: usize, text: &str) -> String {
+    let last = index + 1 == print_stmt.arguments.len();
+    if last && print_stmt.format != PrintFormat::Char {
+        format!("{}\n", text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// `text` as a quoted WebAssembly string, escaping every byte that isn't
+/// printable ASCII.
+fn wat_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => result.push_str(&format!("\\{}", byte as char)),
+            b' '..=b'~' => result.push(byte as char),
+            _ => result.push_str(&format!("\\{:02x}", byte)),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::semantic_analyzer::SemanticAnalyzer;
+
+    fn compile_source_to_wat(source: &str) -> String {
+        let lexer = Lexer::new(source.to_string());
+        let ast = Parser::new(lexer.collect()).parse();
+        SemanticAnalyzer::analyze(&ast).unwrap();
+        generate_wat(&ast, IntWidth::Bits32)
+    }
+
+    #[test]
+    fn test_loop_program() {
+        let wat = compile_source_to_wat(
+            "let mut i = 0; let mut sum = 0; loop 10 { i = i + 1; sum = sum + i; print sum; };",
+        );
+
+        assert!(wat.starts_with("(module\n"));
+        assert!(wat.contains("(import \"env\" \"print_i32\" (func $print_i32 (param i32)))"));
+        assert!(wat.contains("(loop $continue_0"));
+        assert!(wat.contains("(br_if $break_0"));
+        assert!(wat.contains("(call $print_i32 (local.get $sum))"));
+        assert!(wat.contains("(local.set $sum (i32.add (local.get $sum) (local.get $i)))"));
+        assert_eq!(
+            wat.matches('(').count(),
+            wat.matches(')').count(),
+            "unbalanced parentheses in\n{}",
+            wat
+        );
+    }
+
+    #[test]
+    fn test_shadowing_gets_a_fresh_local() {
+        let wat = compile_source_to_wat("let x = 1; loop 2 { let x = 5; print x; } print x;");
+
+        assert!(wat.contains("(local $x i32)"));
+        assert!(wat.contains("(local $x_1 i32)"));
+        assert!(wat.contains("(call $print_i32 (local.get $x_1))"));
+        assert!(
+            wat.trim_end()
+                .ends_with("(call $print_i32 (local.get $x))\n    (i32.const 0)))")
+        );
+    }
+
+    #[test]
+    fn test_helpers_and_imports_only_when_used() {
+        let wat = compile_source_to_wat("print 1;");
+        assert!(!wat.contains("$pow"));
+        assert!(!wat.contains("memory"));
+        assert!(!wat.contains("print_hex_i32"));
+
+        let wat = compile_source_to_wat("let a = [1, 2]; printx min(a[1], 2 ** 3);");
+        assert!(wat.contains("(func $pow"));
+        assert!(wat.contains("(func $min"));
+        assert!(wat.contains("(memory 1)"));
+        assert!(wat.contains("(i32.store (i32.const 4) (i32.const 2))"));
+        assert!(wat.contains("print_hex_i32"));
+    }
+
+    #[test]
+    fn test_print_text_is_stored_in_memory() {
+        let wat = compile_source_to_wat("let x = 5; print \"x = \", x, \"!\"; print \"x = \", x;");
+
+        assert!(wat.contains("(import \"env\" \"print_str\" (func $print_str (param i32 i32)))"));
+        assert!(wat.contains("(data (i32.const 0) \"x = \")"));
+        assert!(wat.contains("(data (i32.const 4) \"!\\0a\")"));
+        assert!(wat.contains(
+            "(call $print_str (i32.const 0) (i32.const 4))\n    (call $write_i32 (local.get $x))\n    (call $print_str (i32.const 4) (i32.const 2))"
+        ));
+        assert!(wat.ends_with(
+            "(call $print_str (i32.const 0) (i32.const 4))\n    (call $print_i32 (local.get $x))\n    (i32.const 0)))\n"
+        ));
+    }
+}
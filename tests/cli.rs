@@ -0,0 +1,206 @@
+//! Tests that run the compiler binary itself, for behavior that lives in
+//! `main.rs`.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Runs the compiler on `source` piped to stdin, with `args` before the `-`.
+fn run(args: &[&str], source: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_compiler"))
+        .args(args)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_max_errors_caps_reported_errors() {
+    let source: String = (0..50).map(|i| format!("print v{};\n", i)).collect();
+
+    let output = run(&[], &source);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert_eq!(stderr.matches("Error at").count(), 20);
+    assert!(stderr.contains("<stdin>:20:7: Use of undeclared variable 'v19'"));
+    assert!(stderr.ends_with("  ... and 30 more\n"));
+
+    let output = run(&["--max-errors", "0"], &source);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.matches("Error at").count(), 50);
+    assert!(!stderr.contains("more"));
+}
+
+#[test]
+fn test_color_flag() {
+    let source = "let x; print x; print y;";
+
+    let output = run(&["--color", "never"], source);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Warning at <stdin>:1:14"));
+    assert!(!stderr.contains('\x1b'));
+
+    let output = run(&["--color", "always"], source);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("\x1b[1;33mWarning\x1b[0m at <stdin>:1:14"));
+    assert!(stderr.contains("  \x1b[1;31mError\x1b[0m at <stdin>:1:23"));
+
+    // Piped stderr isn't a terminal, so the default is no color.
+    let output = run(&[], source);
+    assert!(!String::from_utf8(output.stderr).unwrap().contains('\x1b'));
+}
+
+#[test]
+fn test_explain_error_code() {
+    let output = run(&[], "print y;");
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("'y' [E0001]")
+    );
+
+    let output = run(&["--explain", "E0001"], "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("E0001: use of an undeclared variable\n"));
+
+    let output = run(&["--explain", "E9999"], "");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_run_vm_matches_compiled_output() {
+    let source =
+        "let mut i = 0; let mut sum = 0; loop 10 { i = i + 1; sum = sum + i; print sum; };";
+
+    // The numbers the compiled C program prints.
+    let output = run(&["--run-vm"], source);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1\n3\n6\n10\n15\n21\n28\n36\n45\n55\n"
+    );
+
+    let output = run(&["--run-vm"], "let a[2] = 0; let mut i = 2; print a[i];");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("index 2 is out of bounds for an array of length 2")
+    );
+}
+
+#[test]
+fn test_predict_output() {
+    let output = run(
+        &["--predict-output", "--stdout"],
+        "let mut x = 2; loop 2 { x = x + 1; print x; };",
+    );
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .ends_with("Predicted output:\n3\n4\n")
+    );
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .starts_with("#include <stdio.h>")
+    );
+
+    let output = run(&["--predict-output", "--stdout"], "let mut x; read x;");
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Can't predict the output: the program reads input")
+    );
+}
+
+#[test]
+fn test_deeply_nested_expression_is_an_error() {
+    let source = format!("print {}1{};", "(".repeat(100_000), ")".repeat(100_000));
+    let output = run(&["--stdout"], &source);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("expression nested more than 256 levels deep [E0102]")
+    );
+
+    // Just under the limit, every pass gets through it.
+    let source = format!("print {}1{};", "(".repeat(250), ")".repeat(250));
+    let output = run(&["--run"], &source);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+}
+
+#[test]
+fn test_json_error_format_covers_syntax_errors() {
+    let output = run(&["--error-format", "json"], "let x = 1\nprint x;");
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "{\"severity\":\"error\",\"code\":\"E0100\",\"message\":\"expected `;` after statement, found `print`\",\"line\":1,\"column\":10,\"span\":{\"start\":9,\"end\":9}}\n"
+    );
+
+    let output = run(&["--error-format", "json"], "let x = 1 $ 2;");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("{\"severity\":\"error\",\"code\":\"E0101\","));
+    assert!(stderr.contains("\"message\":\"unexpected character `$`\""));
+}
+
+/// Runs `program` with `input` piped in, or returns `None` if the tool isn't
+/// installed.
+fn run_tool(program: &str, args: &[&str], input: &str) -> Option<String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{} failed", program);
+    Some(String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_range_loop_ending_at_int_max_stops_on_every_path() {
+    let source = "let n = 2147483646; loop i from n to 2147483647 { print i; }
+        let s = 4; let m = 2147483640; loop j from m to 2147483647 step s { print j; }";
+    let expected = "2147483646\n2147483647\n2147483640\n2147483644\n";
+
+    for flag in ["--run", "--run-vm"] {
+        let output = run(&[flag], source);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    }
+
+    let c = String::from_utf8(run(&["--stdout"], source).stdout).unwrap();
+    let binary = std::env::temp_dir().join(format!("range_loop_{}", std::process::id()));
+    let binary = binary.to_str().unwrap();
+    if run_tool("gcc", &["-x", "c", "-", "-o", binary], &c).is_some() {
+        assert_eq!(run_tool(binary, &[], "").unwrap(), expected);
+        std::fs::remove_file(binary).unwrap();
+    }
+
+    let llvm = String::from_utf8(run(&["--emit", "llvm", "--stdout"], source).stdout).unwrap();
+    if let Some(stdout) = run_tool("lli", &["-"], &llvm) {
+        assert_eq!(stdout, expected);
+    }
+}
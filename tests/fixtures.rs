@@ -0,0 +1,61 @@
+//! End-to-end tests that compile every `fixtures/*.hand` file and compare the
+//! result against the committed `fixtures/*.c.golden` file.
+//!
+//! Run with `UPDATE_GOLDENS=1 cargo test` to regenerate the golden files.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+}
+
+fn fixture_sources() -> Vec<PathBuf> {
+    let mut sources: Vec<PathBuf> = fs::read_dir(fixtures_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hand"))
+        .collect();
+    sources.sort();
+    sources
+}
+
+#[test]
+fn test_fixtures_match_goldens() {
+    let update = env::var_os("UPDATE_GOLDENS").is_some();
+    let sources = fixture_sources();
+    assert!(!sources.is_empty(), "no fixtures found");
+
+    let mut mismatches = Vec::new();
+    for source_path in sources {
+        let source = fs::read_to_string(&source_path).unwrap();
+        let generated = compiler::compile(&source, &Default::default())
+            .unwrap_or_else(|error| panic!("{}: {}", source_path.display(), error));
+        let golden_path = source_path.with_extension("c.golden");
+
+        if update {
+            fs::write(&golden_path, &generated).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&golden_path).unwrap_or_else(|_| {
+            panic!(
+                "missing golden file {} (run with UPDATE_GOLDENS=1)",
+                golden_path.display()
+            )
+        });
+        if generated != expected {
+            mismatches.push(format!(
+                "{}\n--- expected ---\n{}--- generated ---\n{}",
+                source_path.display(),
+                expected,
+                generated
+            ));
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}